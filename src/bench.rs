@@ -0,0 +1,293 @@
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use llm::chat::{ChatMessage, ChatRole, MessageType};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use crate::chat::{render_markdown, Failover};
+use crate::config::{resolve_effective_provider, Config};
+use crate::tools::ToolsRegistry;
+
+/// Files in the generated workspace the tools run on.
+const FIXTURE_FILES: usize = 200;
+
+/// Functions per fixture file.
+const FIXTURE_ITEMS: usize = 25;
+
+/// Terminal width answers are rendered at.
+const RENDER_COLS: usize = 100;
+
+/// Asked of the provider on every run, so runs and builds compare.
+const PROMPT: &str = "Reply with three short sentences about the planet Mars.";
+
+/// A typical answer: prose, a list, a table, and a code block.
+const ANSWER: &str = r#"## Reading a config file
+
+Use `serde` with `toml` to load the file into a struct. Missing fields fall back to their defaults:
+
+1. Derive `Deserialize` and `Default` on the struct.
+2. Mark it `#[serde(default)]`.
+3. Read the file and call `toml::from_str`.
+
+| Field     | Type     | Default |
+|-----------|----------|---------|
+| `name`    | `String` | `""`    |
+| `retries` | `u32`    | `3`     |
+
+```rust
+use serde::Deserialize;
+use std::fs;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Config {
+    name: String,
+    retries: u32,
+}
+
+fn load(path: &str) -> anyhow::Result<Config> {
+    let text = fs::read_to_string(path)?;
+    Ok(toml::from_str(&text)?)
+}
+
+fn main() -> anyhow::Result<()> {
+    let cfg = load("config.toml")?;
+    println!("{} retries for {}", cfg.retries, cfg.name);
+    Ok(())
+}
+```
+
+The loader returns an error for malformed TOML rather than silently using defaults, so a typo in the file is reported.
+"#;
+
+fn ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Minimum, median, mean, and maximum of `samples`, in milliseconds, with
+/// the samples themselves in run order.
+fn stats(samples: &[f64]) -> Value {
+    if samples.is_empty() {
+        return json!({ "runs": 0 });
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    };
+    json!({
+        "runs": samples.len(),
+        "min_ms": round(sorted[0]),
+        "median_ms": round(median),
+        "mean_ms": round(samples.iter().sum::<f64>() / samples.len() as f64),
+        "max_ms": round(sorted[sorted.len() - 1]),
+        "samples_ms": samples.iter().map(|s| round(*s)).collect::<Vec<_>>(),
+    })
+}
+
+/// Time from spawning tai to its exit, for the cheapest command it has.
+fn startup(runs: usize) -> Result<Value> {
+    let exe = std::env::current_exe().context("Failed to locate the tai binary")?;
+    let mut samples = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let start = Instant::now();
+        let status = Command::new(&exe)
+            .arg("--help")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .with_context(|| format!("Failed to run {}", exe.display()))?;
+        samples.push(ms(start));
+        if !status.success() {
+            anyhow::bail!("{} --help failed", exe.display());
+        }
+    }
+    Ok(stats(&samples))
+}
+
+/// Time to build the client, then per run the time to the first streamed
+/// chunk and to the end of the answer. The first run includes connecting.
+async fn provider(
+    cfg: &Config,
+    runs: usize,
+    provider: Option<&str>,
+    model: Option<&str>,
+) -> Result<Value> {
+    let eff = resolve_effective_provider(cfg, provider, model)?;
+    let start = Instant::now();
+    let llm = Failover::new(cfg, &ToolsRegistry::new(), &eff)?;
+    let setup = ms(start);
+    let messages = vec![ChatMessage {
+        role: ChatRole::User,
+        message_type: MessageType::Text,
+        content: PROMPT.to_string(),
+    }];
+
+    let mut first_token = Vec::with_capacity(runs);
+    let mut total = Vec::with_capacity(runs);
+    let mut chunks = Vec::with_capacity(runs);
+    for run in 1..=runs {
+        eprintln!("Provider run {}/{}", run, runs);
+        let start = Instant::now();
+        let mut stream = llm
+            .chat_stream(&messages)
+            .await
+            .context("Streaming request failed")?;
+        let mut first = None;
+        let mut count = 0;
+        while let Some(chunk) = stream.next().await {
+            chunk.context("Stream failed")?;
+            first.get_or_insert_with(|| ms(start));
+            count += 1;
+        }
+        total.push(ms(start));
+        first_token.push(first.context("The provider streamed no tokens")?);
+        chunks.push(count);
+    }
+    Ok(json!({
+        "provider": eff.name,
+        "model": eff.model,
+        "setup_ms": round(setup),
+        "first_token": stats(&first_token),
+        "total": stats(&total),
+        "chunks": chunks,
+    }))
+}
+
+/// A workspace of generated Rust files, the same on every run.
+fn fixture() -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join("tai-bench");
+    if dir.exists() {
+        fs::remove_dir_all(&dir).with_context(|| format!("Failed to clear {}", dir.display()))?;
+    }
+    for file in 0..FIXTURE_FILES {
+        let module = dir.join("src").join(format!("m{}", file / 20));
+        fs::create_dir_all(&module)
+            .with_context(|| format!("Failed to create {}", module.display()))?;
+        let mut text = String::new();
+        for item in 0..FIXTURE_ITEMS {
+            text.push_str(&format!(
+                "/// Item {item} of file {file}.\npub fn item_{file}_{item}(x: u32) -> u32 {{\n    x + {item}\n}}\n\n"
+            ));
+        }
+        fs::write(module.join(format!("f{}.rs", file)), text)?;
+    }
+    Ok(dir)
+}
+
+/// Time per call of the read-only tools, run as the model would run them,
+/// in the generated workspace.
+async fn tools(cfg: &Config, runs: usize) -> Result<Value> {
+    let cases: [(&str, Value); 6] = [
+        ("read_file", json!({ "path": "src/m0/f0.rs" })),
+        ("list_dir", json!({ "path": ".", "recursive": true })),
+        ("glob", json!({ "pattern": "**/*.rs" })),
+        ("grep", json!({ "pattern": "item_42_7\\b" })),
+        // The first run parses every file; later runs hit the cache.
+        ("repo_map", json!({})),
+        (
+            "code_search",
+            json!({ "name": "item_42_7", "kind": "reference" }),
+        ),
+    ];
+    let dir = fixture()?;
+    let previous = std::env::current_dir()?;
+    std::env::set_current_dir(&dir)
+        .with_context(|| format!("Failed to enter {}", dir.display()))?;
+    let registry = ToolsRegistry::with_default(cfg);
+
+    let mut out = serde_json::Map::new();
+    let mut failure = None;
+    'cases: for (name, args) in cases {
+        let Some(tool) = registry.find(name) else {
+            continue;
+        };
+        let mut samples = Vec::with_capacity(runs);
+        for _ in 0..runs {
+            let start = Instant::now();
+            if let Err(e) = tool.clone().execute(args.clone()).await {
+                failure = Some(e.context(format!("{} failed", name)));
+                break 'cases;
+            }
+            samples.push(ms(start));
+        }
+        out.insert(name.to_string(), stats(&samples));
+    }
+
+    std::env::set_current_dir(previous)?;
+    fs::remove_dir_all(&dir).ok();
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(Value::Object(out)),
+    }
+}
+
+/// Time to render a typical answer for the terminal, without printing it.
+fn rendering(runs: usize) -> Result<Value> {
+    let mut samples = Vec::with_capacity(runs);
+    let mut bytes = 0;
+    for _ in 0..runs {
+        let start = Instant::now();
+        bytes = render_markdown(ANSWER, RENDER_COLS)?.len();
+        samples.push(ms(start));
+    }
+    let mut result = stats(&samples);
+    result["input_bytes"] = json!(ANSWER.len());
+    result["output_bytes"] = json!(bytes);
+    Ok(result)
+}
+
+/// A stage's result, or its error in place of it, so one failing stage
+/// does not lose the others.
+fn stage(result: Result<Value>) -> Value {
+    result.unwrap_or_else(|e| json!({ "error": format!("{:#}", e) }))
+}
+
+/// `tai bench`: time each stage of answering a question over `runs`
+/// repeated, fixed runs, and print the results as JSON.
+pub async fn run_bench(
+    cfg: &Config,
+    runs: usize,
+    skip_provider: bool,
+    provider_name: Option<&str>,
+    model: Option<&str>,
+) -> Result<()> {
+    let runs = runs.max(1);
+    eprintln!("Measuring startup");
+    let startup = stage(startup(runs));
+    let provider = if skip_provider {
+        Value::Null
+    } else {
+        eprintln!("Measuring the provider");
+        stage(provider(cfg, runs, provider_name, model).await)
+    };
+    eprintln!("Measuring tools");
+    let tools = stage(tools(cfg, runs).await);
+    eprintln!("Measuring rendering");
+    let rendering = stage(rendering(runs));
+
+    let report = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "debug_build": cfg!(debug_assertions),
+        "runs": runs,
+        "startup": startup,
+        "provider": provider,
+        "tools": tools,
+        "rendering": rendering,
+    });
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
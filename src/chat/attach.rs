@@ -0,0 +1,160 @@
+use globset::GlobBuilder;
+use ignore::WalkBuilder;
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use super::images;
+
+/// Largest part of a single file that is attached.
+const MAX_FILE_BYTES: usize = 64 * 1024;
+/// Budget for all attachments in one message.
+const MAX_TOTAL_BYTES: usize = 256 * 1024;
+const MAX_FILES: usize = 50;
+
+static TOKEN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|\s)@([^\s@]+)").expect("valid regex"));
+
+#[derive(Default)]
+pub(super) struct Attachments {
+    /// Fenced file contents to send along with the message.
+    pub text: String,
+    pub files: Vec<String>,
+    /// Image files, sent as separate image messages.
+    pub images: Vec<PathBuf>,
+    /// Matches left out because of the caps, or because they are binary.
+    pub skipped: Vec<String>,
+    /// `@` tokens that matched nothing and were left as typed.
+    pub unmatched: Vec<String>,
+}
+
+fn has_glob(s: &str) -> bool {
+    s.contains(['*', '?', '[', '{'])
+}
+
+/// Files below the current directory that `.gitignore` does not exclude,
+/// relative to it. Walking from the top keeps ignore rules for
+/// subdirectories (`target/`) in effect.
+fn walk() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = WalkBuilder::new(".")
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(|e| e.path().strip_prefix("./").ok().map(|p| p.to_path_buf()))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Resolve one `@` token to files, trying it without trailing punctuation
+/// when it does not match as typed ("see @src/main.rs.").
+fn resolve(token: &str) -> Vec<PathBuf> {
+    let mut candidates = vec![token];
+    let trimmed = token.trim_end_matches([',', '.', ';', ':', '!', '?', ')', '\'', '"']);
+    if trimmed != token && !trimmed.is_empty() {
+        candidates.push(trimmed);
+    }
+    for candidate in candidates {
+        let path = Path::new(candidate);
+        if path.is_file() {
+            return vec![path.to_path_buf()];
+        }
+        if path.is_dir() {
+            let dir = Path::new(candidate.trim_start_matches("./"));
+            let files: Vec<PathBuf> = walk()
+                .into_iter()
+                .filter(|p| p.starts_with(dir) || dir == Path::new("."))
+                .collect();
+            if !files.is_empty() {
+                return files;
+            }
+            continue;
+        }
+        if has_glob(candidate) {
+            let Ok(glob) = GlobBuilder::new(candidate.trim_start_matches("./"))
+                .literal_separator(true)
+                .build()
+            else {
+                continue;
+            };
+            let matcher = glob.compile_matcher();
+            let files: Vec<PathBuf> = walk().into_iter().filter(|p| matcher.is_match(p)).collect();
+            if !files.is_empty() {
+                return files;
+            }
+        }
+    }
+    Vec::new()
+}
+
+fn fence_language(path: &Path) -> &str {
+    path.extension().and_then(|e| e.to_str()).unwrap_or("")
+}
+
+/// Expand `@path`, `@dir/`, and `@glob/**/*.rs` tokens in `input` into file
+/// contents, collecting images separately. The message itself is left as
+/// typed.
+pub(super) fn expand(input: &str) -> Attachments {
+    let mut out = Attachments::default();
+    let mut seen = BTreeSet::new();
+    let mut total = 0;
+    for caps in TOKEN.captures_iter(input) {
+        let token = &caps[1];
+        let files = resolve(token);
+        if files.is_empty() {
+            out.unmatched.push(token.to_string());
+            continue;
+        }
+        for path in files {
+            let name = path.display().to_string();
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            if images::is_image(&path) {
+                out.images.push(path);
+                continue;
+            }
+            if out.files.len() >= MAX_FILES || total >= MAX_TOTAL_BYTES {
+                out.skipped.push(name);
+                continue;
+            }
+            let Ok(bytes) = fs::read(&path) else {
+                out.skipped.push(name);
+                continue;
+            };
+            if bytes.iter().take(8192).any(|b| *b == 0) {
+                out.skipped.push(name);
+                continue;
+            }
+            let budget = MAX_FILE_BYTES.min(MAX_TOTAL_BYTES - total);
+            let mut end = bytes.len().min(budget);
+            while end < bytes.len() && end > 0 && (bytes[end] & 0xC0) == 0x80 {
+                end -= 1;
+            }
+            let content = String::from_utf8_lossy(&bytes[..end]);
+            total += end;
+            out.text.push_str(&format!(
+                "### {}\n```{}\n{}{}```\n",
+                name,
+                fence_language(&path),
+                content,
+                if content.ends_with('\n') { "" } else { "\n" }
+            ));
+            if end < bytes.len() {
+                out.text.push_str(&format!(
+                    "(truncated: showing {} of {} bytes)\n",
+                    end,
+                    bytes.len()
+                ));
+            }
+            out.text.push('\n');
+            out.files.push(name);
+        }
+    }
+    if !out.text.is_empty() {
+        out.text = format!("Attached files:\n\n{}", out.text.trim_end());
+    }
+    out
+}
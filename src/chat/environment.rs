@@ -0,0 +1,95 @@
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::Config;
+use crate::project;
+use crate::tools::UserShell;
+
+/// The branch line of `git status --branch`: "main...origin/main [ahead 1]"
+/// as "main [ahead 1]".
+fn branch(line: &str) -> String {
+    let line = line.trim_start_matches("## ");
+    if let Some(name) = line.strip_prefix("No commits yet on ") {
+        return format!("{} (no commits yet)", name);
+    }
+    if line.starts_with("HEAD (no branch)") {
+        return "detached HEAD".to_string();
+    }
+    let (name, rest) = line.split_once("...").unwrap_or((line, ""));
+    match rest.find('[') {
+        Some(at) => format!("{} {}", name, &rest[at..]),
+        None => name.to_string(),
+    }
+}
+
+/// "branch main, 2 modified, 1 untracked" for the repository `dir` is in.
+fn git_summary(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(["status", "--porcelain=v1", "--branch"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut lines = text.lines();
+    let mut parts = vec![format!("branch {}", branch(lines.next()?))];
+    let (mut staged, mut modified, mut untracked) = (0, 0, 0);
+    for line in lines {
+        let mut codes = line.chars();
+        let (index, tree) = (codes.next(), codes.next());
+        if index == Some('?') {
+            untracked += 1;
+            continue;
+        }
+        if index.is_some_and(|c| c != ' ') {
+            staged += 1;
+        }
+        if tree.is_some_and(|c| c != ' ') {
+            modified += 1;
+        }
+    }
+    for (n, what) in [
+        (staged, "staged"),
+        (modified, "modified"),
+        (untracked, "untracked"),
+    ] {
+        if n > 0 {
+            parts.push(format!("{} {}", n, what));
+        }
+    }
+    if parts.len() == 1 {
+        parts.push("clean".to_string());
+    }
+    Some(parts.join(", "))
+}
+
+/// Where tai runs, for `[prompt] include_env`: the directory, the git
+/// branch and changes, the project types, the shell, and the local time,
+/// so the model need not ask.
+pub(super) fn snapshot(cfg: &Config) -> String {
+    let mut out = String::from("Environment:\n");
+    let cwd = std::env::current_dir().ok();
+    if let Some(dir) = &cwd {
+        out.push_str(&format!("- Working directory: {}\n", dir.display()));
+        if let Some(git) = git_summary(dir) {
+            out.push_str(&format!("- Git: {}\n", git));
+        }
+    }
+    let types = project::detect_current(cfg);
+    if !types.is_empty() {
+        let names: Vec<&str> = types.iter().map(|t| t.as_str()).collect();
+        out.push_str(&format!("- Project: {}", names.join(", ")));
+        match project::project_root() {
+            Some(root) if cwd.as_ref() != Some(&root) => {
+                out.push_str(&format!(" (root {})\n", root.display()))
+            }
+            _ => out.push('\n'),
+        }
+    }
+    out.push_str(&format!("- Shell: {}\n", UserShell::detect().name()));
+    out.push_str(&format!(
+        "- Local time: {}\n\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M (%:z)")
+    ));
+    out
+}
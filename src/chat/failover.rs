@@ -0,0 +1,212 @@
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use llm::chat::{ChatMessage, ChatResponse, StreamResponse};
+use llm::error::LLMError;
+use regex::Regex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use super::provider::{setup, ChatStream, Provider};
+use super::transcript;
+use crate::config::{resolve_effective_provider, Config, EffectiveProvider};
+use crate::tools::ToolsRegistry;
+
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+static STATUS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"status\D{0,20}([1-5]\d\d)\b").expect("valid regex"));
+
+/// Rate limits, server errors, and requests that never got a response are
+/// worth retrying; anything else (bad key, bad request) fails the same way
+/// again.
+fn is_transient(err: &LLMError) -> bool {
+    let status = |msg: &str| STATUS.captures(msg).and_then(|c| c[1].parse::<u16>().ok());
+    let retryable = |code: u16| code == 408 || code == 429 || code >= 500;
+    match err {
+        LLMError::HttpError(msg) => match status(msg) {
+            Some(code) => retryable(code),
+            None => !msg.contains("builder error"),
+        },
+        LLMError::ProviderError(msg) | LLMError::ResponseFormatError { message: msg, .. } => {
+            status(msg).is_some_and(retryable)
+        }
+        _ => false,
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1u64 << attempt.min(5)).min(MAX_DELAY)
+}
+
+struct Backend {
+    name: String,
+    model: String,
+    llm: Box<dyn Provider>,
+}
+
+/// The active provider plus the configured `core.fallback_providers`.
+/// Transient failures are retried with exponential backoff; when a provider
+/// keeps failing the next one takes over for the rest of the session.
+pub struct Failover {
+    chain: Vec<Backend>,
+    active: AtomicUsize,
+    max_retries: u32,
+}
+
+impl Failover {
+    pub fn new(cfg: &Config, tools: &ToolsRegistry, eff: &EffectiveProvider) -> Result<Self> {
+        let mut chain = vec![Backend {
+            name: eff.name.clone(),
+            model: eff.model.clone(),
+            llm: setup(cfg, tools, eff)?,
+        }];
+        for name in cfg.core.fallback_providers.iter().flatten() {
+            if chain.iter().any(|b| &b.name == name) {
+                continue;
+            }
+            let built = resolve_effective_provider(cfg, Some(name), None).and_then(|fallback| {
+                Ok(Backend {
+                    name: fallback.name.clone(),
+                    model: fallback.model.clone(),
+                    llm: setup(cfg, tools, &fallback)?,
+                })
+            });
+            match built {
+                Ok(backend) => chain.push(backend),
+                Err(e) => eprintln!("Warning: Skipping fallback provider {}: {:#}", name, e),
+            }
+        }
+        tracing::debug!(
+            providers = ?chain.iter().map(|b| b.name.as_str()).collect::<Vec<_>>(),
+            "provider chain"
+        );
+        Ok(Self {
+            chain,
+            active: AtomicUsize::new(0),
+            max_retries: cfg.core.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+        })
+    }
+
+    /// The provider answering requests now.
+    pub(super) fn active_name(&self) -> &str {
+        &self.chain[self.active.load(Ordering::Relaxed)].name
+    }
+
+    pub(super) fn active_model(&self) -> &str {
+        &self.chain[self.active.load(Ordering::Relaxed)].model
+    }
+
+    /// Hand the rest of the session to the next provider in the chain, and
+    /// return its name; `None` when there is none left.
+    pub(super) fn fall_back(&self) -> Option<&str> {
+        let next = self.active.load(Ordering::Relaxed) + 1;
+        let backend = self.chain.get(next)?;
+        self.active.store(next, Ordering::Relaxed);
+        Some(&backend.name)
+    }
+
+    async fn call<'a, T>(
+        &'a self,
+        kind: &str,
+        messages: &[ChatMessage],
+        f: impl Fn(&'a dyn Provider) -> BoxFuture<'a, Result<T, LLMError>>,
+    ) -> Result<T> {
+        let start = self.active.load(Ordering::Relaxed);
+        let mut last = None;
+        for (idx, backend) in self.chain.iter().enumerate().skip(start) {
+            let name = &backend.name;
+            if let Some(err) = &last {
+                eprintln!(
+                    "Warning: {} is unavailable ({}); falling back to {}",
+                    self.chain[idx - 1].name,
+                    err,
+                    name
+                );
+                self.active.store(idx, Ordering::Relaxed);
+            }
+            transcript::request(name, &backend.model, kind, messages);
+            let mut attempt = 0;
+            loop {
+                tracing::debug!(
+                    provider = %name,
+                    model = %backend.model,
+                    messages = messages.len(),
+                    attempt,
+                    "{} request",
+                    kind
+                );
+                let started = Instant::now();
+                let result = f(backend.llm.as_ref()).await;
+                tracing::debug!(
+                    provider = %name,
+                    elapsed_ms = started.elapsed().as_millis() as u64,
+                    ok = result.is_ok(),
+                    "{} response",
+                    kind
+                );
+                if let Err(e) = &result {
+                    transcript::error(name, attempt, e);
+                }
+                match result {
+                    Ok(v) => return Ok(v),
+                    Err(e) if is_transient(&e) && attempt < self.max_retries => {
+                        let delay = backoff(attempt);
+                        attempt += 1;
+                        eprintln!(
+                            "Warning: {} request failed ({}); retry {}/{} in {}s",
+                            name,
+                            e,
+                            attempt,
+                            self.max_retries,
+                            delay.as_secs()
+                        );
+                        tokio::time::sleep(delay).await;
+                    }
+                    Err(e) if is_transient(&e) => {
+                        last = Some(e);
+                        break;
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Err(last.map_or_else(|| anyhow!("No provider configured"), |e| e.into()))
+    }
+
+    pub async fn chat(&self, messages: &[ChatMessage]) -> Result<Box<dyn ChatResponse>> {
+        let response = self
+            .call("chat", messages, |llm| llm.chat(messages))
+            .await?;
+        transcript::response(self.active_name(), response.as_ref());
+        Ok(response)
+    }
+
+    pub async fn chat_with_tools(&self, messages: &[ChatMessage]) -> Result<Box<dyn ChatResponse>> {
+        let response = self
+            .call("chat_with_tools", messages, |llm| {
+                llm.chat_with_tools(messages)
+            })
+            .await?;
+        transcript::response(self.active_name(), response.as_ref());
+        Ok(response)
+    }
+
+    /// Callers record the streamed text with `transcript::streamed` once the
+    /// stream ends.
+    pub async fn chat_stream_struct(
+        &self,
+        messages: &[ChatMessage],
+    ) -> Result<ChatStream<StreamResponse>> {
+        self.call("chat_stream", messages, |llm| {
+            llm.chat_stream_struct(messages)
+        })
+        .await
+    }
+
+    pub async fn chat_stream(&self, messages: &[ChatMessage]) -> Result<ChatStream<String>> {
+        self.call("chat_stream", messages, |llm| llm.chat_stream(messages))
+            .await
+    }
+}
@@ -0,0 +1,305 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::LazyLock;
+
+/// Points a block needs before it gets a language; below that it stays plain.
+const MIN_SCORE: u32 = 2;
+
+/// Line patterns and what a match is worth.
+type Signs = Vec<(Regex, u32)>;
+
+/// Per language, its signs. A pattern counts once per block however many
+/// lines match it. The names are fence tags bat's markdown syntax highlights,
+/// which is why Go is `golang` and HTML `xml`.
+static SIGNS: LazyLock<Vec<(&'static str, Signs)>> = LazyLock::new(|| {
+    let table: &[(&str, &[(&str, u32)])] = &[
+        (
+            "rust",
+            &[
+                (r"^\s*(pub(\(crate\))? )?(async )?fn \w+", 3),
+                (r"^\s*let (mut )?\w+(: [\w<>&]+)? =", 1),
+                (r"^\s*(use|mod) [\w:]+(::\{.*\})?;", 2),
+                (r"^\s*impl(<.*>)? \w+", 3),
+                (r"^\s*#\[(derive|cfg|test)", 3),
+                (r"\w+!\(", 1),
+                (r"&(mut |self\b|str\b)", 1),
+            ],
+        ),
+        (
+            "python",
+            &[
+                (r"^\s*def \w+\(.*\):\s*$", 3),
+                (r"^\s*class \w+(\(.*\))?:\s*$", 3),
+                (r"^\s*(from [\w.]+ )?import \w+", 1),
+                (r"^\s*(if|elif|for|while|with|try|except)\b.*:\s*$", 1),
+                (r"\bself\.\w+", 1),
+                (r"^\s*print\(", 1),
+                (r#"^if __name__ == ["']__main__["']"#, 3),
+            ],
+        ),
+        (
+            "bash",
+            &[
+                (r"^\s*\$ \S", 2),
+                (
+                    r"^\s*(sudo |apt(-get)? |brew |npm |pip3? |cargo |git |docker |kubectl |cd |ls |grep |find |chmod |mkdir |curl |export )",
+                    2,
+                ),
+                (r"\|\s*(grep|awk|sed|xargs|sort|head|tail|wc)\b", 2),
+                (r"^\s*(if \[|fi$|then$|done$|do$|esac$)", 2),
+                (r"\$\{?\w+\}?", 1),
+                (r"\s--?[a-z][\w-]*", 1),
+            ],
+        ),
+        (
+            "javascript",
+            &[
+                (r"^\s*(const|let|var) \w+ = ", 2),
+                (r"^\s*(export )?(async )?function\*? \w*\(", 3),
+                (r"=>\s*[{(]?", 1),
+                (r"\b(console\.log|require\(|document\.|module\.exports)", 2),
+                (r#"^\s*import .* from ['"]"#, 2),
+                // TypeScript, which the JavaScript highlighter copes with.
+                (r"^\s*(export )?(interface|type) \w+", 3),
+                (r"\w+: (string|number|boolean|any)\b", 2),
+            ],
+        ),
+        (
+            "golang",
+            &[
+                (r"^package \w+", 3),
+                (r"^func (\(.*\) )?\w+\(", 3),
+                (r":= ", 1),
+                (r"\bfmt\.\w+\(", 2),
+                (r"\bif err != nil", 3),
+            ],
+        ),
+        (
+            "c",
+            &[
+                (r"^#include <\w+\.h>", 3),
+                (r"^\s*(int|void|char|static|unsigned)\s+\**\w+\(", 2),
+                (r"\b(printf|malloc|free|sizeof)\(", 1),
+            ],
+        ),
+        (
+            "cpp",
+            &[
+                (r"^#include <\w+>", 3),
+                (r"\bstd::\w+", 2),
+                (r"^\s*(template\s*<|namespace \w+|class \w+\s*(:|\{|$))", 2),
+                (r"(cout|cin)\s*(<<|>>)", 2),
+            ],
+        ),
+        (
+            "java",
+            &[
+                (
+                    r"^\s*(public|private|protected) (static )?(final )?(class|void|[A-Z]\w*) \w+",
+                    3,
+                ),
+                (r"\bSystem\.out\.print", 3),
+                (r"^\s*@Override", 2),
+            ],
+        ),
+        (
+            "sql",
+            &[
+                (
+                    r"(?i)^\s*(select .+ from|insert into|update \w+ set|delete from|create (table|index|view)|alter table|drop table)\b",
+                    3,
+                ),
+                (r"(?i)\b(where|join|group by|order by|values)\b", 1),
+            ],
+        ),
+        (
+            "xml",
+            &[
+                (r"(?i)^\s*<!doctype html", 3),
+                (r"(?i)<(html|head|body|div|span|p|a|script|style)[\s>]", 2),
+                (r"</\w+>", 1),
+            ],
+        ),
+    ];
+    table
+        .iter()
+        .map(|(lang, signs)| {
+            let signs = signs
+                .iter()
+                .map(|(pattern, points)| (Regex::new(pattern).expect("valid regex"), *points))
+                .collect();
+            (*lang, signs)
+        })
+        .collect()
+});
+
+/// The language a shebang names, if the block starts with one.
+fn from_shebang(first: &str) -> Option<&'static str> {
+    let mut words = first.strip_prefix("#!")?.split_whitespace();
+    let mut name = words.next()?.rsplit('/').next()?;
+    if name == "env" {
+        name = words.find(|w| !w.starts_with('-'))?;
+    }
+    Some(match name {
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => "bash",
+        n if n.starts_with("python") => "python",
+        "node" | "deno" => "javascript",
+        "ruby" => "ruby",
+        _ => return None,
+    })
+}
+
+/// A best guess at the language of `code`, or `None` when nothing stands out.
+fn detect(code: &str) -> Option<&'static str> {
+    let trimmed = code.trim();
+    if let Some(lang) = trimmed.lines().next().and_then(from_shebang) {
+        return Some(lang);
+    }
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(trimmed).is_ok()
+    {
+        return Some("json");
+    }
+    let lines: Vec<&str> = code.lines().collect();
+    SIGNS
+        .iter()
+        .map(|(lang, signs)| {
+            let score: u32 = signs
+                .iter()
+                .filter(|(re, _)| lines.iter().any(|l| re.is_match(l)))
+                .map(|(_, points)| points)
+                .sum();
+            (*lang, score)
+        })
+        .filter(|(_, score)| *score >= MIN_SCORE)
+        // The first language listed wins a tie.
+        .fold(
+            None,
+            |best: Option<(&str, u32)>, (lang, score)| match best {
+                Some((_, top)) if top >= score => best,
+                _ => Some((lang, score)),
+            },
+        )
+        .map(|(lang, _)| lang)
+}
+
+/// The fence a line opens or closes: its character, length, and info string.
+fn fence(line: &str) -> Option<(char, usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let ch = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = rest.chars().take_while(|c| *c == ch).count();
+    (len >= 3).then(|| (ch, len, rest[len..].trim()))
+}
+
+/// A stretch of an answer: prose, or one fenced code block.
+pub(super) enum Segment<'a> {
+    Prose(String),
+    Code(Block<'a>),
+}
+
+pub(super) struct Block<'a> {
+    /// The opening fence line as written, newline included.
+    open: &'a str,
+    /// The info string's language, else the detected one.
+    pub(super) lang: Option<String>,
+    tagged: bool,
+    /// The lines between the fences, verbatim.
+    pub(super) code: String,
+    /// Missing when the answer ends inside the block.
+    close: Option<&'a str>,
+}
+
+impl Block<'_> {
+    /// The block as it was written, fences included.
+    pub(super) fn verbatim(&self) -> String {
+        format!(
+            "{}{}{}",
+            self.open,
+            self.code,
+            self.close.unwrap_or_default()
+        )
+    }
+}
+
+/// Split `text` into prose and fenced code blocks, in order.
+pub(super) fn split(text: &str) -> Vec<Segment<'_>> {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let mut out = Vec::new();
+    let mut prose = String::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let Some((ch, len, info)) = fence(lines[i]) else {
+            prose.push_str(lines[i]);
+            i += 1;
+            continue;
+        };
+        // The closing fence uses the same character, at least as many times,
+        // and has no info string.
+        let close = (i + 1..lines.len()).find(|&j| {
+            fence(lines[j]).is_some_and(|(c, l, rest)| c == ch && l >= len && rest.is_empty())
+        });
+        let end = close.unwrap_or(lines.len());
+        let code = lines[i + 1..end].concat();
+        let lang = match info.split_whitespace().next() {
+            Some(tag) => Some(tag.to_string()),
+            None => detect(&code).map(str::to_string),
+        };
+        if !prose.is_empty() {
+            out.push(Segment::Prose(std::mem::take(&mut prose)));
+        }
+        out.push(Segment::Code(Block {
+            open: lines[i],
+            lang,
+            tagged: !info.is_empty(),
+            code,
+            close: close.map(|c| lines[c]),
+        }));
+        i = end + 1;
+    }
+    if !prose.is_empty() {
+        out.push(Segment::Prose(prose));
+    }
+    out
+}
+
+/// The code blocks of `text`, numbered from 1 in the order they appear.
+pub(super) fn blocks(text: &str) -> Vec<Block<'_>> {
+    split(text)
+        .into_iter()
+        .filter_map(|s| match s {
+            Segment::Code(block) => Some(block),
+            Segment::Prose(_) => None,
+        })
+        .collect()
+}
+
+/// `text` with a detected language added to code fences that have none, so
+/// the markdown highlighter colors them.
+pub(super) fn tag_bare_fences(text: &str) -> Cow<'_, str> {
+    let segments = split(text);
+    let detected = |s: &Segment| matches!(s, Segment::Code(b) if !b.tagged && b.lang.is_some());
+    if !segments.iter().any(detected) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len() + 16);
+    for segment in segments {
+        match segment {
+            Segment::Prose(prose) => out.push_str(&prose),
+            Segment::Code(block) => {
+                let opening = block.open.trim_end_matches(['\n', '\r']);
+                out.push_str(opening);
+                if !block.tagged {
+                    out.push_str(block.lang.as_deref().unwrap_or_default());
+                }
+                out.push_str(&block.open[opening.len()..]);
+                out.push_str(&block.code);
+                out.push_str(block.close.unwrap_or_default());
+            }
+        }
+    }
+    Cow::Owned(out)
+}
@@ -0,0 +1,201 @@
+use globset::GlobBuilder;
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::sync::LazyLock;
+
+use super::fences::{self, Segment};
+use crate::config::FiltersConfig;
+
+/// Model families whose answers tend to open with "As an AI…" and close
+/// with offers of more help, so `boilerplate` is on for them by default.
+const CHATTY_MODELS: &[&str] = &[
+    "*llama*",
+    "*mistral*",
+    "*mixtral*",
+    "*gemma*",
+    "*phi3*",
+    "*phi-*",
+    "*vicuna*",
+];
+
+/// Words too common to tell whether a paragraph repeats the question.
+const STOPWORDS: &[&str] = &[
+    "what", "does", "this", "that", "with", "have", "from", "your", "about", "which", "when",
+    "where", "there", "their", "would", "could", "should", "into", "will", "them", "then", "than",
+    "been", "were", "also", "just", "like", "some", "here", "these", "those",
+];
+
+static THINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<think(?:ing)?>.*?</think(?:ing)?>\s*").expect("valid regex")
+});
+
+/// A disclaimer clause leading a paragraph; the rest of the sentence stays.
+static OPENER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?im)^(?:as an ai(?: language model| assistant)?|as a (?:large )?language model|being an ai(?: language model)?),\s*",
+    )
+    .expect("valid regex")
+});
+
+/// A line that only offers more help.
+static CLOSER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)^\s*(?:i hope (?:this|that|it) helps|hope (?:this|that) helps|let me know if (?:you|there)|feel free to (?:ask|reach out)|if you have any (?:other |more |further )?questions|happy coding)",
+    )
+    .expect("valid regex")
+});
+
+/// Which filters apply to one model's answers.
+pub(super) struct Filters {
+    think: bool,
+    boilerplate: bool,
+    restatement: bool,
+}
+
+pub(super) fn matches(pattern: &str, model: &str) -> bool {
+    GlobBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .is_ok_and(|glob| glob.compile_matcher().is_match(model))
+}
+
+impl Filters {
+    /// The built-in defaults for `model`, then `[filters]`, then the
+    /// `[filters.models]` entries whose glob matches it.
+    pub(super) fn for_model(cfg: &FiltersConfig, model: &str) -> Self {
+        let mut filters = Self {
+            think: true,
+            boilerplate: CHATTY_MODELS.iter().any(|p| matches(p, model)),
+            restatement: false,
+        };
+        filters.set(cfg);
+        for (pattern, over) in cfg.models.iter().flatten() {
+            if matches(pattern, model) {
+                filters.set(over);
+            }
+        }
+        filters
+    }
+
+    fn set(&mut self, cfg: &FiltersConfig) {
+        self.think = cfg.think.unwrap_or(self.think);
+        self.boilerplate = cfg.boilerplate.unwrap_or(self.boilerplate);
+        self.restatement = cfg.restatement.unwrap_or(self.restatement);
+    }
+
+    /// `answer` to `question` with the filters applied. Code blocks are left
+    /// as they are.
+    pub(super) fn apply(&self, answer: &str, question: &str) -> String {
+        let mut text = answer.to_string();
+        if self.think {
+            text = strip_think(&text);
+        }
+        if !self.boilerplate && !self.restatement {
+            return text;
+        }
+        let segments = fences::split(&text);
+        let last = segments.len().saturating_sub(1);
+        let mut out = String::with_capacity(text.len());
+        for (i, segment) in segments.iter().enumerate() {
+            match segment {
+                Segment::Code(block) => out.push_str(&block.verbatim()),
+                Segment::Prose(prose) => {
+                    let mut prose = prose.clone();
+                    if self.boilerplate {
+                        prose = strip_openers(&prose);
+                    }
+                    // Only the end of the answer, and only with something
+                    // before it, so nothing is left empty.
+                    if i == last && (i > 0 || prose.contains("\n\n")) {
+                        if self.boilerplate {
+                            prose = strip_closers(&prose);
+                        }
+                        if self.restatement {
+                            prose = strip_restatement(&prose, question);
+                        }
+                    }
+                    out.push_str(&prose);
+                }
+            }
+        }
+        if out == answer {
+            out
+        } else {
+            out.trim().to_string()
+        }
+    }
+}
+
+/// `text` without reasoning blocks. Some templates drop the opening tag,
+/// leaving everything up to a lone closing one as reasoning.
+fn strip_think(text: &str) -> String {
+    let text = THINK.replace_all(text, "");
+    match text.find("</think>") {
+        Some(end) if !text[..end].contains("<think>") => {
+            text[end + "</think>".len()..].trim_start().to_string()
+        }
+        _ => text.into_owned(),
+    }
+}
+
+fn strip_openers(prose: &str) -> String {
+    let mut out = String::with_capacity(prose.len());
+    let mut last = 0;
+    for m in OPENER.find_iter(prose) {
+        out.push_str(&prose[last..m.start()]);
+        // The sentence now starts after the clause.
+        let rest = &prose[m.end()..];
+        if let Some(first) = rest.chars().next() {
+            out.extend(first.to_uppercase());
+            last = m.end() + first.len_utf8();
+        } else {
+            last = m.end();
+        }
+    }
+    out.push_str(&prose[last..]);
+    out
+}
+
+fn strip_closers(prose: &str) -> String {
+    let mut lines: Vec<&str> = prose.trim_end().lines().collect();
+    while let Some(line) = lines.last() {
+        if line.trim().is_empty() || (line.len() < 160 && CLOSER.is_match(line)) {
+            lines.pop();
+        } else {
+            break;
+        }
+    }
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+fn content_words(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|w| w.chars().count() >= 4 && !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// `prose` without a closing paragraph made mostly of the question's own
+/// words.
+fn strip_restatement(prose: &str, question: &str) -> String {
+    // Called only where something comes before this prose, so all of it may
+    // be the paragraph.
+    let trimmed = prose.trim_end();
+    let (before, paragraph) = trimmed.rsplit_once("\n\n").unwrap_or(("", trimmed));
+    let asked = content_words(question);
+    let said = content_words(paragraph);
+    let plain = !paragraph
+        .trim_start()
+        .starts_with(['#', '-', '*', '|', '>', '1']);
+    if asked.len() < 3 || !plain || paragraph.len() > 300 || said.len() > asked.len() * 2 + 4 {
+        return prose.to_string();
+    }
+    let shared = asked.intersection(&said).count();
+    if shared * 10 >= asked.len() * 7 {
+        format!("{}\n", before.trim_end())
+    } else {
+        prose.to_string()
+    }
+}
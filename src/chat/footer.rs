@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+
+use super::filters::matches;
+use crate::config::ModelPrice;
+use crate::tools::units;
+
+/// USD per million input and output tokens, by model glob; the first
+/// match wins, so more specific patterns come first.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("claude-opus-4-5*", 5.0, 25.0),
+    ("claude-opus-4*", 15.0, 75.0),
+    ("claude-3-opus*", 15.0, 75.0),
+    ("claude-sonnet-4*", 3.0, 15.0),
+    ("claude-3-7-sonnet*", 3.0, 15.0),
+    ("claude-3-5-sonnet*", 3.0, 15.0),
+    ("claude-haiku-4*", 1.0, 5.0),
+    ("claude-3-5-haiku*", 0.8, 4.0),
+    ("claude-3-haiku*", 0.25, 1.25),
+    ("gpt-4o-mini*", 0.15, 0.6),
+    ("gpt-4o*", 2.5, 10.0),
+    ("gpt-4.1-nano*", 0.1, 0.4),
+    ("gpt-4.1-mini*", 0.4, 1.6),
+    ("gpt-4.1*", 2.0, 8.0),
+    ("gpt-5-nano*", 0.05, 0.4),
+    ("gpt-5-mini*", 0.25, 2.0),
+    ("gpt-5*", 1.25, 10.0),
+    ("o3-mini*", 1.1, 4.4),
+    ("o4-mini*", 1.1, 4.4),
+    ("o3*", 2.0, 8.0),
+    ("gemini-2.5-flash-lite*", 0.1, 0.4),
+    ("gemini-2.5-flash*", 0.3, 2.5),
+    ("gemini-2.5-pro*", 1.25, 10.0),
+    ("gemini-2.0-flash*", 0.1, 0.4),
+];
+
+/// Providers that run on this machine, where answers cost nothing.
+const LOCAL: &[&str] = &["ollama", "lmstudio"];
+
+/// What answering one question took, shown under the answer.
+pub(super) struct Footer {
+    pub(super) model: String,
+    pub(super) seconds: f64,
+    pub(super) input_tokens: u64,
+    pub(super) output_tokens: u64,
+    /// In USD; `None` for a model without a known price.
+    pub(super) cost: Option<f64>,
+    pub(super) tool_calls: usize,
+}
+
+/// The cost of `input` and `output` tokens of `model`, with
+/// `[display.prices]` over the built-in prices. OpenRouter models are
+/// matched without their vendor prefix and routing variant.
+pub(super) fn cost(
+    prices: Option<&BTreeMap<String, ModelPrice>>,
+    provider: &str,
+    model: &str,
+    input: u64,
+    output: u64,
+) -> Option<f64> {
+    if LOCAL.contains(&provider) {
+        return Some(0.0);
+    }
+    let per_million = |price_in: f64, price_out: f64| {
+        (input as f64 * price_in + output as f64 * price_out) / 1_000_000.0
+    };
+    if let Some(price) = prices
+        .into_iter()
+        .flatten()
+        .find(|(pattern, _)| matches(pattern, model))
+        .map(|(_, price)| price)
+    {
+        return Some(per_million(price.input, price.output));
+    }
+    let bare = model.split(':').next().unwrap_or(model);
+    let bare = bare.rsplit('/').next().unwrap_or(bare);
+    PRICES
+        .iter()
+        .find(|(pattern, _, _)| matches(pattern, bare))
+        .map(|(_, price_in, price_out)| per_million(*price_in, *price_out))
+}
+
+impl Footer {
+    /// One line: model, time, tokens, cost, and tool calls.
+    pub(super) fn line(&self) -> String {
+        let mut parts = vec![
+            self.model.clone(),
+            units::duration(self.seconds),
+            format!(
+                "{} in / {} out",
+                units::count(self.input_tokens),
+                units::count(self.output_tokens)
+            ),
+        ];
+        if let Some(cost) = self.cost {
+            parts.push(if cost > 0.0 && cost < 0.0001 {
+                "<$0.0001".to_string()
+            } else {
+                format!("${:.4}", cost)
+            });
+        }
+        parts.push(match self.tool_calls {
+            1 => "1 tool call".to_string(),
+            n => format!("{} tool calls", n),
+        });
+        parts.join(" · ")
+    }
+}
@@ -0,0 +1,460 @@
+use llm::chat::Usage;
+use nu_ansi_term::{Color as NuColor, Style};
+use serde_json::{json, Value as JsonValue};
+use std::io::Write;
+use terminal_size::{terminal_size, Height, Width};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::footer::Footer;
+use super::highlight::LineHighlighter;
+use super::theme;
+use super::{fences, format_tool_params, meter, print_copyable, print_markdown};
+use crate::tools::{Tool, SCHEMA_VERSION};
+use crate::{output, say};
+
+/// What a step does, as it happens. The session decides what to do next;
+/// a frontend only shows it.
+pub enum Event<'a> {
+    /// Token counts the provider reported for one request.
+    Usage(&'a Usage),
+    /// The model asked for a tool; `arguments` is its raw JSON.
+    ToolRequested {
+        id: &'a str,
+        name: &'a str,
+        arguments: &'a str,
+    },
+    /// A pre-flight check refused a call before it ran.
+    ToolRejected {
+        id: &'a str,
+        name: &'a str,
+        problem: &'a str,
+    },
+    ToolFinished {
+        id: &'a str,
+        name: &'a str,
+        outcome: Result<(&'a JsonValue, &'a dyn Tool), String>,
+    },
+    /// The final answer is about to stream.
+    AnswerStarted,
+    /// A piece of the streamed answer, with the speed line when it is due
+    /// for a redraw.
+    ModelDelta {
+        text: &'a str,
+        status: Option<&'a str>,
+    },
+    /// The whole answer, after its deltas when it streamed.
+    AnswerReady { text: &'a str, streamed: bool },
+    /// What the answer took, with `[display] show_footer`.
+    Footer(&'a Footer),
+    /// A live-data question with no tool to answer it, so nothing was asked.
+    NeedsWeb { class: &'a str },
+    /// The provider declined to answer; `text` is what it said instead.
+    Refused {
+        provider: &'a str,
+        reason: &'a str,
+        text: &'a str,
+    },
+    /// Ctrl-C stopped the step after `tool_calls` calls and `text`.
+    Interrupted { text: &'a str, tool_calls: usize },
+}
+
+/// Where a session's events are shown.
+pub trait Frontend {
+    /// Whether the final answer is wanted as it streams, as `ModelDelta`
+    /// events, or only whole.
+    fn streams(&self) -> bool;
+    fn event(&mut self, event: Event<'_>);
+}
+
+/// The frontend for the output mode set on the command line.
+pub(super) fn for_output(copyable_code: bool) -> Box<dyn Frontend> {
+    if output::is_json() {
+        Box::new(Json)
+    } else {
+        Box::new(Terminal {
+            raw: output::is_raw(),
+            copyable_code,
+            stream: None,
+        })
+    }
+}
+
+/// The terminal frontend as plain `tai` uses it, for `tai tui` to print
+/// tool calls and results through.
+#[cfg(unix)]
+pub(super) fn terminal() -> Box<dyn Frontend> {
+    Box::new(Terminal {
+        raw: false,
+        copyable_code: false,
+        stream: None,
+    })
+}
+
+/// What is said instead of guessing at a live-data question.
+pub(super) fn needs_web(class: &str) -> String {
+    format!(
+        "This looks like a live {} question, which needs web access. fetch_url is not enabled, so tai won't guess.",
+        class
+    )
+}
+
+/// What is said when Ctrl-C stopped a step.
+pub(super) fn interrupted(tool_calls: usize) -> String {
+    match tool_calls {
+        0 => "Interrupted".to_string(),
+        1 => "Interrupted after 1 tool call".to_string(),
+        n => format!("Interrupted after {} tool calls", n),
+    }
+}
+
+/// An event as `--json` prints it: its type and fields. The streamed
+/// pieces of an answer have none; the whole answer follows them.
+fn json_event(event: Event<'_>) -> Option<(&'static str, JsonValue)> {
+    Some(match event {
+        Event::Usage(usage) => ("usage", json!(usage)),
+        Event::ToolRequested {
+            id,
+            name,
+            arguments,
+        } => {
+            let arguments = serde_json::from_str::<JsonValue>(arguments)
+                .unwrap_or_else(|_| JsonValue::String(arguments.to_string()));
+            (
+                "tool_call",
+                json!({ "id": id, "name": name, "arguments": arguments }),
+            )
+        }
+        Event::ToolRejected { id, name, problem } => (
+            "tool_result",
+            json!({
+                "id": id,
+                "name": name,
+                "error": problem,
+                "schema_version": SCHEMA_VERSION,
+            }),
+        ),
+        Event::ToolFinished { id, name, outcome } => match outcome {
+            Ok((result, _)) => (
+                "tool_result",
+                json!({
+                    "id": id,
+                    "name": name,
+                    "result": result,
+                    "schema_version": SCHEMA_VERSION,
+                }),
+            ),
+            Err(e) => (
+                "tool_result",
+                json!({
+                    "id": id,
+                    "name": name,
+                    "error": e,
+                    "schema_version": SCHEMA_VERSION,
+                }),
+            ),
+        },
+        Event::AnswerStarted | Event::ModelDelta { .. } => return None,
+        Event::AnswerReady { text, .. } => ("assistant_text", json!({ "text": text })),
+        Event::Footer(footer) => (
+            "footer",
+            json!({
+                "model": footer.model,
+                "seconds": footer.seconds,
+                "input_tokens": footer.input_tokens,
+                "output_tokens": footer.output_tokens,
+                "cost_usd": footer.cost,
+                "tool_calls": footer.tool_calls,
+            }),
+        ),
+        Event::NeedsWeb { class } => (
+            "assistant_text",
+            json!({ "text": format!("This looks like a live {} question, which needs web access.", class) }),
+        ),
+        Event::Refused {
+            provider,
+            reason,
+            text,
+        } => (
+            "refusal",
+            json!({ "provider": provider, "reason": reason, "text": text }),
+        ),
+        Event::Interrupted { text, tool_calls } => (
+            "interrupted",
+            json!({ "text": text, "tool_calls": tool_calls }),
+        ),
+    })
+}
+
+/// One JSON event per line on stdout, for scripts and editors.
+struct Json;
+
+impl Frontend for Json {
+    fn streams(&self) -> bool {
+        false
+    }
+
+    fn event(&mut self, event: Event<'_>) {
+        if let Some((kind, fields)) = json_event(event) {
+            output::emit(kind, fields);
+        }
+    }
+}
+
+/// The same events as `--json`, sent to `tai serve` for its client.
+pub(super) struct Channel(pub(super) UnboundedSender<JsonValue>);
+
+impl Frontend for Channel {
+    fn streams(&self) -> bool {
+        false
+    }
+
+    fn event(&mut self, event: Event<'_>) {
+        if let Some((kind, fields)) = json_event(event) {
+            // The client may have gone; the run finishes regardless.
+            let _ = self.0.send(output::event(kind, fields));
+        }
+    }
+}
+
+/// The streamed answer on screen: complete lines in the colors of the
+/// rendered answer, and the line still arriving in plain gray.
+struct StreamView {
+    cols: usize,
+    rows: usize,
+    /// Terminal rows the complete lines take up.
+    lines: usize,
+    /// The line still arriving.
+    partial: String,
+    /// All of the answer so far, to compare with the final one.
+    text: String,
+    highlighter: LineHighlighter,
+    /// Speed lines drawn, for the spinner.
+    redraws: usize,
+}
+
+impl StreamView {
+    /// Rows `line` takes up once wrapped.
+    fn rows_of(&self, line: &str) -> usize {
+        let width = line.trim_end_matches('\n').chars().count();
+        width.div_ceil(self.cols).max(1)
+    }
+
+    /// Rows from the separator to the cursor.
+    fn cursor_row(&self) -> usize {
+        self.lines
+            + if self.partial.is_empty() {
+                0
+            } else {
+                self.rows_of(&self.partial) - 1
+            }
+    }
+
+    /// Take the arriving line off the screen.
+    fn clear_partial(&self) {
+        if self.partial.is_empty() {
+            return;
+        }
+        print!("\r");
+        let up = self.rows_of(&self.partial) - 1;
+        if up > 0 {
+            print!("\x1b[{}A", up);
+        }
+        print!("\x1b[0J");
+    }
+
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+        if !text.contains('\n') {
+            self.partial.push_str(text);
+            print!("{}", partial_style().paint(text));
+            return;
+        }
+        self.clear_partial();
+        self.partial.push_str(text);
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            print!("{}", self.highlighter.line(&line));
+            self.lines += self.rows_of(&line);
+        }
+        if !self.partial.is_empty() {
+            print!("{}", partial_style().paint(&self.partial));
+        }
+    }
+
+    /// Color the last line, which has no newline.
+    fn finish(&mut self) {
+        if self.partial.is_empty() {
+            return;
+        }
+        self.clear_partial();
+        let line = format!("{}\n", std::mem::take(&mut self.partial));
+        print!("{}", self.highlighter.line(&line));
+        self.lines += self.rows_of(&line);
+    }
+}
+
+/// The terminal: tool calls as they happen and the answer streamed, then
+/// rendered as markdown. In raw mode only the answer goes to stdout, as
+/// plain text, and the rest to stderr.
+struct Terminal {
+    raw: bool,
+    copyable_code: bool,
+    stream: Option<StreamView>,
+}
+
+pub(super) fn term_cols() -> usize {
+    match terminal_size() {
+        Some((Width(w), _)) => w as usize,
+        None => 80,
+    }
+}
+
+fn separator_style() -> Style {
+    theme::current().separator()
+}
+
+fn partial_style() -> Style {
+    if output::color() {
+        Style::new().fg(NuColor::Rgb(160, 160, 160))
+    } else {
+        Style::new()
+    }
+}
+
+impl Terminal {
+    fn render(&self, text: &str, cols: usize) {
+        if self.copyable_code {
+            print_copyable(text, cols);
+        } else {
+            print_markdown(&fences::tag_bare_fences(text), cols, true);
+        }
+    }
+}
+
+impl Frontend for Terminal {
+    fn streams(&self) -> bool {
+        !self.raw
+    }
+
+    fn event(&mut self, event: Event<'_>) {
+        match event {
+            Event::Usage(_) => {}
+            Event::ToolRequested {
+                name, arguments, ..
+            } => {
+                let header = theme::current().tool_header().paint("Tool call");
+                let name_col = Style::new().bold().fg(NuColor::Yellow).paint(name);
+                say!("{}: {}", header, name_col);
+                let args_label = Style::new().fg(NuColor::Green).paint("params");
+                say!("{}:\n{}", args_label, format_tool_params(arguments));
+            }
+            Event::ToolRejected { name, .. } => say!(
+                "{}",
+                Style::new().dimmed().paint(format!(
+                    "{} call rejected by a pre-flight check; asking the model to correct it",
+                    name
+                ))
+            ),
+            Event::ToolFinished { outcome, .. } => match outcome {
+                Ok((result, tool)) => {
+                    if !self.raw {
+                        tool.print_result(result);
+                    }
+                }
+                Err(e) => {
+                    let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+                    say!("{}: {}", result_label, e);
+                }
+            },
+            Event::AnswerStarted => {
+                let sz = terminal_size();
+                let cols = match sz {
+                    Some((Width(w), _)) => w as usize,
+                    None => 80,
+                };
+                std::io::stdout().flush().ok();
+                std::io::stderr().flush().ok();
+                println!("{}", separator_style().paint("─".repeat(cols)));
+                std::io::stdout().flush().ok();
+                self.stream = Some(StreamView {
+                    cols,
+                    rows: sz.map_or(0, |(_, Height(h))| h as usize),
+                    lines: 0,
+                    partial: String::new(),
+                    text: String::new(),
+                    highlighter: LineHighlighter::new(),
+                    redraws: 0,
+                });
+            }
+            Event::ModelDelta { text, status } => {
+                let Some(view) = &mut self.stream else {
+                    return;
+                };
+                view.push(text);
+                std::io::stdout().flush().ok();
+                // The separator carries the speed while it is still on screen.
+                let row = view.cursor_row();
+                if row + 1 < view.rows {
+                    if let Some(status) = status {
+                        let status = match theme::current().spinner(view.redraws) {
+                            Some(frame) => format!("{} {}", frame, status),
+                            None => status.to_string(),
+                        };
+                        view.redraws += 1;
+                        meter::draw(&status, row + 1, view.cols, separator_style());
+                    }
+                }
+            }
+            Event::AnswerReady { text, streamed } => {
+                if self.raw {
+                    if text.ends_with('\n') {
+                        print!("{}", text);
+                    } else {
+                        println!("{}", text);
+                    }
+                    return;
+                }
+                let Some(mut view) = self.stream.take().filter(|_| streamed) else {
+                    self.render(text, term_cols());
+                    return;
+                };
+                view.finish();
+                // Filters and copyable code blocks change what the stream
+                // showed; the answer replaces it while it is all on screen
+                // and follows it otherwise.
+                if !self.copyable_code && view.text.trim() == text.trim() {
+                    println!("{}", separator_style().paint("─".repeat(view.cols)));
+                } else if view.lines + 1 < view.rows {
+                    print!("\x1b[{}A\x1b[0J", view.lines + 1);
+                    std::io::stdout().flush().ok();
+                    self.render(text, view.cols);
+                } else {
+                    self.render(text, view.cols);
+                }
+            }
+            Event::Footer(footer) => {
+                say!("{}", Style::new().dimmed().paint(footer.line()))
+            }
+            Event::NeedsWeb { class } => say!("{}", NuColor::Yellow.paint(needs_web(class))),
+            Event::Refused {
+                provider,
+                reason,
+                text,
+            } => {
+                say!(
+                    "{}: {} did not answer; {}",
+                    Style::new().fg(NuColor::Yellow).bold().paint("refused"),
+                    provider,
+                    reason
+                );
+                for line in text.lines() {
+                    say!("  {}", Style::new().dimmed().paint(line));
+                }
+            }
+            Event::Interrupted { tool_calls, .. } => {
+                say!();
+                say!("{}", NuColor::Yellow.paint(interrupted(tool_calls)));
+            }
+        }
+    }
+}
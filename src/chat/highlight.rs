@@ -0,0 +1,62 @@
+use bat::assets::HighlightingAssets;
+use syntect::highlighting::{HighlightIterator, HighlightState, Highlighter};
+use syntect::parsing::{ParseState, ScopeStack};
+use syntect::util::as_24_bit_terminal_escaped;
+
+use super::theme;
+use crate::output;
+
+/// Colors an answer's markdown one line at a time, with bat's syntaxes and
+/// the `ui.theme` of the full render, so a streamed answer can be shown as it
+/// will look without printing it again. Fenced code is highlighted in its
+/// own language, carried over from line to line.
+pub(super) struct LineHighlighter {
+    assets: HighlightingAssets,
+    /// Set on the first line; `None` after that when markdown cannot be
+    /// parsed, and lines pass through plain.
+    state: Option<Option<(ParseState, HighlightState)>>,
+}
+
+impl LineHighlighter {
+    pub(super) fn new() -> Self {
+        Self {
+            assets: HighlightingAssets::from_binary(),
+            state: None,
+        }
+    }
+
+    /// `line`, ending in a newline, in terminal colors, unless colors are
+    /// off.
+    pub(super) fn line(&mut self, line: &str) -> String {
+        if !output::color() {
+            return line.to_string();
+        }
+        let Ok(syntaxes) = self.assets.get_syntax_set() else {
+            return line.to_string();
+        };
+        let theme = self.assets.get_theme(&theme::current().name);
+        let highlighter = Highlighter::new(theme);
+        let state = self.state.get_or_insert_with(|| {
+            let markdown = syntaxes.find_syntax_by_name("Markdown")?;
+            Some((
+                ParseState::new(markdown),
+                HighlightState::new(&highlighter, ScopeStack::new()),
+            ))
+        });
+        let Some((parse, highlight)) = state else {
+            return line.to_string();
+        };
+        let Ok(ops) = parse.parse_line(line, syntaxes) else {
+            return line.to_string();
+        };
+        let ranges: Vec<_> = HighlightIterator::new(highlight, &ops, line, &highlighter).collect();
+        let body = line.trim_end_matches('\n');
+        let colored = as_24_bit_terminal_escaped(&ranges, false);
+        let colored = colored.trim_end_matches('\n');
+        format!(
+            "{}\x1b[0m{}",
+            colored,
+            if body.len() < line.len() { "\n" } else { "" }
+        )
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use llm::chat::{ChatMessage, ChatRole, ImageMime, MessageType};
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::config::{openrouter_accepts_images, Config, EffectiveProvider};
+
+/// Longest edge sent to the provider; larger images cost tokens without
+/// helping the model.
+const MAX_EDGE: u32 = 1568;
+/// Stays under the strictest per-image limit (Anthropic's 5 MB of base64).
+const MAX_BYTES: usize = 3_700_000;
+
+pub(super) const EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp"];
+
+pub(super) fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+pub(super) struct Image {
+    mime: ImageMime,
+    bytes: Vec<u8>,
+}
+
+/// Read an image, passing it through unchanged when the provider can take
+/// it as is, otherwise scaled down and re-encoded as PNG or JPEG.
+pub(super) fn load(path: &Path) -> Result<Image> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let format = image::guess_format(&bytes)
+        .with_context(|| format!("{} is not a recognized image", path.display()))?;
+    let img = image::load_from_memory_with_format(&bytes, format)
+        .with_context(|| format!("Failed to decode {}", path.display()))?;
+    let passthrough = match format {
+        ImageFormat::Png => Some(ImageMime::PNG),
+        ImageFormat::Jpeg => Some(ImageMime::JPEG),
+        ImageFormat::Gif => Some(ImageMime::GIF),
+        ImageFormat::WebP => Some(ImageMime::WEBP),
+        _ => None,
+    };
+    let fits = img.width().max(img.height()) <= MAX_EDGE && bytes.len() <= MAX_BYTES;
+    if let (Some(mime), true) = (passthrough, fits) {
+        return Ok(Image { mime, bytes });
+    }
+    let img = if img.width().max(img.height()) > MAX_EDGE {
+        img.resize(MAX_EDGE, MAX_EDGE, FilterType::Lanczos3)
+    } else {
+        img
+    };
+    encode(&img).with_context(|| format!("Failed to re-encode {}", path.display()))
+}
+
+fn encode(img: &DynamicImage) -> Result<Image> {
+    let mut buf = Cursor::new(Vec::new());
+    if img.color().has_alpha() {
+        img.write_to(&mut buf, ImageFormat::Png)?;
+        if buf.get_ref().len() <= MAX_BYTES {
+            return Ok(Image {
+                mime: ImageMime::PNG,
+                bytes: buf.into_inner(),
+            });
+        }
+        buf = Cursor::new(Vec::new());
+    }
+    DynamicImage::ImageRgb8(img.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut buf, 85))?;
+    Ok(Image {
+        mime: ImageMime::JPEG,
+        bytes: buf.into_inner(),
+    })
+}
+
+/// Best guess at whether the active model accepts images. Unknown models get
+/// the benefit of the doubt; the provider's own error is still shown.
+fn supports_vision(cfg: &Config, eff: &EffectiveProvider) -> bool {
+    let model = eff.model.to_ascii_lowercase();
+    match eff.name.as_str() {
+        "anthropic" => !model.starts_with("claude-2") && !model.starts_with("claude-instant"),
+        "openai" => {
+            !(model.starts_with("gpt-3.5")
+                || model == "gpt-4"
+                || model.starts_with("gpt-4-0")
+                || model.starts_with("o1-mini")
+                || model.starts_with("o3-mini"))
+        }
+        "gemini" => true,
+        "openrouter" => openrouter_accepts_images(cfg, &eff.model)
+            .ok()
+            .flatten()
+            .unwrap_or(true),
+        _ => [
+            "llava",
+            "vision",
+            "moondream",
+            "-vl",
+            "vl:",
+            "minicpm-v",
+            "gemma3",
+            "llama4",
+            "pixtral",
+            "mistral-small3",
+        ]
+        .iter()
+        .any(|hint| model.contains(hint)),
+    }
+}
+
+fn vision_example(provider: &str) -> &'static str {
+    match provider {
+        "anthropic" => "claude-sonnet-4-0",
+        "openai" => "gpt-4o",
+        "gemini" => "gemini-2.5-flash",
+        "openrouter" => "openai/gpt-4o",
+        _ => "llama3.2-vision",
+    }
+}
+
+/// One user message per image, in the form the provider's backend expects:
+/// OpenAI-compatible APIs take data URLs, the others raw bytes.
+pub(super) fn messages(
+    cfg: &Config,
+    eff: &EffectiveProvider,
+    images: Vec<Image>,
+) -> Result<Vec<ChatMessage>> {
+    if images.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !supports_vision(cfg, eff) {
+        return Err(anyhow!(
+            "Model '{}' ({}) does not accept images. Pick a vision model, e.g. --model {}",
+            eff.model,
+            eff.name,
+            vision_example(&eff.name)
+        ));
+    }
+    let data_url = matches!(eff.name.as_str(), "openai" | "openrouter" | "lmstudio");
+    Ok(images
+        .into_iter()
+        .map(|img| ChatMessage {
+            role: ChatRole::User,
+            message_type: if data_url {
+                MessageType::ImageURL(format!(
+                    "data:{};base64,{}",
+                    img.mime.mime_type(),
+                    base64::engine::general_purpose::STANDARD.encode(&img.bytes)
+                ))
+            } else {
+                MessageType::Image((img.mime, img.bytes))
+            },
+            content: String::new(),
+        })
+        .collect())
+}
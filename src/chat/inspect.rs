@@ -0,0 +1,352 @@
+use anyhow::{bail, Context, Result};
+use chrono::Duration;
+use nu_ansi_term::{Color as NuColor, Style};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::{
+    find_context_files, find_project_instructions, get_global_config_dir, system_prompt_extra,
+    Config,
+};
+use crate::history::{History, HistoryEntry};
+use crate::index::Excerpt;
+use crate::output;
+use crate::tools::ToolsRegistry;
+
+/// What a piece of the system prompt is, which decides its heading.
+#[derive(Clone, Copy, PartialEq)]
+pub(super) enum Kind {
+    Instructions,
+    Context,
+    Excerpt,
+    History,
+}
+
+/// One removable piece of the system prompt.
+pub(super) struct Part {
+    /// Stable across runs, so a drop made now applies to the next turn.
+    pub(super) id: String,
+    pub(super) label: String,
+    pub(super) kind: Kind,
+    pub(super) text: String,
+}
+
+/// The project instructions, contexts, project excerpts, and history
+/// entries, in the order they appear in the system prompt.
+pub(super) fn parts(
+    instructions: Option<&str>,
+    contexts: &[(String, String)],
+    excerpts: &[Excerpt],
+    entries: &[(HistoryEntry, Duration)],
+) -> Vec<Part> {
+    let mut out = Vec::new();
+    if let Some(instructions) = instructions {
+        out.push(Part {
+            id: "instructions".into(),
+            label: "Project instructions (TAI.md)".into(),
+            kind: Kind::Instructions,
+            text: format!(
+                "\n## Project Instructions\n\nStanding instructions for this project. Follow them unless the user says otherwise.\n\n{}\n",
+                instructions.trim()
+            ),
+        });
+    }
+    for (name, content) in contexts {
+        out.push(Part {
+            id: format!("context:{}", name),
+            label: format!("Context {}", name),
+            kind: Kind::Context,
+            text: format!("### Context from {}\n\n{}\n\n", name, content),
+        });
+    }
+    for excerpt in excerpts {
+        out.push(Part {
+            id: format!("excerpt:{}:{}", excerpt.path, excerpt.start),
+            label: format!(
+                "Excerpt {} lines {}-{}",
+                excerpt.path, excerpt.start, excerpt.end
+            ),
+            kind: Kind::Excerpt,
+            text: format!(
+                "### {} (lines {}-{})\n\n```\n{}\n```\n\n",
+                excerpt.path, excerpt.start, excerpt.end, excerpt.text
+            ),
+        });
+    }
+    for (entry, age) in entries {
+        let minutes = age.num_minutes();
+        out.push(Part {
+            id: format!("history:{}", entry.timestamp.to_rfc3339()),
+            label: format!(
+                "History from {} min ago: {}",
+                minutes,
+                entry.user_input.lines().next().unwrap_or_default()
+            ),
+            kind: Kind::History,
+            text: format!(
+                "(from {} minutes ago):\nUser: {}\nAssistant: {}\n\n",
+                minutes, entry.user_input, entry.llm_response
+            ),
+        });
+    }
+    out
+}
+
+/// The parts as they follow the system rules, with the section headings of
+/// the kinds present.
+pub(super) fn assemble(parts: &[&Part]) -> String {
+    let mut context_section = String::new();
+    let mut excerpt_section = String::new();
+    let mut history_section = String::new();
+    let mut interaction = 0;
+    for part in parts {
+        match part.kind {
+            Kind::Instructions => context_section.push_str(&part.text),
+            Kind::Context => {
+                if !context_section.contains("\n## Additional Context\n") {
+                    context_section.push_str("\n## Additional Context\n\n");
+                }
+                context_section.push_str(&part.text);
+            }
+            Kind::Excerpt => {
+                if excerpt_section.is_empty() {
+                    excerpt_section.push_str("\n## Related Project Files\n\nExcerpts of the project that may bear on the question:\n\n");
+                }
+                excerpt_section.push_str(&part.text);
+            }
+            Kind::History => {
+                if interaction == 0 {
+                    history_section.push_str("\nHere are some of your previous interactions (these may not be related to the current query and are just for reference):\n\n");
+                }
+                interaction += 1;
+                history_section.push_str(&format!("Interaction {} {}", interaction, part.text));
+            }
+        }
+    }
+    context_section + &excerpt_section + &history_section
+}
+
+/// Rough token count: about four characters per token for English text and
+/// code, which is close enough to compare parts.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Parts left out of the next turn. Cleared once that turn is sent, so a
+/// drop never outlives the question it was made for.
+#[derive(Default)]
+pub struct Dropped(BTreeSet<String>);
+
+impl Dropped {
+    fn path() -> Result<PathBuf> {
+        Ok(get_global_config_dir()?.join("dropped.json"))
+    }
+
+    pub(super) fn load() -> Self {
+        Self::path()
+            .ok()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .map(Self)
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if self.0.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string(&self.0)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub(super) fn contains(&self, id: &str) -> bool {
+        self.0.contains(id)
+    }
+
+    /// Forget the drops once the turn they were made for has been built.
+    pub(super) fn consume(self) {
+        if !self.0.is_empty() {
+            let _ = Self::default().save();
+        }
+    }
+}
+
+/// `/context [drop N | keep N | reset]`: list what the next turn sends, with
+/// estimated token counts, and choose parts to leave out of it.
+pub(super) fn run(cfg: &Config, args: &str, nocontext: bool, context: Option<&str>) -> Result<()> {
+    let instructions = if nocontext {
+        None
+    } else {
+        find_project_instructions().map(|(_, content)| content)
+    };
+    let contexts = if nocontext {
+        Vec::new()
+    } else {
+        find_context_files(cfg, context).unwrap_or_default()
+    };
+    let history = History::load(&cfg.history).unwrap_or_default();
+    let parts = parts(
+        instructions.as_deref(),
+        &contexts,
+        &[],
+        &history.get_relevant_entries(),
+    );
+
+    let mut dropped = Dropped::load();
+    // Drops for parts that no longer exist (expired history, another
+    // project's contexts) are forgotten.
+    dropped.0.retain(|id| parts.iter().any(|p| &p.id == id));
+    let mut words = args.split_whitespace();
+    match (words.next(), words.next()) {
+        (None, _) => {}
+        (Some("reset"), None) => dropped.0.clear(),
+        (Some(action @ ("drop" | "keep")), Some(_)) => {
+            for n in args.split_whitespace().skip(1) {
+                let part = n
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|i| parts.get(i))
+                    .with_context(|| {
+                        format!("No item {}; `/context` lists 1 to {}", n, parts.len())
+                    })?;
+                if action == "drop" {
+                    dropped.0.insert(part.id.clone());
+                } else {
+                    dropped.0.remove(&part.id);
+                }
+            }
+        }
+        _ => bail!("Usage: /context [drop N... | keep N... | reset]"),
+    }
+    dropped.save()?;
+
+    let tools = ToolsRegistry::with_default(cfg);
+    let tool_tokens: usize = tools
+        .describe()
+        .iter()
+        .map(|(name, description)| estimate_tokens(name) + estimate_tokens(description))
+        .sum();
+    let rules = match super::custom_prompt(cfg) {
+        Some(template) => super::custom_rules(&template),
+        None => super::system_rules(
+            &tools,
+            &super::style::Style::new(&cfg.style)
+                .unwrap_or_default()
+                .with_profile(cfg),
+        ),
+    };
+    let rules_tokens = estimate_tokens(&rules)
+        + estimate_tokens(&super::extra_rules(system_prompt_extra(cfg).as_deref()))
+        + if cfg.prompt.include_env.unwrap_or(false) {
+            estimate_tokens(&super::environment::snapshot(cfg))
+        } else {
+            0
+        };
+    let kept_tokens: usize = parts
+        .iter()
+        .filter(|p| !dropped.contains(&p.id))
+        .map(|p| estimate_tokens(&p.text))
+        .sum();
+    let total = rules_tokens + tool_tokens + kept_tokens;
+
+    if output::is_json() {
+        let items: Vec<_> = parts
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                json!({
+                    "item": i + 1,
+                    "id": p.id,
+                    "label": p.label,
+                    "tokens": estimate_tokens(&p.text),
+                    "dropped": dropped.contains(&p.id),
+                })
+            })
+            .collect();
+        output::emit(
+            "context",
+            json!({
+                "system_rules": rules_tokens,
+                "tool_definitions": { "tools": tools.describe().len(), "tokens": tool_tokens },
+                "items": items,
+                "total_tokens": total,
+            }),
+        );
+        return Ok(());
+    }
+
+    let dim = Style::new().dimmed();
+    let row = |marker: &str, label: &str, tokens: usize, style: Style| {
+        println!(
+            "{:>4}  {}  {}",
+            marker,
+            style.paint(format!("{:>7}", format!("~{}", tokens))),
+            style.paint(label)
+        );
+    };
+    println!(
+        "{}",
+        Style::new()
+            .bold()
+            .paint("Sent with the next question (estimated tokens):")
+    );
+    row("", "System rules", rules_tokens, Style::new());
+    row(
+        "",
+        &format!("Tool definitions ({} tools)", tools.describe().len()),
+        tool_tokens,
+        Style::new(),
+    );
+    for (i, part) in parts.iter().enumerate() {
+        let label = super::truncate_str(&part.label, 70);
+        if dropped.contains(&part.id) {
+            row(
+                &format!("{}", i + 1),
+                &format!("{} (dropped)", label),
+                estimate_tokens(&part.text),
+                dim.strikethrough(),
+            );
+        } else {
+            row(
+                &format!("{}", i + 1),
+                &label,
+                estimate_tokens(&part.text),
+                Style::new(),
+            );
+        }
+    }
+    println!(
+        "      {}  {}",
+        Style::new()
+            .bold()
+            .paint(format!("{:>7}", format!("~{}", total))),
+        Style::new().bold().paint("Total, plus the question itself")
+    );
+    if cfg.index.enabled == Some(true) {
+        println!(
+            "{}",
+            NuColor::DarkGray.paint(
+                "With [index] enabled, history entries and project excerpts are chosen by the question; the recent entries are listed."
+            )
+        );
+    }
+    if !parts.is_empty() {
+        println!(
+            "{}",
+            NuColor::DarkGray.paint(
+                "Leave items out of the next question with /context drop N, bring them back with /context keep N."
+            )
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use nu_ansi_term::Style;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// How often the status line is redrawn while tokens arrive.
+const REDRAW: Duration = Duration::from_millis(250);
+
+/// Tokens per second of a streamed answer. Chunks stand in for tokens until
+/// the provider reports the real count at the end.
+pub(super) struct Meter {
+    started: Instant,
+    first: Option<Instant>,
+    last: Instant,
+    chunks: u64,
+    drawn: Option<Instant>,
+}
+
+impl Meter {
+    pub(super) fn start() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            first: None,
+            last: now,
+            chunks: 0,
+            drawn: None,
+        }
+    }
+
+    pub(super) fn chunk(&mut self) {
+        let now = Instant::now();
+        self.first.get_or_insert(now);
+        self.last = now;
+        self.chunks += 1;
+    }
+
+    fn rate(&self, tokens: u64) -> Option<f64> {
+        let secs = self.last.duration_since(self.first?).as_secs_f64();
+        (tokens > 1 && secs > 0.0).then(|| (tokens - 1) as f64 / secs)
+    }
+
+    /// The status text when a redraw is due: elapsed time, tokens so far, and
+    /// the current rate.
+    pub(super) fn due(&mut self) -> Option<String> {
+        let now = Instant::now();
+        if self.drawn.is_some_and(|at| now.duration_since(at) < REDRAW) {
+            return None;
+        }
+        self.drawn = Some(now);
+        let mut status = format!(
+            "{:.1}s · {} tok",
+            now.duration_since(self.started).as_secs_f64(),
+            self.chunks
+        );
+        if let Some(rate) = self.rate(self.chunks) {
+            status.push_str(&format!(" · {:.1} tok/s", rate));
+        }
+        Some(status)
+    }
+
+    /// Tokens, generation rate, and time to first token, using the
+    /// provider's count when it sent one. `None` for answers too short to
+    /// measure.
+    pub(super) fn finish(&self, reported: Option<u32>) -> Option<(u64, f64, f64)> {
+        let tokens = reported.map_or(self.chunks, u64::from);
+        let rate = self.rate(tokens)?;
+        let first = self.first?.duration_since(self.started).as_secs_f64();
+        Some((tokens, rate, first))
+    }
+}
+
+/// Rewrite the separator `lines_up` lines above the cursor with `status`
+/// at its right end, leaving the cursor where the answer continues.
+pub(super) fn draw(status: &str, lines_up: usize, cols: usize, style: Style) {
+    let label = format!(" {} ", status);
+    let rule = "─".repeat(cols.saturating_sub(label.chars().count() + 2));
+    print!(
+        "\x1b7\x1b[{}A\r{}{}{}\x1b8",
+        lines_up,
+        style.paint(rule),
+        style.paint(label),
+        style.paint("──")
+    );
+    std::io::stdout().flush().ok();
+}
@@ -1,22 +1,69 @@
 use anyhow::{Context, Result};
 use bat::{PagingMode, PrettyPrinter, WrappingMode};
-use futures::future::{FutureExt, LocalBoxFuture};
 use futures::StreamExt;
-use llm::{
-    builder::{LLMBackend, LLMBuilder},
-    chat::{ChatMessage, ChatRole, MessageType, StreamResponse},
-    LLMProvider,
-};
+use llm::chat::{ChatMessage, ChatRole, MessageType, StreamResponse, Usage};
 use nu_ansi_term::{Color as NuColor, Style};
-use serde_json::Value as JsonValue;
-use std::io::Write;
-use terminal_size::{terminal_size, Height, Width};
+use serde_json::{json, Value as JsonValue};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::time::Instant;
+use terminal_size::{terminal_size, Height};
+use tokio::sync::mpsc::UnboundedSender;
 
-use crate::config::{find_context_files, load_config, select_effective_provider};
+use crate::config::{
+    apply_profile, find_context_files, find_project_instructions, resolve_effective_provider,
+    system_prompt_extra, Config, DisplayConfig, EffectiveProvider, FiltersConfig, QuickFactsConfig,
+    RefusalsConfig, StyleConfig,
+};
+use crate::guard;
 use crate::history::History;
-use crate::tools::ToolsRegistry;
+use crate::index::{Index, Retrieved};
+use crate::say;
+use crate::tools::{ask_line, ask_user_async, handle_interrupts, ToolsRegistry, UserShell};
+
+mod attach;
+mod environment;
+mod failover;
+mod fences;
+mod filters;
+mod footer;
+mod frontend;
+mod highlight;
+mod images;
+mod inspect;
+mod meter;
+mod provider;
+mod refusal;
+mod replay;
+mod save;
+mod style;
+mod task;
+mod theme;
+mod tour;
+mod transcript;
+#[cfg(unix)]
+mod tui;
+
+use frontend::{Event, Frontend};
+
+pub use failover::Failover;
+pub use inspect::estimate_tokens;
+pub use provider::{setup, warm_client, Provider};
+pub use replay::replay;
+pub use save::{save_blocks, save_command};
+pub use task::{discard as discard_task, offer_resume, resume_prompt};
+pub use theme::{check as check_ui_setting, theme_names, DEFAULT_THEME};
+pub use tour::run_tour;
+pub use transcript::path as transcript_path;
+#[cfg(unix)]
+pub use tui::run_tui;
+
+const DEFAULT_MAX_TOOL_ITERATIONS: u32 = 25;
+
+/// This run's last answer, for `--copy`; the history may not keep it.
+static LAST_ANSWER: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
 
-fn is_sensitive_key(key: &str) -> bool {
+pub(crate) fn is_sensitive_key(key: &str) -> bool {
     let k = key.to_ascii_lowercase();
     let hints = [
         "key",
@@ -121,416 +168,1203 @@ fn format_tool_params(args_raw: &str) -> String {
     }
 }
 
+/// One line per tool call for the checkpoint summary: the tool, its first
+/// argument, and how it went.
+fn summarize_call(name: &str, args_raw: &str, outcome: &str) -> String {
+    let first = match serde_json::from_str::<JsonValue>(args_raw) {
+        Ok(JsonValue::Object(map)) => map
+            .iter()
+            .find(|(k, _)| !is_sensitive_key(k))
+            .map(|(k, v)| render_value_for_kv(k, v)),
+        _ => None,
+    };
+    match first {
+        Some(arg) => format!("{} {} ({})", name, truncate_str(&arg, 60), outcome),
+        None => format!("{} ({})", name, outcome),
+    }
+}
+
+enum Checkpoint {
+    Continue,
+    Abort,
+    TakeOver(String),
+}
+
+/// Where a step is: each stage does its work and names the next.
+enum Stage {
+    /// Send the conversation to the model.
+    Ask,
+    /// Run the tools the model called, then ask again.
+    Tools(Vec<llm::ToolCall>),
+    /// Stream the final answer.
+    Stream,
+    /// The final answer, whole.
+    Answer(String),
+    /// The provider declined to answer.
+    Refused(refusal::Refusal),
+    Done,
+}
+
 pub struct Session<'a> {
-    llm: &'a dyn LLMProvider,
+    llm: &'a Failover,
     tools: ToolsRegistry,
+    quick_facts: QuickFactsConfig,
+    filters: FiltersConfig,
+    refusals: RefusalsConfig,
+    display: DisplayConfig,
+    /// Input and output tokens the current step has used.
+    spent: (u64, u64),
+    style: style::Style,
+    /// Contents of the project's `TAI.md`, if any.
+    instructions: Option<String>,
+    /// `--system` or `core.system_prompt`, in place of the built-in rules.
+    custom_prompt: Option<String>,
+    /// `core.system_prompt_extra` and `system_prompt_file`, after the rules.
+    prompt_extra: Option<String>,
+    /// The environment snapshot, with `[prompt] include_env`.
+    environment: Option<String>,
+    /// Tool rounds in one step before the user is asked whether to go on.
+    max_tool_iterations: u32,
     history: Vec<ChatMessage>,
+    /// The answer streamed so far in the current step, and the tool calls it
+    /// made, kept for a checkpoint or an interrupt.
+    partial: String,
+    tool_log: Vec<String>,
     file_history: History,
     context_added: bool,
-}
-
-pub fn setup(tools: &ToolsRegistry) -> Result<Box<dyn LLMProvider>> {
-    let cfg = load_config().unwrap_or_default();
-    let eff = select_effective_provider(&cfg);
-
-    let mut builder = LLMBuilder::new();
-    let is_openai_gpt5 =
-        eff.name == "openai" && (eff.model.starts_with("gpt-5") || eff.model.starts_with("gpt-5-"));
-    if !is_openai_gpt5 {
-        builder = builder.temperature(eff.temperature);
-        builder = builder.max_tokens(eff.max_tokens);
-    }
-    let builder = tools.apply_to_builder(builder);
-
-    match eff.name.as_str() {
-        "anthropic" => {
-            let key = std::env::var("ANTHROPIC_API_KEY").unwrap_or_default();
-            builder
-                .backend(LLMBackend::Anthropic)
-                .api_key(key)
-                .model(&eff.model)
-                .build()
-                .context("Failed to build Anthropic Client")
-        }
-        "openai" => {
-            let key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
-            let mut b = builder
-                .backend(LLMBackend::OpenAI)
-                .api_key(key)
-                .model(&eff.model);
-            if let Some(base) = eff.base_url_or_host.clone() {
-                b = b.base_url(base);
-            }
-            b.build().context("Failed to build OpenAI Client")
-        }
-        "ollama" => {
-            let mut b = builder.backend(LLMBackend::Ollama).model(&eff.model);
-            if let Some(host) = eff.base_url_or_host.clone() {
-                b = b.base_url(host);
-            }
-            b.build().context("Failed to build Ollama Client")
-        }
-        "lmstudio" => {
-            let key = std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "lm-studio".into());
-            let mut b = builder
-                .backend(LLMBackend::OpenAI)
-                .api_key(key)
-                .model(&eff.model);
-            if let Some(base) = eff.base_url_or_host.clone() {
-                b = b.base_url(base);
-            }
-            b.build()
-                .context("Failed to build LM Studio (OpenAI compat) Client")
-        }
-        _ => builder
-            .backend(LLMBackend::Ollama)
-            .model(&eff.model)
-            .build()
-            .context("Failed to build Ollama Client"),
-    }
+    /// Parts of the system prompt left out of this turn with `/context drop`.
+    dropped: inspect::Dropped,
+    /// Shows the step's events: the terminal or JSON lines.
+    frontend: Box<dyn Frontend>,
+    /// Picks the history and project excerpts sent with the first question,
+    /// when `[index]` is enabled.
+    index: Option<Index>,
+    /// Saves the run's tool calls, so an interrupted run can be resumed.
+    progress: Option<task::Progress>,
 }
 
 impl<'a> Session<'a> {
-    pub fn new(llm: &'a dyn LLMProvider, tools: ToolsRegistry) -> Self {
-        let file_history = History::load().unwrap_or_default();
+    pub fn new(
+        llm: &'a Failover,
+        tools: ToolsRegistry,
+        cfg: &Config,
+        instructions: Option<String>,
+        dropped: inspect::Dropped,
+    ) -> Self {
+        let file_history = History::load(&cfg.history).unwrap_or_default();
+        let index = Index::new(cfg).unwrap_or_else(|e| {
+            eprintln!(
+                "Warning: Semantic index unavailable, using recent history: {:#}",
+                e
+            );
+            None
+        });
 
         Self {
             llm,
             tools,
+            quick_facts: cfg.quick_facts.clone(),
+            filters: cfg.filters.clone(),
+            refusals: cfg.refusals.clone(),
+            display: cfg.display.clone(),
+            spent: (0, 0),
+            // `prepare` has already refused a style that does not resolve.
+            style: style::Style::new(&cfg.style)
+                .unwrap_or_default()
+                .with_profile(cfg),
+            instructions,
+            custom_prompt: custom_prompt(cfg),
+            prompt_extra: system_prompt_extra(cfg),
+            environment: cfg
+                .prompt
+                .include_env
+                .unwrap_or(false)
+                .then(|| environment::snapshot(cfg)),
+            max_tool_iterations: cfg
+                .core
+                .max_tool_iterations
+                .unwrap_or(DEFAULT_MAX_TOOL_ITERATIONS),
             history: Vec::new(),
+            partial: String::new(),
+            tool_log: Vec::new(),
             file_history,
             context_added: false,
+            dropped,
+            frontend: frontend::for_output(
+                cfg.display.copyable_code.unwrap_or(false)
+                    || cfg.ui.code_style.as_deref() == Some("copyable"),
+            ),
+            index,
+            progress: None,
         }
     }
 
-    pub fn step<'b>(
-        &'b mut self,
-        input: &'b str,
-        contexts: &'b [(String, String)],
-    ) -> LocalBoxFuture<'b, Result<()>> {
-        async move {
+    /// Answer `input`: ask the model, run the tools it calls until it
+    /// answers, and send each stage to the frontend as it happens.
+    pub async fn step(
+        &mut self,
+        input: &str,
+        attachments: &str,
+        images: Vec<ChatMessage>,
+        contexts: &[(String, String)],
+    ) -> Result<()> {
+        if self.history.is_empty() {
+            let retrieved = match &self.index {
+                Some(index) => index
+                    .retrieve(input, &self.file_history)
+                    .await
+                    .map_err(|e| {
+                        eprintln!(
+                            "Warning: Semantic lookup failed, using recent history: {:#}",
+                            e
+                        )
+                    })
+                    .ok(),
+                None => None,
+            };
+            let system_prompt = self.build_system_prompt(contexts, retrieved);
+            self.history.push(ChatMessage {
+                role: ChatRole::Assistant,
+                message_type: MessageType::Text,
+                content: system_prompt,
+            });
+        }
+
+        // Attachments go to the model but not into the saved history.
+        // Image messages carry no text, so they precede the prompt.
+        self.history.extend(images);
+        self.history.push(ChatMessage {
+            role: ChatRole::User,
+            message_type: MessageType::Text,
+            content: if attachments.is_empty() {
+                input.to_string()
+            } else {
+                format!("{}\n\n{}", input, attachments)
+            },
+        });
+
+        if let Some(hit) = guard::check(&self.quick_facts, input) {
+            let route = hit
+                .tool
+                .into_iter()
+                .chain(Some("fetch_url"))
+                .find(|t| self.tools.find(t).is_some());
+            match route {
+                Some(tool) => self.history.push(ChatMessage {
+                    role: ChatRole::Assistant,
+                    message_type: MessageType::Text,
+                    content: format!(
+                        "This question needs live {} information. Answer it only from the {} tool's result; do not guess or rely on training data.",
+                        hit.class, tool
+                    ),
+                }),
+                None => {
+                    self.frontend.event(Event::NeedsWeb { class: &hit.class });
+                    return Ok(());
+                }
+            }
+        }
+
+        self.partial.clear();
+        self.tool_log.clear();
+        self.spent = (0, 0);
+        let started = Instant::now();
+        let mut rounds = 0;
+        let mut retried = false;
+        let mut stage = Stage::Ask;
+        loop {
+            stage = match stage {
+                Stage::Ask => self.ask().await?,
+                Stage::Refused(refusal) => self.refused(refusal, &mut retried),
+                Stage::Tools(calls) => self.run_tools(input, calls, &mut rounds).await?,
+                Stage::Stream => {
+                    let text = self.stream_answer().await;
+                    transcript::streamed(self.llm.active_name(), &text);
+                    let text = self.filter(&text, input);
+                    keep_answer(&text);
+                    self.file_history
+                        .add_entry(input.to_string(), text.clone())?;
+                    self.frontend.event(Event::AnswerReady {
+                        text: &text,
+                        streamed: true,
+                    });
+                    self.footer(started);
+                    Stage::Done
+                }
+                Stage::Answer(text) => {
+                    self.partial = text.clone();
+                    let text = self.filter(&text, input);
+                    keep_answer(&text);
+                    self.frontend.event(Event::AnswerReady {
+                        text: &text,
+                        streamed: false,
+                    });
+                    self.footer(started);
+                    self.file_history.add_entry(input.to_string(), text)?;
+                    Stage::Done
+                }
+                Stage::Done => return Ok(()),
+            };
+        }
+    }
+
+    /// Send the conversation and see whether the model calls tools or
+    /// answers.
+    async fn ask(&mut self) -> Result<Stage> {
+        let detect = self.refusals.detect != Some(false);
+        let response = match self.llm.chat_with_tools(&self.history).await {
+            Ok(response) => response,
+            Err(e) => {
+                if let Some(refusal) = refusal::in_error(&e).filter(|_| detect) {
+                    return Ok(Stage::Refused(refusal));
+                }
+                return Err(e).context("Chat failed");
+            }
+        };
+        if let Some(usage) = response.usage() {
+            self.spend(&usage);
+            self.frontend.event(Event::Usage(&usage));
+        }
+        if let Some(calls) = response.tool_calls().filter(|calls| !calls.is_empty()) {
+            return Ok(Stage::Tools(calls));
+        }
+        let text = response.text().unwrap_or_default();
+        if let Some(refusal) = refusal::in_answer(&text).filter(|_| detect) {
+            return Ok(Stage::Refused(refusal));
+        }
+        // A frontend that streams gets the answer from a second, streamed
+        // request; others take it whole from this response.
+        Ok(if self.frontend.streams() {
+            Stage::Stream
+        } else {
+            Stage::Answer(text)
+        })
+    }
 
-            if self.history.is_empty() {
-                let system_prompt = self.build_system_prompt(contexts);
+    /// Show a refusal apart from answers, leaving it out of the history,
+    /// and ask once more if `[refusals] retry` says how.
+    fn refused(&mut self, refusal: refusal::Refusal, retried: &mut bool) -> Stage {
+        let llm = self.llm;
+        self.frontend.event(Event::Refused {
+            provider: llm.active_name(),
+            reason: refusal.reason,
+            text: &refusal.text,
+        });
+        if *retried {
+            return Stage::Done;
+        }
+        *retried = true;
+        match self.refusals.retry.as_deref() {
+            Some("rephrase") => {
+                say!("Asking again with a note on how to read the request");
                 self.history.push(ChatMessage {
                     role: ChatRole::Assistant,
                     message_type: MessageType::Text,
-                    content: system_prompt,
+                    content: "The previous reply declined the request. If it has a plausible legitimate reading for someone working in a terminal, such as administering their own systems, answer that reading. If only part of it is a problem, answer the rest and say briefly what you left out.".to_string(),
                 });
+                Stage::Ask
             }
+            Some("fallback") => match llm.fall_back() {
+                Some(name) => {
+                    say!("Asking {} instead", name);
+                    Stage::Ask
+                }
+                None => Stage::Done,
+            },
+            _ => Stage::Done,
+        }
+    }
 
-            self.history.push(ChatMessage {
-                role: ChatRole::User,
-                message_type: MessageType::Text,
-                content: input.to_string(),
+    /// Run one round of tool calls and put their results in the
+    /// conversation, asking first whether to go on once the rounds reach
+    /// the limit.
+    async fn run_tools(
+        &mut self,
+        input: &str,
+        calls: Vec<llm::ToolCall>,
+        rounds: &mut u32,
+    ) -> Result<Stage> {
+        if *rounds >= self.max_tool_iterations {
+            match Self::checkpoint(*rounds, &self.tool_log).await? {
+                Checkpoint::Continue => *rounds = 0,
+                Checkpoint::Abort => {
+                    say!("Stopped after {} tool calls.", self.tool_log.len());
+                    self.file_history.add_entry(
+                        input.to_string(),
+                        format!("(stopped after {} tool calls)", self.tool_log.len()),
+                    )?;
+                    return Ok(Stage::Done);
+                }
+                Checkpoint::TakeOver(message) => {
+                    // The pending calls are dropped; the model continues
+                    // from the user's instructions.
+                    *rounds = 0;
+                    self.history.push(ChatMessage {
+                        role: ChatRole::User,
+                        message_type: MessageType::Text,
+                        content: message,
+                    });
+                    return Ok(Stage::Ask);
+                }
+            }
+        }
+        *rounds += 1;
+
+        self.history.push(
+            ChatMessage::assistant()
+                .tool_use(calls.clone())
+                .content("")
+                .build(),
+        );
+
+        let mut tool_results = Vec::new();
+        for call in &calls {
+            let name = &call.function.name;
+            let args_raw = &call.function.arguments;
+            let result = match self.tools.preflight(call) {
+                Some(problem) => {
+                    self.frontend.event(Event::ToolRejected {
+                        id: &call.id,
+                        name,
+                        problem: &problem,
+                    });
+                    self.tool_log
+                        .push(summarize_call(name, args_raw, "rejected"));
+                    json!({ "error": problem })
+                }
+                None => {
+                    self.frontend.event(Event::ToolRequested {
+                        id: &call.id,
+                        name,
+                        arguments: args_raw,
+                    });
+                    if let Some(progress) = &mut self.progress {
+                        progress.started(summarize_call(name, args_raw, "running"));
+                    }
+                    let started = Instant::now();
+                    let outcome = self.tools.handle_tool_call(call).await;
+                    tracing::debug!(
+                        tool = %name,
+                        elapsed_ms = started.elapsed().as_millis() as u64,
+                        ok = outcome.is_ok(),
+                        "tool call finished"
+                    );
+                    transcript::tool(
+                        name,
+                        started.elapsed(),
+                        outcome
+                            .as_ref()
+                            .map(|(result, _)| result)
+                            .map_err(|e| format!("{:#}", e)),
+                    );
+                    let summary = summarize_call(
+                        name,
+                        args_raw,
+                        if outcome.is_ok() { "ok" } else { "failed" },
+                    );
+                    if let Some(progress) = &mut self.progress {
+                        progress.finished(summary.clone());
+                    }
+                    self.tool_log.push(summary);
+                    self.frontend.event(Event::ToolFinished {
+                        id: &call.id,
+                        name,
+                        outcome: match &outcome {
+                            Ok((result, tool)) => Ok((result, tool.as_ref())),
+                            Err(e) => Err(e.to_string()),
+                        },
+                    });
+                    match outcome {
+                        Ok((result, _)) => result,
+                        Err(e) => json!({ "error": e.to_string() }),
+                    }
+                }
+            };
+            tool_results.push(llm::ToolCall {
+                id: call.id.clone(),
+                call_type: "function".to_string(),
+                function: llm::FunctionCall {
+                    name: call.function.name.clone(),
+                    arguments: serde_json::to_string(&result).unwrap_or("{}".into()),
+                },
             });
+        }
 
-            loop {
-                let response = self
-                    .llm
-                    .chat_with_tools(&self.history, self.llm.tools())
-                    .await
-                    .context("Chat failed")?;
-
-                if let Some(calls) = response.tool_calls() {
-                    if !calls.is_empty() {
-
-                        self.history.push(
-                            ChatMessage::assistant()
-                                .tool_use(calls.clone())
-                                .content("")
-                                .build(),
-                        );
-
-                        let mut tool_results = Vec::new();
-                        for call in &calls {
-                            let name = &call.function.name;
-                            let args_raw = &call.function.arguments;
-                            let formatted = format_tool_params(args_raw);
-                            let header = Style::new()
-                                .bold()
-                                .fg(NuColor::LightCyan)
-                                .paint("Tool call");
-                            let name_col = Style::new().bold().fg(NuColor::Yellow).paint(name);
-                            println!("{}: {}", header, name_col);
-                            let args_label = Style::new().fg(NuColor::Green).paint("params");
-                            println!("{}:\n{}", args_label, formatted);
-
-                            match self.tools.handle_tool_call(call) {
-                                Ok((result, tool)) => {
-                                    tool.print_result(&result);
-
-                                    tool_results.push(llm::ToolCall {
-                                        id: call.id.clone(),
-                                        call_type: "function".to_string(),
-                                        function: llm::FunctionCall {
-                                            name: call.function.name.clone(),
-                                            arguments: serde_json::to_string(&result)
-                                                .unwrap_or("{}".into()),
-                                        },
-                                    });
-                                }
-                                Err(e) => {
-                                    let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
-                                    println!("{}: {}", result_label, e);
-
-                                    tool_results.push(llm::ToolCall {
-                                        id: call.id.clone(),
-                                        call_type: "function".to_string(),
-                                        function: llm::FunctionCall {
-                                            name: call.function.name.clone(),
-                                            arguments: serde_json::to_string(
-                                                &serde_json::json!({"error": e.to_string()}),
-                                            )
-                                            .unwrap_or("{}".into()),
-                                        },
-                                    });
-                                }
-                            }
-                        }
+        self.history.push(
+            ChatMessage::user()
+                .tool_result(tool_results)
+                .content("")
+                .build(),
+        );
 
-                        self.history.push(
-                            ChatMessage::user()
-                                .tool_result(tool_results)
-                                .content("")
-                                .build(),
-                        );
-
-                        let has_shell = calls.iter().any(|c| c.function.name == "run_shell");
-                        if has_shell {
-                            self.history.push(ChatMessage {
-                                role: ChatRole::Assistant,
-                                message_type: MessageType::Text,
-                                content: "Summarize the results of the terminal command succinctly and proceed with any next steps to complete the user's request. If the command output already satisfies the request, provide the final answer concisely.".to_string(),
-                            });
-                        } else {
-                            self.history.push(ChatMessage {
-                                role: ChatRole::Assistant,
-                                message_type: MessageType::Text,
-                                content: "Use the tool outputs above to answer the user directly. Provide a concise summary or the requested information. If more actions are needed, call a tool.".to_string(),
-                            });
-                        }
+        let has_shell = calls.iter().any(|c| c.function.name == "run_shell");
+        self.history.push(ChatMessage {
+            role: ChatRole::Assistant,
+            message_type: MessageType::Text,
+            content: if has_shell {
+                "Summarize the results of the terminal command succinctly and proceed with any next steps to complete the user's request. If the command output already satisfies the request, provide the final answer concisely."
+            } else {
+                "Use the tool outputs above to answer the user directly. Provide a concise summary or the requested information. If more actions are needed, call a tool."
+            }
+            .to_string(),
+        });
+        Ok(Stage::Ask)
+    }
 
+    /// Stream the answer to the frontend and return it, recording how fast
+    /// the provider was. A provider that cannot stream with usage falls
+    /// back to plain text chunks.
+    async fn stream_answer(&mut self) -> String {
+        self.frontend.event(Event::AnswerStarted);
+        let mut meter = meter::Meter::start();
+        let mut reported = None;
+        match self.llm.chat_stream_struct(&self.history).await {
+            Ok(mut stream) => {
+                while let Some(chunk) = stream.next().await {
+                    let Ok(StreamResponse { choices, usage }) = chunk else {
                         continue;
+                    };
+                    if let Some(usage) = usage {
+                        reported = Some(usage);
+                    }
+                    if let Some(content) = choices.first().and_then(|c| c.delta.content.as_deref())
+                    {
+                        self.delta(&mut meter, content);
                     }
                 }
-
-                let sz = terminal_size();
-                let term_cols = match sz { Some((Width(w), _)) => w as usize, None => 80 };
-
-                let (text, total_lines_to_clear) = {
-                    let mut buf = String::new();
-                    let darker_style = Style::new().fg(NuColor::Rgb(160, 160, 160));
-
-                    std::io::stdout().flush().ok();
-                    std::io::stderr().flush().ok();
-
-                    let mut lines_output = 0;
-
-                    let separator = "─".repeat(term_cols);
-                    let separator_style = Style::new().fg(NuColor::Rgb(100, 100, 100));
-                    println!("{}", separator_style.paint(&separator));
-                    lines_output += 1;
-                    std::io::stdout().flush().ok();
-
-                    let mut stream_lines = 0;
-                    let mut current_line_len = 0;
-
-                    match self.llm.chat_stream_struct(&self.history).await {
-                        Ok(mut stream) => {
-                            while let Some(chunk) = stream.next().await {
-                                match chunk {
-                                    Ok(StreamResponse { choices, .. }) => {
-                                        if let Some(delta) = choices.first().map(|c| &c.delta) {
-                                            if let Some(content) = &delta.content {
-                                                buf.push_str(content);
-                                                print!("{}", darker_style.paint(content));
-
-                                                for ch in content.chars() {
-                                                    if ch == '\n' {
-                                                        stream_lines += 1;
-                                                        current_line_len = 0;
-                                                    } else {
-                                                        current_line_len += 1;
-                                                        if current_line_len >= term_cols {
-                                                            stream_lines += 1;
-                                                            current_line_len = 0;
-                                                        }
-                                                    }
-                                                }
-
-                                                std::io::stdout().flush().ok();
-                                            }
-                                        }
-                                    }
-                                    Err(_e) => {}
-                                }
-                            }
-                            if current_line_len > 0 { stream_lines += 1; }
-                        }
-                        Err(_e) => {
-                            match self.llm.chat_stream(&self.history).await {
-                                Ok(mut stream) => {
-                                    while let Some(delta) = stream.next().await {
-                                        if let Ok(token) = delta {
-                                            buf.push_str(&token);
-                                            print!("{}", darker_style.paint(&token));
-
-                                            for ch in token.chars() {
-                                                if ch == '\n' {
-                                                    stream_lines += 1;
-                                                    current_line_len = 0;
-                                                } else {
-                                                    current_line_len += 1;
-                                                    if current_line_len >= term_cols {
-                                                        stream_lines += 1;
-                                                        current_line_len = 0;
-                                                    }
-                                                }
-                                            }
-
-                                            std::io::stdout().flush().ok();
-                                        }
-                                    }
-                                    if current_line_len > 0 { stream_lines += 1; }
-                                }
-                                Err(_e2) => {
-                                    eprintln!("Error: streaming failed");
-                                }
-                            }
+            }
+            Err(_) => match self.llm.chat_stream(&self.history).await {
+                Ok(mut stream) => {
+                    while let Some(token) = stream.next().await {
+                        if let Ok(token) = token {
+                            self.delta(&mut meter, &token);
                         }
                     }
+                }
+                Err(_) => eprintln!("Error: streaming failed"),
+            },
+        }
+        if let Some(usage) = &reported {
+            self.spend(usage);
+            self.frontend.event(Event::Usage(usage));
+        }
+        if let Some((tokens, rate, first_token)) =
+            meter.finish(reported.map(|u| u.completion_tokens))
+        {
+            if let Err(e) = crate::models::record_speed(
+                self.llm.active_name(),
+                self.llm.active_model(),
+                tokens,
+                rate,
+                first_token,
+            ) {
+                tracing::debug!("Failed to record speed: {}", e);
+            }
+        }
+        self.partial.clone()
+    }
 
-                    println!();
-                    let total = lines_output + stream_lines + 1;
-                    (buf, total)
-                };
+    fn spend(&mut self, usage: &Usage) {
+        self.spent.0 += u64::from(usage.prompt_tokens);
+        self.spent.1 += u64::from(usage.completion_tokens);
+    }
 
-                self.file_history
-                    .add_entry(input.to_string(), text.clone())?;
+    /// Show what the step took, if `[display] show_footer` asks for it.
+    fn footer(&mut self, started: Instant) {
+        if self.display.show_footer != Some(true) {
+            return;
+        }
+        let (input, output) = self.spent;
+        let footer = footer::Footer {
+            model: self.llm.active_model().to_string(),
+            seconds: started.elapsed().as_secs_f64(),
+            input_tokens: input,
+            output_tokens: output,
+            cost: footer::cost(
+                self.display.prices.as_ref(),
+                self.llm.active_name(),
+                self.llm.active_model(),
+                input,
+                output,
+            ),
+            tool_calls: self.tool_log.len(),
+        };
+        self.frontend.event(Event::Footer(&footer));
+    }
 
-                {
-                    if total_lines_to_clear > 0 {
-                        print!("\x1b[{}A", total_lines_to_clear);
-                        print!("\x1b[0J");
-                        std::io::stdout().flush().ok();
-                    }
+    fn delta(&mut self, meter: &mut meter::Meter, text: &str) {
+        meter.chunk();
+        self.partial.push_str(text);
+        let status = meter.due();
+        self.frontend.event(Event::ModelDelta {
+            text,
+            status: status.as_deref(),
+        });
+    }
 
-                    let mut printer = PrettyPrinter::new();
-                    printer
-                        .input_from_bytes(text.as_bytes())
-                        .language("markdown")
-                        .wrapping_mode(WrappingMode::Character)
-                        .paging_mode(PagingMode::Never)
-                        .term_width(term_cols)
-                        .use_italics(true)
-                        .grid(true)
-                        .line_numbers(false)
-                        .header(false)
-                        .theme("1337");
-
-                    let _ = printer.print();
-                }
+    /// The answer as it is shown and saved, after the `[filters]` that
+    /// apply to the model that gave it.
+    fn filter(&self, text: &str, input: &str) -> String {
+        filters::Filters::for_model(&self.filters, self.llm.active_model()).apply(text, input)
+    }
 
-                break;
-            }
-            Ok(())
+    /// Show text that did not come from the model in this step, as an
+    /// answer would be shown.
+    fn show(&mut self, text: &str) {
+        self.frontend.event(Event::AnswerReady {
+            text,
+            streamed: false,
+        });
+    }
+
+    /// After Ctrl-C cancelled a step: show that it stopped and keep what
+    /// was produced in the history.
+    pub fn interrupted(&mut self, input: &str) -> Result<()> {
+        self.frontend.event(Event::Interrupted {
+            text: &self.partial,
+            tool_calls: self.tool_log.len(),
+        });
+        if self.partial.is_empty() && self.tool_log.is_empty() {
+            return Ok(());
         }
-        .boxed_local()
+        let response = format!("{}\n\n(interrupted)", self.partial);
+        self.file_history
+            .add_entry(input.to_string(), response.trim_start().to_string())
     }
 
-    fn build_system_prompt(&mut self, contexts: &[(String, String)]) -> String {
-        let relevant_entries = self.file_history.get_relevant_entries();
+    /// The run ended as it should; there is nothing to resume.
+    fn complete_task(&mut self) {
+        if let Some(progress) = self.progress.take() {
+            progress.complete();
+        }
+    }
 
-        let mut history_context = String::new();
-        if !relevant_entries.is_empty() {
-            history_context.push_str("\nHere are some of your previous interactions (these may not be related to the current query and are just for reference):\n\n");
+    /// Leaves the last question and answer out of the history sent, and out
+    /// of the file once the new answer is saved.
+    pub fn forget_last(&mut self) {
+        self.file_history.entries.pop();
+    }
 
-            for (idx, (entry, age)) in relevant_entries.iter().enumerate() {
-                let minutes = age.num_minutes();
-                history_context.push_str(&format!(
-                    "Interaction {} (from {} minutes ago):\n",
-                    idx + 1,
-                    minutes
-                ));
-                history_context.push_str(&format!("User: {}\n", entry.user_input));
-                history_context.push_str(&format!("Assistant: {}\n\n", entry.llm_response));
-            }
+    /// Asks whether to keep going after `rounds` tool rounds, listing the
+    /// calls made so far. Without a terminal to ask on, the step fails.
+    async fn checkpoint(rounds: u32, done: &[String]) -> Result<Checkpoint> {
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!(
+                "Stopped after {} tool rounds ({} calls); raise core.max_tool_iterations to allow more",
+                rounds,
+                done.len()
+            );
         }
-
-        let mut context_section = String::new();
-        if !contexts.is_empty() && !self.context_added {
-            context_section.push_str("\n## Additional Context\n\n");
-            for (name, content) in contexts {
-                context_section.push_str(&format!("### Context from {}\n\n{}\n\n", name, content));
+        let shown = done.len().min(10);
+        eprintln!(
+            "{}",
+            NuColor::Yellow.paint(format!(
+                "The model has run {} tool rounds ({} calls) without answering:",
+                rounds,
+                done.len()
+            ))
+        );
+        if done.len() > shown {
+            eprintln!("  … {} earlier calls", done.len() - shown);
+        }
+        for line in &done[done.len() - shown..] {
+            eprintln!("  {}", line);
+        }
+        loop {
+            let choice =
+                ask_user_async("[c]ontinue, [a]bort, or [t]ake over with instructions? ".into())
+                    .await?;
+            match choice.as_str() {
+                "c" | "continue" => return Ok(Checkpoint::Continue),
+                "a" | "abort" | "" => return Ok(Checkpoint::Abort),
+                "t" | "take over" => {
+                    let message =
+                        tokio::task::spawn_blocking(|| ask_line("Instructions for the model: "))
+                            .await
+                            .context("Prompt task panicked")??;
+                    if message.is_empty() {
+                        return Ok(Checkpoint::Abort);
+                    }
+                    return Ok(Checkpoint::TakeOver(message));
+                }
+                _ => continue,
             }
-            self.context_added = true;
         }
+    }
 
-        #[cfg(target_os = "windows")]
-        let os = "Windows";
-        #[cfg(target_os = "linux")]
-        let os = "Linux";
-        #[cfg(target_os = "macos")]
-        let os = "Mac OS";
-
-        let sz = terminal_size();
-        let term_lines = match sz {
-            Some((_, Height(h))) => h as usize,
-            None => 50,
+    fn build_system_prompt(
+        &mut self,
+        contexts: &[(String, String)],
+        retrieved: Option<Retrieved>,
+    ) -> String {
+        let contexts = if self.context_added {
+            &[][..]
+        } else {
+            contexts
+        };
+        self.context_added = true;
+        let (excerpts, entries) = match retrieved {
+            Some(found) => (found.excerpts, found.entries),
+            None => (Vec::new(), self.file_history.get_relevant_entries()),
         };
-        let max_words = (term_lines - 6) * 16;
+        let parts = inspect::parts(self.instructions.as_deref(), contexts, &excerpts, &entries);
+        let kept: Vec<&inspect::Part> = parts
+            .iter()
+            .filter(|p| !self.dropped.contains(&p.id))
+            .collect();
+        std::mem::take(&mut self.dropped).consume();
+        let rules = match &self.custom_prompt {
+            Some(template) => custom_rules(template),
+            None => system_rules(&self.tools, &self.style),
+        };
+        format!(
+            "{}{}{}{}",
+            rules,
+            extra_rules(self.prompt_extra.as_deref()),
+            self.environment.as_deref().unwrap_or_default(),
+            inspect::assemble(&kept)
+        )
+    }
+}
+
+fn os_name() -> &'static str {
+    #[cfg(target_os = "windows")]
+    let os = "Windows";
+    #[cfg(target_os = "linux")]
+    let os = "Linux";
+    #[cfg(target_os = "macos")]
+    let os = "Mac OS";
+    os
+}
 
+/// The system prompt set with `--system` or `core.system_prompt`: the
+/// contents of the file it names, or else the text itself.
+fn custom_prompt(cfg: &Config) -> Option<String> {
+    let value = cfg.core.system_prompt.as_deref()?;
+    let path = std::path::Path::new(value);
+    if path.is_file() {
+        match std::fs::read_to_string(path) {
+            Ok(text) => return Some(text),
+            Err(e) => eprintln!("Warning: Failed to read {}: {}", path.display(), e),
+        }
+    }
+    Some(value.to_string())
+}
+
+/// A custom system prompt in place of the built-in rules, with its
+/// placeholders filled in.
+fn custom_rules(template: &str) -> String {
+    let cwd = std::env::current_dir()
+        .map(|d| d.display().to_string())
+        .unwrap_or_default();
+    let text = template
+        .replace("{{os}}", os_name())
+        .replace("{{shell}}", UserShell::detect().name())
+        .replace("{{cwd}}", &cwd)
+        .replace(
+            "{{date}}",
+            &chrono::Local::now().format("%Y-%m-%d").to_string(),
+        );
+    format!("{}\n\n", text.trim_end())
+}
+
+/// The fixed part of the system prompt; contexts and history follow it.
+fn system_rules(tools: &ToolsRegistry, style: &style::Style) -> String {
+    let user_shell = UserShell::detect();
+    let run_rule = if tools.find("run_shell").is_some() {
+        "If the user asks you to perform a terminal task, call the run_shell tool with the exact command to execute. Prefer pipes over multiple sequential commands when possible."
+    } else {
+        "You cannot run commands in this session. If the user asks for a terminal task, show the exact command for them to run. Prefer pipes over multiple sequential commands when possible."
+    };
+    let shell_rule = if user_shell.is_posix() {
+        String::new()
+    } else {
         format!(
-            r#"You are an AI assistant running in a terminal that can call tools to operate on the user's machine.
+            "\n- The user's interactive shell is {0}, but run_shell executes commands with POSIX sh, so write run_shell commands in sh syntax. Use {0} syntax only in commands you show the user to type themselves.",
+            user_shell.name()
+        )
+    };
+
+    let os = os_name();
+
+    let sz = terminal_size();
+    let term_lines = match sz {
+        Some((_, Height(h))) => h as usize,
+        None => 50,
+    };
+    let max_words = style.max_words((term_lines - 6) * 16);
+    let explain_rule = style.explain_rule();
+    let style_rule = style.extra_rule();
+
+    format!(
+        r#"You are an AI assistant running in a terminal that can call tools to operate on the user's machine.
 Your goal is to help the user achieve their task efficiently and safely.
 
 System rules:
-- If the user asks you to perform a terminal task, call the run_shell tool with the exact command to execute. Prefer pipes over multiple sequential commands when possible.
+- {run_rule}
 - Keep commands non-interactive, idempotent, and safe by default. Avoid destructive operations unless the user explicitly requests them.
-- The commands are being executed on {os}.
+- The commands are being executed on {os}.{shell_rule}
 - When executing a terminal command the user can already see the output of the command. Do NOT summarize or restate the command's output.
-- If the user is asking about a command (explanatory), answer concisely and include a one-line example, then a brief explanation of key flags.
+- {explain_rule}
 - After running a command via the tool, use its output to decide next steps. You may call tools multiple times until the task is complete.
 - Do not invent file paths or secrets. Never print sensitive values.
-- Keep your answer short and concise. Do not exceed {max_words} words!
+- Keep your answer short and concise. Do not exceed {max_words} words!{style_rule}
 - When you include code, always use fenced code blocks with a language identifier like ```rust, ```bash, ```python, etc. Avoid plain triple backticks without a language.
 - Always respond using Markdown syntax.
 
-{context_section}{history_context}"#
-        )
+"#
+    )
+}
+
+/// The configured additions to the system rules, as a section of their own.
+fn extra_rules(extra: Option<&str>) -> String {
+    match extra {
+        Some(text) => format!("Project rules:\n{}\n\n", text),
+        None => String::new(),
+    }
+}
+
+fn markdown_printer(text: &str, term_cols: usize, grid: bool) -> PrettyPrinter<'_> {
+    let mut printer = PrettyPrinter::new();
+    printer
+        .input_from_bytes(text.as_bytes())
+        .language("markdown")
+        .wrapping_mode(WrappingMode::Character)
+        .paging_mode(PagingMode::Never)
+        .term_width(term_cols)
+        .use_italics(true)
+        .grid(grid)
+        .line_numbers(false)
+        .header(false)
+        .colored_output(crate::output::color())
+        .theme(&theme::current().name);
+    printer
+}
+
+fn print_markdown(text: &str, term_cols: usize, grid: bool) {
+    let _ = markdown_printer(text, term_cols, grid).print();
+}
+
+/// An answer as it is rendered for the terminal, without printing it.
+pub fn render_markdown(text: &str, term_cols: usize) -> Result<String> {
+    let mut out = String::new();
+    markdown_printer(&fences::tag_bare_fences(text), term_cols, true)
+        .print_with_writer(Some(&mut out))
+        .map_err(|e| anyhow::anyhow!("Failed to render markdown: {}", e))?;
+    Ok(out)
+}
+
+/// The answer with each code block printed bare: no fences, borders, or
+/// wrapping, so selecting it copies exactly the code. A label above each
+/// block gives the number `tai copy` takes.
+fn print_copyable(text: &str, term_cols: usize) {
+    let rule = theme::current().separator().paint("─".repeat(term_cols));
+    println!("{}", rule);
+    let known = PrettyPrinter::new().syntaxes().collect::<Vec<_>>();
+    let mut index = 0;
+    for segment in fences::split(text) {
+        match segment {
+            fences::Segment::Prose(prose) => {
+                if !prose.trim().is_empty() {
+                    print_markdown(&prose, term_cols, false);
+                }
+            }
+            fences::Segment::Code(block) => {
+                index += 1;
+                let label = match &block.lang {
+                    Some(lang) => format!("[{}] {}", index, lang),
+                    None => format!("[{}]", index),
+                };
+                println!("{}", Style::new().dimmed().paint(label));
+                // Fence tags name languages loosely ("golang", "py"); bat
+                // knows them by name or file extension.
+                let lang = block
+                    .lang
+                    .as_deref()
+                    .map(|l| if l == "golang" { "go" } else { l });
+                let syntax = lang.filter(|l| {
+                    known.iter().any(|s| {
+                        s.name.eq_ignore_ascii_case(l) || s.file_extensions.iter().any(|e| e == l)
+                    })
+                });
+                let mut printer = PrettyPrinter::new();
+                printer
+                    .input_from_bytes(block.code.as_bytes())
+                    .wrapping_mode(WrappingMode::NoWrapping(true))
+                    .paging_mode(PagingMode::Never)
+                    .grid(false)
+                    .line_numbers(false)
+                    .header(false)
+                    .colored_output(crate::output::color())
+                    .theme(&theme::current().name);
+                if let Some(syntax) = syntax {
+                    printer.language(syntax);
+                }
+                let _ = printer.print();
+            }
+        }
     }
+    println!("{}", rule);
 }
 
-pub async fn run_chat(nocontext: bool, context: Option<String>, user_input: String) -> Result<()> {
-    let tools = ToolsRegistry::with_default();
-    let cfg = load_config().unwrap_or_default();
-    let eff = select_effective_provider(&cfg);
-    let llm = setup(&tools)?;
-    println!(
-        "Using provider {} (model: {}{})",
+/// Copy code block `index` (from 1) of the last answer to the clipboard,
+/// exactly as the model wrote it.
+pub fn copy_block(cfg: &Config, index: usize) -> Result<()> {
+    let history = History::load(&cfg.history)?;
+    let blocks = save::last_blocks(&history)?;
+    let block = index
+        .checked_sub(1)
+        .and_then(|i| blocks.get(i))
+        .with_context(|| {
+            format!(
+                "The last answer has {} code block{}",
+                blocks.len(),
+                if blocks.len() == 1 { "" } else { "s" }
+            )
+        })?;
+    to_clipboard(&block.code, &format!("block {}", index))
+}
+
+fn keep_answer(text: &str) {
+    *LAST_ANSWER.lock().unwrap_or_else(|e| e.into_inner()) = Some(text.to_string());
+}
+
+/// Copy the answer this run gave to the clipboard, for `--copy`.
+pub fn copy_answer() -> Result<()> {
+    let answer = LAST_ANSWER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .context("There is no answer to copy")?;
+    to_clipboard(answer.trim(), "the answer")
+}
+
+/// Write the answer this run gave to `path`, for `--output`.
+pub fn output_answer(path: &std::path::Path) -> Result<()> {
+    let answer = LAST_ANSWER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .context("There is no answer to save")?;
+    save::write_answer(path, &answer)
+}
+
+fn to_clipboard(text: &str, what: &str) -> Result<()> {
+    let mut cb = arboard::Clipboard::new().context("Failed to access the clipboard")?;
+    cb.set_text(text)
+        .context("Failed to copy to the clipboard")?;
+    let lines = text.lines().count();
+    say!(
+        "Copied {} ({} line{}) to the clipboard",
+        what,
+        lines,
+        if lines == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+/// Handle `/context ...` typed in place of a question.
+pub fn context_command(
+    cfg: &Config,
+    args: &str,
+    nocontext: bool,
+    context: Option<&str>,
+) -> Result<()> {
+    inspect::run(cfg, args, nocontext, context)
+}
+
+/// Fails when `[style]` names a preset that does not resolve.
+pub fn check_style(cfg: &StyleConfig) -> Result<()> {
+    style::Style::new(cfg).map(|_| ())
+}
+
+/// The command-line flags a chat runs with.
+#[derive(Clone)]
+pub struct ChatOptions {
+    pub nocontext: bool,
+    pub context: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub images: Vec<PathBuf>,
+    pub transcript: bool,
+    /// The only tools to offer, from `--tools` or `--no-tools`.
+    pub tools: Option<Vec<String>>,
+    /// The style preset from `--style`, over `[style]` in config.
+    pub style: Option<String>,
+    /// The profile from `--profile`, over `core.default_profile`.
+    pub profile: Option<String>,
+    /// The system prompt from `--system`, over `core.system_prompt`.
+    pub system: Option<String>,
+    /// `/retry`: the question is the last one in the history, and its new
+    /// answer replaces the old.
+    pub retry: bool,
+    /// The temperature from `/retry`, over the provider's.
+    pub temperature: Option<f32>,
+    /// `tai resume-task`: the run continues the interrupted task.
+    pub resume: bool,
+}
+
+/// Run after the model answers, e.g. a build. A failure report goes back to
+/// the model for another try, up to `attempts` times.
+pub struct Check {
+    pub attempts: u32,
+    pub run: Box<dyn Fn() -> Result<Option<String>>>,
+}
+
+/// Run one step; `true` when Ctrl-C cancelled it.
+async fn interruptible_step(
+    session: &mut Session<'_>,
+    input: &str,
+    attachments: &str,
+    images: Vec<ChatMessage>,
+    contexts: &[(String, String)],
+) -> Result<bool> {
+    // Ctrl-C drops the step, which cancels the request and kills any
+    // running command, instead of killing tai with it.
+    let interrupted = tokio::select! {
+        result = session.step(input, attachments, images, contexts) => {
+            result?;
+            false
+        }
+        _ = tokio::signal::ctrl_c() => true,
+    };
+    if interrupted {
+        session.interrupted(input)?;
+    }
+    Ok(interrupted)
+}
+
+fn exit_interrupted(session: Session) -> ! {
+    // Stops background jobs before exiting.
+    drop(session);
+    crate::scratch::remove();
+    std::process::exit(130);
+}
+
+/// What a chat starts from: the config, the client, and the system prompt
+/// parts the flags and `/context drop` leave in.
+struct Prepared {
+    cfg: Config,
+    eff: EffectiveProvider,
+    llm: Failover,
+    tools: ToolsRegistry,
+    instructions: Option<String>,
+    dropped: inspect::Dropped,
+    contexts: Vec<(String, String)>,
+}
+
+fn prepare(mut cfg: Config, opts: &ChatOptions) -> Result<Prepared> {
+    handle_interrupts();
+    let profile = apply_profile(&mut cfg, opts.profile.as_deref())?;
+    theme::init(&cfg.ui);
+    if let Some(names) = &opts.tools {
+        cfg.tools.enabled = Some(names.clone());
+        cfg.tools.disabled = None;
+    }
+    if opts.style.is_some() {
+        cfg.style.preset = opts.style.clone();
+    }
+    if opts.system.is_some() {
+        cfg.core.system_prompt = opts.system.clone();
+    }
+    style::Style::new(&cfg.style)?;
+    if opts.transcript || cfg.logging.transcripts == Some(true) {
+        match transcript::start() {
+            Ok(path) => say!("Writing transcript to {}", path.display()),
+            Err(e) => eprintln!("Warning: Transcript disabled: {:#}", e),
+        }
+    }
+    let tools = ToolsRegistry::with_default(&cfg);
+    if let Some(unknown) = opts
+        .tools
+        .iter()
+        .flatten()
+        .find(|name| name.as_str() != "read-only" && tools.find(name).is_none())
+    {
+        anyhow::bail!("No tool named '{}' here", unknown);
+    }
+    let mut eff =
+        resolve_effective_provider(&cfg, opts.provider.as_deref(), opts.model.as_deref())?;
+    if let Some(temperature) = opts.temperature {
+        eff.temperature = temperature;
+    }
+    let llm = Failover::new(&cfg, &tools, &eff)?;
+    say!(
+        "Using provider {} (model: {}{}{})",
         eff.name,
         eff.model,
         eff.base_url_or_host
             .as_ref()
             .map(|u| format!("; base: {}", u))
+            .unwrap_or_default(),
+        profile
+            .map(|p| format!("; profile: {}", p))
             .unwrap_or_default()
     );
-    let mut session = Session::new(llm.as_ref(), tools);
+    let dropped = inspect::Dropped::load();
+    let instructions = if opts.nocontext || dropped.contains("instructions") {
+        None
+    } else {
+        find_project_instructions()
+    };
+    if let Some((path, _)) = &instructions {
+        say!("Using project instructions: {}", path.display());
+    }
 
-    let contexts = if nocontext {
+    let mut contexts = if opts.nocontext {
         Vec::new()
     } else {
-        find_context_files(context.as_deref()).unwrap_or_else(|e| {
+        find_context_files(&cfg, opts.context.as_deref()).unwrap_or_else(|e| {
             eprintln!("Warning: Failed to load context files: {}", e);
             Vec::new()
         })
     };
+    contexts.retain(|(name, _)| !dropped.contains(&format!("context:{}", name)));
 
     if !contexts.is_empty() {
         let context_names: Vec<&str> = contexts.iter().map(|(name, _)| name.as_str()).collect();
-        println!("Using context files: [{}]", context_names.join(", "));
+        say!("Using context files: [{}]", context_names.join(", "));
+    }
+    Ok(Prepared {
+        cfg,
+        eff,
+        llm,
+        tools,
+        instructions: instructions.map(|(_, content)| content),
+        dropped,
+        contexts,
+    })
+}
+
+/// The last question in the history, for `/retry`.
+pub fn last_question(cfg: &Config) -> Result<String> {
+    History::load(&cfg.history)?
+        .entries
+        .pop()
+        .map(|entry| entry.user_input)
+        .context("No questions in the history yet")
+}
+
+pub async fn run_chat(
+    cfg: Config,
+    opts: ChatOptions,
+    user_input: String,
+    check: Option<Check>,
+) -> Result<()> {
+    let Prepared {
+        cfg,
+        eff,
+        llm,
+        tools,
+        instructions,
+        dropped,
+        contexts,
+    } = prepare(cfg, &opts)?;
+    let mut session = Session::new(&llm, tools, &cfg, instructions, dropped);
+    if opts.retry {
+        session.forget_last();
+    }
+    session.progress = Some(task::Progress::new(&user_input, opts.resume));
+
+    let attachments = attach::expand(&user_input);
+    let image_paths: Vec<PathBuf> = opts
+        .images
+        .into_iter()
+        .chain(attachments.images.iter().cloned())
+        .collect();
+    let loaded = image_paths
+        .iter()
+        .map(|p| images::load(p))
+        .collect::<Result<Vec<_>>>()?;
+    let image_messages = images::messages(&cfg, &eff, loaded)?;
+    let attached: Vec<String> = attachments
+        .files
+        .iter()
+        .cloned()
+        .chain(image_paths.iter().map(|p| p.display().to_string()))
+        .collect();
+    if !attached.is_empty() {
+        say!("Attached: {}", attached.join(", "));
     }
+    if !attachments.skipped.is_empty() {
+        let shown = attachments.skipped.len().min(5);
+        let more = attachments.skipped.len() - shown;
+        eprintln!(
+            "Warning: Not attached (binary, unreadable, or over the size limit): {}{}",
+            attachments.skipped[..shown].join(", "),
+            if more > 0 {
+                format!(" and {} more", more)
+            } else {
+                String::new()
+            }
+        );
+    }
+    if !attachments.unmatched.is_empty() {
+        eprintln!(
+            "Warning: No files match {}",
+            attachments
+                .unmatched
+                .iter()
+                .map(|t| format!("@{}", t))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    if interruptible_step(
+        &mut session,
+        &user_input,
+        &attachments.text,
+        image_messages,
+        &contexts,
+    )
+    .await?
+    {
+        exit_interrupted(session);
+    }
+
+    let Some(check) = check else {
+        session.complete_task();
+        return Ok(());
+    };
+    for attempt in 1..=check.attempts {
+        let Some(failure) = (check.run)()? else {
+            session.complete_task();
+            return Ok(());
+        };
+        if attempt == check.attempts {
+            break;
+        }
+        if interruptible_step(&mut session, &failure, "", Vec::new(), &contexts).await? {
+            exit_interrupted(session);
+        }
+    }
+    anyhow::bail!(
+        "Still failing after {} attempt{}",
+        check.attempts,
+        if check.attempts == 1 { "" } else { "s" }
+    )
+}
 
-    session.step(&user_input, &contexts).await
+/// Answer `input` with tools, for `tai serve`: the events `--json` would
+/// print go to `events` instead. Approvals are still asked in the terminal
+/// tai runs in.
+pub async fn run_agent(
+    cfg: Config,
+    opts: ChatOptions,
+    input: String,
+    events: UnboundedSender<JsonValue>,
+) -> Result<()> {
+    let Prepared {
+        cfg,
+        llm,
+        tools,
+        instructions,
+        dropped,
+        contexts,
+        ..
+    } = prepare(cfg, &opts)?;
+    let mut session = Session::new(&llm, tools, &cfg, instructions, dropped);
+    session.frontend = Box::new(frontend::Channel(events));
+    session.step(&input, "", Vec::new(), &contexts).await
 }
@@ -0,0 +1,254 @@
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use futures::Stream;
+use llm::builder::{LLMBackend, LLMBuilder};
+use llm::chat::{ChatMessage, ChatResponse, StreamResponse};
+use llm::error::LLMError;
+use llm::LLMProvider;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use crate::config::{Config, EffectiveProvider};
+use crate::tools::ToolsRegistry;
+use crate::{net, relay};
+
+pub type ChatStream<T> = Pin<Box<dyn Stream<Item = Result<T, LLMError>> + Send>>;
+
+/// A model backend, as tai uses one. Clients built with the llm crate are
+/// one implementation; a backend it does not support, or a stand-in, is
+/// another. Errors stay `LLMError` so retries can tell transient failures
+/// from the rest.
+pub trait Provider: Send + Sync {
+    fn chat<'a>(
+        &'a self,
+        messages: &'a [ChatMessage],
+    ) -> BoxFuture<'a, Result<Box<dyn ChatResponse>, LLMError>>;
+
+    /// A chat turn with the tools the provider was built with.
+    fn chat_with_tools<'a>(
+        &'a self,
+        messages: &'a [ChatMessage],
+    ) -> BoxFuture<'a, Result<Box<dyn ChatResponse>, LLMError>>;
+
+    /// The answer as it is generated, with usage in the last chunk where
+    /// the provider reports it.
+    fn chat_stream_struct<'a>(
+        &'a self,
+        messages: &'a [ChatMessage],
+    ) -> BoxFuture<'a, Result<ChatStream<StreamResponse>, LLMError>>;
+
+    /// The answer as plain text chunks, for providers without
+    /// `chat_stream_struct`.
+    fn chat_stream<'a>(
+        &'a self,
+        messages: &'a [ChatMessage],
+    ) -> BoxFuture<'a, Result<ChatStream<String>, LLMError>>;
+
+    fn embed(&self, texts: Vec<String>) -> BoxFuture<'_, Result<Vec<Vec<f32>>, LLMError>>;
+}
+
+/// A client from the llm crate.
+struct LlmClient(Box<dyn LLMProvider>);
+
+impl Provider for LlmClient {
+    fn chat<'a>(
+        &'a self,
+        messages: &'a [ChatMessage],
+    ) -> BoxFuture<'a, Result<Box<dyn ChatResponse>, LLMError>> {
+        self.0.chat(messages)
+    }
+
+    fn chat_with_tools<'a>(
+        &'a self,
+        messages: &'a [ChatMessage],
+    ) -> BoxFuture<'a, Result<Box<dyn ChatResponse>, LLMError>> {
+        self.0.chat_with_tools(messages, self.0.tools())
+    }
+
+    fn chat_stream_struct<'a>(
+        &'a self,
+        messages: &'a [ChatMessage],
+    ) -> BoxFuture<'a, Result<ChatStream<StreamResponse>, LLMError>> {
+        self.0.chat_stream_struct(messages)
+    }
+
+    fn chat_stream<'a>(
+        &'a self,
+        messages: &'a [ChatMessage],
+    ) -> BoxFuture<'a, Result<ChatStream<String>, LLMError>> {
+        self.0.chat_stream(messages)
+    }
+
+    fn embed(&self, texts: Vec<String>) -> BoxFuture<'_, Result<Vec<Vec<f32>>, LLMError>> {
+        self.0.embed(texts)
+    }
+}
+
+/// A client the daemon built before forking, and what it was built from.
+static WARM: Mutex<Option<(String, Box<dyn Provider>)>> = Mutex::new(None);
+
+/// Environment variables a client reads when it is built.
+const BUILD_VARS: &[&str] = &[
+    "ANTHROPIC_API_KEY",
+    "OPENAI_API_KEY",
+    "GEMINI_API_KEY",
+    "OPENROUTER_API_KEY",
+    "HTTP_PROXY",
+    "http_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+    "NO_PROXY",
+    "no_proxy",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+];
+
+/// Everything `build_llm` builds a client from, so a warm client is only
+/// used for a run that would have built the same one.
+fn fingerprint(tools: &ToolsRegistry, eff: &EffectiveProvider) -> String {
+    let mut key = format!(
+        "{}\n{}\n{:?}\n{}\n{:?}",
+        eff.name, eff.model, eff.temperature, eff.max_tokens, eff.base_url_or_host
+    );
+    for (name, about) in tools.describe() {
+        key.push_str(&format!("\n{}: {}", name, about));
+    }
+    for var in BUILD_VARS {
+        key.push_str(&format!("\n{}={:?}", var, std::env::var(var).ok()));
+    }
+    key
+}
+
+/// Build the client `setup` would for `eff` and keep it for a run forked
+/// later, which takes it instead of building its own. Providers that need
+/// the header relay are skipped; the relay runs in the run itself.
+pub fn warm_client(cfg: &Config, tools: &ToolsRegistry, eff: &EffectiveProvider) -> Result<()> {
+    if relay::headers(cfg, &eff.name)?.is_some() {
+        return Ok(());
+    }
+    let warm = net::with_provider_env(cfg, &eff.name, || {
+        Ok((fingerprint(tools, eff), build_llm(tools, eff)?))
+    })?;
+    *WARM.lock().unwrap_or_else(|e| e.into_inner()) = Some(warm);
+    Ok(())
+}
+
+/// The warm client, when it was built for exactly this.
+fn take_warm(tools: &ToolsRegistry, eff: &EffectiveProvider) -> Option<Box<dyn Provider>> {
+    let mut warm = WARM.lock().unwrap_or_else(|e| e.into_inner());
+    match warm.take() {
+        Some((key, llm)) if key == fingerprint(tools, eff) => Some(llm),
+        _ => None,
+    }
+}
+
+/// The provider `eff` names, with `tools` offered to the model, reached
+/// through the configured proxy and, when it needs extra headers, the
+/// relay.
+pub fn setup(
+    cfg: &Config,
+    tools: &ToolsRegistry,
+    eff: &EffectiveProvider,
+) -> Result<Box<dyn Provider>> {
+    let Some(headers) = relay::headers(cfg, &eff.name)? else {
+        return net::with_provider_env(cfg, &eff.name, || match take_warm(tools, eff) {
+            Some(llm) => Ok(llm),
+            None => build_llm(tools, eff),
+        });
+    };
+    // Only clients built on a base URL can be pointed at the relay.
+    let target = match (eff.name.as_str(), &eff.base_url_or_host) {
+        ("anthropic" | "gemini", _) => anyhow::bail!(
+            "providers.{}.headers is not supported: the {} client has a fixed endpoint. Reach the gateway through the openai provider's base_url instead",
+            eff.name,
+            eff.name
+        ),
+        (_, Some(base)) => base.clone(),
+        (_, None) => "https://api.openai.com/v1/".to_string(),
+    };
+    let mut relayed = eff.clone();
+    relayed.base_url_or_host = Some(relay::start(cfg, &eff.name, &target, headers)?);
+    tracing::debug!(provider = %eff.name, target = %target, "relaying requests to add headers");
+    net::with_provider_env(cfg, &eff.name, || build_llm(tools, &relayed))
+}
+
+fn build_llm(tools: &ToolsRegistry, eff: &EffectiveProvider) -> Result<Box<dyn Provider>> {
+    let mut builder = LLMBuilder::new();
+    let is_openai_gpt5 =
+        eff.name == "openai" && (eff.model.starts_with("gpt-5") || eff.model.starts_with("gpt-5-"));
+    if !is_openai_gpt5 {
+        builder = builder.temperature(eff.temperature);
+        builder = builder.max_tokens(eff.max_tokens);
+    }
+    let builder = tools.apply_to_builder(builder);
+
+    let llm = match eff.name.as_str() {
+        "anthropic" => {
+            let key = std::env::var("ANTHROPIC_API_KEY").unwrap_or_default();
+            builder
+                .backend(LLMBackend::Anthropic)
+                .api_key(key)
+                .model(&eff.model)
+                .build()
+                .context("Failed to build Anthropic Client")
+        }
+        "openai" => {
+            let key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
+            let mut b = builder
+                .backend(LLMBackend::OpenAI)
+                .api_key(key)
+                .model(&eff.model);
+            if let Some(base) = eff.base_url_or_host.clone() {
+                b = b.base_url(base);
+            }
+            b.build().context("Failed to build OpenAI Client")
+        }
+        "gemini" => {
+            let key = std::env::var("GEMINI_API_KEY").unwrap_or_default();
+            builder
+                .backend(LLMBackend::Google)
+                .api_key(key)
+                .model(&eff.model)
+                .build()
+                .context("Failed to build Gemini Client")
+        }
+        "openrouter" => {
+            let key = std::env::var("OPENROUTER_API_KEY").unwrap_or_default();
+            let mut b = builder
+                .backend(LLMBackend::OpenRouter)
+                .api_key(key)
+                .model(&eff.model);
+            if let Some(base) = eff.base_url_or_host.clone() {
+                b = b.base_url(base);
+            }
+            b.build().context("Failed to build OpenRouter Client")
+        }
+        "ollama" => {
+            let mut b = builder.backend(LLMBackend::Ollama).model(&eff.model);
+            if let Some(host) = eff.base_url_or_host.clone() {
+                b = b.base_url(host);
+            }
+            b.build().context("Failed to build Ollama Client")
+        }
+        "lmstudio" => {
+            let key = std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "lm-studio".into());
+            let mut b = builder
+                .backend(LLMBackend::OpenAI)
+                .api_key(key)
+                .model(&eff.model);
+            if let Some(base) = eff.base_url_or_host.clone() {
+                b = b.base_url(base);
+            }
+            b.build()
+                .context("Failed to build LM Studio (OpenAI compat) Client")
+        }
+        _ => builder
+            .backend(LLMBackend::Ollama)
+            .model(&eff.model)
+            .build()
+            .context("Failed to build Ollama Client"),
+    }?;
+    Ok(Box::new(LlmClient(llm)))
+}
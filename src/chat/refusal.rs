@@ -0,0 +1,68 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Longer answers that open with a refusal usually go on to help anyway.
+const MAX_REFUSAL_CHARS: usize = 400;
+
+/// What providers put in the error when their content filter blocks a
+/// request: Azure and OpenAI, Gemini, OpenRouter's moderation, and
+/// Anthropic.
+const FILTER_MARKERS: &[&str] = &[
+    "content_filter",
+    "content_policy_violation",
+    "responsibleaipolicyviolation",
+    "blockreason",
+    "prohibited_content",
+    "\"safety\"",
+    "flagged by moderation",
+    "flagged for moderation",
+    "output blocked by content filtering",
+];
+
+/// An answer that only declines.
+static DECLINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)^\W*(?:(?:i'?m|i am) (?:really |very )?sorry|sorry|i apologi[sz]e|unfortunately)?[,.!]?\s*(?:but\s+)?(?:i|i'm|i am)\s+(?:can(?:no|')?t|cannot|won'?t|will not|(?:am |'m )?(?:not able|unable) to|must decline to)\s+(?:help|assist|provide|comply|do that|fulfil|fulfill|support|create|generate|write|answer|share)",
+    )
+    .expect("valid regex")
+});
+
+/// The provider did not answer the question.
+pub(super) struct Refusal {
+    pub(super) reason: &'static str,
+    /// What the provider said instead, if anything.
+    pub(super) text: String,
+}
+
+/// A refusal in `text`, the whole answer to a request that called no
+/// tools. An empty answer counts: Anthropic's refusal stop reason, OpenAI's
+/// `refusal` field, and Gemini's safety finish all arrive as one.
+pub(super) fn in_answer(text: &str) -> Option<Refusal> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(Refusal {
+            reason: "it returned an empty answer, which is how filtered answers arrive",
+            text: String::new(),
+        });
+    }
+    if text.chars().count() <= MAX_REFUSAL_CHARS && DECLINE.is_match(text) {
+        return Some(Refusal {
+            reason: "the model declined",
+            text: text.to_string(),
+        });
+    }
+    None
+}
+
+/// A refusal in the error a request failed with.
+pub(super) fn in_error(err: &anyhow::Error) -> Option<Refusal> {
+    let message = format!("{:#}", err);
+    let lower = message.to_lowercase();
+    FILTER_MARKERS
+        .iter()
+        .any(|marker| lower.contains(marker))
+        .then_some(Refusal {
+            reason: "its content filter blocked the request",
+            text: message,
+        })
+}
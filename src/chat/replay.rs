@@ -0,0 +1,289 @@
+use anyhow::{bail, Context, Result};
+use nu_ansi_term::{Color as NuColor, Style};
+use serde_json::Value;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use super::frontend::term_cols;
+use super::render_markdown;
+use crate::config::{get_global_config_dir, Config};
+use crate::tools::{ask_line, units};
+
+/// Tool arguments and results are cut to this many characters until
+/// expanded.
+const FOLDED_CHARS: usize = 120;
+
+/// One request to the provider, what came back, and the tools it ran.
+#[derive(Default)]
+struct Turn {
+    time: String,
+    provider: String,
+    model: String,
+    call: String,
+    /// The user's message, when it is not the one of the turn before.
+    question: Option<String>,
+    thinking: Option<String>,
+    answer: Option<String>,
+    calls: Vec<Value>,
+    tools: Vec<Value>,
+    errors: Vec<String>,
+    usage: Option<(u64, u64)>,
+}
+
+/// The transcript `id` names: a file in `~/.config/tai/logs` by its name or
+/// the start of it, "last" for the newest, or a path.
+fn find(id: &str) -> Result<PathBuf> {
+    if Path::new(id).is_file() {
+        return Ok(PathBuf::from(id));
+    }
+    let dir = get_global_config_dir()?.join("logs");
+    let mut names: Vec<String> = std::fs::read_dir(&dir)
+        .with_context(|| format!("No transcripts in {}", dir.display()))?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            e.file_name()
+                .to_str()?
+                .strip_suffix(".jsonl")
+                .map(String::from)
+        })
+        .collect();
+    names.sort();
+    let found: Vec<&String> = if id == "last" {
+        names.last().into_iter().collect()
+    } else {
+        names.iter().filter(|name| name.starts_with(id)).collect()
+    };
+    match found.as_slice() {
+        [name] => Ok(dir.join(format!("{}.jsonl", name))),
+        [] => bail!("No transcript '{}' in {}", id, dir.display()),
+        many => bail!(
+            "'{}' matches {} transcripts: {}",
+            id,
+            many.len(),
+            many.iter()
+                .map(|n| n.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// The text of the last user message that is not a tool result.
+fn question(request: &Value) -> Option<String> {
+    request["messages"]
+        .as_array()?
+        .iter()
+        .rev()
+        .find(|m| m["role"] == "user" && m.get("tool_result").is_none())
+        .and_then(|m| m["content"].as_str())
+        .map(String::from)
+}
+
+fn turns(text: &str) -> Vec<Turn> {
+    let mut turns: Vec<Turn> = Vec::new();
+    let mut asked: Option<String> = None;
+    for entry in text
+        .lines()
+        .filter_map(|l| serde_json::from_str::<Value>(l).ok())
+    {
+        let field = |key: &str| entry[key].as_str().unwrap_or_default().to_string();
+        if entry["type"] == "request" {
+            let question = question(&entry).filter(|q| asked.as_ref() != Some(q));
+            if question.is_some() {
+                asked = question.clone();
+            }
+            turns.push(Turn {
+                time: field("time"),
+                provider: field("provider"),
+                model: field("model"),
+                call: field("call"),
+                question,
+                ..Default::default()
+            });
+            continue;
+        }
+        if turns.is_empty() {
+            turns.push(Turn {
+                time: field("time"),
+                ..Default::default()
+            });
+        }
+        let turn = turns.last_mut().expect("a turn was pushed");
+        match entry["type"].as_str() {
+            Some("response") => {
+                turn.answer = entry["text"].as_str().map(String::from);
+                turn.thinking = entry["thinking"].as_str().map(String::from);
+                turn.calls = entry["tool_calls"].as_array().cloned().unwrap_or_default();
+                let usage = &entry["usage"];
+                if let Some(input) = usage["prompt_tokens"].as_u64() {
+                    turn.usage = Some((input, usage["completion_tokens"].as_u64().unwrap_or(0)));
+                }
+            }
+            Some("tool") => turn.tools.push(entry),
+            Some("error") => turn.errors.push(format!(
+                "attempt {} on {} failed: {}",
+                entry["attempt"],
+                field("provider"),
+                field("error")
+            )),
+            _ => {}
+        }
+    }
+    turns
+}
+
+/// `value` on one line, cut to [`FOLDED_CHARS`] unless `expanded`.
+fn fold(value: &Value, expanded: bool) -> String {
+    if expanded {
+        return match value {
+            Value::String(s) => s.clone(),
+            other => serde_json::to_string_pretty(other).unwrap_or_default(),
+        };
+    }
+    let line = match value {
+        Value::String(s) => s.split_whitespace().collect::<Vec<_>>().join(" "),
+        other => other.to_string(),
+    };
+    match line.char_indices().nth(FOLDED_CHARS) {
+        Some((at, _)) => format!("{}…", &line[..at]),
+        None => line,
+    }
+}
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("    {}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn show(turn: &Turn, number: usize, total: usize, expanded: bool) {
+    let dim = Style::new().dimmed();
+    let time = chrono::DateTime::parse_from_rfc3339(&turn.time)
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|_| turn.time.clone());
+    println!(
+        "{}",
+        Style::new().bold().paint(format!(
+            "Turn {} of {} · {} · {} {}{}",
+            number,
+            total,
+            time,
+            turn.provider,
+            turn.model,
+            if turn.call.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", turn.call)
+            }
+        ))
+    );
+    if let Some(question) = &turn.question {
+        println!("{} {}", NuColor::Cyan.bold().paint(">"), question.trim());
+    }
+    for error in &turn.errors {
+        println!("{}", NuColor::Red.paint(error));
+    }
+    if let Some(thinking) = turn.thinking.as_deref().filter(|t| !t.trim().is_empty()) {
+        println!("{}", dim.paint(fold(&Value::from(thinking), expanded)));
+    }
+    if let Some(answer) = turn.answer.as_deref().filter(|a| !a.trim().is_empty()) {
+        match render_markdown(answer, term_cols()) {
+            Ok(rendered) => print!("{}", rendered),
+            Err(_) => println!("{}", answer),
+        }
+    }
+    for call in &turn.calls {
+        println!(
+            "{} {} {}",
+            NuColor::Yellow.paint("→"),
+            call["name"].as_str().unwrap_or("?"),
+            dim.paint(fold(&call["arguments"], expanded))
+        );
+    }
+    for tool in &turn.tools {
+        let took = units::duration(tool["elapsed_ms"].as_f64().unwrap_or(0.0) / 1000.0);
+        let name = tool["name"].as_str().unwrap_or("?");
+        match tool.get("error") {
+            Some(error) => println!(
+                "{} {} ({}): {}",
+                NuColor::Red.paint("✗"),
+                name,
+                took,
+                NuColor::Red.paint(fold(error, expanded))
+            ),
+            None => {
+                let result = fold(&tool["result"], expanded);
+                if expanded {
+                    println!("{} {} ({})", NuColor::Green.paint("✓"), name, took);
+                    println!("{}", dim.paint(indent(&result)));
+                } else {
+                    println!(
+                        "{} {} ({}) {}",
+                        NuColor::Green.paint("✓"),
+                        name,
+                        took,
+                        dim.paint(result)
+                    );
+                }
+            }
+        }
+    }
+    if let Some((input, output)) = turn.usage {
+        println!(
+            "{}",
+            dim.paint(format!(
+                "{} in / {} out",
+                units::count(input),
+                units::count(output)
+            ))
+        );
+    }
+    println!();
+}
+
+/// `tai session replay <id>`: a transcript turn by turn. On a terminal it
+/// waits for a key after each turn; otherwise every turn is printed in full.
+pub fn replay(cfg: &Config, id: &str) -> Result<()> {
+    super::theme::init(&cfg.ui);
+    let path = find(id)?;
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let turns = turns(&text);
+    if turns.is_empty() {
+        bail!("{} has no requests in it", path.display());
+    }
+    println!(
+        "{}\n",
+        Style::new().dimmed().paint(path.display().to_string())
+    );
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        for (i, turn) in turns.iter().enumerate() {
+            show(turn, i + 1, turns.len(), true);
+        }
+        return Ok(());
+    }
+    let mut at = 0;
+    let mut expanded = false;
+    loop {
+        show(&turns[at], at + 1, turns.len(), expanded);
+        let key = ask_line(&format!(
+            "{} ",
+            Style::new()
+                .dimmed()
+                .paint("[Enter] next  [p] previous  [e] expand/fold  [N] turn N  [q] quit:")
+        ))?;
+        match key.as_str() {
+            "" | "n" if at + 1 == turns.len() => break,
+            "" | "n" => at += 1,
+            "p" => at = at.saturating_sub(1),
+            "e" => expanded = !expanded,
+            "q" => break,
+            other => match other.parse::<usize>() {
+                Ok(n) if (1..=turns.len()).contains(&n) => at = n - 1,
+                _ => eprintln!("Warning: No turn '{}'; there are {}", other, turns.len()),
+            },
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,281 @@
+use anyhow::{bail, Context, Result};
+use nu_ansi_term::Color as NuColor;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::fences::{self, Block};
+use crate::config::Config;
+use crate::history::History;
+use crate::say;
+use crate::tools::ask_user;
+
+/// Unchanged lines shown around each change in the overwrite preview.
+const CONTEXT_LINES: usize = 3;
+
+/// Above this many line pairs the changed middle is shown as replaced
+/// wholesale rather than diffed.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// The file extension for a fence tag; unknown tags get `.txt`.
+fn extension(lang: Option<&str>) -> &'static str {
+    let lang = lang.unwrap_or_default().to_ascii_lowercase();
+    match lang.as_str() {
+        "rust" | "rs" => "rs",
+        "python" | "py" | "python3" => "py",
+        "bash" | "sh" | "shell" | "zsh" | "console" => "sh",
+        "fish" => "fish",
+        "powershell" | "ps1" | "pwsh" => "ps1",
+        "javascript" | "js" | "node" => "js",
+        "typescript" | "ts" => "ts",
+        "tsx" => "tsx",
+        "jsx" => "jsx",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "ini" => "ini",
+        "sql" => "sql",
+        "c" | "h" => "c",
+        "cpp" | "c++" | "cc" | "hpp" => "cpp",
+        "csharp" | "cs" | "c#" => "cs",
+        "java" => "java",
+        "kotlin" | "kt" => "kt",
+        "go" | "golang" => "go",
+        "ruby" | "rb" => "rb",
+        "php" => "php",
+        "swift" => "swift",
+        "lua" => "lua",
+        "perl" | "pl" => "pl",
+        "html" => "html",
+        "xml" => "xml",
+        "css" => "css",
+        "scss" => "scss",
+        "markdown" | "md" => "md",
+        "diff" | "patch" => "diff",
+        "hcl" | "terraform" | "tf" => "tf",
+        "makefile" | "make" => "mk",
+        "dockerfile" | "docker" => "dockerfile",
+        _ => "txt",
+    }
+}
+
+/// The file name a block gets when none is given: `block-N.<ext>`.
+fn default_name(index: usize, block: &Block) -> String {
+    format!("block-{}.{}", index, extension(block.lang.as_deref()))
+}
+
+/// The code blocks of the last answer in the history.
+pub(super) fn last_blocks(history: &History) -> Result<Vec<Block<'_>>> {
+    let last = history
+        .entries
+        .last()
+        .context("No answers in the history yet")?;
+    let blocks = fences::blocks(&last.llm_response);
+    if blocks.is_empty() {
+        bail!("The last answer has no code blocks");
+    }
+    Ok(blocks)
+}
+
+enum Op<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// A line diff of `old` against `new`: the longest common subsequence of
+/// the lines between their common prefix and suffix.
+fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Op<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let mut ops: Vec<Op> = old[..prefix].iter().map(|l| Op::Same(l)).collect();
+    if a.len() * b.len() > MAX_DIFF_CELLS {
+        ops.extend(a.iter().map(|l| Op::Removed(l)));
+        ops.extend(b.iter().map(|l| Op::Added(l)));
+    } else {
+        // lcs[i][j]: common lines of a[i..] and b[j..].
+        let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                ops.push(Op::Same(a[i]));
+                i += 1;
+                j += 1;
+            } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                ops.push(Op::Removed(a[i]));
+                i += 1;
+            } else {
+                ops.push(Op::Added(b[j]));
+                j += 1;
+            }
+        }
+    }
+    ops.extend(old[old.len() - suffix..].iter().map(|l| Op::Same(l)));
+    ops
+}
+
+/// Print what overwriting `old` with `new` changes, with a few unchanged
+/// lines around each change.
+fn print_diff(old: &str, new: &str) {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let ops = diff(&old, &new);
+    let changed: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+    let near_change = |i: usize| changed.iter().any(|&c| c.abs_diff(i) <= CONTEXT_LINES);
+    let mut skipped = false;
+    for (i, op) in ops.iter().enumerate() {
+        match op {
+            Op::Same(line) if near_change(i) => println!("  {}", line),
+            Op::Same(_) => {
+                if !skipped {
+                    println!("{}", NuColor::DarkGray.paint("  …"));
+                }
+                skipped = true;
+                continue;
+            }
+            Op::Removed(line) => println!("{}", NuColor::Red.paint(format!("- {}", line))),
+            Op::Added(line) => println!("{}", NuColor::Green.paint(format!("+ {}", line))),
+        }
+        skipped = false;
+    }
+}
+
+/// Write `code` to `path`. An existing file with other content is only
+/// replaced after its diff is shown and the user agrees.
+fn write_block(path: &Path, code: &str) -> Result<bool> {
+    match fs::read_to_string(path) {
+        Ok(old) if old == code => {
+            println!("{} is unchanged", path.display());
+            return Ok(false);
+        }
+        Ok(old) => {
+            println!("{} exists:", path.display());
+            print_diff(&old, code);
+            if ask_user(&format!("Overwrite {}? [y/N] ", path.display()))? != "y" {
+                println!("Skipped {}", path.display());
+                return Ok(false);
+            }
+        }
+        Err(_) if path.exists() => bail!("{} exists and is not a text file", path.display()),
+        Err(_) => {}
+    }
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(path, code).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(true)
+}
+
+fn saved(path: &Path, code: &str) {
+    let lines = code.lines().count();
+    println!(
+        "Saved {} ({} line{})",
+        path.display(),
+        lines,
+        if lines == 1 { "" } else { "s" }
+    );
+}
+
+/// `tai save-blocks`: write every code block of the last answer into `dir`,
+/// named `block-N.<ext>` after the block's language.
+pub fn save_blocks(cfg: &Config, dir: &Path) -> Result<()> {
+    let history = History::load(&cfg.history)?;
+    for (i, block) in last_blocks(&history)?.iter().enumerate() {
+        let path = dir.join(default_name(i + 1, block));
+        if write_block(&path, &block.code)? {
+            saved(&path, &block.code);
+        }
+    }
+    Ok(())
+}
+
+/// An answer as it is written to a file: its markdown, ending in a newline.
+fn answer_file(answer: &str) -> String {
+    format!("{}\n", answer.trim())
+}
+
+/// `--output PATH`: write `answer` to `path`, replacing what is there, as
+/// `>` would.
+pub(super) fn write_answer(path: &Path, answer: &str) -> Result<()> {
+    let text = answer_file(answer);
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(path, &text).with_context(|| format!("Failed to write {}", path.display()))?;
+    let lines = text.lines().count();
+    say!(
+        "Saved the answer to {} ({} line{})",
+        path.display(),
+        lines,
+        if lines == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+/// `/save [N [PATH]]` typed in place of a question: save block N to PATH,
+/// or every block when no number is given. `/save PATH` saves the whole
+/// last answer instead.
+pub fn save_command(cfg: &Config, args: &str) -> Result<()> {
+    let mut words = args.split_whitespace();
+    let Some(n) = words.next() else {
+        return save_blocks(cfg, Path::new("."));
+    };
+    let path = words.next().map(PathBuf::from);
+    if words.next().is_some() {
+        bail!("Usage: /save [N [PATH]] or /save PATH");
+    }
+    let history = History::load(&cfg.history)?;
+    if path.is_none() && n.parse::<usize>().is_err() {
+        let last = history
+            .entries
+            .last()
+            .context("No answers in the history yet")?;
+        let path = Path::new(n);
+        let text = answer_file(&last.llm_response);
+        if write_block(path, &text)? {
+            saved(path, &text);
+        }
+        return Ok(());
+    }
+    let blocks = last_blocks(&history)?;
+    let index = n
+        .parse::<usize>()
+        .ok()
+        .filter(|i| (1..=blocks.len()).contains(i))
+        .with_context(|| {
+            format!(
+                "No block {}; the last answer has {} code block{}",
+                n,
+                blocks.len(),
+                if blocks.len() == 1 { "" } else { "s" }
+            )
+        })?;
+    let block = &blocks[index - 1];
+    let path = path.unwrap_or_else(|| PathBuf::from(default_name(index, block)));
+    if write_block(&path, &block.code)? {
+        saved(&path, &block.code);
+    }
+    Ok(())
+}
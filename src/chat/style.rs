@@ -0,0 +1,160 @@
+use anyhow::{bail, Result};
+
+use crate::config::{Config, StyleConfig, StylePreset};
+
+/// How much a command is explained.
+#[derive(Clone, Copy, PartialEq)]
+enum Explain {
+    None,
+    Brief,
+    Detailed,
+}
+
+/// What the system prompt asks of answers: how long they are, how much
+/// they explain, and anything a preset or the profile adds.
+pub(super) struct Style {
+    length: f32,
+    explain: Explain,
+    explain_flags: bool,
+    instructions: Option<String>,
+    profile: Option<String>,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            length: 1.0,
+            explain: Explain::Brief,
+            explain_flags: true,
+            instructions: None,
+            profile: None,
+        }
+    }
+}
+
+/// The presets tai ships with.
+const BUILT_IN: &[&str] = &["beginner", "expert", "terse"];
+
+fn built_in(name: &str) -> Option<Style> {
+    let (length, explain, explain_flags, instructions) = match name {
+        "beginner" => (
+            1.5,
+            Explain::Detailed,
+            true,
+            "Assume the user is new to the command line. Define technical terms the first time you use them, and say what a command will change before running it.",
+        ),
+        "expert" => (
+            0.75,
+            Explain::Brief,
+            false,
+            "Assume the user is an experienced engineer. Skip the basics and use precise technical terms.",
+        ),
+        "terse" => (
+            0.3,
+            Explain::None,
+            false,
+            "Reply with the command or the result alone, without preamble or closing remarks.",
+        ),
+        _ => return None,
+    };
+    Some(Style {
+        length,
+        explain,
+        explain_flags,
+        instructions: Some(instructions.to_string()),
+        profile: None,
+    })
+}
+
+impl Style {
+    /// The preset `cfg` selects, with its `[style.presets]` entry over the
+    /// built-in one of the same name.
+    pub(super) fn new(cfg: &StyleConfig) -> Result<Self> {
+        let Some(name) = &cfg.preset else {
+            return Ok(Self::default());
+        };
+        let custom = cfg.presets.as_ref().and_then(|p| p.get(name));
+        let mut style = match (built_in(name), custom) {
+            (Some(style), _) => style,
+            (None, Some(_)) => Self::default(),
+            (None, None) => {
+                let mut names: Vec<&str> = BUILT_IN.to_vec();
+                names.extend(cfg.presets.iter().flatten().map(|(n, _)| n.as_str()));
+                bail!("No style named '{}'; choose {}", name, names.join(", "));
+            }
+        };
+        if let Some(preset) = custom {
+            style.set(preset)?;
+        }
+        Ok(style)
+    }
+
+    /// Add the instructions of the profile in use.
+    pub(super) fn with_profile(mut self, cfg: &Config) -> Self {
+        self.profile = cfg.profile().and_then(|p| p.instructions.clone());
+        self
+    }
+
+    fn set(&mut self, preset: &StylePreset) -> Result<()> {
+        if let Some(length) = preset.length {
+            if length <= 0.0 {
+                bail!("style length must be above 0");
+            }
+            self.length = length;
+        }
+        if let Some(explain) = &preset.explain {
+            self.explain = match explain.as_str() {
+                "none" => Explain::None,
+                "brief" => Explain::Brief,
+                "detailed" => Explain::Detailed,
+                other => bail!(
+                    "style explain must be \"none\", \"brief\", or \"detailed\", not \"{}\"",
+                    other
+                ),
+            };
+        }
+        if let Some(flags) = preset.explain_flags {
+            self.explain_flags = flags;
+        }
+        if preset.instructions.is_some() {
+            self.instructions = preset.instructions.clone();
+        }
+        Ok(())
+    }
+
+    /// The word limit for a terminal that fits `default` words.
+    pub(super) fn max_words(&self, default: usize) -> usize {
+        ((default as f32 * self.length).round() as usize).max(20)
+    }
+
+    /// The rule for questions about a command.
+    pub(super) fn explain_rule(&self) -> String {
+        let answer = match self.explain {
+            Explain::None => "answer with a one-line example only",
+            Explain::Brief => "answer concisely and include a one-line example",
+            Explain::Detailed => {
+                "explain what the command does and why, step by step, and include a one-line example"
+            }
+        };
+        let flags = match (self.explain_flags, self.explain) {
+            (true, Explain::Detailed) => ", then an explanation of every flag it uses.",
+            (true, _) => ", then a brief explanation of key flags.",
+            (false, _) => ". Do not explain flags unless asked.",
+        };
+        format!(
+            "If the user is asking about a command (explanatory), {}{}",
+            answer, flags
+        )
+    }
+
+    /// The preset's and the profile's own instructions as rules, if they
+    /// have any.
+    pub(super) fn extra_rule(&self) -> String {
+        [&self.instructions, &self.profile]
+            .into_iter()
+            .flatten()
+            .filter(|text| !text.trim().is_empty())
+            .map(|text| format!("\n- {}", text.trim()))
+            .collect()
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use nu_ansi_term::Color as NuColor;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::get_global_config_dir;
+use crate::output;
+use crate::say;
+use crate::scratch;
+
+/// Completed calls listed in the resume prompt, from the end.
+const MAX_LISTED: usize = 40;
+
+/// A run with tool calls that has not finished yet, as it is kept in
+/// `~/.config/tai/tasks`, one per directory. The run removes it when it
+/// ends; one that is still there was cut short.
+#[derive(Serialize, Deserialize)]
+struct Task {
+    cwd: PathBuf,
+    started: DateTime<Local>,
+    question: String,
+    /// The finished tool calls, one line each, in order.
+    done: Vec<String>,
+    /// The call that was running, which may or may not have taken effect.
+    pending: Option<String>,
+}
+
+fn path() -> Result<PathBuf> {
+    let cwd = std::env::current_dir()?;
+    let name = format!("{:x}", md5::compute(cwd.to_string_lossy().as_bytes()));
+    Ok(get_global_config_dir()?
+        .join("tasks")
+        .join(format!("{}.json", name)))
+}
+
+fn load() -> Option<Task> {
+    let text = fs::read_to_string(path().ok()?).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// Forget the unfinished task in this directory, if any.
+pub fn discard() -> Result<()> {
+    let path = path()?;
+    if path.exists() {
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Keeps a run's progress on disk after each tool call, so it can be
+/// picked up after a crash, Ctrl-C, or reboot.
+pub(super) struct Progress {
+    task: Option<Task>,
+    question: String,
+}
+
+impl Progress {
+    /// For a run of `question`; with `resume`, continuing the saved task.
+    pub(super) fn new(question: &str, resume: bool) -> Self {
+        Self {
+            task: if resume { load() } else { None },
+            question: question.to_string(),
+        }
+    }
+
+    fn save(&mut self) {
+        let Some(task) = &self.task else {
+            return;
+        };
+        let result = path().and_then(|path| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            scratch::write_atomic(&path, serde_json::to_string_pretty(task)?.as_bytes())
+        });
+        if let Err(e) = result {
+            tracing::debug!("Failed to save task progress: {:#}", e);
+        }
+    }
+
+    /// A tool call, as `summary`, is about to run.
+    pub(super) fn started(&mut self, summary: String) {
+        let question = &self.question;
+        let task = self.task.get_or_insert_with(|| Task {
+            cwd: std::env::current_dir().unwrap_or_default(),
+            started: Local::now(),
+            question: question.clone(),
+            done: Vec::new(),
+            pending: None,
+        });
+        task.pending = Some(summary);
+        self.save();
+    }
+
+    /// The running call finished, as `summary`.
+    pub(super) fn finished(&mut self, summary: String) {
+        if let Some(task) = &mut self.task {
+            task.pending = None;
+            task.done.push(summary);
+        }
+        self.save();
+    }
+
+    /// The run got to its end; nothing is left to resume.
+    pub(super) fn complete(self) {
+        if self.task.is_some() {
+            if let Err(e) = discard() {
+                tracing::debug!("Failed to remove task progress: {:#}", e);
+            }
+        }
+    }
+}
+
+/// Tell the user about an unfinished task in this directory.
+pub fn offer_resume() {
+    let Some(task) = load() else {
+        return;
+    };
+    let notice = NuColor::Yellow.paint(format!(
+        "A task started {} was interrupted after {} tool call{}: {}",
+        task.started.format("%Y-%m-%d %H:%M"),
+        task.done.len(),
+        if task.done.len() == 1 { "" } else { "s" },
+        task.question.lines().next().unwrap_or_default()
+    ));
+    eprintln!("{}", output::plain(&notice.to_string()));
+    eprintln!("Run `tai resume-task` to continue it, or `tai resume-task --discard` to drop it.");
+}
+
+/// The message that has the model continue the unfinished task.
+pub fn resume_prompt() -> Result<String> {
+    let task = load().context("There is no interrupted task in this directory")?;
+    let mut prompt = format!(
+        "Continue this task, which was interrupted on {}:\n\n{}\n\n",
+        task.started.format("%Y-%m-%d %H:%M"),
+        task.question
+    );
+    if task.done.is_empty() {
+        prompt.push_str("No tool calls had finished yet.\n");
+    } else {
+        prompt.push_str("These tool calls were already made; do not repeat their changes:\n");
+        let skipped = task.done.len().saturating_sub(MAX_LISTED);
+        if skipped > 0 {
+            prompt.push_str(&format!("- … {} earlier calls\n", skipped));
+        }
+        for line in &task.done[skipped..] {
+            prompt.push_str(&format!("- {}\n", line));
+        }
+    }
+    if let Some(pending) = &task.pending {
+        prompt.push_str(&format!(
+            "\nThis call was running when it stopped, and may or may not have taken effect: {}\n",
+            pending
+        ));
+    }
+    prompt.push_str(
+        "\nFiles may have changed since; read them again before editing, then finish the rest of the task.",
+    );
+    say!(
+        "Resuming: {} ({} tool call{} done)",
+        task.question.lines().next().unwrap_or_default(),
+        task.done.len(),
+        if task.done.len() == 1 { "" } else { "s" }
+    );
+    Ok(prompt)
+}
@@ -0,0 +1,147 @@
+use nu_ansi_term::{Color as NuColor, Style};
+use std::sync::OnceLock;
+
+use bat::assets::HighlightingAssets;
+
+use crate::config::UiConfig;
+use crate::output;
+
+/// The bat theme answers are rendered with unless `ui.theme` names another.
+pub const DEFAULT_THEME: &str = "1337";
+
+/// Spinner frames by `ui.spinner`.
+const SPINNERS: &[(&str, &[&str])] = &[
+    ("dots", &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
+    ("line", &["-", "\\", "|", "/"]),
+];
+
+const CODE_STYLES: &[&str] = &["rendered", "copyable"];
+
+/// `[ui]`, read once and checked, with defaults filled in.
+pub(super) struct Theme {
+    pub(super) name: String,
+    tool_header: NuColor,
+    separator: NuColor,
+    spinner: &'static [&'static str],
+}
+
+/// The bat themes there are to pick from.
+pub fn theme_names() -> Vec<String> {
+    HighlightingAssets::from_binary()
+        .themes()
+        .map(String::from)
+        .collect()
+}
+
+/// A color by name ("cyan", "light_red"), 256-color number, or "#rrggbb".
+fn parse_color(value: &str) -> Option<NuColor> {
+    let value = value.trim().to_ascii_lowercase().replace(['-', ' '], "_");
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).ok();
+        return Some(NuColor::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    if let Ok(n) = value.parse::<u8>() {
+        return Some(NuColor::Fixed(n));
+    }
+    Some(match value.as_str() {
+        "black" => NuColor::Black,
+        "red" => NuColor::Red,
+        "green" => NuColor::Green,
+        "yellow" => NuColor::Yellow,
+        "blue" => NuColor::Blue,
+        "purple" | "magenta" => NuColor::Purple,
+        "cyan" => NuColor::Cyan,
+        "white" => NuColor::White,
+        "gray" | "grey" | "dark_gray" | "dark_grey" => NuColor::DarkGray,
+        "light_red" => NuColor::LightRed,
+        "light_green" => NuColor::LightGreen,
+        "light_yellow" => NuColor::LightYellow,
+        "light_blue" => NuColor::LightBlue,
+        "light_purple" | "light_magenta" => NuColor::LightPurple,
+        "light_cyan" => NuColor::LightCyan,
+        "light_gray" | "light_grey" => NuColor::LightGray,
+        _ => return None,
+    })
+}
+
+/// The problem with a `[ui]` setting, if it has one.
+pub fn check(key: &str, value: &str) -> Option<String> {
+    match key {
+        "theme" if !theme_names().iter().any(|t| t == value) => Some(format!(
+            "there is no theme '{}'; run `tai config ui.theme` for the list",
+            value
+        )),
+        "code_style" if !CODE_STYLES.contains(&value) => {
+            Some("use \"rendered\" or \"copyable\"".to_string())
+        }
+        "tool_header" | "separator" if parse_color(value).is_none() => Some(
+            "use a color name such as \"cyan\", a number from 0 to 255, or \"#rrggbb\"".to_string(),
+        ),
+        "spinner" if value != "none" && !SPINNERS.iter().any(|(name, _)| *name == value) => {
+            Some("use \"none\", \"dots\", or \"line\"".to_string())
+        }
+        _ => None,
+    }
+}
+
+impl Theme {
+    fn from_config(ui: &UiConfig) -> Self {
+        // A theme that does not exist falls back to the default; `tai
+        // doctor` names it.
+        Self {
+            name: ui
+                .theme
+                .clone()
+                .filter(|name| check("theme", name).is_none())
+                .unwrap_or_else(|| DEFAULT_THEME.to_string()),
+            tool_header: ui
+                .tool_header
+                .as_deref()
+                .and_then(parse_color)
+                .unwrap_or(NuColor::LightCyan),
+            separator: ui
+                .separator
+                .as_deref()
+                .and_then(parse_color)
+                .unwrap_or(NuColor::Rgb(100, 100, 100)),
+            spinner: ui
+                .spinner
+                .as_deref()
+                .and_then(|s| SPINNERS.iter().find(|(name, _)| *name == s))
+                .map_or(&[], |(_, frames)| *frames),
+        }
+    }
+
+    pub(super) fn tool_header(&self) -> Style {
+        Style::new().bold().fg(self.tool_header)
+    }
+
+    /// Plain without colors, as it is printed past `say!`.
+    pub(super) fn separator(&self) -> Style {
+        if output::color() {
+            Style::new().fg(self.separator)
+        } else {
+            Style::new()
+        }
+    }
+
+    /// The spinner frame for the `n`th redraw, if there is a spinner.
+    pub(super) fn spinner(&self, n: usize) -> Option<&'static str> {
+        (!self.spinner.is_empty()).then(|| self.spinner[n % self.spinner.len()])
+    }
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Render with `ui` for the rest of the run; only the first call counts.
+pub(super) fn init(ui: &UiConfig) {
+    THEME.get_or_init(|| Theme::from_config(ui));
+}
+
+/// The theme [`init`] set, or the defaults before it was called.
+pub(super) fn current() -> &'static Theme {
+    THEME.get_or_init(|| Theme::from_config(&UiConfig::default()))
+}
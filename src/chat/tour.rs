@@ -0,0 +1,321 @@
+use anyhow::{Context, Result};
+use llm::chat::{ChatMessage, ChatRole, MessageType};
+use nu_ansi_term::{Color as NuColor, Style};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::LazyLock;
+
+use super::{exit_interrupted, interruptible_step, prepare, ChatOptions, Prepared, Session};
+use crate::config::{get_git_root, get_global_config_dir, Config};
+use crate::project::detect_project_types;
+use crate::tools::{ask_line, repo_map};
+use crate::{output, say};
+
+/// Files outlined in the first prompt.
+const MAP_FILES: usize = 300;
+
+/// Characters of the outline sent; a larger repository is cut.
+const MAX_MAP_CHARS: usize = 30_000;
+
+/// Characters of the README and of each manifest sent.
+const MAX_README_CHARS: usize = 4000;
+const MAX_MANIFEST_CHARS: usize = 3000;
+
+/// Files that say how a project is built, sent alongside the outline.
+const MANIFESTS: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "go.mod",
+    "Makefile",
+];
+
+/// A section heading of the overview: `## 3. Storage`.
+static SECTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^##\s+(\d+)\.\s*(.+?)\s*$").expect("valid regex"));
+
+/// Input asking for a section by number: `2`, `more on 2`, `tell me more
+/// about section 2`.
+static DRILL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(?:tell me )?(?:more )?(?:about |on )?(?:section )?#?(\d+)\.?$")
+        .expect("valid regex")
+});
+
+/// The analysis of one commit: the overview and the sections drilled into.
+#[derive(Default, Deserialize, Serialize)]
+struct Tour {
+    commit: String,
+    overview: String,
+    details: BTreeMap<usize, String>,
+}
+
+fn cache_path(root: &Path) -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("tours").join(format!(
+        "{:x}.json",
+        md5::compute(root.display().to_string())
+    )))
+}
+
+fn head_commit(root: &Path) -> Option<String> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .filter(|c| !c.is_empty())
+}
+
+fn save(path: &Path, tour: &Tour) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string(tour)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn clip(text: &str, max: usize) -> String {
+    if text.chars().count() > max {
+        format!("{}\n…", text.chars().take(max).collect::<String>())
+    } else {
+        text.to_string()
+    }
+}
+
+fn readme(root: &Path) -> Option<(String, String)> {
+    let mut names: Vec<String> = fs::read_dir(root)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|n| n.to_lowercase().starts_with("readme"))
+        .collect();
+    names.sort();
+    let name = names.into_iter().next()?;
+    let text = fs::read_to_string(root.join(&name)).ok()?;
+    Some((name, clip(&text, MAX_README_CHARS)))
+}
+
+/// The request for the overview, with what the repository shows of itself.
+fn overview_prompt(root: &Path) -> Result<String> {
+    let map = repo_map(".", MAP_FILES)?;
+    let mut outline = clip(
+        map.get("map").and_then(|v| v.as_str()).unwrap_or_default(),
+        MAX_MAP_CHARS,
+    );
+    if let Some(note) = map.get("truncated").and_then(|v| v.as_str()) {
+        outline.push_str(&format!("({})\n", note));
+    }
+
+    let mut prompt = String::from(
+        "Give a guided tour of the repository in the current directory to a developer who is new to it.\n\n",
+    );
+    let types: Vec<&str> = detect_project_types(root)
+        .iter()
+        .map(|t| t.as_str())
+        .collect();
+    if !types.is_empty() {
+        prompt.push_str(&format!("Project type: {}\n\n", types.join(", ")));
+    }
+    prompt.push_str(&format!(
+        "Outline (files, with their functions and types at 1-based lines):\n```\n{}```\n",
+        outline
+    ));
+    if let Some((name, text)) = readme(root) {
+        prompt.push_str(&format!("\n{}:\n```\n{}\n```\n", name, text.trim_end()));
+    }
+    for name in MANIFESTS {
+        if let Ok(text) = fs::read_to_string(root.join(name)) {
+            prompt.push_str(&format!(
+                "\n{}:\n```\n{}\n```\n",
+                name,
+                clip(&text, MAX_MANIFEST_CHARS).trim_end()
+            ));
+        }
+    }
+    prompt.push_str(
+        r#"
+Write the tour in markdown:
+- Start with two or three sentences on what the project is, and how it is built and run.
+- Then 5 to 9 sections, each headed exactly `## N. Title` and numbered from 1: the layout, the entry points, and the key modules, in the order a newcomer should read them.
+- In each section, name the files and the main functions or types involved, in a short paragraph or a few bullets.
+- Read a file with read_file only where the outline is not enough. Keep each section brief; the user can ask for any section in depth afterwards."#,
+    );
+    Ok(prompt)
+}
+
+fn drill_prompt(number: usize, title: &str) -> String {
+    format!(
+        r#"Tell me more about section {number}, "{title}". Read the files it names with read_file, and use grep or repo_map for anything else it depends on. Then explain how that part works: its main types and functions, how control and data flow through them and to the rest of the project, and where to start when changing it. Cite files with line numbers."#
+    )
+}
+
+/// Send `input` and return the answer, keeping it in the conversation so a
+/// later question can refer to it. `None` when Ctrl-C cancelled it.
+async fn ask(
+    session: &mut Session<'_>,
+    input: &str,
+    contexts: &[(String, String)],
+) -> Result<Option<String>> {
+    if interruptible_step(session, input, "", Vec::new(), contexts).await? {
+        return Ok(None);
+    }
+    let answer = session.partial.clone();
+    session.history.push(ChatMessage {
+        role: ChatRole::Assistant,
+        message_type: MessageType::Text,
+        content: answer.clone(),
+    });
+    Ok(Some(answer))
+}
+
+/// `tai tour`: an overview of the repository in numbered sections, then a
+/// prompt to drill into one or ask about the code. The overview and each
+/// section explained are kept per commit, so a second tour of the same
+/// commit costs nothing until it goes further.
+pub async fn run_tour(cfg: Config, opts: ChatOptions, refresh: bool) -> Result<()> {
+    let git_root = get_git_root();
+    let root = match &git_root {
+        Some(root) => {
+            std::env::set_current_dir(root)
+                .with_context(|| format!("Failed to enter {}", root.display()))?;
+            root.clone()
+        }
+        None => std::env::current_dir()?,
+    };
+    let commit = git_root.as_deref().and_then(head_commit);
+    if commit.is_none() {
+        eprintln!("Warning: Not a git repository with commits; the tour is not cached");
+    }
+    let cache_file = cache_path(&root)?;
+    let mut tour = commit
+        .as_ref()
+        .filter(|_| !refresh)
+        .and_then(|commit| {
+            fs::read_to_string(&cache_file)
+                .ok()
+                .and_then(|s| serde_json::from_str::<Tour>(&s).ok())
+                .filter(|t| &t.commit == commit && !t.overview.is_empty())
+        })
+        .unwrap_or_default();
+
+    let Prepared {
+        cfg,
+        llm,
+        tools,
+        instructions,
+        dropped,
+        contexts,
+        ..
+    } = prepare(cfg, &opts)?;
+    let mut session = Session::new(&llm, tools, &cfg, instructions, dropped);
+
+    // Whether the model has seen the overview in this conversation.
+    let mut overview_sent = false;
+    if tour.overview.is_empty() {
+        say!("Outlining {}", root.display());
+        let prompt = overview_prompt(&root)?;
+        let Some(overview) = ask(&mut session, &prompt, &contexts).await? else {
+            exit_interrupted(session);
+        };
+        overview_sent = true;
+        tour = Tour {
+            commit: commit.clone().unwrap_or_default(),
+            overview,
+            details: BTreeMap::new(),
+        };
+        if commit.is_some() {
+            save(&cache_file, &tour)?;
+        }
+    } else {
+        say!(
+            "{}",
+            NuColor::DarkGray.paint(format!(
+                "Tour of commit {} from cache; --refresh to redo it",
+                &tour.commit[..tour.commit.len().min(12)]
+            ))
+        );
+        session.show(&tour.overview);
+    }
+
+    let sections: BTreeMap<usize, String> = SECTION
+        .captures_iter(&tour.overview)
+        .filter_map(|c| Some((c[1].parse().ok()?, c[2].to_string())))
+        .collect();
+    if sections.is_empty() {
+        eprintln!("Warning: The overview has no numbered sections to drill into");
+    }
+    if output::stdout_reserved() {
+        return Ok(());
+    }
+
+    loop {
+        say!();
+        let input = ask_line(&format!(
+            "{} ",
+            Style::new().bold().paint(
+                "Drill into a section (number), ask about the code, or press Enter to quit:"
+            )
+        ))?;
+        if input.is_empty() || matches!(input.as_str(), "q" | "quit" | "exit") {
+            return Ok(());
+        }
+        let requested = DRILL
+            .captures(&input)
+            .and_then(|c| c[1].parse::<usize>().ok());
+        if let Some(number) =
+            requested.filter(|n| !sections.is_empty() && !sections.contains_key(n))
+        {
+            say!(
+                "{}",
+                NuColor::Yellow.paint(format!(
+                    "There is no section {}; pick one of {}",
+                    number,
+                    sections
+                        .keys()
+                        .map(|n| n.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            );
+            continue;
+        }
+        let section = requested.and_then(|n| sections.get(&n).map(|title| (n, title)));
+
+        if let Some(detail) = section.and_then(|(n, _)| tour.details.get(&n)) {
+            session.show(detail);
+            continue;
+        }
+        let request = match section {
+            Some((n, title)) => drill_prompt(n, title),
+            None => input.clone(),
+        };
+        let prompt = if overview_sent {
+            request
+        } else {
+            format!(
+                "Here is a tour of this repository you wrote earlier:\n\n{}\n\n{}",
+                tour.overview.trim_end(),
+                request
+            )
+        };
+        let Some(answer) = ask(&mut session, &prompt, &contexts).await? else {
+            continue;
+        };
+        overview_sent = true;
+        if let Some((n, _)) = section {
+            tour.details.insert(n, answer);
+            if commit.is_some() {
+                save(&cache_file, &tour)?;
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use llm::chat::{ChatMessage, ChatResponse, ChatRole, MessageType};
+use llm::ToolCall;
+use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use super::is_sensitive_key;
+use crate::config::get_global_config_dir;
+use crate::logging;
+
+/// The session's transcript, one JSON object per line. Unset unless
+/// transcripts are turned on.
+static FILE: OnceLock<Mutex<File>> = OnceLock::new();
+static PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Open `~/.config/tai/logs/<time>-<pid>.jsonl` for this session.
+pub(super) fn start() -> Result<PathBuf> {
+    let dir = get_global_config_dir()?.join("logs");
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!(
+        "{}-{}.jsonl",
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        std::process::id()
+    ));
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    // Transcripts hold whole conversations and tool output.
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let file = options
+        .open(&path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let _ = FILE.set(Mutex::new(file));
+    let _ = PATH.set(path.clone());
+    Ok(path)
+}
+
+/// Where this session's transcript is written, if it is.
+pub fn path() -> Option<&'static Path> {
+    PATH.get().map(PathBuf::as_path)
+}
+
+fn record(kind: &str, fields: Value) {
+    let Some(file) = FILE.get() else {
+        return;
+    };
+    let mut entry = serde_json::Map::new();
+    entry.insert("time".into(), json!(chrono::Local::now().to_rfc3339()));
+    entry.insert("type".into(), json!(kind));
+    if let Value::Object(map) = redact(fields) {
+        entry.extend(map);
+    }
+    if let Ok(mut file) = file.lock() {
+        let _ = writeln!(file, "{}", Value::Object(entry));
+    }
+}
+
+/// Masks secrets in every string, and whole values under sensitive-looking
+/// keys inside tool arguments and results.
+fn redact(value: Value) -> Value {
+    match value {
+        Value::String(s) => Value::String(logging::redact(&s).into_owned()),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact).collect()),
+        Value::Object(map) => Value::Object(map.into_iter().map(|(k, v)| (k, redact(v))).collect()),
+        other => other,
+    }
+}
+
+fn redact_keys(value: Value) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(items.into_iter().map(redact_keys).collect()),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| {
+                    let v = if is_sensitive_key(&k) && !v.is_object() && !v.is_array() {
+                        json!("***")
+                    } else {
+                        redact_keys(v)
+                    };
+                    (k, v)
+                })
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+fn tool_calls(calls: &[ToolCall]) -> Value {
+    calls
+        .iter()
+        .map(|call| {
+            let arguments = serde_json::from_str::<Value>(&call.function.arguments)
+                .unwrap_or_else(|_| json!(call.function.arguments));
+            json!({
+                "id": call.id,
+                "name": call.function.name,
+                "arguments": redact_keys(arguments),
+            })
+        })
+        .collect()
+}
+
+/// Images and PDFs are logged by size; the rest as sent.
+fn message(m: &ChatMessage) -> Value {
+    let role = match m.role {
+        ChatRole::User => "user",
+        ChatRole::Assistant => "assistant",
+    };
+    let mut out = json!({ "role": role, "content": m.content });
+    match &m.message_type {
+        MessageType::Text => {}
+        MessageType::Image((mime, bytes)) => {
+            out["image"] = json!({ "mime": mime.mime_type(), "bytes": bytes.len() });
+        }
+        MessageType::Pdf(bytes) => out["pdf"] = json!({ "bytes": bytes.len() }),
+        MessageType::ImageURL(url) => {
+            out["image_url"] = match url.split_once(";base64,") {
+                Some((prefix, data)) => json!(format!("{};base64,<{} chars>", prefix, data.len())),
+                None => json!(url),
+            };
+        }
+        MessageType::ToolUse(calls) => out["tool_use"] = tool_calls(calls),
+        MessageType::ToolResult(calls) => out["tool_result"] = tool_calls(calls),
+    }
+    out
+}
+
+pub(super) fn request(provider: &str, model: &str, kind: &str, messages: &[ChatMessage]) {
+    if FILE.get().is_none() {
+        return;
+    }
+    record(
+        "request",
+        json!({
+            "provider": provider,
+            "model": model,
+            "call": kind,
+            "messages": messages.iter().map(message).collect::<Vec<_>>(),
+        }),
+    );
+}
+
+pub(super) fn response(provider: &str, response: &dyn ChatResponse) {
+    if FILE.get().is_none() {
+        return;
+    }
+    record(
+        "response",
+        json!({
+            "provider": provider,
+            "text": response.text(),
+            "thinking": response.thinking(),
+            "tool_calls": response.tool_calls().as_deref().map(tool_calls),
+            "usage": response.usage(),
+        }),
+    );
+}
+
+/// The text of a streamed answer, once the stream has ended.
+pub(super) fn streamed(provider: &str, text: &str) {
+    record(
+        "response",
+        json!({ "provider": provider, "text": text, "streamed": true }),
+    );
+}
+
+pub(super) fn error(provider: &str, attempt: u32, err: &dyn std::fmt::Display) {
+    record(
+        "error",
+        json!({ "provider": provider, "attempt": attempt, "error": err.to_string() }),
+    );
+}
+
+pub(super) fn tool(name: &str, elapsed: Duration, result: std::result::Result<&Value, String>) {
+    if FILE.get().is_none() {
+        return;
+    }
+    let elapsed_ms = elapsed.as_millis() as u64;
+    let fields = match result {
+        Ok(value) => {
+            json!({ "name": name, "elapsed_ms": elapsed_ms, "result": redact_keys(value.clone()) })
+        }
+        Err(e) => json!({ "name": name, "elapsed_ms": elapsed_ms, "error": e }),
+    };
+    record("tool", fields);
+}
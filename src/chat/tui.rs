@@ -0,0 +1,776 @@
+use anyhow::{anyhow, Context, Result};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::cursor::Show;
+use ratatui::crossterm::event::{
+    self, DisableBracketedPaste, EnableBracketedPaste, Event as TermEvent, KeyCode, KeyEvent,
+    KeyEventKind, KeyModifiers,
+};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{IsTerminal, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use super::frontend::{self, Event, Frontend};
+use super::{prepare, theme, ChatOptions, Failover, Prepared, Session};
+use crate::config::Config;
+use crate::output::printable;
+use crate::tools::route_prompts;
+
+/// Lines of output kept in the output pane; older ones are dropped.
+const OUTPUT_LINES: usize = 5000;
+
+/// How often the screen is redrawn while a step runs, for the spinner, and
+/// how long the key reader waits before looking whether to stop.
+const TICK: Duration = Duration::from_millis(100);
+
+/// Rows the input box grows to before it scrolls.
+const INPUT_ROWS: u16 = 5;
+
+/// What reaches the screen: the session's events, what was printed, and
+/// the questions tools ask.
+enum Update {
+    /// Text written to stdout or stderr.
+    Output(String),
+    /// Input and output tokens of one request.
+    Usage(u64, u64),
+    AnswerStarted,
+    Delta(String),
+    /// The whole answer, in place of its deltas.
+    Answer(String),
+    Notice(String),
+    /// A question for the user and where the answer goes.
+    Prompt(String, mpsc::Sender<String>),
+}
+
+/// The session's frontend: the conversation goes to its pane; tool calls
+/// and results are printed as on the terminal and so end up in the output
+/// pane, in order with what the tools print themselves.
+struct Panes {
+    updates: UnboundedSender<Update>,
+    terminal: Box<dyn Frontend>,
+}
+
+impl Frontend for Panes {
+    fn streams(&self) -> bool {
+        true
+    }
+
+    fn event(&mut self, event: Event<'_>) {
+        let update = match event {
+            Event::Usage(usage) => Update::Usage(
+                u64::from(usage.prompt_tokens),
+                u64::from(usage.completion_tokens),
+            ),
+            Event::ToolRequested { .. }
+            | Event::ToolRejected { .. }
+            | Event::ToolFinished { .. } => {
+                return self.terminal.event(event);
+            }
+            Event::AnswerStarted => Update::AnswerStarted,
+            Event::ModelDelta { text, .. } => Update::Delta(text.to_string()),
+            Event::AnswerReady { text, .. } => Update::Answer(text.to_string()),
+            Event::Footer(footer) => Update::Notice(footer.line()),
+            Event::NeedsWeb { class } => Update::Notice(frontend::needs_web(class)),
+            Event::Refused {
+                provider,
+                reason,
+                text,
+            } => Update::Notice(
+                format!("{} did not answer; {}\n{}", provider, reason, text)
+                    .trim_end()
+                    .to_string(),
+            ),
+            Event::Interrupted { tool_calls, .. } => {
+                Update::Notice(frontend::interrupted(tool_calls))
+            }
+        };
+        // The screen may be gone; the step finishes regardless.
+        let _ = self.updates.send(update);
+    }
+}
+
+/// The terminal in raw mode on the alternate screen, drawn through a copy
+/// of stdout taken before the capture; it is restored on drop.
+struct Screen(Terminal<CrosstermBackend<File>>);
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        let tty = std::io::stdout()
+            .as_fd()
+            .try_clone_to_owned()
+            .context("Failed to open the terminal")?;
+        let mut screen = Self(Terminal::new(CrosstermBackend::new(File::from(tty)))?);
+        terminal::enable_raw_mode().context("Failed to put the terminal in raw mode")?;
+        execute!(
+            screen.0.backend_mut(),
+            EnterAlternateScreen,
+            EnableBracketedPaste
+        )
+        .context("Failed to switch to the alternate screen")?;
+        Ok(screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(
+            self.0.backend_mut(),
+            DisableBracketedPaste,
+            LeaveAlternateScreen,
+            Show
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// stdout and stderr sent into a pipe read for the output pane, so what
+/// tools and notices print does not draw over the screen. Both come back
+/// on drop.
+struct Capture {
+    saved: [OwnedFd; 2],
+}
+
+impl Capture {
+    fn start(updates: UnboundedSender<Update>) -> Result<Self> {
+        let capture = Self {
+            saved: [
+                std::io::stdout().as_fd().try_clone_to_owned()?,
+                std::io::stderr().as_fd().try_clone_to_owned()?,
+            ],
+        };
+        let (mut reader, writer) = std::io::pipe().context("Failed to open a pipe")?;
+        std::io::stdout().flush().ok();
+        for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            // SAFETY: dup2 onto a standard descriptor from one owned here.
+            if unsafe { libc::dup2(writer.as_raw_fd(), fd) } < 0 {
+                return Err(std::io::Error::last_os_error()).context("Failed to capture output");
+            }
+        }
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            let mut pending = Vec::new();
+            while let Ok(n @ 1..) = reader.read(&mut buf) {
+                pending.extend_from_slice(&buf[..n]);
+                // A character cut at the end waits for the rest of it.
+                let whole = match std::str::from_utf8(&pending) {
+                    Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                    _ => pending.len(),
+                };
+                let text = String::from_utf8_lossy(&pending[..whole]).into_owned();
+                pending.drain(..whole);
+                // Read on after the screen is gone, so writers never block.
+                let _ = updates.send(Update::Output(text));
+            }
+        });
+        Ok(capture)
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        std::io::stdout().flush().ok();
+        for (saved, fd) in self
+            .saved
+            .iter()
+            .zip([libc::STDOUT_FILENO, libc::STDERR_FILENO])
+        {
+            // SAFETY: dup2 back onto the standard descriptor it was copied from.
+            unsafe { libc::dup2(saved.as_raw_fd(), fd) };
+        }
+    }
+}
+
+/// Terminal events, read on a thread of their own until dropped.
+struct Keys {
+    events: UnboundedReceiver<TermEvent>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Keys {
+    fn start() -> Self {
+        let (sender, events) = unbounded_channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                match event::poll(TICK) {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        let Ok(event) = event::read() else { return };
+                        if sender.send(event).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => return,
+                }
+            }
+        });
+        Self { events, stop }
+    }
+}
+
+impl Drop for Keys {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+enum Entry {
+    Question(String),
+    Answer(String),
+    Notice(String),
+    Error(String),
+}
+
+/// The pane the scroll keys move; also its index in `App::views`.
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    Conversation = 0,
+    Output = 1,
+}
+
+/// Where a pane is scrolled to, and what the last draw found it holds.
+#[derive(Default)]
+struct View {
+    /// The first row shown; `None` follows the end.
+    top: Option<usize>,
+    /// The first row when at the end, and the rows shown.
+    bottom: usize,
+    height: usize,
+}
+
+impl View {
+    fn scroll(&mut self, by: isize) {
+        let top = self.top.unwrap_or(self.bottom).saturating_add_signed(by);
+        self.top = (top < self.bottom).then_some(top);
+    }
+
+    fn page(&self) -> isize {
+        self.height.max(1) as isize
+    }
+}
+
+/// What a key asks for.
+enum Action {
+    None,
+    Ask(String),
+    Stop,
+    Quit,
+}
+
+struct App {
+    entries: Vec<Entry>,
+    /// Whether the last answer is still streaming.
+    streaming: bool,
+    output: Vec<String>,
+    /// The output line still arriving.
+    partial: String,
+    show_output: bool,
+    focus: Focus,
+    views: [View; 2],
+    input: String,
+    /// Byte offset of the cursor in `input`.
+    cursor: usize,
+    /// Questions from tools, the first shown above the input.
+    prompts: VecDeque<(String, mpsc::Sender<String>)>,
+    busy: bool,
+    tokens: (u64, u64),
+    /// Redraws while busy, for the spinner.
+    ticks: usize,
+}
+
+impl App {
+    fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            streaming: false,
+            output: Vec::new(),
+            partial: String::new(),
+            show_output: true,
+            focus: Focus::Conversation,
+            views: Default::default(),
+            input: String::new(),
+            cursor: 0,
+            prompts: VecDeque::new(),
+            busy: false,
+            tokens: (0, 0),
+            ticks: 0,
+        }
+    }
+
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Output(text) => self.print(&text),
+            Update::Usage(input, output) => {
+                self.tokens.0 += input;
+                self.tokens.1 += output;
+            }
+            Update::AnswerStarted => {
+                self.entries.push(Entry::Answer(String::new()));
+                self.streaming = true;
+            }
+            Update::Delta(text) => {
+                if let Some(Entry::Answer(answer)) =
+                    self.entries.last_mut().filter(|_| self.streaming)
+                {
+                    answer.push_str(&text);
+                }
+            }
+            Update::Answer(text) => {
+                match self.entries.last_mut().filter(|_| self.streaming) {
+                    Some(Entry::Answer(answer)) => *answer = text,
+                    _ => self.entries.push(Entry::Answer(text)),
+                }
+                self.streaming = false;
+            }
+            Update::Notice(text) => self.entries.push(Entry::Notice(text)),
+            Update::Prompt(question, answer) => {
+                // Lines before the question itself go with the output.
+                let question = printable(&question);
+                let question = question.trim_end();
+                let (before, last) = question.rsplit_once('\n').unwrap_or(("", question));
+                if !before.trim().is_empty() {
+                    self.print(&format!("{}\n", before));
+                }
+                self.prompts.push_back((last.trim().to_string(), answer));
+            }
+        }
+    }
+
+    /// Add printed text to the output pane. A carriage return starts its
+    /// line over, as progress lines expect.
+    fn print(&mut self, text: &str) {
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\n' => {
+                    let line = printable(&std::mem::take(&mut self.partial));
+                    self.output.push(line);
+                }
+                '\r' if chars.peek() != Some(&'\n') => self.partial.clear(),
+                '\r' => {}
+                c => self.partial.push(c),
+            }
+        }
+        if self.output.len() > 2 * OUTPUT_LINES {
+            self.output.drain(..self.output.len() - OUTPUT_LINES);
+        }
+    }
+
+    fn ask(&mut self, question: &str) {
+        self.entries.push(Entry::Question(question.to_string()));
+        self.views[Focus::Conversation as usize].top = None;
+        self.busy = true;
+        self.ticks = 0;
+    }
+
+    /// The step ended, one way or another.
+    fn finish(&mut self, result: Result<()>) {
+        if let Err(e) = result {
+            self.entries.push(Entry::Error(format!("Error: {:#}", e)));
+        }
+        self.busy = false;
+        self.streaming = false;
+        self.prompts.clear();
+    }
+
+    fn event(&mut self, event: TermEvent) -> Action {
+        match event {
+            TermEvent::Key(key) if key.kind != KeyEventKind::Release => self.key(key),
+            TermEvent::Paste(text) => {
+                self.insert(&text.replace("\r\n", "\n").replace('\r', "\n"));
+                Action::None
+            }
+            _ => Action::None,
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) -> Action {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if ctrl => {
+                return if self.busy {
+                    Action::Stop
+                } else {
+                    Action::Quit
+                };
+            }
+            KeyCode::Char('d') if ctrl && self.input.is_empty() && !self.busy => {
+                return Action::Quit;
+            }
+            KeyCode::Char('t') if ctrl => {
+                self.show_output = !self.show_output;
+                self.focus = Focus::Conversation;
+            }
+            KeyCode::Tab if self.show_output => {
+                self.focus = match self.focus {
+                    Focus::Conversation => Focus::Output,
+                    Focus::Output => Focus::Conversation,
+                };
+            }
+            KeyCode::Up => self.views[self.focus as usize].scroll(-1),
+            KeyCode::Down => self.views[self.focus as usize].scroll(1),
+            KeyCode::PageUp => {
+                let view = &mut self.views[self.focus as usize];
+                view.scroll(-view.page());
+            }
+            KeyCode::PageDown => {
+                let view = &mut self.views[self.focus as usize];
+                view.scroll(view.page());
+            }
+            KeyCode::Enter if key.modifiers.contains(KeyModifiers::ALT) => self.insert("\n"),
+            KeyCode::Enter => return self.submit(),
+            KeyCode::Esc => {
+                self.input.clear();
+                self.cursor = 0;
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                let at = self.before_cursor();
+                self.input.remove(at);
+                self.cursor = at;
+            }
+            KeyCode::Delete if self.cursor < self.input.len() => {
+                self.input.remove(self.cursor);
+            }
+            KeyCode::Left if self.cursor > 0 => self.cursor = self.before_cursor(),
+            KeyCode::Right if self.cursor < self.input.len() => {
+                self.cursor = self.input[self.cursor..]
+                    .char_indices()
+                    .nth(1)
+                    .map_or(self.input.len(), |(at, _)| self.cursor + at);
+            }
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.input.len(),
+            KeyCode::Char(c) if !ctrl => self.insert(c.encode_utf8(&mut [0; 4])),
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn before_cursor(&self) -> usize {
+        self.input[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(at, _)| at)
+    }
+
+    fn insert(&mut self, text: &str) {
+        self.input.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    /// Enter: answer the tool's question, or ask the typed one.
+    fn submit(&mut self) -> Action {
+        let input = std::mem::take(&mut self.input);
+        self.cursor = 0;
+        if let Some((question, answer)) = self.prompts.pop_front() {
+            self.print(&format!("{} {}\n", question, input));
+            // The step may have been stopped and stopped waiting.
+            let _ = answer.send(input);
+            return Action::None;
+        }
+        if self.busy || input.trim().is_empty() {
+            self.input = input;
+            self.cursor = self.input.len();
+            return Action::None;
+        }
+        Action::Ask(input.trim().to_string())
+    }
+
+    fn draw(&mut self, frame: &mut Frame, llm: &Failover) {
+        let input_rows = (self.input.split('\n').count() as u16).min(INPUT_ROWS);
+        let [main, input, status] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(input_rows + 2),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let chat = if self.show_output {
+            let [chat, output] =
+                Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)])
+                    .areas(main);
+            let mut text: Text = self.output.iter().map(|l| Line::raw(l.as_str())).collect();
+            if !self.partial.is_empty() {
+                text.push_line(Line::raw(printable(&self.partial)));
+            }
+            pane(
+                frame,
+                output,
+                " Output (Ctrl-T hides) ",
+                text,
+                &mut self.views[Focus::Output as usize],
+                self.focus == Focus::Output,
+            );
+            chat
+        } else {
+            main
+        };
+        pane(
+            frame,
+            chat,
+            " Conversation ",
+            conversation(&self.entries),
+            &mut self.views[Focus::Conversation as usize],
+            self.focus == Focus::Conversation,
+        );
+        self.draw_input(frame, input);
+        self.draw_status(frame, status, llm);
+    }
+
+    fn draw_input(&self, frame: &mut Frame, area: Rect) {
+        let (title, color) = match self.prompts.front() {
+            Some((question, _)) => (format!(" {} ", question), Color::Yellow),
+            None if self.busy => (" Working; Ctrl-C stops ".to_string(), Color::DarkGray),
+            None => (
+                " Ask (Enter sends, Alt-Enter adds a line) ".to_string(),
+                Color::Cyan,
+            ),
+        };
+        let before = &self.input[..self.cursor];
+        let row = before.matches('\n').count() as u16;
+        let col = before.rsplit('\n').next().unwrap_or("").chars().count() as u16;
+        let inner = area.height.saturating_sub(2).max(1);
+        let width = area.width.saturating_sub(2).max(1);
+        let scroll = (row.saturating_sub(inner - 1), col.saturating_sub(width - 1));
+        frame.render_widget(
+            Paragraph::new(self.input.as_str())
+                .block(
+                    Block::bordered()
+                        .title(title)
+                        .border_style(Style::new().fg(color)),
+                )
+                .scroll(scroll),
+            area,
+        );
+        frame.set_cursor_position((area.x + 1 + col - scroll.1, area.y + 1 + row - scroll.0));
+    }
+
+    fn draw_status(&mut self, frame: &mut Frame, area: Rect, llm: &Failover) {
+        let state = if !self.prompts.is_empty() {
+            "waiting for your answer".to_string()
+        } else if self.busy {
+            self.ticks += 1;
+            match theme::current().spinner(self.ticks) {
+                Some(frame) => format!("{} working", frame),
+                None => "working".to_string(),
+            }
+        } else {
+            "ready".to_string()
+        };
+        let style = Style::new().add_modifier(Modifier::REVERSED);
+        let left = Line::from(vec![
+            Span::styled(
+                format!(" {} ", llm.active_name()),
+                style.add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                format!(
+                    " {} · {} in / {} out tokens · {} ",
+                    llm.active_model(),
+                    self.tokens.0,
+                    self.tokens.1,
+                    state
+                ),
+                style,
+            ),
+        ]);
+        frame.render_widget(Paragraph::new(left).style(style), area);
+        frame.render_widget(
+            Paragraph::new(
+                Line::raw("Tab focus · ↑↓ PgUp PgDn scroll · Ctrl-C stop/quit ").right_aligned(),
+            )
+            .style(style),
+            area,
+        );
+    }
+}
+
+/// The questions, answers, and notices so far, one blank line apart.
+fn conversation(entries: &[Entry]) -> Text<'_> {
+    let mut lines = Vec::new();
+    for entry in entries {
+        if !lines.is_empty() {
+            lines.push(Line::default());
+        }
+        match entry {
+            Entry::Question(text) => {
+                let style = Style::new().fg(Color::Cyan).add_modifier(Modifier::BOLD);
+                for (i, line) in text.lines().enumerate() {
+                    let mark = if i == 0 { "> " } else { "  " };
+                    lines.push(Line::styled(format!("{}{}", mark, line), style));
+                }
+            }
+            Entry::Answer(text) => lines.extend(markdown(text)),
+            Entry::Notice(text) => lines.extend(
+                text.lines()
+                    .map(|l| Line::styled(l, Style::new().fg(Color::Yellow))),
+            ),
+            Entry::Error(text) => lines.extend(
+                text.lines()
+                    .map(|l| Line::styled(l, Style::new().fg(Color::Red))),
+            ),
+        }
+    }
+    Text::from(lines)
+}
+
+/// Draw `text` in a bordered pane, wrapped, at the end unless scrolled up.
+fn pane(frame: &mut Frame, area: Rect, title: &str, text: Text, view: &mut View, focused: bool) {
+    let paragraph = Paragraph::new(text).wrap(Wrap { trim: false });
+    let rows = paragraph.line_count(area.width.saturating_sub(2));
+    view.height = area.height.saturating_sub(2) as usize;
+    view.bottom = rows.saturating_sub(view.height);
+    let top = view.top.map_or(view.bottom, |top| top.min(view.bottom));
+    let border = if focused {
+        Color::Cyan
+    } else {
+        Color::DarkGray
+    };
+    frame.render_widget(
+        paragraph
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .border_style(Style::new().fg(border)),
+            )
+            .scroll((top.min(u16::MAX as usize) as u16, 0)),
+        area,
+    );
+}
+
+/// An answer's markdown, lightly styled: headings bold and code blocks in
+/// color, fences dimmed.
+fn markdown(text: &str) -> Vec<Line<'_>> {
+    let mut in_code = false;
+    text.lines()
+        .map(|line| {
+            let style = if line.trim_start().starts_with("```") {
+                in_code = !in_code;
+                Style::new().fg(Color::DarkGray)
+            } else if in_code {
+                Style::new().fg(Color::Green)
+            } else if line.starts_with('#') {
+                Style::new().add_modifier(Modifier::BOLD)
+            } else {
+                Style::new()
+            };
+            Line::styled(line, style)
+        })
+        .collect()
+}
+
+/// `tai tui`: chat in a full-screen interface with the conversation, the
+/// tool calls and their output, an input box, and a status bar with the
+/// provider, model, and tokens used.
+pub async fn run_tui(cfg: Config, opts: ChatOptions) -> Result<()> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        anyhow::bail!("tai tui needs a terminal");
+    }
+    let (updates, mut received) = unbounded_channel();
+    let mut screen = Screen::enter()?;
+    let _capture = Capture::start(updates.clone())?;
+    let mut keys = Keys::start();
+    let Prepared {
+        cfg,
+        llm,
+        tools,
+        instructions,
+        dropped,
+        contexts,
+        ..
+    } = prepare(cfg, &opts)?;
+    let mut session = Session::new(&llm, tools, &cfg, instructions, dropped);
+    session.frontend = Box::new(Panes {
+        updates: updates.clone(),
+        terminal: frontend::terminal(),
+    });
+    route_prompts(Some(Box::new(move |question| {
+        let (answer, answered) = mpsc::channel();
+        updates
+            .send(Update::Prompt(question.to_string(), answer))
+            .map_err(|_| anyhow!("tai tui has closed"))?;
+        answered
+            .recv()
+            .map_err(|_| anyhow!("The question was not answered"))
+    })));
+    let mut app = App::new();
+    let result = converse(
+        &mut screen,
+        &mut session,
+        &llm,
+        &mut app,
+        &mut received,
+        &mut keys,
+        &contexts,
+    )
+    .await;
+    route_prompts(None);
+    result
+}
+
+/// Ask the questions typed until the user quits.
+async fn converse(
+    screen: &mut Screen,
+    session: &mut Session<'_>,
+    llm: &Failover,
+    app: &mut App,
+    received: &mut UnboundedReceiver<Update>,
+    keys: &mut Keys,
+    contexts: &[(String, String)],
+) -> Result<()> {
+    loop {
+        screen.0.draw(|frame| app.draw(frame, llm))?;
+        let action = tokio::select! {
+            Some(update) = received.recv() => {
+                app.apply(update);
+                continue;
+            }
+            Some(event) = keys.events.recv() => app.event(event),
+            else => return Ok(()),
+        };
+        let question = match action {
+            Action::Ask(question) => question,
+            Action::Quit => return Ok(()),
+            Action::None | Action::Stop => continue,
+        };
+        app.ask(&question);
+        // Ctrl-C drops the step, which cancels the request and kills any
+        // running command, as in the terminal.
+        let stopped = {
+            let step = session.step(&question, "", Vec::new(), contexts);
+            tokio::pin!(step);
+            let mut tick = tokio::time::interval(TICK);
+            loop {
+                screen.0.draw(|frame| app.draw(frame, llm))?;
+                tokio::select! {
+                    result = &mut step => {
+                        app.finish(result);
+                        break false;
+                    }
+                    Some(update) = received.recv() => app.apply(update),
+                    Some(event) = keys.events.recv() => {
+                        if let Action::Stop = app.event(event) {
+                            break true;
+                        }
+                    }
+                    _ = tick.tick() => {}
+                }
+            }
+        };
+        if stopped {
+            app.finish(Ok(()));
+            session.interrupted(&question)?;
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{anyhow, Context, Result};
+use llm::chat::ChatMessage;
+use nu_ansi_term::{Color as NuColor, Style};
+use std::process::Command;
+
+use crate::chat::Failover;
+use crate::config::{select_effective_provider, Config};
+use crate::tools::{ask_user, ToolsRegistry};
+
+const MAX_DIFF_CHARS: usize = 60_000;
+
+/// The tree git uses for "nothing", which a root commit is amended from.
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+fn staged_diff(amend: bool) -> Result<String> {
+    let mut args = vec!["diff", "--cached", "--no-color"];
+    if amend {
+        let has_parent = Command::new("git")
+            .args(["rev-parse", "--verify", "--quiet", "HEAD~1"])
+            .output()
+            .is_ok_and(|o| o.status.success());
+        args.push(if has_parent { "HEAD~1" } else { EMPTY_TREE });
+    }
+    let output = Command::new("git")
+        .args(&args)
+        .output()
+        .context("Failed to run git diff")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git diff failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// `project_format` is `conventions.commit_format`, which `--conventional`
+/// overrides.
+fn build_prompt(diff: &str, conventional: bool, project_format: Option<&str>) -> String {
+    let style = match project_format {
+        _ if conventional => "- Use the Conventional Commits format: `<type>(<optional scope>): <description>` where type is one of feat, fix, docs, style, refactor, perf, test, build, ci, chore, revert.\n".to_string(),
+        Some(format) => format!("- Follow the project's commit message format: {}\n", format),
+        None => "- Start the subject with a capitalized imperative verb (e.g. \"Add\", \"Fix\", \"Refactor\").\n".to_string(),
+    };
+    let (diff, truncated) = if diff.len() > MAX_DIFF_CHARS {
+        let mut end = MAX_DIFF_CHARS;
+        while !diff.is_char_boundary(end) {
+            end -= 1;
+        }
+        (&diff[..end], true)
+    } else {
+        (diff, false)
+    };
+    format!(
+        r#"Write a git commit message for the following staged changes.
+
+Rules:
+- The subject line must be at most 72 characters and must not end with a period.
+{style}- If the change is non-trivial, add a blank line followed by a short body explaining what changed and why, wrapped at 72 characters.
+- Output ONLY the commit message. No code fences, no quotes, no commentary.
+{truncated_note}
+```diff
+{diff}
+```"#,
+        truncated_note = if truncated {
+            "- The diff below was truncated; describe only what is visible.\n"
+        } else {
+            ""
+        }
+    )
+}
+
+fn clean_message(raw: &str) -> String {
+    let trimmed = raw.trim();
+    let unfenced = trimmed
+        .strip_prefix("```")
+        .map(|rest| {
+            let rest = rest.split_once('\n').map(|(_, body)| body).unwrap_or("");
+            rest.strip_suffix("```").unwrap_or(rest)
+        })
+        .unwrap_or(trimmed);
+    unfenced.trim().trim_matches('"').trim().to_string()
+}
+
+pub async fn run_commit(cfg: &Config, conventional: bool, amend: bool) -> Result<()> {
+    let diff = staged_diff(amend)?;
+    if diff.trim().is_empty() {
+        return Err(anyhow!(
+            "No staged changes found. Stage files with 'git add' first."
+        ));
+    }
+
+    let eff = select_effective_provider(cfg);
+    let llm = Failover::new(cfg, &ToolsRegistry::new(), &eff)?;
+    println!("Using provider {} (model: {})", eff.name, eff.model);
+
+    let messages = vec![ChatMessage::user()
+        .content(build_prompt(
+            &diff,
+            conventional,
+            cfg.conventions.commit_format.as_deref(),
+        ))
+        .build()];
+    let response = llm
+        .chat(&messages)
+        .await
+        .context("Failed to generate commit message")?;
+    let message = clean_message(&response.text().unwrap_or_default());
+    if message.is_empty() {
+        return Err(anyhow!("Provider returned an empty commit message"));
+    }
+
+    let separator = Style::new().fg(NuColor::Rgb(100, 100, 100)).paint("─".repeat(40));
+    println!("{}", separator);
+    println!("{}", message);
+    println!("{}", separator);
+
+    let choice = ask_user("Commit with this message? [Y/n/c] ")?;
+    if choice == "c" {
+        match arboard::Clipboard::new() {
+            Ok(mut cb) => match cb.set_text(&message) {
+                Ok(()) => println!("Commit message copied to clipboard"),
+                Err(e) => eprintln!("Failed to copy to clipboard: {}", e),
+            },
+            Err(_) => eprintln!("Failed to access clipboard"),
+        }
+        return Ok(());
+    }
+    if choice == "n" {
+        println!("Commit cancelled");
+        return Ok(());
+    }
+
+    let mut cmd = Command::new("git");
+    cmd.arg("commit");
+    if amend {
+        cmd.arg("--amend");
+    }
+    let status = cmd
+        .args(["-m", &message])
+        .status()
+        .context("Failed to run git commit")?;
+    if !status.success() {
+        return Err(anyhow!("git commit exited with {}", status));
+    }
+    Ok(())
+}
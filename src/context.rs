@@ -0,0 +1,368 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::chat::estimate_tokens;
+use crate::config::{get_git_root, get_global_config_dir, named_context_path, Config};
+use crate::project;
+use crate::team;
+use crate::tools::ask_user;
+
+/// Paragraphs shorter than this are headings or boilerplate, and sharing
+/// them is not worth pointing out.
+const MIN_SHARED_CHARS: usize = 40;
+
+/// Above this, contexts take a noticeable share of every request.
+const LARGE_CONTEXT_TOKENS: usize = 8_000;
+
+fn user_context_dir() -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("context"))
+}
+
+fn user_context_path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("Invalid context name '{}'", name);
+    }
+    Ok(user_context_dir()?.join(format!("{}.context.tai", name)))
+}
+
+fn team_context_path(config: &Config, name: &str) -> Option<PathBuf> {
+    let dir = team::team_context_dir(config.core.team_config.as_deref()?)?;
+    let path = dir.join(format!("{}.context.tai", name));
+    path.exists().then_some(path)
+}
+
+fn context_names(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|e| {
+            e.file_name()
+                .to_string_lossy()
+                .strip_suffix(".context.tai")
+                .map(|n| n.to_string())
+        })
+        .collect()
+}
+
+/// Open `path` in `$VISUAL`/`$EDITOR`, falling back to vi (notepad on Windows).
+fn edit_file(path: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| {
+            if cfg!(target_os = "windows") {
+                "notepad".into()
+            } else {
+                "vi".into()
+            }
+        });
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().context("Empty editor command")?;
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to start editor '{}'", editor))?;
+    if !status.success() {
+        return Err(anyhow!("Editor exited with {}", status));
+    }
+    Ok(())
+}
+
+pub fn handle_context_list(config: &Config) -> Result<()> {
+    let mut contexts: BTreeMap<String, &str> = BTreeMap::new();
+    if let Some(dir) = config
+        .core
+        .team_config
+        .as_deref()
+        .and_then(team::team_context_dir)
+    {
+        for name in context_names(&dir) {
+            contexts.insert(name, "team");
+        }
+    }
+    for name in context_names(&user_context_dir()?) {
+        contexts.insert(name, "user");
+    }
+    if contexts.is_empty() {
+        println!("No contexts in {}", user_context_dir()?.display());
+        println!("Create one with: tai context add <name>");
+        return Ok(());
+    }
+    let width = contexts.keys().map(|n| n.len()).max().unwrap_or(0);
+    for (name, source) in &contexts {
+        let global = if config.global_contexts.contains(name) {
+            ", global"
+        } else {
+            ""
+        };
+        println!("{:width$}  ({}{})", name, source, global, width = width);
+    }
+    Ok(())
+}
+
+pub fn handle_context_show(config: &Config, name: &str) -> Result<()> {
+    let user = user_context_path(name)?;
+    let path = if user.exists() {
+        user
+    } else {
+        team_context_path(config, name).ok_or_else(|| anyhow!("Context '{}' not found", name))?
+    };
+    let content = fs::read_to_string(&path)?;
+    print!("{}", content);
+    if !content.ends_with('\n') {
+        println!();
+    }
+    Ok(())
+}
+
+/// Create a context from piped stdin, or in the editor when run interactively.
+pub fn handle_context_add(name: &str) -> Result<()> {
+    let path = user_context_path(name)?;
+    if path.exists() {
+        bail!(
+            "Context '{}' already exists; use `tai context edit {}`",
+            name,
+            name
+        );
+    }
+    fs::create_dir_all(user_context_dir()?)?;
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        fs::write(&path, format!("# {}\n\n", name))?;
+        edit_file(&path)?;
+        let content = fs::read_to_string(&path).unwrap_or_default();
+        if content.trim().is_empty() || content.trim() == format!("# {}", name) {
+            fs::remove_file(&path)?;
+            println!("Context left empty; nothing saved");
+            return Ok(());
+        }
+    } else {
+        let mut content = String::new();
+        stdin.read_to_string(&mut content)?;
+        if content.trim().is_empty() {
+            bail!("No context content on stdin");
+        }
+        fs::write(&path, content)?;
+    }
+    println!("Saved context '{}' to {}", name, path.display());
+    Ok(())
+}
+
+/// Edit a user context. A team context is copied first, so the edit
+/// overrides it locally instead of touching the shared checkout.
+pub fn handle_context_edit(config: &Config, name: &str) -> Result<()> {
+    let path = user_context_path(name)?;
+    if !path.exists() {
+        let team = team_context_path(config, name).ok_or_else(|| {
+            anyhow!(
+                "Context '{}' not found; create it with `tai context add {}`",
+                name,
+                name
+            )
+        })?;
+        fs::create_dir_all(user_context_dir()?)?;
+        fs::copy(&team, &path)?;
+        println!("Copied team context '{}' to {}", name, path.display());
+    }
+    edit_file(&path)
+}
+
+pub fn handle_context_remove(config: &Config, name: &str, yes: bool) -> Result<()> {
+    let path = user_context_path(name)?;
+    if !path.exists() {
+        if team_context_path(config, name).is_some() {
+            bail!(
+                "Context '{}' comes from the team config and cannot be removed here",
+                name
+            );
+        }
+        bail!("Context '{}' not found", name);
+    }
+    if !yes {
+        let choice = ask_user(&format!("Delete {}? [y/N] ", path.display()))?;
+        if choice != "y" {
+            println!("Kept context '{}'", name);
+            return Ok(());
+        }
+    }
+    fs::remove_file(&path)?;
+    println!("Removed context '{}'", name);
+    if config.global_contexts.iter().any(|c| c == name) {
+        println!("Note: '{}' is still listed in global_contexts", name);
+    }
+    Ok(())
+}
+
+/// A context that loads in the current directory, and where it comes from.
+struct Loaded {
+    name: String,
+    source: String,
+    content: String,
+}
+
+/// The paragraphs of `text` long enough to count as shared.
+fn paragraphs(text: &str) -> BTreeSet<String> {
+    text.split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| p.len() >= MIN_SHARED_CHARS)
+        .collect()
+}
+
+/// Report which contexts load in the current directory and their size,
+/// global contexts that are missing or listed twice, and contexts that
+/// repeat each other. Fails when a global context is missing.
+pub fn handle_context_validate(config: &Config) -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let mut loaded = Vec::new();
+    let mut notes = Vec::new();
+    let mut missing = Vec::new();
+
+    let local = current_dir.join(".context.tai");
+    let project = get_git_root()
+        .map(|root| root.join(".context.tai"))
+        .filter(|p| p.exists() && *p != local);
+    if local.exists() {
+        loaded.push(Loaded {
+            name: "local".into(),
+            source: local.display().to_string(),
+            content: fs::read_to_string(&local)?,
+        });
+        if let Some(project) = project {
+            notes.push(format!(
+                "{} is not loaded because {} takes its place",
+                project.display(),
+                local.display()
+            ));
+        }
+    } else if let Some(project) = project {
+        loaded.push(Loaded {
+            name: "project".into(),
+            source: project.display().to_string(),
+            content: fs::read_to_string(&project)?,
+        });
+    }
+
+    let mut seen = BTreeSet::new();
+    for name in &config.global_contexts {
+        if !seen.insert(name) {
+            notes.push(format!(
+                "'{}' is listed twice in global_contexts and loads twice",
+                name
+            ));
+        }
+        let Some(path) = named_context_path(config, name)? else {
+            missing.push(name.clone());
+            continue;
+        };
+        if team_context_path(config, name).is_some_and(|team| team != path) {
+            notes.push(format!(
+                "your context '{}' is used instead of the team one",
+                name
+            ));
+        }
+        loaded.push(Loaded {
+            name: format!("global:{}", name),
+            source: path.display().to_string(),
+            content: fs::read_to_string(&path)?,
+        });
+    }
+
+    for (name, content) in project::bundled_contexts(config) {
+        loaded.push(Loaded {
+            name,
+            source: "bundled".into(),
+            content,
+        });
+    }
+
+    // A context listed twice is compared once.
+    let first = |i: usize| !loaded[..i].iter().any(|l| l.name == loaded[i].name);
+    for (i, a) in loaded.iter().enumerate().filter(|(i, _)| first(*i)) {
+        let shared_by_a = paragraphs(&a.content);
+        for (_, b) in loaded
+            .iter()
+            .enumerate()
+            .skip(i + 1)
+            .filter(|(j, _)| first(*j))
+        {
+            if a.content.trim() == b.content.trim() {
+                notes.push(format!(
+                    "{} and {} are identical; drop one of them",
+                    a.name, b.name
+                ));
+                continue;
+            }
+            let shared: Vec<String> = paragraphs(&b.content)
+                .intersection(&shared_by_a)
+                .cloned()
+                .collect();
+            if !shared.is_empty() {
+                notes.push(format!(
+                    "{} and {} share {} paragraph{} (~{} tokens)",
+                    a.name,
+                    b.name,
+                    shared.len(),
+                    if shared.len() == 1 { "" } else { "s" },
+                    estimate_tokens(&shared.join("\n\n"))
+                ));
+            }
+        }
+    }
+
+    if loaded.is_empty() {
+        println!("No contexts load in {}", current_dir.display());
+    } else {
+        println!("Contexts loaded in {}:", current_dir.display());
+        let width = loaded.iter().map(|l| l.name.len()).max().unwrap_or(0);
+        let mut total = 0;
+        for l in &loaded {
+            let tokens = estimate_tokens(&l.content);
+            total += tokens;
+            println!(
+                "  {:width$}  {:>7} tokens  {}",
+                l.name,
+                format!("~{}", tokens),
+                l.source,
+                width = width
+            );
+        }
+        println!(
+            "  {:width$}  {:>7} tokens",
+            "total",
+            format!("~{}", total),
+            width = width
+        );
+        if total > LARGE_CONTEXT_TOKENS {
+            notes.push(format!(
+                "contexts add ~{} tokens to every request; `--nocontext` skips them for one run",
+                total
+            ));
+        }
+    }
+
+    for note in &notes {
+        println!("Note: {}", note);
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+    for name in &missing {
+        eprintln!(
+            "Missing: global context '{}'; create it with `tai context add {}` or remove it from global_contexts",
+            name, name
+        );
+    }
+    bail!(
+        "{} global context{} not found",
+        missing.len(),
+        if missing.len() == 1 { "" } else { "s" }
+    )
+}
@@ -0,0 +1,420 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::{get_global_config_dir, load_config, Config};
+use crate::tools::ToolsRegistry;
+use crate::{chat, config, index, say};
+
+/// How often an idle daemon probes the local servers again.
+const REFRESH: Duration = Duration::from_secs(10);
+
+/// Set to run in the calling process even when a daemon is up.
+const BYPASS_VAR: &str = "TAI_NO_DAEMON";
+
+/// What a client sends after its stdin, stdout, and stderr.
+#[derive(Serialize, Deserialize, Default)]
+struct Request {
+    #[serde(default)]
+    stop: bool,
+    #[serde(default)]
+    build: u64,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    cwd: PathBuf,
+    #[serde(default)]
+    env: Vec<(String, String)>,
+}
+
+/// Where the daemon listens: the runtime directory, or the config
+/// directory where there is none.
+fn socket_path() -> Result<PathBuf> {
+    let dir = match dirs::runtime_dir() {
+        Some(dir) => dir,
+        None => get_global_config_dir()?,
+    };
+    Ok(dir.join("tai.sock"))
+}
+
+/// The modification time of this binary. A client and a daemon from
+/// different builds do not hand off to each other.
+fn build_stamp() -> u64 {
+    std::env::current_exe()
+        .and_then(|exe| exe.metadata())
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Send `fds` along with one byte, which carries them.
+fn send_fds(stream: &UnixStream, fds: &[RawFd]) -> std::io::Result<()> {
+    let byte = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_ptr() as *mut libc::c_void,
+        iov_len: 1,
+    };
+    let size = std::mem::size_of_val(fds) as u32;
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(size) } as usize];
+    // SAFETY: msghdr is plain data; the buffers it points to outlive the
+    // call, and the control buffer has room for one header with `fds`.
+    unsafe {
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = control.len() as _;
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(size) as _;
+        std::ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast(), fds.len());
+        if libc::sendmsg(stream.as_raw_fd(), &msg, 0) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// The descriptors `send_fds` sent, up to three.
+fn recv_fds(stream: &UnixStream) -> std::io::Result<Vec<OwnedFd>> {
+    let mut byte = [0u8; 1];
+    let mut iov = libc::iovec {
+        iov_base: byte.as_mut_ptr().cast(),
+        iov_len: 1,
+    };
+    let mut control =
+        vec![0u8; unsafe { libc::CMSG_SPACE(3 * std::mem::size_of::<RawFd>() as u32) } as usize];
+    let mut fds = Vec::new();
+    // SAFETY: as in `send_fds`; each descriptor the kernel put in the
+    // control buffer is new to this process and owned from here on.
+    unsafe {
+        let mut msg: libc::msghdr = std::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = control.len() as _;
+        match libc::recvmsg(stream.as_raw_fd(), &mut msg, 0) {
+            n if n < 0 => return Err(std::io::Error::last_os_error()),
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            _ => {}
+        }
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize)
+                    / std::mem::size_of::<RawFd>();
+                for i in 0..count {
+                    fds.push(OwnedFd::from_raw_fd(std::ptr::read_unaligned(data.add(i))));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok(fds)
+}
+
+fn send_request(stream: &mut UnixStream, request: &Request) -> Result<()> {
+    send_fds(stream, &[0, 1, 2])?;
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+    Ok(())
+}
+
+/// The pid of the run the daemon forked for this client, for Ctrl-C.
+static RUN: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward_interrupt(_: libc::c_int) {
+    let pid = RUN.load(Ordering::Relaxed);
+    if pid > 0 {
+        // SAFETY: kill is async-signal-safe.
+        unsafe { libc::kill(pid, libc::SIGINT) };
+    }
+}
+
+/// Hand this invocation to the daemon, if one is running: it runs with this
+/// terminal, directory, and environment, and its exit code is returned.
+/// `None` means tai should run here instead.
+pub fn forward() -> Option<i32> {
+    if std::env::var_os(BYPASS_VAR).is_some() {
+        return None;
+    }
+    let mut stream = UnixStream::connect(socket_path().ok()?).ok()?;
+    let request = Request {
+        build: build_stamp(),
+        args: std::env::args_os()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect(),
+        cwd: std::env::current_dir().ok()?,
+        env: std::env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+            .collect(),
+        ..Default::default()
+    };
+    send_request(&mut stream, &request).ok()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let pid: i32 = match line.trim() {
+        "stale" => {
+            eprintln!("Warning: The tai daemon runs an older build; restart it with `tai daemon --stop` and `tai daemon`");
+            return None;
+        }
+        "unsafe" => {
+            eprintln!("Warning: The tai daemon cannot fork a run safely; see its output. Running here instead");
+            return None;
+        }
+        pid => pid.parse().ok()?,
+    };
+    RUN.store(pid, Ordering::Relaxed);
+    // SAFETY: the handler only reads an atomic and calls kill.
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            forward_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        )
+    };
+    line.clear();
+    match reader.read_line(&mut line) {
+        Ok(n) if n > 0 => Some(line.trim().parse().unwrap_or(1)),
+        _ => {
+            eprintln!("Error: Lost the connection to the tai daemon");
+            Some(1)
+        }
+    }
+}
+
+/// Probe the local servers, load the index files, and build the default
+/// provider's client, so runs forked afterwards find them ready.
+fn warm(cfg: &Config) {
+    config::warm_probes(cfg);
+    index::warm(cfg, None);
+    let eff = config::select_effective_provider(cfg);
+    if let Err(e) = chat::warm_client(cfg, &ToolsRegistry::with_default(cfg), &eff) {
+        tracing::debug!(error = %format!("{:#}", e), "provider client not built ahead");
+    }
+}
+
+fn readable(fd: RawFd, timeout: Duration) -> bool {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: one valid pollfd.
+    unsafe { libc::poll(&mut pfd, 1, timeout.as_millis() as libc::c_int) > 0 }
+}
+
+/// Report the exit codes of finished runs to their clients. With `block`,
+/// wait until every run has finished.
+fn reap(running: &mut BTreeMap<i32, UnixStream>, block: bool) {
+    while !running.is_empty() {
+        let mut status = 0;
+        let flags = if block { 0 } else { libc::WNOHANG };
+        // SAFETY: status is a valid out pointer.
+        let pid = unsafe { libc::waitpid(-1, &mut status, flags) };
+        if pid <= 0 {
+            break;
+        }
+        let code = if libc::WIFEXITED(status) {
+            libc::WEXITSTATUS(status)
+        } else if libc::WIFSIGNALED(status) {
+            128 + libc::WTERMSIG(status)
+        } else {
+            continue;
+        };
+        if let Some(mut stream) = running.remove(&pid) {
+            let _ = writeln!(stream, "{}", code);
+        }
+    }
+}
+
+/// What the daemon does after reading a request.
+enum Next {
+    Serve,
+    Stop(UnixStream),
+    /// In the forked child: run tai with these arguments.
+    Run(Vec<String>),
+}
+
+/// Threads in this process, where the system lists them.
+fn thread_count() -> Option<usize> {
+    let tasks = fs::read_dir("/proc/self/task").ok()?;
+    Some(tasks.count())
+}
+
+/// Read one request and fork a run for it.
+fn handle(
+    stream: UnixStream,
+    stamp: u64,
+    cfg: &Config,
+    running: &mut BTreeMap<i32, UnixStream>,
+) -> Result<Next> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let fds = match recv_fds(&stream) {
+        Ok(fds) => fds,
+        // A connection only to see whether the daemon is up.
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(Next::Serve),
+        Err(e) => return Err(e.into()),
+    };
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let request: Request = serde_json::from_str(&line).context("Invalid request")?;
+    if request.stop {
+        return Ok(Next::Stop(stream));
+    }
+    let mut stream = stream;
+    if request.build != stamp {
+        writeln!(stream, "stale")?;
+        return Ok(Next::Serve);
+    }
+    if fds.len() != 3 {
+        bail!(
+            "Expected stdin, stdout, and stderr, got {} descriptors",
+            fds.len()
+        );
+    }
+    // A thread left behind by warming up, such as a runtime worker, could
+    // hold a lock the child then waits on forever; the client runs the
+    // request itself instead.
+    if let Some(threads) = thread_count().filter(|&n| n > 1) {
+        writeln!(stream, "unsafe")?;
+        bail!(
+            "The daemon has {} threads, so it cannot fork a run safely",
+            threads
+        );
+    }
+    // SAFETY: the daemon is single-threaded, checked above where the system
+    // shows it, so the child gets a consistent copy of it.
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()).context("Failed to fork"),
+        0 => {
+            drop(stream);
+            if let Err(e) = become_run(&request, fds) {
+                eprintln!("Error: {:#}", e);
+                std::process::exit(1);
+            }
+            Ok(Next::Run(request.args))
+        }
+        pid => {
+            drop(fds);
+            writeln!(stream, "{}", pid)?;
+            running.insert(pid, stream);
+            // The next run in this project finds its index loaded.
+            index::warm(cfg, Some(&request.cwd));
+            Ok(Next::Serve)
+        }
+    }
+}
+
+/// Turn the forked child into the client's run: its terminal, directory,
+/// and environment. A session of its own keeps the daemon's terminal, if
+/// any, from stopping it when it reads the client's.
+fn become_run(request: &Request, fds: Vec<OwnedFd>) -> Result<()> {
+    // SAFETY: plain system calls on descriptors this process owns.
+    unsafe {
+        libc::setsid();
+        for (target, fd) in fds.iter().enumerate() {
+            if libc::dup2(fd.as_raw_fd(), target as RawFd) < 0 {
+                return Err(std::io::Error::last_os_error()).context("Failed to take over stdio");
+            }
+        }
+    }
+    drop(fds);
+    std::env::set_current_dir(&request.cwd)
+        .with_context(|| format!("Failed to enter {}", request.cwd.display()))?;
+    for (key, _) in std::env::vars_os() {
+        std::env::remove_var(key);
+    }
+    for (key, value) in &request.env {
+        std::env::set_var(key, value);
+    }
+    // What the daemon loaded came from its own directory and environment.
+    config::forget_config();
+    Ok(())
+}
+
+fn stop(path: &Path) -> Result<()> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("No tai daemon is listening on {}", path.display()))?;
+    send_request(
+        &mut stream,
+        &Request {
+            stop: true,
+            ..Default::default()
+        },
+    )?;
+    // The daemon closes the connection once it is down.
+    let _ = BufReader::new(stream).read_line(&mut String::new());
+    say!("Stopped the tai daemon");
+    Ok(())
+}
+
+/// Run the daemon until `tai daemon --stop`, which `stop` sends instead.
+/// Each request is served by a child forked from the daemon, in which this
+/// returns the request's arguments for `main` to run as usual.
+pub fn serve(stop_running: bool) -> Result<Option<Vec<String>>> {
+    let path = socket_path()?;
+    if stop_running {
+        stop(&path)?;
+        return Ok(None);
+    }
+    if UnixStream::connect(&path).is_ok() {
+        bail!("A tai daemon is already listening on {}", path.display());
+    }
+    // Left behind by a daemon that did not stop cleanly.
+    let _ = fs::remove_file(&path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+
+    let stamp = build_stamp();
+    let mut cfg = load_config().unwrap_or_default();
+    warm(&cfg);
+    let mut warmed = Instant::now();
+    let mut running = BTreeMap::new();
+    say!("tai daemon listening on {}", path.display());
+    loop {
+        reap(&mut running, false);
+        if readable(listener.as_raw_fd(), Duration::from_millis(250)) {
+            if let Ok((stream, _)) = listener.accept() {
+                match handle(stream, stamp, &cfg, &mut running) {
+                    Ok(Next::Serve) => {}
+                    Ok(Next::Run(args)) => {
+                        drop(listener);
+                        drop(running);
+                        return Ok(Some(args));
+                    }
+                    Ok(Next::Stop(stream)) => {
+                        let _ = fs::remove_file(&path);
+                        drop(listener);
+                        reap(&mut running, true);
+                        drop(stream);
+                        return Ok(None);
+                    }
+                    Err(e) => eprintln!("Warning: Dropped a request: {:#}", e),
+                }
+            }
+        }
+        if warmed.elapsed() >= REFRESH {
+            config::forget_config();
+            cfg = load_config().unwrap_or_default();
+            warm(&cfg);
+            warmed = Instant::now();
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use anyhow::Result;
+use nu_ansi_term::{Color as NuColor, Style};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+use terminal_size::{terminal_size, Width};
+
+use crate::chat;
+use crate::config::{
+    apply_profile, find_config_file, find_project_instructions, get_git_root, global_config_path,
+    list_providers, load_config, named_context_path, select_effective_provider, Config, PROVIDERS,
+};
+use crate::models;
+
+/// Narrower terminals wrap tables and code blocks badly.
+const MIN_COLUMNS: u16 = 60;
+
+/// Keys tai still reads from old configs and moves on the next save.
+const LEGACY_KEYS: &[&str] = &["model", "temperature", "max_tokens", "anthropic_api_key"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Level {
+    Ok,
+    Note,
+    Warn,
+    Fail,
+}
+
+/// The findings of one run, printed as they come.
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    problems: usize,
+}
+
+impl Report {
+    fn section(&self, title: &str) {
+        println!("{}", Style::new().bold().paint(title));
+    }
+
+    fn line(&mut self, level: Level, text: &str, fix: Option<&str>) {
+        let icon = match level {
+            Level::Ok => NuColor::Green.paint("✓"),
+            Level::Note => NuColor::DarkGray.paint("·"),
+            Level::Warn => NuColor::Yellow.paint("!"),
+            Level::Fail => NuColor::Red.paint("✗"),
+        };
+        println!("  {} {}", icon, text);
+        if let Some(fix) = fix {
+            println!("    {} {}", NuColor::Cyan.paint("fix:"), fix);
+        }
+        match level {
+            Level::Warn => self.warnings += 1,
+            Level::Fail => self.problems += 1,
+            _ => {}
+        }
+    }
+
+    fn ok(&mut self, text: &str) {
+        self.line(Level::Ok, text, None);
+    }
+
+    fn note(&mut self, text: &str) {
+        self.line(Level::Note, text, None);
+    }
+
+    fn warn(&mut self, text: &str, fix: &str) {
+        self.line(Level::Warn, text, Some(fix));
+    }
+
+    fn fail(&mut self, text: &str, fix: &str) {
+        self.line(Level::Fail, text, Some(fix));
+    }
+}
+
+/// Check the config, the provider and its endpoint, the context files, and
+/// the terminal, and print what to fix. Fails when something would stop
+/// tai from working.
+pub fn run() -> Result<()> {
+    let mut report = Report::default();
+
+    report.section("Config");
+    let mut files = Vec::new();
+    match global_config_path() {
+        Ok(path) if path.exists() => files.push(path),
+        Ok(path) => report.note(&format!("no global config at {}", path.display())),
+        Err(e) => report.fail(
+            &format!("cannot find the config directory: {:#}", e),
+            "set HOME to your home directory",
+        ),
+    }
+    files.extend(find_config_file());
+    for path in &files {
+        check_file(&mut report, path);
+    }
+    let mut cfg = match load_config() {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            if report.problems == 0 {
+                report.fail(
+                    &format!("the config does not load: {:#}", e),
+                    "check the team config and the files above",
+                );
+            }
+            report.note("the checks below use the defaults");
+            Config::default()
+        }
+    };
+    check_values(&mut report, &cfg);
+    match apply_profile(&mut cfg, None) {
+        Ok(Some(name)) => report.ok(&format!("profile {}", name)),
+        Ok(None) => {}
+        Err(e) => report.fail(
+            &format!("{:#}", e),
+            "fix the profile under [profiles], or change core.default_profile",
+        ),
+    }
+
+    report.section("Provider");
+    check_provider(&mut report, &cfg);
+
+    report.section("Context");
+    check_context(&mut report, &cfg);
+
+    report.section("Terminal");
+    check_terminal(&mut report);
+
+    println!();
+    match (report.problems, report.warnings) {
+        (0, 0) => println!("{}", NuColor::Green.paint("No problems found")),
+        (0, w) => println!(
+            "{}",
+            NuColor::Yellow.paint(format!("{} warning{}", w, plural(w)))
+        ),
+        (p, w) => anyhow::bail!(
+            "{} problem{} and {} warning{} found",
+            p,
+            plural(p),
+            w,
+            plural(w)
+        ),
+    }
+    Ok(())
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+/// Parse one config file on its own, and list the keys tai does not know.
+fn check_file(report: &mut Report, path: &Path) {
+    let shown = path.display().to_string();
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            report.fail(
+                &format!("{}: cannot be read ({})", shown, e),
+                "check the file's permissions",
+            );
+            return;
+        }
+    };
+    let raw: toml::Table = match toml::from_str(&text) {
+        Ok(raw) => raw,
+        Err(e) => {
+            report.fail(
+                &format!("{} is not valid TOML: {}", shown, e.message()),
+                &format!("correct the syntax{}", location(&e)),
+            );
+            return;
+        }
+    };
+    let parsed: Config = match toml::from_str(&text) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            report.fail(
+                &format!("{}: {}", shown, e.message()),
+                &format!("correct the value{}", location(&e)),
+            );
+            return;
+        }
+    };
+    report.ok(&format!("{} parses", shown));
+    // Keys that do not survive a round trip were ignored when reading.
+    let Ok(toml::Value::Table(known)) = toml::Value::try_from(&parsed) else {
+        return;
+    };
+    let mut unknown = Vec::new();
+    unknown_keys(&raw, &known, "", &mut unknown);
+    for key in unknown {
+        if LEGACY_KEYS.contains(&key.as_str()) {
+            report.note(&format!(
+                "{}: {} is an old key; it moves under [providers] on the next save",
+                shown, key
+            ));
+        } else {
+            report.warn(
+                &format!("{}: unknown key {} is ignored", shown, key),
+                "check its spelling and section against the README, or remove it",
+            );
+        }
+    }
+}
+
+/// " at line N, column M" for a parse error.
+fn location(e: &toml::de::Error) -> String {
+    e.to_string()
+        .lines()
+        .next()
+        .and_then(|l| l.strip_prefix("TOML parse error"))
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn unknown_keys(raw: &toml::Table, known: &toml::Table, prefix: &str, out: &mut Vec<String>) {
+    for (key, value) in raw {
+        let path = format!("{}{}", prefix, key);
+        match (value, known.get(key)) {
+            (_, None) => out.push(path),
+            (toml::Value::Table(raw), Some(toml::Value::Table(known))) => {
+                unknown_keys(raw, known, &format!("{}.", path), out)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Values that parse but name something that does not exist.
+fn check_values(report: &mut Report, cfg: &Config) {
+    let known = PROVIDERS.join(", ");
+    if let Some(name) = &cfg.core.active_provider {
+        if !PROVIDERS.contains(&name.as_str()) {
+            report.fail(
+                &format!("core.active_provider names unknown provider '{}'", name),
+                &format!("use one of {}, or run `tai config provider auto`", known),
+            );
+        }
+    }
+    for name in cfg.core.fallback_providers.iter().flatten() {
+        if !PROVIDERS.contains(&name.as_str()) {
+            report.warn(
+                &format!("core.fallback_providers names unknown provider '{}'", name),
+                &format!("use one of {}", known),
+            );
+        }
+    }
+    if let Err(e) = chat::check_style(&cfg.style) {
+        report.fail(
+            &format!("[style]: {:#}", e),
+            "pick an existing preset or define it under [style.presets]",
+        );
+    }
+    if let Some(mode) = &cfg.sandbox.mode {
+        if !["off", "read-only", "workspace"].contains(&mode.as_str()) {
+            report.warn(
+                &format!("sandbox.mode '{}' is not a mode", mode),
+                "use \"off\", \"read-only\", or \"workspace\"",
+            );
+        }
+    }
+    if let Some(retry) = &cfg.refusals.retry {
+        if !["off", "rephrase", "fallback"].contains(&retry.as_str()) {
+            report.warn(
+                &format!("refusals.retry '{}' is not an option", retry),
+                "use \"off\", \"rephrase\", or \"fallback\"",
+            );
+        }
+    }
+    let ui = [
+        ("theme", &cfg.ui.theme),
+        ("code_style", &cfg.ui.code_style),
+        ("tool_header", &cfg.ui.tool_header),
+        ("separator", &cfg.ui.separator),
+        ("spinner", &cfg.ui.spinner),
+    ];
+    for (key, value) in ui {
+        let Some(value) = value else { continue };
+        if let Some(problem) = crate::chat::check_ui_setting(key, value) {
+            report.warn(
+                &format!("ui.{} '{}' is not an option", key, value),
+                &problem,
+            );
+        }
+    }
+    if let Some(mode) = &cfg.editor.mode {
+        if !["emacs", "vi"].contains(&mode.as_str()) {
+            report.warn(
+                &format!("editor.mode '{}' is not an option", mode),
+                "use \"emacs\" or \"vi\"",
+            );
+        }
+    }
+    let approvals = cfg
+        .policy
+        .edits
+        .iter()
+        .map(|v| ("policy.edits".to_string(), v))
+        .chain(
+            cfg.policy
+                .approve
+                .iter()
+                .flatten()
+                .map(|(tool, v)| (format!("policy.approve.{}", tool), v)),
+        );
+    for (key, value) in approvals {
+        if !["ask", "auto", "deny"].contains(&value.as_str()) {
+            report.fail(
+                &format!("{} is '{}'", key, value),
+                "use \"ask\", \"auto\", or \"deny\"",
+            );
+        }
+    }
+}
+
+/// The key each cloud provider needs, and where to get one.
+fn key_fix(provider: &str) -> Option<&'static str> {
+    Some(match provider {
+        "anthropic" => "export ANTHROPIC_API_KEY=... (from console.anthropic.com)",
+        "openai" => {
+            "export OPENAI_API_KEY=..., or set providers.openai.base_url for a compatible server"
+        }
+        "gemini" => "export GEMINI_API_KEY=... (from aistudio.google.com)",
+        "openrouter" => "export OPENROUTER_API_KEY=... (from openrouter.ai)",
+        _ => return None,
+    })
+}
+
+fn check_provider(report: &mut Report, cfg: &Config) {
+    let statuses = list_providers(cfg);
+    for status in &statuses {
+        if status.available {
+            report.ok(&format!("{}: {}", status.name, status.reason));
+        } else {
+            report.note(&format!("{}: {}", status.name, status.reason));
+        }
+    }
+    let eff = select_effective_provider(cfg);
+    let usable = statuses.iter().any(|s| s.name == eff.name && s.available);
+    if !usable {
+        let fix = match key_fix(&eff.name) {
+            Some(fix) => fix.to_string(),
+            None => format!(
+                "start {}, or set a cloud provider's API key",
+                if eff.name == "ollama" {
+                    "Ollama (`ollama serve`)"
+                } else {
+                    "the LM Studio server"
+                }
+            ),
+        };
+        report.fail(
+            &format!("{} would be used but is not available", eff.name),
+            &fix,
+        );
+        return;
+    }
+    match models::check_endpoint(cfg, &eff) {
+        Ok((count, true)) => report.ok(&format!(
+            "{} answers and lists {} models, including {}",
+            eff.name, count, eff.model
+        )),
+        Ok((count, false)) => report.warn(
+            &format!(
+                "{} answers, but its {} models do not include {}",
+                eff.name, count, eff.model
+            ),
+            &format!(
+                "pick one from `tai models --provider {}` and set it with `tai config {} --model ...`",
+                eff.name, eff.name
+            ),
+        ),
+        Err(e) => report.fail(
+            &format!("{} does not answer: {:#}", eff.name, e),
+            "check the key, the endpoint, and [network] proxy settings; `tai config provider list` shows the details",
+        ),
+    }
+}
+
+fn check_context(report: &mut Report, cfg: &Config) {
+    let local = std::env::current_dir()
+        .ok()
+        .map(|dir| dir.join(".context.tai"))
+        .filter(|p| p.exists())
+        .or_else(|| {
+            get_git_root()
+                .map(|root| root.join(".context.tai"))
+                .filter(|p| p.exists())
+        });
+    match local {
+        Some(path) => report.ok(&format!("project context {}", path.display())),
+        None => report.note("no .context.tai here or at the repository root"),
+    }
+    if let Some((path, _)) = find_project_instructions() {
+        report.ok(&format!("project instructions {}", path.display()));
+    }
+    for name in &cfg.global_contexts {
+        match named_context_path(cfg, name) {
+            Ok(Some(path)) => report.ok(&format!("global context {} ({})", name, path.display())),
+            Ok(None) => report.warn(
+                &format!("global context '{}' does not exist", name),
+                &format!(
+                    "create it with `tai context add {}` or remove it from global_contexts",
+                    name
+                ),
+            ),
+            Err(e) => report.warn(
+                &format!("global context '{}': {:#}", name, e),
+                "remove it from global_contexts",
+            ),
+        }
+    }
+}
+
+fn check_terminal(report: &mut Report) {
+    if !std::io::stdout().is_terminal() {
+        report.note("stdout is not a terminal, so answers print as plain text");
+        return;
+    }
+    match terminal_size() {
+        Some((Width(w), _)) if w < MIN_COLUMNS => report.warn(
+            &format!("the terminal is {} columns wide", w),
+            &format!(
+                "widen it to at least {} columns so tables and code fit",
+                MIN_COLUMNS
+            ),
+        ),
+        Some((Width(w), _)) => report.ok(&format!("{} columns", w)),
+        None => report.note("the terminal width is unknown; 80 columns are assumed"),
+    }
+    let term = std::env::var("TERM").unwrap_or_default();
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    if std::env::var_os("NO_COLOR").is_some() {
+        report.note("NO_COLOR is set, so output is not colored");
+    } else if term == "dumb" {
+        report.warn(
+            "TERM is \"dumb\", so colors and redraws are off",
+            "run tai in a terminal emulator, or set TERM=xterm-256color",
+        );
+    } else if matches!(colorterm.as_str(), "truecolor" | "24bit") {
+        report.ok("truecolor");
+    } else if term.contains("256color") {
+        report.warn(
+            "256 colors only, so syntax highlighting is approximated",
+            "if your terminal supports truecolor, export COLORTERM=truecolor",
+        );
+    } else {
+        report.warn(
+            &format!(
+                "TERM is \"{}\" without truecolor, so highlighting is limited",
+                term
+            ),
+            "use a terminal with truecolor and export COLORTERM=truecolor",
+        );
+    }
+}
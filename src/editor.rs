@@ -0,0 +1,85 @@
+use anyhow::Result;
+use rustyline::config::EditMode;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use crate::config::{get_global_config_dir, EditorConfig};
+use crate::output;
+
+/// Questions kept in the prompt history; the oldest go first.
+const MAX_HISTORY: usize = 1000;
+
+fn history_path() -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("prompt_history"))
+}
+
+/// The question typed after `> `, up to an empty line. On a terminal it is
+/// read with line editing, `[editor]`'s key bindings, and the prompt
+/// history; otherwise as plain lines. Empty when nothing was typed.
+pub fn read_prompt(settings: &EditorConfig) -> Result<String> {
+    if !std::io::stdin().is_terminal() || output::stdout_reserved() {
+        return read_plain();
+    }
+    let mode = match settings.mode.as_deref() {
+        Some("vi") => EditMode::Vi,
+        _ => EditMode::Emacs,
+    };
+    let config = rustyline::Config::builder()
+        .edit_mode(mode)
+        .max_history_size(MAX_HISTORY)?
+        .history_ignore_dups(true)?
+        .build();
+    let mut editor = DefaultEditor::with_config(config)?;
+    let path = history_path()
+        .ok()
+        .filter(|_| settings.history != Some(false));
+    if let Some(path) = &path {
+        // Missing on the first run.
+        let _ = editor.load_history(path);
+    }
+    let mut lines: Vec<String> = Vec::new();
+    loop {
+        let prompt = if lines.is_empty() { "> " } else { "  " };
+        match editor.readline(prompt) {
+            Ok(line) if line.trim().is_empty() && !lines.is_empty() => break,
+            Ok(line) => lines.push(line),
+            Err(ReadlineError::Eof) => break,
+            Err(ReadlineError::Interrupted) => return Ok(String::new()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let input = lines.join("\n").trim().to_string();
+    if let Some(path) = path.filter(|_| !input.is_empty()) {
+        editor.add_history_entry(input.as_str())?;
+        if let Err(e) = editor.save_history(&path) {
+            tracing::debug!("Failed to save prompt history: {}", e);
+        }
+    }
+    Ok(input)
+}
+
+fn read_plain() -> Result<String> {
+    if output::stdout_reserved() {
+        eprint!("> ");
+    } else {
+        print!("> ");
+        std::io::Write::flush(&mut std::io::stdout())?;
+    }
+    let mut input = String::new();
+    loop {
+        let mut line = String::new();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {
+                input.push_str(&line);
+                if line.trim().is_empty() && !input.trim().is_empty() {
+                    break;
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(input.trim().to_string())
+}
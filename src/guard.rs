@@ -0,0 +1,112 @@
+use crate::config::QuickFactsConfig;
+
+/// A class of question whose answer depends on live data the model cannot
+/// know. Matching is by lowercase phrase so it stays cheap and predictable.
+struct FactClass {
+    name: &'static str,
+    phrases: &'static [&'static str],
+    /// Local tool that can answer this class without web access.
+    tool: Option<&'static str>,
+}
+
+const BUILTIN: &[FactClass] = &[
+    FactClass {
+        name: "weather",
+        phrases: &[
+            "weather",
+            "will it rain",
+            "is it raining",
+            "will it snow",
+            "is it snowing",
+            "how hot is it",
+            "how cold is it",
+        ],
+        tool: None,
+    },
+    FactClass {
+        name: "prices",
+        phrases: &[
+            "stock price",
+            "share price",
+            "exchange rate",
+            "bitcoin price",
+            "price of bitcoin",
+            "btc price",
+            "eth price",
+            "crypto price",
+            "gas price",
+            "trading at",
+        ],
+        tool: None,
+    },
+    FactClass {
+        name: "news",
+        phrases: &[
+            "latest news",
+            "breaking news",
+            "headlines",
+            "what happened today",
+        ],
+        tool: None,
+    },
+    FactClass {
+        name: "sports",
+        phrases: &[
+            "live score",
+            "final score",
+            "who won the game",
+            "who won the match",
+            "who won last night",
+        ],
+        tool: None,
+    },
+    FactClass {
+        name: "time",
+        phrases: &[
+            "what time is it",
+            "what's the time",
+            "current time",
+            "time right now",
+            "what day is it",
+            "today's date",
+            "what is the date",
+        ],
+        tool: Some("convert"),
+    },
+];
+
+pub struct QuickFactHit {
+    pub class: String,
+    pub tool: Option<&'static str>,
+}
+
+/// Return the first blocked class whose phrases appear in `input`.
+pub fn check(cfg: &QuickFactsConfig, input: &str) -> Option<QuickFactHit> {
+    if !cfg.enabled.unwrap_or(false) {
+        return None;
+    }
+    let text = input.to_lowercase();
+    let blocked = |name: &str| {
+        cfg.blocked
+            .as_ref()
+            .is_none_or(|list| list.iter().any(|b| b == name))
+    };
+    for class in BUILTIN.iter().filter(|c| blocked(c.name)) {
+        if class.phrases.iter().any(|p| text.contains(p)) {
+            return Some(QuickFactHit {
+                class: class.name.to_string(),
+                tool: class.tool,
+            });
+        }
+    }
+    // User-defined classes are always active.
+    for (name, phrases) in cfg.patterns.iter().flatten() {
+        if phrases.iter().any(|p| text.contains(&p.to_lowercase())) {
+            return Some(QuickFactHit {
+                class: name.clone(),
+                tool: None,
+            });
+        }
+    }
+    None
+}
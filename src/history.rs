@@ -1,10 +1,28 @@
-use anyhow::{Context, Result};
-use chrono::{DateTime, Duration, Utc};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local, Utc};
 use dirs::home_dir;
+use nu_ansi_term::{Color as NuColor, Style};
+use regex::RegexBuilder;
 use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::config::{get_global_config_dir, HistoryConfig};
+use crate::say;
+
+/// The session questions go to until `/branch` or `/session` picks another;
+/// its history is ~/.tai.history.
+pub const MAIN_SESSION: &str = "main";
+
+/// Entries kept in the history file by default; the oldest go first.
+const MAX_ENTRIES: usize = 1000;
+
+/// Minutes an entry is sent along with new questions by default.
+const RELEVANT_MINUTES: u64 = 60;
+
+/// Recent entries sent along with a question, at most.
+const RELEVANT_ENTRIES: usize = 10;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct HistoryEntry {
@@ -16,10 +34,24 @@ pub struct HistoryEntry {
 #[derive(Debug, Deserialize, Serialize, Default)]
 pub struct History {
     pub entries: Vec<HistoryEntry>,
+    #[serde(skip)]
+    settings: HistoryConfig,
 }
 
 impl History {
-    pub fn load() -> Result<Self> {
+    /// The history as `settings` allow: empty, and never written, when it is
+    /// turned off.
+    pub fn load(settings: &HistoryConfig) -> Result<Self> {
+        let mut history = if settings.enabled == Some(false) {
+            Self::default()
+        } else {
+            Self::read()?
+        };
+        history.settings = settings.clone();
+        Ok(history)
+    }
+
+    fn read() -> Result<Self> {
         let history_path = Self::history_path()?;
 
         if !history_path.exists() {
@@ -62,6 +94,9 @@ impl History {
     }
 
     pub fn add_entry(&mut self, user_input: String, llm_response: String) -> Result<()> {
+        if self.settings.enabled == Some(false) {
+            return Ok(());
+        }
         let entry = HistoryEntry {
             timestamp: Utc::now(),
             user_input,
@@ -70,8 +105,9 @@ impl History {
 
         self.entries.push(entry);
 
-        if self.entries.len() > 10 {
-            self.entries = self.entries.split_off(self.entries.len() - 10);
+        let max = self.settings.max_entries.unwrap_or(MAX_ENTRIES);
+        if self.entries.len() > max {
+            self.entries = self.entries.split_off(self.entries.len() - max);
         }
 
         self.save()
@@ -79,13 +115,18 @@ impl History {
 
     pub fn get_relevant_entries(&self) -> Vec<(HistoryEntry, Duration)> {
         let now = Utc::now();
-        let one_hour = Duration::hours(1);
+        let minutes = self.settings.relevant_minutes.unwrap_or(RELEVANT_MINUTES);
+        let window = i64::try_from(minutes)
+            .ok()
+            .and_then(Duration::try_minutes)
+            .unwrap_or(Duration::MAX);
 
-        self.entries
+        let recent = self.entries.len().saturating_sub(RELEVANT_ENTRIES);
+        self.entries[recent..]
             .iter()
             .filter_map(|entry| {
                 let age = now - entry.timestamp;
-                if age < one_hour {
+                if age < window {
                     Some((entry.clone(), age))
                 } else {
                     None
@@ -95,9 +136,7 @@ impl History {
     }
 
     pub fn clear() -> Result<()> {
-        let history_file = dirs::home_dir()
-            .context("Failed to get home directory")?
-            .join(".tai_history.json");
+        let history_file = Self::history_path()?;
 
         if history_file.exists() {
             fs::remove_file(&history_file)?;
@@ -107,8 +146,264 @@ impl History {
     }
 
     fn history_path() -> Result<PathBuf> {
+        session_path(&active_session())
+    }
+}
+
+fn sessions_dir() -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("sessions"))
+}
+
+/// The session in use, as `/branch` and `/session` left it.
+pub fn active_session() -> String {
+    sessions_dir()
+        .and_then(|dir| Ok(fs::read_to_string(dir.join("active"))?))
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| valid_name(name).is_ok())
+        .unwrap_or_else(|| MAIN_SESSION.to_string())
+}
+
+fn set_active_session(name: &str) -> Result<()> {
+    let dir = sessions_dir()?;
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create directory at {:?}", dir))?;
+    fs::write(dir.join("active"), name).context("Failed to save the active session")
+}
+
+fn valid_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("Session names may only use letters, digits, '-', and '_'");
+    }
+    Ok(())
+}
+
+fn session_path(name: &str) -> Result<PathBuf> {
+    if name == MAIN_SESSION {
         let mut path = home_dir().context("Failed to get home directory")?;
         path.push(".tai.history");
-        Ok(path)
+        return Ok(path);
+    }
+    Ok(sessions_dir()?.join(format!("{}.json", name)))
+}
+
+/// `/branch <name>`: copies the current session's history into a new
+/// session `name` and continues there.
+pub fn branch(settings: &HistoryConfig, name: &str) -> Result<()> {
+    if settings.enabled == Some(false) {
+        bail!("History is turned off (history.enabled = false)");
+    }
+    valid_name(name)?;
+    if session_path(name)?.exists() || name == MAIN_SESSION {
+        bail!("A session named '{}' already exists", name);
+    }
+    let from = active_session();
+    let history = History::load(settings)?;
+    set_active_session(name)?;
+    history.save()?;
+    say!(
+        "Branched session '{}' into '{}' with {} entries; later questions continue there",
+        from,
+        name,
+        history.entries.len()
+    );
+    Ok(())
+}
+
+/// `/session [NAME]`: switches to session `name`, or lists the sessions.
+pub fn session_command(name: &str) -> Result<()> {
+    if !name.is_empty() {
+        valid_name(name)?;
+        if !session_path(name)?.exists() && name != MAIN_SESSION {
+            bail!("No session named '{}'; /branch {} starts one", name, name);
+        }
+        set_active_session(name)?;
+        say!("Switched to session '{}'", name);
+        return Ok(());
+    }
+    let mut names = vec![MAIN_SESSION.to_string()];
+    if let Ok(entries) = fs::read_dir(sessions_dir()?) {
+        let mut branched: Vec<_> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                e.file_name()
+                    .to_str()?
+                    .strip_suffix(".json")
+                    .map(str::to_string)
+            })
+            .collect();
+        branched.sort();
+        names.extend(branched);
+    }
+    let active = active_session();
+    for name in names {
+        let marker = if name == active { "*" } else { " " };
+        println!("{} {}", marker, name);
+    }
+    Ok(())
+}
+
+fn first_line(text: &str, max: usize) -> String {
+    let line = text.trim().lines().next().unwrap_or_default();
+    if line.chars().count() > max {
+        format!("{}…", line.chars().take(max).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+fn local_time(entry: &HistoryEntry) -> String {
+    entry
+        .timestamp
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// The 1-based `page` of `items`, newest first, and the number of pages.
+fn page_of<T>(items: &[T], page: usize, per_page: usize) -> Result<(Vec<&T>, usize)> {
+    let per_page = per_page.max(1);
+    let pages = items.len().div_ceil(per_page).max(1);
+    if page == 0 || page > pages {
+        bail!("No page {}; the last is page {}", page, pages);
+    }
+    Ok((
+        items
+            .iter()
+            .rev()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .collect(),
+        pages,
+    ))
+}
+
+fn print_entry_line(number: usize, entry: &HistoryEntry) {
+    println!(
+        "{}  {}  {}",
+        NuColor::DarkGray.paint(format!("{:>5}", number)),
+        NuColor::DarkGray.paint(local_time(entry)),
+        first_line(&entry.user_input, 80)
+    );
+}
+
+fn print_page_footer(page: usize, pages: usize) {
+    if page < pages {
+        println!(
+            "{}",
+            NuColor::DarkGray.paint(format!(
+                "Page {} of {}; --page {} shows older entries",
+                page,
+                pages,
+                page + 1
+            ))
+        );
+    }
+}
+
+/// `tai history list`: entries newest first, numbered from the oldest so a
+/// number stays the same as new ones are added.
+pub fn handle_history_list(settings: &HistoryConfig, page: usize, per_page: usize) -> Result<()> {
+    if settings.enabled == Some(false) {
+        println!("History is turned off (history.enabled = false)");
+        return Ok(());
+    }
+    let history = History::load(settings)?;
+    if history.entries.is_empty() {
+        println!("No history yet");
+        return Ok(());
+    }
+    let numbered: Vec<_> = history.entries.iter().enumerate().collect();
+    let (shown, pages) = page_of(&numbered, page, per_page)?;
+    for (i, entry) in shown {
+        print_entry_line(i + 1, entry);
+    }
+    print_page_footer(page, pages);
+    Ok(())
+}
+
+/// `tai history search <regex>`: entries whose question or answer matches,
+/// newest first, with the matching lines.
+pub fn handle_history_search(
+    settings: &HistoryConfig,
+    pattern: &str,
+    ignore_case: bool,
+    page: usize,
+    per_page: usize,
+) -> Result<()> {
+    let re = RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .with_context(|| format!("Invalid pattern '{}'", pattern))?;
+    let history = History::load(settings)?;
+    let hits: Vec<_> = history
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| re.is_match(&e.user_input) || re.is_match(&e.llm_response))
+        .collect();
+    if hits.is_empty() {
+        println!("No entries match '{}'", pattern);
+        return Ok(());
+    }
+    let (shown, pages) = page_of(&hits, page, per_page)?;
+    let highlight = Style::new().bold().fg(NuColor::Yellow);
+    for (i, entry) in shown {
+        print_entry_line(i + 1, entry);
+        // The question's first line is already on the entry line.
+        let lines = entry
+            .user_input
+            .lines()
+            .skip(1)
+            .chain(entry.llm_response.lines())
+            .filter(|l| re.is_match(l));
+        for line in lines.take(3) {
+            let line = first_line(line, 100);
+            let marked = re.replace_all(&line, |c: &regex::Captures| {
+                highlight.paint(&c[0]).to_string()
+            });
+            println!("       {}", marked);
+        }
+    }
+    print_page_footer(page, pages);
+    Ok(())
+}
+
+/// `tai history export`: every entry, oldest first, as JSON lines or
+/// Markdown, to `output` or stdout.
+pub fn handle_history_export(
+    settings: &HistoryConfig,
+    markdown: bool,
+    output: Option<&Path>,
+) -> Result<()> {
+    let history = History::load(settings)?;
+    let mut out = String::new();
+    for entry in &history.entries {
+        if markdown {
+            out.push_str(&format!(
+                "## {}\n\n**Question:** {}\n\n{}\n\n",
+                local_time(entry),
+                entry.user_input.trim(),
+                entry.llm_response.trim()
+            ));
+        } else {
+            out.push_str(&serde_json::to_string(entry)?);
+            out.push('\n');
+        }
+    }
+    match output {
+        Some(path) => {
+            fs::write(path, out).with_context(|| format!("Failed to write {}", path.display()))?;
+            eprintln!(
+                "Exported {} entries to {}",
+                history.entries.len(),
+                path.display()
+            );
+        }
+        None => print!("{}", out),
     }
+    Ok(())
 }
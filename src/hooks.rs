@@ -0,0 +1,144 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::fs;
+use std::sync::LazyLock;
+
+/// Error output sent to the model, from the end where the error usually is.
+const MAX_STDERR_CHARS: usize = 4000;
+
+static ESCAPES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\x1b(\[[0-9;?]*[A-Za-z]|\][^\x07]*\x07|[()][A-Z0-9]|[=>])").expect("valid regex")
+});
+
+const BASH: &str = r#"# tai shell integration: `tai fix` corrects the last failed command.
+__tai_record() {
+    local status=$?
+    export TAI_LAST_STATUS=$status
+    export TAI_LAST_COMMAND="$(HISTTIMEFORMAT= builtin history 1 | sed 's/^ *[0-9]* *//')"
+    return $status
+}
+PROMPT_COMMAND="__tai_record${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+"#;
+
+/// Tees the shell's stderr into a file that is emptied as each command
+/// starts (PS0) and copied once it ends.
+const BASH_STDERR: &str = r#"export TAI_LAST_STDERR="${TMPDIR:-/tmp}/tai-stderr-$$"
+: > "$TAI_LAST_STDERR.live"
+exec 2> >(tee -a "$TAI_LAST_STDERR.live" >&2)
+PS0='$(: > "$TAI_LAST_STDERR.live")'"$PS0"
+__tai_record_stderr() {
+    local status=$?
+    cp "$TAI_LAST_STDERR.live" "$TAI_LAST_STDERR" 2>/dev/null
+    return $status
+}
+PROMPT_COMMAND="__tai_record_stderr;$PROMPT_COMMAND"
+"#;
+
+const ZSH: &str = r#"# tai shell integration: `tai fix` corrects the last failed command.
+__tai_preexec() {
+    __tai_command="$1"
+}
+__tai_precmd() {
+    export TAI_LAST_STATUS=$?
+    export TAI_LAST_COMMAND="$__tai_command"
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec __tai_preexec
+precmd_functions=(__tai_precmd $precmd_functions)
+"#;
+
+const ZSH_STDERR: &str = r#"export TAI_LAST_STDERR="${TMPDIR:-/tmp}/tai-stderr-$$"
+: > "$TAI_LAST_STDERR.live"
+exec 2> >(tee -a "$TAI_LAST_STDERR.live" >&2)
+__tai_preexec_stderr() {
+    : > "$TAI_LAST_STDERR.live"
+}
+__tai_precmd_stderr() {
+    cp "$TAI_LAST_STDERR.live" "$TAI_LAST_STDERR" 2>/dev/null
+}
+add-zsh-hook preexec __tai_preexec_stderr
+add-zsh-hook precmd __tai_precmd_stderr
+"#;
+
+const FISH: &str = r#"# tai shell integration: `tai fix` corrects the last failed command.
+function __tai_record --on-event fish_postexec
+    set -gx TAI_LAST_STATUS $status
+    set -gx TAI_LAST_COMMAND $argv[1]
+end
+"#;
+
+/// `tai init <shell>`: print the hook that records each command and its exit
+/// status for `tai fix`, and with `stderr` its error output as well.
+pub fn init(shell: &str, stderr: bool) -> Result<()> {
+    let (hook, capture) = match shell {
+        "bash" => (BASH, Some(BASH_STDERR)),
+        "zsh" => (ZSH, Some(ZSH_STDERR)),
+        "fish" => (FISH, None),
+        other => bail!("Unsupported shell '{}'", other),
+    };
+    if stderr && capture.is_none() {
+        bail!("--stderr is not supported for {}", shell);
+    }
+    print!("{}", hook);
+    if let Some(capture) = capture.filter(|_| stderr) {
+        print!("{}", capture);
+    }
+    Ok(())
+}
+
+/// The question `tai fix` asks: the last command the shell hook recorded,
+/// its exit status and error output, and the user's `note`.
+pub fn fix_prompt(note: &str) -> Result<String> {
+    let command = std::env::var("TAI_LAST_COMMAND")
+        .ok()
+        .filter(|c| !c.trim().is_empty())
+        .context(
+            "No command recorded. Load the shell hook first, e.g. eval \"$(tai init bash)\" in ~/.bashrc, eval \"$(tai init zsh)\" in ~/.zshrc, or tai init fish | source in config.fish",
+        )?;
+    let status = std::env::var("TAI_LAST_STATUS").unwrap_or_default();
+    if status.trim() == "0" {
+        bail!("`{}` succeeded; there is nothing to fix", command.trim());
+    }
+    let mut prompt = format!(
+        "My last shell command failed.\n\nCommand: `{}`\nExit status: {}\n",
+        command.trim(),
+        if status.is_empty() {
+            "unknown"
+        } else {
+            status.trim()
+        }
+    );
+    let stderr = std::env::var("TAI_LAST_STDERR")
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|s| clean_stderr(&s, command.trim()))
+        .filter(|s| !s.trim().is_empty());
+    if let Some(stderr) = stderr {
+        let chars = stderr.chars().count();
+        let tail: String = stderr
+            .chars()
+            .skip(chars.saturating_sub(MAX_STDERR_CHARS))
+            .collect();
+        prompt.push_str(&format!("Error output:\n```\n{}\n```\n", tail.trim_end()));
+    }
+    if !note.trim().is_empty() {
+        prompt.push_str(&format!("Note: {}\n", note.trim()));
+    }
+    prompt.push_str(
+        "\nSay in one sentence what went wrong, then propose the corrected command by calling run_shell with it.",
+    );
+    Ok(prompt)
+}
+
+/// Captured stderr without terminal escapes, and without the prompt and
+/// command line bash echoes through stderr, which can reach the file after
+/// it was emptied.
+fn clean_stderr(raw: &str, command: &str) -> String {
+    let text = ESCAPES.replace_all(raw, "").replace('\r', "");
+    let lines: Vec<&str> = text.lines().collect();
+    let start = lines
+        .iter()
+        .rposition(|l| l.trim_end().ends_with(command))
+        .map_or(0, |i| i + 1);
+    lines[start..].join("\n")
+}
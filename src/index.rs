@@ -0,0 +1,466 @@
+use anyhow::{bail, Context, Result};
+use chrono::{Duration, Utc};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::chat::{setup, Provider};
+use crate::config::{get_git_root, get_global_config_dir, resolve_effective_provider, Config};
+use crate::history::{History, HistoryEntry};
+use crate::say;
+use crate::tools::ToolsRegistry;
+
+/// History entries and file excerpts sent per question by default, each.
+const TOP_K: usize = 5;
+
+/// Lines per file excerpt.
+const EXCERPT_LINES: usize = 40;
+
+/// Files larger than this are not indexed.
+const MAX_FILE_BYTES: u64 = 256 * 1024;
+
+/// Excerpts a project index holds at most; files past it are left out.
+const MAX_EXCERPTS: usize = 4000;
+
+/// Characters of an entry or excerpt that are embedded.
+const MAX_EMBED_CHARS: usize = 4000;
+
+/// Texts sent per embedding request.
+const BATCH: usize = 64;
+
+/// Generated files that only add noise to a search.
+const SKIPPED_FILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "go.sum",
+];
+
+/// The embedding model used when `index.model` is not set.
+fn default_model(provider: &str) -> Result<&'static str> {
+    Ok(match provider {
+        "openai" => "text-embedding-3-small",
+        "gemini" => "text-embedding-004",
+        "openrouter" => "openai/text-embedding-3-small",
+        "ollama" => "nomic-embed-text",
+        "lmstudio" => "text-embedding-nomic-embed-text-v1.5",
+        "anthropic" => {
+            bail!("anthropic has no embeddings API; set index.provider to another provider")
+        }
+        other => bail!("Unsupported index provider: {}", other),
+    })
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct Item {
+    /// md5 of the embedded text, so a changed text is embedded again.
+    hash: String,
+    vector: Vec<f32>,
+}
+
+/// One index file: embeddings by key, valid for one model.
+#[derive(Deserialize, Serialize, Default, Clone)]
+struct Store {
+    model: String,
+    items: BTreeMap<String, Item>,
+}
+
+/// Index files read ahead by a daemon, with the modification time they had
+/// then, so the runs it forks skip parsing them.
+static PRELOADED: Mutex<BTreeMap<PathBuf, (SystemTime, Store)>> = Mutex::new(BTreeMap::new());
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Store {
+    fn read(path: &Path) -> Option<Self> {
+        let preloaded = PRELOADED.lock().unwrap();
+        match (preloaded.get(path), modified(path)) {
+            (Some((at, store)), Some(now)) if *at == now => Some(store.clone()),
+            _ => {
+                drop(preloaded);
+                serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+            }
+        }
+    }
+
+    fn load(path: &Path, model: &str) -> Self {
+        Self::read(path)
+            // Vectors of another model cannot be compared with this one's.
+            .filter(|store| store.model == model)
+            .unwrap_or_else(|| Store {
+                model: model.to_string(),
+                items: BTreeMap::new(),
+            })
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The keys of `texts` whose text is new or changed, after dropping the
+    /// items that are no longer in `texts`.
+    fn stale<'t>(&mut self, texts: &'t [(String, String)]) -> Vec<&'t (String, String)> {
+        self.items
+            .retain(|key, _| texts.iter().any(|(k, _)| k == key));
+        texts
+            .iter()
+            .filter(|(key, text)| {
+                self.items
+                    .get(key)
+                    .is_none_or(|item| item.hash != hash(text))
+            })
+            .collect()
+    }
+
+    /// Keys by similarity to `query`, best first.
+    fn ranked(&self, query: &[f32]) -> Vec<(&str, f32)> {
+        let mut scored: Vec<(&str, f32)> = self
+            .items
+            .iter()
+            .map(|(key, item)| (key.as_str(), cosine(query, &item.vector)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+    }
+}
+
+fn hash(text: &str) -> String {
+    format!("{:x}", md5::compute(text))
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 {
+        0.0
+    } else {
+        dot / denominator
+    }
+}
+
+fn clip(text: &str) -> String {
+    text.chars().take(MAX_EMBED_CHARS).collect()
+}
+
+fn index_dir() -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("index"))
+}
+
+fn history_index_path() -> Result<PathBuf> {
+    Ok(index_dir()?.join("history.json"))
+}
+
+fn project_index_path(root: &Path) -> Result<PathBuf> {
+    Ok(index_dir()?.join(format!(
+        "project-{}.json",
+        hash(&root.display().to_string())
+    )))
+}
+
+/// Read the history's index file, and the project's at `dir` when given,
+/// ahead of the runs a daemon forks.
+pub fn warm(cfg: &Config, dir: Option<&Path>) {
+    if cfg.index.enabled != Some(true) {
+        return;
+    }
+    let path = match dir {
+        None => history_index_path().ok(),
+        Some(dir) if cfg.index.project_files == Some(true) => std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["rev-parse", "--show-toplevel"])
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .and_then(|out| String::from_utf8(out.stdout).ok())
+            .and_then(|root| project_index_path(Path::new(root.trim())).ok()),
+        Some(_) => None,
+    };
+    let Some(path) = path else {
+        return;
+    };
+    let Some(at) = modified(&path) else {
+        return;
+    };
+    if PRELOADED
+        .lock()
+        .unwrap()
+        .get(&path)
+        .is_some_and(|(loaded, _)| *loaded == at)
+    {
+        return;
+    }
+    if let Some(store) = Store::read(&path) {
+        PRELOADED.lock().unwrap().insert(path, (at, store));
+    }
+}
+
+/// Forget the embeddings of the history, along with the history itself.
+pub fn clear_history() -> Result<()> {
+    let path = history_index_path()?;
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+/// A stretch of a project file, lines `start..=end`.
+pub struct Excerpt {
+    pub path: String,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl Excerpt {
+    fn key(&self) -> String {
+        format!("{}:{}", self.path, self.start)
+    }
+}
+
+/// The text files of the project at `root`, cut into excerpts.
+fn project_excerpts(root: &Path) -> Vec<Excerpt> {
+    let mut out = Vec::new();
+    let mut files: Vec<PathBuf> = WalkBuilder::new(root)
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+        .filter(|e| e.metadata().is_ok_and(|m| m.len() <= MAX_FILE_BYTES))
+        .filter(|e| !SKIPPED_FILES.iter().any(|s| e.file_name() == *s))
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+    for file in files {
+        let Ok(text) = fs::read_to_string(&file) else {
+            continue;
+        };
+        if text.contains('\0') {
+            continue;
+        }
+        let path = file
+            .strip_prefix(root)
+            .unwrap_or(&file)
+            .display()
+            .to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        for (i, chunk) in lines.chunks(EXCERPT_LINES).enumerate() {
+            if chunk.iter().all(|l| l.trim().is_empty()) {
+                continue;
+            }
+            if out.len() == MAX_EXCERPTS {
+                eprintln!(
+                    "Warning: Indexed the first {} excerpts of {}; the rest of the project is left out",
+                    MAX_EXCERPTS,
+                    root.display()
+                );
+                return out;
+            }
+            let start = i * EXCERPT_LINES + 1;
+            out.push(Excerpt {
+                path: path.clone(),
+                start,
+                end: start + chunk.len() - 1,
+                text: chunk.join("\n"),
+            });
+        }
+    }
+    out
+}
+
+/// What the index found for a question.
+pub struct Retrieved {
+    /// Oldest first, as the history lists them.
+    pub entries: Vec<(HistoryEntry, Duration)>,
+    /// Best match first.
+    pub excerpts: Vec<Excerpt>,
+}
+
+/// Embeddings of the history, and optionally of the project's files, kept
+/// under `~/.config/tai/index` and brought up to date before each lookup.
+pub struct Index {
+    llm: Box<dyn Provider>,
+    model: String,
+    top_k: usize,
+    history: bool,
+    project_files: bool,
+}
+
+impl Index {
+    /// The index `cfg` asks for, or `None` when it is turned off.
+    pub fn new(cfg: &Config) -> Result<Option<Self>> {
+        if cfg.index.enabled != Some(true) {
+            return Ok(None);
+        }
+        let provider = cfg.index.provider.as_deref().unwrap_or("openai");
+        let model = match &cfg.index.model {
+            Some(model) => model.clone(),
+            None => default_model(provider)?.to_string(),
+        };
+        let eff = resolve_effective_provider(cfg, Some(provider), Some(&model))?;
+        Ok(Some(Self {
+            llm: setup(cfg, &ToolsRegistry::new(), &eff)?,
+            model,
+            top_k: cfg.index.top_k.unwrap_or(TOP_K),
+            history: cfg.history.enabled != Some(false),
+            project_files: cfg.index.project_files.unwrap_or(false),
+        }))
+    }
+
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(BATCH) {
+            let embedded = self
+                .llm
+                .embed(batch.to_vec())
+                .await
+                .with_context(|| format!("Failed to get embeddings from {}", self.model))?;
+            if embedded.len() != batch.len() {
+                bail!(
+                    "{} returned {} embeddings for {} texts",
+                    self.model,
+                    embedded.len(),
+                    batch.len()
+                );
+            }
+            vectors.extend(embedded);
+        }
+        Ok(vectors)
+    }
+
+    /// The history entries and project excerpts closest to `question`. The
+    /// latest entry is kept when it is still within the history window, so a
+    /// follow-up question finds what it follows up on.
+    pub async fn retrieve(&self, question: &str, history: &History) -> Result<Retrieved> {
+        let history_path = history_index_path()?;
+        let mut history_store = Store::load(&history_path, &self.model);
+        let entry_texts: Vec<(String, String)> = if self.history {
+            history
+                .entries
+                .iter()
+                .map(|e| {
+                    (
+                        e.timestamp.to_rfc3339(),
+                        clip(&format!(
+                            "User: {}\nAssistant: {}",
+                            e.user_input, e.llm_response
+                        )),
+                    )
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let root = self.project_files.then(get_git_root).flatten();
+        let project_path = match &root {
+            Some(root) => Some(project_index_path(root)?),
+            None => None,
+        };
+        let mut project_store = project_path
+            .as_deref()
+            .map(|p| Store::load(p, &self.model))
+            .unwrap_or_default();
+        let excerpts = root.as_deref().map(project_excerpts).unwrap_or_default();
+        let excerpt_texts: Vec<(String, String)> =
+            excerpts.iter().map(|e| (e.key(), clip(&e.text))).collect();
+
+        let stale_entries = if self.history {
+            history_store.stale(&entry_texts)
+        } else {
+            Vec::new()
+        };
+        let stale_excerpts = project_store.stale(&excerpt_texts);
+        let pending = stale_entries.len() + stale_excerpts.len();
+        if pending > BATCH {
+            say!(
+                "Indexing {} new history entries and file excerpts…",
+                pending
+            );
+        }
+        let mut texts: Vec<String> = stale_entries
+            .iter()
+            .chain(&stale_excerpts)
+            .map(|(_, text)| text.clone())
+            .collect();
+        texts.push(question.to_string());
+        let mut vectors = self.embed(texts).await?;
+        let query = vectors.pop().unwrap_or_default();
+        let mut vectors = vectors.into_iter();
+        for ((key, text), vector) in stale_entries.iter().zip(vectors.by_ref()) {
+            history_store.items.insert(
+                key.clone(),
+                Item {
+                    hash: hash(text),
+                    vector,
+                },
+            );
+        }
+        for ((key, text), vector) in stale_excerpts.iter().zip(vectors) {
+            project_store.items.insert(
+                key.clone(),
+                Item {
+                    hash: hash(text),
+                    vector,
+                },
+            );
+        }
+        if self.history {
+            history_store.save(&history_path)?;
+        }
+        if let Some(path) = &project_path {
+            project_store.save(path)?;
+        }
+
+        let mut keys: Vec<String> = history_store
+            .ranked(&query)
+            .into_iter()
+            .take(self.top_k)
+            .map(|(key, _)| key.to_string())
+            .collect();
+        if let Some((latest, _)) = history.get_relevant_entries().last() {
+            let key = latest.timestamp.to_rfc3339();
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        let now = Utc::now();
+        let entries = history
+            .entries
+            .iter()
+            .filter(|e| keys.contains(&e.timestamp.to_rfc3339()))
+            .map(|e| (e.clone(), now - e.timestamp))
+            .collect();
+
+        let mut by_key: BTreeMap<String, Excerpt> =
+            excerpts.into_iter().map(|e| (e.key(), e)).collect();
+        let excerpts: Vec<Excerpt> = project_store
+            .ranked(&query)
+            .into_iter()
+            .take(self.top_k)
+            .filter_map(|(key, _)| by_key.remove(key))
+            .collect();
+        tracing::debug!(
+            embedded = pending,
+            entries = keys.len(),
+            excerpts = excerpts.len(),
+            "semantic lookup"
+        );
+        Ok(Retrieved { entries, excerpts })
+    }
+}
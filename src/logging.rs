@@ -0,0 +1,266 @@
+use nu_ansi_term::Color as NuColor;
+use regex::Regex;
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, OnceLock};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// Targets `-v` turns up; dependencies (reqwest, h2) stay at warnings unless
+/// `TAI_LOG` names them.
+const OWN_TARGETS: &[&str] = &["tai", "llm"];
+
+/// `TAI_LOG` directives: a bare level ("debug") or `target=level` pairs
+/// ("tai=debug,llm=trace"), the most specific matching target winning.
+struct Filter {
+    directives: Vec<(Option<String>, LevelFilter)>,
+}
+
+impl Filter {
+    fn parse(spec: &str) -> Self {
+        let mut directives = Vec::new();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (target, level) = match part.split_once('=') {
+                Some((target, level)) => (Some(target.trim().to_string()), level),
+                None => (None, part),
+            };
+            match level.trim().parse::<LevelFilter>() {
+                Ok(level) => directives.push((target, level)),
+                Err(_) => eprintln!("Warning: Ignoring invalid TAI_LOG directive '{}'", part),
+            }
+        }
+        Self { directives }
+    }
+
+    fn verbose(count: u8) -> Self {
+        let level = match count {
+            1 => LevelFilter::INFO,
+            2 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        };
+        let mut directives = vec![(None, LevelFilter::WARN)];
+        directives.extend(OWN_TARGETS.iter().map(|t| (Some(t.to_string()), level)));
+        Self { directives }
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter(|(t, _)| {
+                t.as_deref().is_none_or(|t| {
+                    target == t || target.strip_prefix(t).is_some_and(|r| r.starts_with("::"))
+                })
+            })
+            .max_by_key(|(t, _)| t.as_ref().map_or(0, |t| t.len() + 1))
+            .map_or(LevelFilter::OFF, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .map(|(_, level)| *level)
+            .max()
+            .unwrap_or(LevelFilter::OFF)
+    }
+}
+
+static FILTER: OnceLock<Filter> = OnceLock::new();
+
+fn write_line(level: Level, target: &str, message: &str) {
+    let color = std::io::stderr().is_terminal();
+    let label = format!("{:>5}", level.as_str());
+    let label = if !color {
+        label
+    } else {
+        let paint = match level {
+            Level::ERROR => NuColor::Red,
+            Level::WARN => NuColor::Yellow,
+            Level::INFO => NuColor::Green,
+            Level::DEBUG => NuColor::Blue,
+            Level::TRACE => NuColor::DarkGray,
+        };
+        paint.paint(label).to_string()
+    };
+    let time = chrono::Local::now().format("%H:%M:%S%.3f");
+    let mut err = std::io::stderr().lock();
+    let _ = writeln!(err, "{} {} {}: {}", time, label, target, redact(message));
+}
+
+/// Formats an event as its message followed by `key=value` fields.
+#[derive(Default)]
+struct Fields(String);
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0.insert_str(0, value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{:?}", value));
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Writes events to stderr. Spans are not shown; tai's own logging is flat.
+struct StderrSubscriber {
+    next_id: AtomicU64,
+}
+
+impl Subscriber for StderrSubscriber {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        FILTER
+            .get()
+            .is_some_and(|f| *metadata.level() <= f.level_for(metadata.target()))
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        FILTER.get().map(Filter::max_level)
+    }
+
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let meta = event.metadata();
+        write_line(*meta.level(), meta.target(), &fields.0);
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+/// The llm crate and reqwest log through the `log` facade; their records go
+/// to the same place.
+struct LogBridge;
+
+impl log::Log for LogBridge {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        FILTER
+            .get()
+            .is_some_and(|f| as_tracing(metadata.level()) <= f.level_for(metadata.target()))
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if self.enabled(record.metadata()) {
+            write_line(
+                as_tracing(record.level()),
+                record.target(),
+                &record.args().to_string(),
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn as_tracing(level: log::Level) -> Level {
+    match level {
+        log::Level::Error => Level::ERROR,
+        log::Level::Warn => Level::WARN,
+        log::Level::Info => Level::INFO,
+        log::Level::Debug => Level::DEBUG,
+        log::Level::Trace => Level::TRACE,
+    }
+}
+
+/// Turn on diagnostic logging to stderr: `-v` for info, `-vv` for debug,
+/// `-vvv` for trace from tai and the llm crate, or `TAI_LOG` for anything
+/// finer. Without either nothing is logged.
+pub fn init(verbose: u8) {
+    let filter = match std::env::var("TAI_LOG") {
+        Ok(spec) if verbose == 0 && !spec.trim().is_empty() => Filter::parse(&spec),
+        _ if verbose > 0 => Filter::verbose(verbose),
+        _ => return,
+    };
+    let max = filter.max_level();
+    if FILTER.set(filter).is_err() {
+        return;
+    }
+    let _ = tracing::subscriber::set_global_default(StderrSubscriber {
+        next_id: AtomicU64::new(1),
+    });
+    if log::set_logger(&LogBridge).is_ok() {
+        log::set_max_level(match max.into_level() {
+            Some(Level::ERROR) => log::LevelFilter::Error,
+            Some(Level::WARN) => log::LevelFilter::Warn,
+            Some(Level::INFO) => log::LevelFilter::Info,
+            Some(Level::DEBUG) => log::LevelFilter::Debug,
+            Some(Level::TRACE) => log::LevelFilter::Trace,
+            None => log::LevelFilter::Off,
+        });
+    }
+}
+
+static SECRET_PATTERNS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    [
+        (r"\bsk-[A-Za-z0-9_-]{16,}", "sk-***"),
+        (r"\bAIza[0-9A-Za-z_-]{30,}", "AIza***"),
+        (r"\bgh[pousr]_[A-Za-z0-9]{30,}", "gh*_***"),
+        (r"\bAKIA[0-9A-Z]{16}\b", "AKIA***"),
+        (r"\bxox[abpr]-[A-Za-z0-9-]{10,}", "xox*-***"),
+        (r"(?i)\b(bearer\s+)[A-Za-z0-9._~+/=-]{8,}", "${1}***"),
+        (
+            r#"(?i)([?&](?:key|api_key|apikey|token|access_token)=)[^&\s"']+"#,
+            "${1}***",
+        ),
+        (r"(://)[^/\s:@]+:[^/\s@]+@", "${1}***@"),
+        (
+            r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+            "[private key]",
+        ),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| (Regex::new(pattern).expect("valid regex"), replacement))
+    .collect()
+});
+
+/// Values of `*_KEY`, `*_TOKEN`, `*_SECRET`, and `*_PASSWORD` environment
+/// variables, which is where provider API keys come from.
+static ENV_SECRETS: LazyLock<Vec<String>> = LazyLock::new(|| {
+    std::env::vars()
+        .filter(|(name, value)| {
+            let name = name.to_ascii_uppercase();
+            ["KEY", "TOKEN", "SECRET", "PASSWORD"]
+                .iter()
+                .any(|suffix| name.ends_with(suffix))
+                && value.len() >= 8
+        })
+        .map(|(_, value)| value)
+        .collect()
+});
+
+/// `text` with API keys, bearer tokens, URL credentials, and private keys
+/// masked.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let mut out = Cow::Borrowed(text);
+    for secret in ENV_SECRETS.iter() {
+        if out.contains(secret.as_str()) {
+            out = Cow::Owned(out.replace(secret.as_str(), "***"));
+        }
+    }
+    for (pattern, replacement) in SECRET_PATTERNS.iter() {
+        if pattern.is_match(&out) {
+            out = Cow::Owned(pattern.replace_all(&out, *replacement).into_owned());
+        }
+    }
+    out
+}
@@ -1,19 +1,73 @@
 use anyhow::Result;
 use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::io::IsTerminal;
+use std::path::PathBuf;
 
 mod history;
 use history::History;
 
+mod index;
+
 mod tools;
 
 mod config;
 use config::{
     handle_config_command, handle_config_provider_auto, handle_config_provider_list,
     handle_config_provider_set, handle_config_provider_show, handle_config_provider_update,
+    handle_config_team_refresh, handle_config_team_show,
 };
 
 mod chat;
 
+mod commit;
+
+mod team;
+
+mod project;
+
+mod models;
+
+mod guard;
+
+mod logging;
+
+mod net;
+
+mod relay;
+
+mod output;
+
+mod prompts;
+
+mod context;
+
+mod hooks;
+
+mod suggest;
+
+mod scaffold;
+
+mod refactor;
+
+mod upgrade;
+
+mod bench;
+
+mod serve;
+
+mod doctor;
+
+mod report;
+
+mod scratch;
+
+mod editor;
+
+mod palette;
+
+#[cfg(unix)]
+mod daemon;
+
 #[derive(Parser)]
 #[command(name = "tai")]
 #[command(about = "Terminal AI Assistant")]
@@ -34,6 +88,94 @@ struct Cli {
     #[arg(long)]
     clear_history: bool,
 
+    /// Use this provider for this invocation instead of the configured one
+    #[arg(long, value_enum)]
+    provider: Option<ProviderChoice>,
+
+    /// Use this model for this invocation instead of the provider default
+    #[arg(long)]
+    model: Option<String>,
+
+    /// Send an image along with the message (repeatable; needs a vision model)
+    #[arg(long = "image", value_name = "PATH")]
+    images: Vec<PathBuf>,
+
+    /// Print a JSON event per line (tool_call, tool_result, assistant_text,
+    /// usage) on stdout instead of formatted output
+    #[arg(long)]
+    json: bool,
+
+    /// Suggest a single shell command for the task, then execute, copy, or
+    /// refine it, without the tool-calling loop
+    #[arg(long, short = 's')]
+    suggest: bool,
+
+    /// Print only the final answer as plain text (default when stdout is not
+    /// a terminal)
+    #[arg(long, short = 'q')]
+    raw: bool,
+
+    /// Log diagnostics to stderr (-v info, -vv debug, -vvv trace); TAI_LOG
+    /// sets finer filters such as "tai=debug,llm=trace"
+    #[arg(long, short = 'v', action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Write this session's requests, responses, and tool calls to
+    /// ~/.config/tai/logs, with secrets masked
+    #[arg(long)]
+    transcript: bool,
+
+    /// Apply the model's file edits without showing their diff and asking
+    /// first (an edit policy of "deny" still holds)
+    #[arg(long)]
+    auto_approve_edits: bool,
+
+    /// Offer only these tools to the model, e.g. --tools read-only or
+    /// --tools read_file,grep (overrides [tools] in config)
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        conflicts_with = "no_tools"
+    )]
+    tools: Option<Vec<String>>,
+
+    /// Offer the model no tools, for plain questions and answers
+    #[arg(long)]
+    no_tools: bool,
+
+    /// Answer in this style: beginner, expert, terse, or a preset from
+    /// [style.presets] (overrides [style] in config)
+    #[arg(long, value_name = "PRESET")]
+    style: Option<String>,
+
+    /// Use the provider, model, and tools of a profile from [profiles]
+    /// (overrides core.default_profile)
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Replace the built-in system rules with this file or text; {{os}},
+    /// {{shell}}, {{cwd}}, and {{date}} are filled in (overrides
+    /// core.system_prompt)
+    #[arg(long, value_name = "FILE|TEXT")]
+    system: Option<String>,
+
+    /// Print without colors, as when NO_COLOR is set
+    #[arg(long)]
+    no_color: bool,
+
+    /// Read the config files each time settings are needed instead of once
+    #[arg(long)]
+    no_config_cache: bool,
+
+    /// Copy the whole answer to the clipboard once it is complete
+    #[arg(long)]
+    copy: bool,
+
+    /// Also write the answer, as markdown, to this file once it is complete
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
     /// The message to send to the AI
     #[arg(trailing_var_arg = true)]
     message: Vec<String>,
@@ -43,6 +185,311 @@ struct Cli {
 enum Commands {
     /// Configure tai settings
     Config(ConfigCommand),
+    /// Generate a commit message for staged changes and commit them
+    Commit(CommitArgs),
+    /// List the models each configured provider offers
+    Models(ModelsArgs),
+    /// Run a prompt template from ~/.config/tai/prompts (lists them when no
+    /// name is given)
+    Prompt(PromptArgs),
+    /// Manage named contexts in ~/.config/tai/context
+    Context(ContextCmd),
+    /// Have the model write a new project into a new directory, e.g.
+    /// `tai new rust-cli myproj "a tool that renames photos by date"`
+    New(NewArgs),
+    /// Refactor the code in one directory, with writes limited to it, and
+    /// check that the project still builds afterwards
+    Refactor(RefactorArgs),
+    /// Bump a dependency in Cargo.toml or package.json and have the model fix
+    /// the build, with a summary and the option to roll back afterwards
+    Upgrade(UpgradeArgs),
+    /// Walk through the repository in numbered sections you can drill into,
+    /// cached per commit
+    Tour(TourArgs),
+    /// Chat in a full-screen interface, with panes for the conversation and
+    /// for tool calls and their output (Unix only)
+    Tui,
+    /// Time startup, the provider, tools, and rendering over repeated runs,
+    /// and print the results as JSON
+    Bench(BenchArgs),
+    /// Keep tai loaded in the background, so that other invocations hand
+    /// their work to it and start faster
+    Daemon(DaemonArgs),
+    /// Serve an OpenAI-compatible /v1/chat/completions and a /v1/agent
+    /// endpoint that runs the tool loop, on localhost
+    Serve(ServeArgs),
+    /// Check the config, the provider, context files, and the terminal, and
+    /// say how to fix what is wrong
+    Doctor,
+    /// Propose a corrected version of the last failed shell command (needs
+    /// the hook from `tai init`)
+    Fix(FixArgs),
+    /// Print the shell hook for `tai fix`, e.g. eval "$(tai init bash)"
+    Init(InitArgs),
+    /// Browse, search, and export past questions and answers
+    History(HistoryCmd),
+    /// Look back at sessions recorded with --transcript
+    Session(SessionCmd),
+    /// Continue the task a crash, Ctrl-C, or reboot cut short in this
+    /// directory
+    ResumeTask {
+        /// Drop the interrupted task instead
+        #[arg(long)]
+        discard: bool,
+    },
+    /// Copy code block N of the last answer to the clipboard
+    Copy {
+        /// The block's number, counting from 1
+        #[arg(default_value_t = 1)]
+        block: usize,
+    },
+    /// Write the code blocks of the last answer to files named after their
+    /// language, e.g. block-1.py
+    SaveBlocks {
+        /// Directory to write into (created if missing)
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct NewArgs {
+    /// rust-cli, rust-lib, python-cli, node-cli, go-cli, web, or any other
+    /// kind of project described in a word or two
+    kind: String,
+
+    /// Directory to create; its last part is the project's name
+    name: PathBuf,
+
+    /// What the project should do
+    #[arg(trailing_var_arg = true)]
+    description: Vec<String>,
+}
+
+#[derive(Args)]
+struct RefactorArgs {
+    /// Directory or file the refactor may change
+    #[arg(long)]
+    scope: PathBuf,
+
+    /// Command that must pass afterwards, instead of the project's detected
+    /// diagnostics (e.g. "cargo test")
+    #[arg(long)]
+    verify: Option<String>,
+
+    /// How often the model may try to make the verification pass
+    #[arg(long, default_value_t = 3)]
+    attempts: u32,
+
+    /// What to change, e.g. "rename Token to Lexeme and update call sites"
+    #[arg(trailing_var_arg = true)]
+    task: Vec<String>,
+}
+
+#[derive(Args)]
+struct UpgradeArgs {
+    /// The crate or package to upgrade
+    dep: String,
+
+    /// Version to upgrade to (default: the newest)
+    #[arg(long)]
+    to: Option<String>,
+
+    /// Command that must pass afterwards, instead of the project's detected
+    /// diagnostics (e.g. "cargo test")
+    #[arg(long)]
+    verify: Option<String>,
+
+    /// How often the model may try to make the build pass
+    #[arg(long, default_value_t = 5)]
+    attempts: u32,
+}
+
+#[derive(Args)]
+struct TourArgs {
+    /// Analyze the repository again instead of using the cached tour
+    #[arg(long)]
+    refresh: bool,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// Runs per measurement
+    #[arg(long, default_value_t = 5)]
+    runs: usize,
+
+    /// Leave out the provider, e.g. to measure offline
+    #[arg(long)]
+    skip_provider: bool,
+}
+
+#[derive(Args)]
+struct DaemonArgs {
+    /// Stop the running daemon, once the runs it started have finished
+    #[arg(long)]
+    stop: bool,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// Port to listen on
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+}
+
+#[derive(Args)]
+struct FixArgs {
+    /// What you were trying to do, if the command doesn't make it clear
+    #[arg(trailing_var_arg = true)]
+    note: Vec<String>,
+}
+
+#[derive(Args)]
+struct InitArgs {
+    shell: InitShell,
+
+    /// Also capture each command's error output (bash and zsh; programs then
+    /// see stderr as a pipe rather than the terminal)
+    #[arg(long)]
+    stderr: bool,
+}
+
+#[derive(Clone, ValueEnum)]
+enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl InitShell {
+    fn as_str(&self) -> &'static str {
+        match self {
+            InitShell::Bash => "bash",
+            InitShell::Zsh => "zsh",
+            InitShell::Fish => "fish",
+        }
+    }
+}
+
+#[derive(Args)]
+struct HistoryCmd {
+    #[command(subcommand)]
+    cmd: HistorySub,
+}
+
+#[derive(Args)]
+struct SessionCmd {
+    #[command(subcommand)]
+    cmd: SessionSub,
+}
+
+#[derive(Subcommand)]
+enum SessionSub {
+    /// Show a transcript turn by turn: each request, its answer, and the
+    /// tools it ran
+    Replay {
+        /// Transcript name in ~/.config/tai/logs or the start of it, "last"
+        /// for the newest, or a path
+        id: String,
+    },
+}
+
+#[derive(Args)]
+struct PageArgs {
+    /// Page to show, 1 being the newest entries
+    #[arg(long, default_value_t = 1)]
+    page: usize,
+
+    /// Entries per page
+    #[arg(long, default_value_t = 20)]
+    per_page: usize,
+}
+
+#[derive(Subcommand)]
+enum HistorySub {
+    /// List entries, newest first
+    List(PageArgs),
+    /// List entries whose question or answer matches a regex
+    Search {
+        pattern: String,
+        /// Match regardless of case
+        #[arg(long, short = 'i')]
+        ignore_case: bool,
+        #[command(flatten)]
+        pages: PageArgs,
+    },
+    /// Write all entries to stdout or a file
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Jsonl)]
+        format: ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, ValueEnum)]
+enum ExportFormat {
+    Jsonl,
+    Md,
+}
+
+#[derive(Args)]
+struct ContextCmd {
+    #[command(subcommand)]
+    cmd: ContextSub,
+}
+
+#[derive(Subcommand)]
+enum ContextSub {
+    /// List user and team contexts
+    List,
+    /// Create a context from stdin, or in $EDITOR when run interactively
+    Add { name: String },
+    /// Open a context in $EDITOR
+    Edit { name: String },
+    /// Print a context
+    Show { name: String },
+    /// Delete a user context
+    Remove {
+        name: String,
+        /// Do not ask for confirmation
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Check global contexts and show which contexts load here
+    Validate,
+}
+
+#[derive(Args)]
+struct PromptArgs {
+    /// Template name, i.e. the file name without `.tai`
+    name: Option<String>,
+
+    /// Values for {{arg1}}, {{arg2}}, ... and {{args}}
+    #[arg(trailing_var_arg = true)]
+    args: Vec<String>,
+}
+
+#[derive(Args)]
+struct ModelsArgs {
+    /// Only list models for this provider
+    provider: Option<ProviderChoice>,
+
+    /// Show the streaming speed measured for each model instead
+    #[arg(long)]
+    speed: bool,
+}
+
+#[derive(Args)]
+struct CommitArgs {
+    /// Use the Conventional Commits format
+    #[arg(long)]
+    conventional: bool,
+
+    /// Amend the previous commit instead of creating a new one
+    #[arg(long)]
+    amend: bool,
 }
 
 #[derive(Args)]
@@ -61,6 +508,8 @@ struct ConfigCommand {
 enum ConfigSubcommand {
     /// Provider management
     Provider(ProviderCmd),
+    /// Shared team configuration
+    Team(TeamCmd),
     /// Show or set legacy values (global_contexts only)
     Legacy,
     /// Provider-specific settings
@@ -68,6 +517,10 @@ enum ConfigSubcommand {
     Anthropic(ProviderSettingsArgs),
     #[command(name = "openai")]
     OpenAI(OpenAISettingsArgs),
+    #[command(name = "gemini")]
+    Gemini(ProviderSettingsArgs),
+    #[command(name = "openrouter")]
+    OpenRouter(OpenRouterSettingsArgs),
     #[command(name = "ollama")]
     Ollama(OllamaSettingsArgs),
     #[command(name = "lmstudio")]
@@ -92,10 +545,26 @@ enum ProviderSub {
     Show { provider: ProviderChoice },
 }
 
+#[derive(Args)]
+struct TeamCmd {
+    #[command(subcommand)]
+    cmd: TeamSub,
+}
+
+#[derive(Subcommand)]
+enum TeamSub {
+    /// Fetch the latest team config now
+    Refresh,
+    /// Show the team config source and cache location
+    Show,
+}
+
 #[derive(Clone, ValueEnum)]
 enum ProviderChoice {
     Anthropic,
     Openai,
+    Gemini,
+    Openrouter,
     Ollama,
     Lmstudio,
 }
@@ -105,6 +574,8 @@ impl ProviderChoice {
         match self {
             ProviderChoice::Anthropic => "anthropic",
             ProviderChoice::Openai => "openai",
+            ProviderChoice::Gemini => "gemini",
+            ProviderChoice::Openrouter => "openrouter",
             ProviderChoice::Ollama => "ollama",
             ProviderChoice::Lmstudio => "lmstudio",
         }
@@ -133,6 +604,21 @@ struct OpenAISettingsArgs {
     max_tokens: Option<u32>,
 }
 
+#[derive(Args)]
+struct OpenRouterSettingsArgs {
+    #[arg(long)]
+    model: Option<String>,
+    #[arg(long)]
+    base_url: Option<String>,
+    /// Routing variant appended to the model (e.g. nitro, floor)
+    #[arg(long)]
+    route: Option<String>,
+    #[arg(long)]
+    temperature: Option<f32>,
+    #[arg(long)]
+    max_tokens: Option<u32>,
+}
+
 #[derive(Args)]
 struct OllamaSettingsArgs {
     #[arg(long)]
@@ -157,8 +643,166 @@ struct LMStudioSettingsArgs {
     max_tokens: Option<u32>,
 }
 
+/// The arguments of `input` when it is the slash command `name`.
+fn slash_command<'a>(input: &'a str, name: &str) -> Option<&'a str> {
+    input
+        .strip_prefix(name)
+        .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        .map(str::trim)
+}
+
 fn main() -> Result<()> {
+    report::catch_panics();
+    match std::panic::catch_unwind(run) {
+        Ok(result) => result,
+        Err(_) => {
+            if let Some(crash) = report::take_crash().filter(|_| report::should_offer()) {
+                report::offer(report::Failure::Panic(crash));
+            }
+            std::process::exit(101);
+        }
+    }
+}
+
+fn run() -> Result<()> {
     let cli = Cli::parse();
+    #[cfg(unix)]
+    let cli = match &cli.command {
+        Some(Commands::Daemon(args)) => match daemon::serve(args.stop)? {
+            // In the run the daemon forked for a client.
+            Some(args) => Cli::parse_from(args),
+            None => return Ok(()),
+        },
+        // The daemon's runs have no terminal of their own to draw on.
+        Some(Commands::Tui) => cli,
+        _ => {
+            if let Some(code) = daemon::forward() {
+                std::process::exit(code);
+            }
+            cli
+        }
+    };
+    #[cfg(not(unix))]
+    if let Some(Commands::Daemon(_)) = &cli.command {
+        anyhow::bail!("tai daemon needs Unix sockets");
+    }
+
+    if cli.no_color || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        output::disable_color();
+    }
+    if cli.no_config_cache {
+        config::disable_config_cache();
+    }
+    logging::init(cli.verbose);
+    // Read once here and handed to whatever needs it.
+    let cfg = config::load_config().unwrap_or_else(|e| {
+        eprintln!(
+            "Warning: Failed to load the config, using defaults: {:#}",
+            e
+        );
+        config::Config::default()
+    });
+    report::keep_config(&cfg);
+    let _scratch = scratch::start();
+    if cli.auto_approve_edits {
+        tools::auto_approve_edits();
+    }
+
+    if let Some(Commands::Commit(args)) = &cli.command {
+        let rt = tokio::runtime::Runtime::new()?;
+        return rt.block_on(commit::run_commit(&cfg, args.conventional, args.amend));
+    }
+
+    if let Some(Commands::Bench(args)) = &cli.command {
+        let rt = tokio::runtime::Runtime::new()?;
+        return rt.block_on(bench::run_bench(
+            &cfg,
+            args.runs,
+            args.skip_provider,
+            cli.provider.as_ref().map(|p| p.as_str()),
+            cli.model.as_deref(),
+        ));
+    }
+
+    if let Some(Commands::Models(args)) = &cli.command {
+        let provider = args.provider.as_ref().map(|p| p.as_str());
+        if args.speed {
+            return models::handle_speeds(provider);
+        }
+        return models::handle_models(&cfg, provider);
+    }
+
+    if let Some(Commands::Context(c)) = &cli.command {
+        return match &c.cmd {
+            ContextSub::List => context::handle_context_list(&cfg),
+            ContextSub::Add { name } => context::handle_context_add(name),
+            ContextSub::Edit { name } => context::handle_context_edit(&cfg, name),
+            ContextSub::Show { name } => context::handle_context_show(&cfg, name),
+            ContextSub::Remove { name, yes } => context::handle_context_remove(&cfg, name, *yes),
+            ContextSub::Validate => context::handle_context_validate(&cfg),
+        };
+    }
+
+    if let Some(Commands::History(h)) = &cli.command {
+        return match &h.cmd {
+            HistorySub::List(p) => history::handle_history_list(&cfg.history, p.page, p.per_page),
+            HistorySub::Search {
+                pattern,
+                ignore_case,
+                pages,
+            } => history::handle_history_search(
+                &cfg.history,
+                pattern,
+                *ignore_case,
+                pages.page,
+                pages.per_page,
+            ),
+            HistorySub::Export { format, output } => history::handle_history_export(
+                &cfg.history,
+                matches!(format, ExportFormat::Md),
+                output.as_deref(),
+            ),
+        };
+    }
+
+    if let Some(Commands::Session(s)) = &cli.command {
+        return match &s.cmd {
+            SessionSub::Replay { id } => chat::replay(&cfg, id),
+        };
+    }
+
+    if let Some(Commands::Doctor) = &cli.command {
+        return doctor::run();
+    }
+
+    if cli.command.is_none() && cli.message == ["?"] {
+        use clap::CommandFactory;
+        let commands = Cli::command()
+            .get_subcommands()
+            .filter(|c| !c.is_hide_set())
+            .map(|c| {
+                let about = c.get_about().map(|a| a.to_string()).unwrap_or_default();
+                (c.get_name().to_string(), about)
+            })
+            .collect();
+        return palette::run(&cfg, commands);
+    }
+
+    if let Some(Commands::Copy { block }) = &cli.command {
+        return chat::copy_block(&cfg, *block);
+    }
+
+    if let Some(Commands::SaveBlocks { dir }) = &cli.command {
+        return chat::save_blocks(&cfg, dir);
+    }
+
+    if let Some(Commands::Init(args)) = &cli.command {
+        return hooks::init(args.shell.as_str(), args.stderr);
+    }
+
+    if let Some(Commands::Prompt(PromptArgs { name: None, .. })) = &cli.command {
+        return prompts::list();
+    }
 
     if let Some(Commands::Config(cfg)) = &cli.command {
         if let Some(sub) = &cfg.command {
@@ -173,6 +817,10 @@ fn main() -> Result<()> {
                         return handle_config_provider_show(provider.as_str())
                     }
                 },
+                ConfigSubcommand::Team(t) => match &t.cmd {
+                    TeamSub::Refresh => return handle_config_team_refresh(),
+                    TeamSub::Show => return handle_config_team_show(),
+                },
                 ConfigSubcommand::Anthropic(args) => {
                     return handle_config_provider_update(
                         "anthropic",
@@ -181,6 +829,7 @@ fn main() -> Result<()> {
                         None,
                         args.temperature,
                         args.max_tokens,
+                        None,
                     );
                 }
                 ConfigSubcommand::OpenAI(args) => {
@@ -191,6 +840,29 @@ fn main() -> Result<()> {
                         None,
                         args.temperature,
                         args.max_tokens,
+                        None,
+                    );
+                }
+                ConfigSubcommand::Gemini(args) => {
+                    return handle_config_provider_update(
+                        "gemini",
+                        args.model.clone(),
+                        None,
+                        None,
+                        args.temperature,
+                        args.max_tokens,
+                        None,
+                    );
+                }
+                ConfigSubcommand::OpenRouter(args) => {
+                    return handle_config_provider_update(
+                        "openrouter",
+                        args.model.clone(),
+                        args.base_url.clone(),
+                        None,
+                        args.temperature,
+                        args.max_tokens,
+                        args.route.clone(),
                     );
                 }
                 ConfigSubcommand::Ollama(args) => {
@@ -201,6 +873,7 @@ fn main() -> Result<()> {
                         args.host.clone(),
                         args.temperature,
                         args.max_tokens,
+                        None,
                     );
                 }
                 ConfigSubcommand::LMStudio(args) => {
@@ -211,6 +884,7 @@ fn main() -> Result<()> {
                         None,
                         args.temperature,
                         args.max_tokens,
+                        None,
                     );
                 }
                 ConfigSubcommand::Legacy => {}
@@ -221,35 +895,253 @@ fn main() -> Result<()> {
 
     if cli.clear_history {
         History::clear()?;
+        index::clear_history()?;
         println!("History cleared");
         return Ok(());
     }
 
-    let user_input = if cli.message.is_empty() {
-        print!("> ");
-        std::io::Write::flush(&mut std::io::stdout())?;
-        let mut input = String::new();
-        loop {
-            let mut line = String::new();
-            match std::io::stdin().read_line(&mut line) {
-                Ok(0) => break,
-                Ok(_) => {
-                    input.push_str(&line);
-                    if line.trim().is_empty() && !input.trim().is_empty() {
-                        break;
-                    }
-                }
-                Err(e) => return Err(e.into()),
+    if cli.json {
+        output::enable_json();
+    } else if cli.raw || !std::io::stdout().is_terminal() {
+        output::enable_raw();
+    }
+
+    if let Some(Commands::Tour(args)) = &cli.command {
+        let opts = chat::ChatOptions {
+            nocontext: cli.nocontext,
+            context: cli.context.clone(),
+            provider: cli.provider.as_ref().map(|p| p.as_str().to_string()),
+            model: cli.model.clone(),
+            images: Vec::new(),
+            transcript: cli.transcript,
+            tools: cli.no_tools.then(Vec::new).or_else(|| cli.tools.clone()),
+            style: cli.style.clone(),
+            profile: cli.profile.clone(),
+            system: cli.system.clone(),
+            retry: false,
+            temperature: None,
+            resume: false,
+        };
+        let result =
+            tokio::runtime::Runtime::new()?.block_on(chat::run_tour(cfg, opts, args.refresh));
+        if let Err(e) = &result {
+            if cli.json {
+                output::emit(
+                    "error",
+                    serde_json::json!({ "message": format!("{:#}", e) }),
+                );
             }
         }
-        if input.trim().is_empty() {
+        return result;
+    }
+
+    if let Some(Commands::Tui) = &cli.command {
+        let opts = chat::ChatOptions {
+            nocontext: cli.nocontext,
+            context: cli.context.clone(),
+            provider: cli.provider.as_ref().map(|p| p.as_str().to_string()),
+            model: cli.model.clone(),
+            images: Vec::new(),
+            transcript: cli.transcript,
+            tools: cli.no_tools.then(Vec::new).or_else(|| cli.tools.clone()),
+            style: cli.style.clone(),
+            profile: cli.profile.clone(),
+            system: cli.system.clone(),
+            retry: false,
+            temperature: None,
+            resume: false,
+        };
+        #[cfg(unix)]
+        return tokio::runtime::Runtime::new()?.block_on(chat::run_tui(cfg, opts));
+        #[cfg(not(unix))]
+        {
+            let _ = opts;
+            anyhow::bail!("tai tui is not supported on this platform; it needs Linux or macOS");
+        }
+    }
+
+    if let Some(Commands::Serve(args)) = &cli.command {
+        let defaults = chat::ChatOptions {
+            nocontext: cli.nocontext,
+            context: cli.context.clone(),
+            provider: cli.provider.as_ref().map(|p| p.as_str().to_string()),
+            model: cli.model.clone(),
+            images: Vec::new(),
+            transcript: cli.transcript,
+            tools: cli.no_tools.then(Vec::new).or_else(|| cli.tools.clone()),
+            style: cli.style.clone(),
+            profile: cli.profile.clone(),
+            system: cli.system.clone(),
+            retry: false,
+            temperature: None,
+            resume: false,
+        };
+        return tokio::runtime::Runtime::new()?.block_on(serve::run(args.port, defaults));
+    }
+
+    if let Some(Commands::ResumeTask { discard: true }) = &cli.command {
+        chat::discard_task()?;
+        say!("Dropped the interrupted task");
+        return Ok(());
+    }
+    let resume = matches!(cli.command, Some(Commands::ResumeTask { .. }));
+    if !resume && !cli.json {
+        chat::offer_resume();
+    }
+
+    let mut check = None;
+    let mut upgrade = None;
+    let user_input = if resume {
+        chat::resume_prompt()?
+    } else if let Some(Commands::Prompt(PromptArgs {
+        name: Some(name),
+        args,
+    })) = &cli.command
+    {
+        prompts::render(name, args)?
+    } else if let Some(Commands::New(args)) = &cli.command {
+        scaffold::prepare(&args.kind, &args.name, &args.description.join(" "))?
+    } else if let Some(Commands::Refactor(args)) = &cli.command {
+        let (prompt, verification) = refactor::prepare(
+            &cfg,
+            &args.scope,
+            &args.task.join(" "),
+            args.verify.as_deref(),
+            args.attempts.max(1),
+        )?;
+        check = verification;
+        prompt
+    } else if let Some(Commands::Upgrade(args)) = &cli.command {
+        let (started, task) = upgrade::Upgrade::start(
+            &cfg,
+            &args.dep,
+            args.to.as_deref(),
+            args.verify.as_deref(),
+            args.attempts.max(1),
+        )?;
+        let Some((prompt, verification)) = task else {
+            return started.finish(Ok(()));
+        };
+        check = Some(verification);
+        upgrade = Some(started);
+        prompt
+    } else if let Some(Commands::Fix(args)) = &cli.command {
+        hooks::fix_prompt(&args.note.join(" "))?
+    } else if cli.message.is_empty() {
+        let input = editor::read_prompt(&cfg.editor)?;
+        if input.is_empty() {
+            scratch::remove();
             std::process::exit(0);
         }
-        input.trim().to_string()
+        input
     } else {
         cli.message.join(" ")
     };
 
+    if let Some(args) = slash_command(&user_input, "/context") {
+        return chat::context_command(&cfg, args, cli.nocontext, cli.context.as_deref());
+    }
+    if let Some(args) = slash_command(&user_input, "/save") {
+        return chat::save_command(&cfg, args);
+    }
+    if let Some(args) = slash_command(&user_input, "/copy") {
+        let block = match args {
+            "" => 1,
+            n => n.parse().map_err(|_| anyhow::anyhow!("Usage: /copy [N]"))?,
+        };
+        return chat::copy_block(&cfg, block);
+    }
+    if let Some(args) = slash_command(&user_input, "/branch") {
+        if args.is_empty() {
+            anyhow::bail!("Usage: /branch NAME");
+        }
+        return history::branch(&cfg.history, args);
+    }
+    if let Some(args) = slash_command(&user_input, "/session") {
+        return history::session_command(args);
+    }
+    let retry = slash_command(&user_input, "/retry")
+        .map(|args| {
+            let mut words = args.split_whitespace();
+            let (mut model, mut temperature) = (None, None);
+            for word in words.by_ref().take(2) {
+                match word.parse::<f32>() {
+                    Ok(t) if temperature.is_none() => temperature = Some(t),
+                    Err(_) if model.is_none() && temperature.is_none() => {
+                        model = Some(word.to_string())
+                    }
+                    _ => anyhow::bail!("Usage: /retry [MODEL] [TEMPERATURE]"),
+                }
+            }
+            if words.next().is_some() {
+                anyhow::bail!("Usage: /retry [MODEL] [TEMPERATURE]");
+            }
+            Ok((model, temperature))
+        })
+        .transpose()?;
+    let user_input = match retry {
+        Some(_) => {
+            let question = chat::last_question(&cfg)?;
+            say!("Retrying: {}", question);
+            question
+        }
+        None => user_input,
+    };
+
     let rt = tokio::runtime::Runtime::new()?;
-    rt.block_on(chat::run_chat(cli.nocontext, cli.context, user_input))
+    if cli.suggest {
+        return rt.block_on(suggest::run_suggest(
+            cfg,
+            user_input,
+            cli.provider.as_ref().map(|p| p.as_str()),
+            cli.model.as_deref(),
+            cli.profile.as_deref(),
+        ));
+    }
+    let opts = chat::ChatOptions {
+        nocontext: cli.nocontext,
+        context: cli.context,
+        provider: cli.provider.as_ref().map(|p| p.as_str().to_string()),
+        model: retry
+            .as_ref()
+            .and_then(|(model, _)| model.clone())
+            .or(cli.model),
+        images: cli.images,
+        transcript: cli.transcript,
+        tools: cli.no_tools.then(Vec::new).or(cli.tools),
+        style: cli.style,
+        profile: cli.profile,
+        system: cli.system,
+        retry: retry.is_some(),
+        temperature: retry.and_then(|(_, temperature)| temperature),
+        resume,
+    };
+    let mut result = rt.block_on(chat::run_chat(cfg, opts, user_input, check));
+    if let Some(upgrade) = &upgrade {
+        result = upgrade.finish(result);
+    }
+    if let (Ok(()), Some(Commands::New(args))) = (&result, &cli.command) {
+        scaffold::summary(&args.name)?;
+    }
+    if result.is_ok() && cli.copy {
+        if let Err(e) = chat::copy_answer() {
+            eprintln!("Warning: {:#}", e);
+        }
+    }
+    if let (Ok(()), Some(path)) = (&result, &cli.output) {
+        result = chat::output_answer(path);
+    }
+    if let Err(e) = &result {
+        if cli.json {
+            output::emit(
+                "error",
+                serde_json::json!({ "message": format!("{:#}", e) }),
+            );
+        } else if report::should_offer() {
+            eprintln!("Error: {:?}", e);
+            report::offer(report::Failure::Error(e));
+            std::process::exit(1);
+        }
+    }
+    result
 }
@@ -0,0 +1,278 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::{
+    fetch_openrouter_models, get_global_config_dir, list_providers, resolve_effective_provider,
+    Config, EffectiveProvider,
+};
+use crate::{net, relay};
+
+/// Anthropic models are addressed by stable aliases, so list those rather
+/// than every dated snapshot.
+const ANTHROPIC_MODELS: &[&str] = &[
+    "claude-opus-4-1",
+    "claude-opus-4-0",
+    "claude-sonnet-4-0",
+    "claude-3-7-sonnet-latest",
+    "claude-3-5-sonnet-latest",
+    "claude-3-5-haiku-latest",
+];
+
+fn get_json(cfg: &Config, provider: &str, url: &str, bearer: Option<&str>) -> Result<Value> {
+    let client = net::blocking_client(cfg, Some(provider))?
+        .timeout(Duration::from_secs(10))
+        .build()?;
+    let mut req = client.get(url);
+    if let Some(key) = bearer {
+        req = req.bearer_auth(key);
+    }
+    if let Some(headers) = relay::headers(cfg, provider)? {
+        req = req.headers(headers);
+    }
+    Ok(req.send()?.error_for_status()?.json()?)
+}
+
+fn ids(v: &Value, list: &str, field: &str) -> Vec<String> {
+    v[list]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|m| m[field].as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn fetch_models(name: &str, base: Option<&str>, cfg: &Config) -> Result<Vec<String>> {
+    let base = base.map(|b| b.trim_end_matches('/').to_string());
+    match name {
+        "anthropic" => Ok(ANTHROPIC_MODELS.iter().map(|s| s.to_string()).collect()),
+        "openai" => {
+            let base = base.unwrap_or_else(|| "https://api.openai.com/v1".into());
+            let key = std::env::var("OPENAI_API_KEY").ok();
+            let v = get_json(cfg, name, &format!("{}/models", base), key.as_deref())?;
+            Ok(ids(&v, "data", "id"))
+        }
+        "gemini" => {
+            let key = std::env::var("GEMINI_API_KEY").unwrap_or_default();
+            let v = get_json(
+                cfg,
+                name,
+                &format!(
+                    "https://generativelanguage.googleapis.com/v1beta/models?pageSize=1000&key={}",
+                    key
+                ),
+                None,
+            )?;
+            Ok(v["models"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|m| {
+                    m["supportedGenerationMethods"]
+                        .as_array()
+                        .is_some_and(|ms| ms.iter().any(|x| x == "generateContent"))
+                })
+                .filter_map(|m| m["name"].as_str())
+                .map(|n| n.trim_start_matches("models/").to_string())
+                .collect())
+        }
+        "openrouter" => fetch_openrouter_models(cfg),
+        "ollama" => {
+            let base = base.unwrap_or_else(|| "http://127.0.0.1:11434".into());
+            let v = get_json(cfg, name, &format!("{}/api/tags", base), None)?;
+            Ok(ids(&v, "models", "name"))
+        }
+        "lmstudio" => {
+            let base = base.unwrap_or_else(|| "http://127.0.0.1:1234/v1".into());
+            let v = get_json(cfg, name, &format!("{}/models", base), None)?;
+            Ok(ids(&v, "data", "id"))
+        }
+        other => Err(anyhow!("Unsupported provider: {}", other)),
+    }
+}
+
+/// Whether `models`, as `provider` lists them, include `model`. OpenRouter
+/// models may carry a routing variant, and Ollama ones default to the
+/// `latest` tag.
+fn offers(models: &[String], provider: &str, model: &str) -> bool {
+    let model = match provider {
+        "openrouter" => model.split(':').next().unwrap_or(model).to_string(),
+        "ollama" if !model.contains(':') => format!("{}:latest", model),
+        _ => model.to_string(),
+    };
+    models.contains(&model)
+}
+
+/// Ask `eff`'s endpoint for its models, which checks that it answers and
+/// accepts the key. Returns how many models it lists and whether the
+/// configured one is among them.
+pub fn check_endpoint(cfg: &Config, eff: &EffectiveProvider) -> Result<(usize, bool)> {
+    if eff.name != "anthropic" {
+        let models = fetch_models(&eff.name, eff.base_url_or_host.as_deref(), cfg)?;
+        return Ok((models.len(), offers(&models, &eff.name, &eff.model)));
+    }
+    // The aliases above are listed without asking, so ask the API here.
+    let client = net::blocking_client(cfg, Some("anthropic"))?
+        .timeout(Duration::from_secs(10))
+        .build()?;
+    let mut req = client
+        .get("https://api.anthropic.com/v1/models?limit=1000")
+        .header(
+            "x-api-key",
+            std::env::var("ANTHROPIC_API_KEY").unwrap_or_default(),
+        )
+        .header("anthropic-version", "2023-06-01");
+    if let Some(headers) = relay::headers(cfg, "anthropic")? {
+        req = req.headers(headers);
+    }
+    let v: Value = req.send()?.error_for_status()?.json()?;
+    let models = ids(&v, "data", "id");
+    let known =
+        offers(&models, "anthropic", &eff.model) || ANTHROPIC_MODELS.contains(&eff.model.as_str());
+    Ok((models.len(), known))
+}
+
+/// Print the models each available provider offers, marking the default.
+pub fn handle_models(cfg: &Config, provider: Option<&str>) -> Result<()> {
+    use nu_ansi_term::{Color as NuColor, Style};
+    let statuses = list_providers(cfg);
+    if let Some(p) = provider {
+        if !statuses.iter().any(|s| s.name == p) {
+            return Err(anyhow!("Unsupported provider: {}", p));
+        }
+    }
+    for status in statuses
+        .iter()
+        .filter(|s| provider.is_none_or(|p| p == s.name))
+    {
+        let mark = if status.active { " [active]" } else { "" };
+        let header = Style::new()
+            .bold()
+            .paint(format!("{}{}", status.name, mark));
+        if !status.available {
+            println!("{} ({})", header, status.reason);
+            continue;
+        }
+        let eff = resolve_effective_provider(cfg, Some(&status.name), None)?;
+        println!("{} (default: {})", header, eff.model);
+        let models = match fetch_models(&status.name, eff.base_url_or_host.as_deref(), cfg) {
+            Ok(mut m) => {
+                m.sort();
+                m
+            }
+            Err(e) => {
+                println!(
+                    "  {}",
+                    NuColor::Red.paint(format!("failed to list models: {}", e))
+                );
+                continue;
+            }
+        };
+        if models.is_empty() {
+            println!("  <none>");
+        }
+        for m in &models {
+            if *m == eff.model {
+                println!(
+                    "  {} {}",
+                    NuColor::Green.paint("*"),
+                    NuColor::Green.paint(m)
+                );
+            } else {
+                println!("    {}", m);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Streaming speed measured for one model, summed over its answers.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Speed {
+    answers: u64,
+    tokens: u64,
+    /// Seconds spent generating, from the first token to the last.
+    generating_secs: f64,
+    /// Seconds from sending the request to the first token.
+    first_token_secs: f64,
+}
+
+fn speeds_path() -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("speeds.json"))
+}
+
+/// Keyed by `provider/model`.
+fn load_speeds() -> Result<BTreeMap<String, Speed>> {
+    let path = speeds_path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content = fs::read_to_string(&path)?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Add one streamed answer to the model's totals.
+pub fn record_speed(
+    provider: &str,
+    model: &str,
+    tokens: u64,
+    rate: f64,
+    first_token: f64,
+) -> Result<()> {
+    let mut speeds = load_speeds().unwrap_or_default();
+    let speed = speeds.entry(format!("{}/{}", provider, model)).or_default();
+    speed.answers += 1;
+    speed.tokens += tokens;
+    speed.generating_secs += tokens.saturating_sub(1) as f64 / rate;
+    speed.first_token_secs += first_token;
+    fs::write(speeds_path()?, serde_json::to_string_pretty(&speeds)?)?;
+    Ok(())
+}
+
+/// `tai models --speed`: average streaming speed per model, fastest first.
+pub fn handle_speeds(provider: Option<&str>) -> Result<()> {
+    use nu_ansi_term::Style;
+    let speeds = load_speeds()?;
+    let mut rows: Vec<(&String, &Speed, f64)> = speeds
+        .iter()
+        .filter(|(key, _)| provider.is_none_or(|p| key.starts_with(&format!("{}/", p))))
+        .filter(|(_, s)| s.generating_secs > 0.0)
+        .map(|(key, s)| {
+            (
+                key,
+                s,
+                s.tokens.saturating_sub(s.answers) as f64 / s.generating_secs,
+            )
+        })
+        .collect();
+    if rows.is_empty() {
+        println!("No speeds recorded yet; they are measured as answers stream.");
+        return Ok(());
+    }
+    rows.sort_by(|a, b| b.2.total_cmp(&a.2));
+    let width = rows.iter().map(|(key, _, _)| key.len()).max().unwrap_or(0);
+    println!(
+        "{}",
+        Style::new().bold().paint(format!(
+            "{:<width$}  {:>8}  {:>11}  {:>7}",
+            "model", "tok/s", "first token", "answers"
+        ))
+    );
+    for (key, speed, rate) in rows {
+        println!(
+            "{:<width$}  {:>8.1}  {:>10.2}s  {:>7}",
+            key,
+            rate,
+            speed.first_token_secs / speed.answers as f64,
+            speed.answers
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::config::{Config, NetworkConfig};
+
+/// Always reached directly. Local model servers (Ollama, LM Studio) are
+/// otherwise sent to the proxy, which cannot reach them.
+const LOOPBACK: &str = "localhost,127.0.0.1,::1";
+
+/// The proxy environment as tai was started, before `export_for_provider`
+/// replaced it.
+struct EnvProxies {
+    http: Option<String>,
+    https: Option<String>,
+    no_proxy: Option<String>,
+}
+
+fn env_proxies() -> &'static EnvProxies {
+    static ENV: OnceLock<EnvProxies> = OnceLock::new();
+    ENV.get_or_init(|| {
+        let var = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|n| std::env::var(n).ok().filter(|v| !v.trim().is_empty()))
+        };
+        let all = var(&["ALL_PROXY", "all_proxy"]);
+        EnvProxies {
+            http: var(&["HTTP_PROXY", "http_proxy"]).or_else(|| all.clone()),
+            https: var(&["HTTPS_PROXY", "https_proxy"]).or(all),
+            no_proxy: var(&["NO_PROXY", "no_proxy"]),
+        }
+    })
+}
+
+struct Proxies {
+    http: Option<String>,
+    https: Option<String>,
+    no_proxy: String,
+}
+
+/// A provider's own proxy wins over `[network] proxy`, which wins over the
+/// environment.
+fn resolve(network: &NetworkConfig, provider_proxy: Option<&str>) -> Proxies {
+    let env = env_proxies();
+    let configured = provider_proxy
+        .or(network.proxy.as_deref())
+        .map(str::trim)
+        .filter(|p| !p.is_empty());
+    let (http, https) = match configured {
+        Some("direct") => (None, None),
+        Some(url) => (Some(url.to_string()), Some(url.to_string())),
+        None => (env.http.clone(), env.https.clone()),
+    };
+    let no_proxy = match network.no_proxy.as_deref().or(env.no_proxy.as_deref()) {
+        Some(list) if !list.trim().is_empty() => format!("{},{}", list.trim(), LOOPBACK),
+        _ => LOOPBACK.to_string(),
+    };
+    Proxies {
+        http,
+        https,
+        no_proxy,
+    }
+}
+
+pub fn provider_proxy<'a>(cfg: &'a Config, provider: Option<&str>) -> Option<&'a str> {
+    cfg.providers.common(provider?)?.proxy.as_deref()
+}
+
+/// `url` with any password replaced, for error messages.
+fn redact(url: &str) -> String {
+    match (url.find("://"), url.rfind('@')) {
+        (Some(start), Some(at)) if at > start => {
+            format!("{}://***@{}", &url[..start], &url[at + 1..])
+        }
+        _ => url.to_string(),
+    }
+}
+
+fn proxies(p: &Proxies) -> Result<Vec<reqwest::Proxy>> {
+    let no_proxy = reqwest::NoProxy::from_string(&p.no_proxy);
+    let mut out = Vec::new();
+    if let Some(url) = &p.http {
+        out.push(
+            reqwest::Proxy::http(url)
+                .with_context(|| format!("Invalid proxy URL '{}'", redact(url)))?
+                .no_proxy(no_proxy.clone()),
+        );
+    }
+    if let Some(url) = &p.https {
+        out.push(
+            reqwest::Proxy::https(url)
+                .with_context(|| format!("Invalid proxy URL '{}'", redact(url)))?
+                .no_proxy(no_proxy),
+        );
+    }
+    Ok(out)
+}
+
+fn bypassed(host: &str, no_proxy: &str) -> bool {
+    no_proxy.split(',').map(str::trim).any(|entry| {
+        let domain = entry.trim_start_matches('.');
+        entry == "*"
+            || (!domain.is_empty() && (host == domain || host.ends_with(&format!(".{}", domain))))
+    })
+}
+
+/// The proxy a request to `url` goes through, with its password redacted,
+/// so failures can say where they happened.
+pub fn proxy_for(cfg: &Config, provider: Option<&str>, url: &str) -> Option<String> {
+    let p = resolve(&cfg.network, provider_proxy(cfg, provider));
+    let parsed = reqwest::Url::parse(url).ok()?;
+    let host = parsed
+        .host_str()?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    if bypassed(host, &p.no_proxy) {
+        return None;
+    }
+    let proxy = match parsed.scheme() {
+        "https" => p.https,
+        _ => p.http,
+    };
+    proxy.map(|u| redact(&u))
+}
+
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn read_pem(path: &str, what: &str) -> Result<Vec<u8>> {
+    let path = expand_home(path);
+    fs::read(&path).with_context(|| format!("Failed to read {} {}", what, path.display()))
+}
+
+struct Tls {
+    roots: Vec<reqwest::Certificate>,
+    system_roots: bool,
+    identity: Option<reqwest::Identity>,
+}
+
+fn tls(network: &NetworkConfig) -> Result<Tls> {
+    let roots = match &network.ca_bundle {
+        Some(path) => {
+            let certs = reqwest::Certificate::from_pem_bundle(&read_pem(path, "CA bundle")?)
+                .with_context(|| format!("CA bundle {} is not valid PEM", path))?;
+            if certs.is_empty() {
+                bail!("CA bundle {} contains no certificates", path);
+            }
+            certs
+        }
+        None => Vec::new(),
+    };
+    let system_roots = network.system_roots.unwrap_or(true);
+    if !system_roots && roots.is_empty() {
+        bail!("network.system_roots = false needs a network.ca_bundle to trust");
+    }
+    let identity = match (&network.client_cert, &network.client_key) {
+        (Some(cert), key) => {
+            let cert_pem = read_pem(cert, "client certificate")?;
+            // The key may sit in the same file as the certificate.
+            let key_pem = match key {
+                Some(key) => read_pem(key, "client key")?,
+                None => cert_pem.clone(),
+            };
+            Some(
+                reqwest::Identity::from_pkcs8_pem(&cert_pem, &key_pem)
+                    .context("Invalid client certificate or key (expected PEM and PKCS#8)")?,
+            )
+        }
+        (None, Some(_)) => bail!("network.client_key is set without network.client_cert"),
+        (None, None) => None,
+    };
+    Ok(Tls {
+        roots,
+        system_roots,
+        identity,
+    })
+}
+
+/// A blocking client builder with the proxy for `provider` applied, or the
+/// general one when `provider` is `None`, and the TLS settings.
+pub fn blocking_client(
+    cfg: &Config,
+    provider: Option<&str>,
+) -> Result<reqwest::blocking::ClientBuilder> {
+    blocking_client_for(&cfg.network, provider_proxy(cfg, provider))
+}
+
+/// Like `blocking_client`, for callers that only have the network settings.
+pub fn blocking_client_for(
+    network: &NetworkConfig,
+    provider_proxy: Option<&str>,
+) -> Result<reqwest::blocking::ClientBuilder> {
+    let tls = tls(network)?;
+    let mut builder = reqwest::blocking::Client::builder()
+        .no_proxy()
+        .tls_built_in_root_certs(tls.system_roots);
+    for proxy in proxies(&resolve(network, provider_proxy))? {
+        builder = builder.proxy(proxy);
+    }
+    for cert in tls.roots {
+        builder = builder.add_root_certificate(cert);
+    }
+    if let Some(identity) = tls.identity {
+        builder = builder.identity(identity);
+    }
+    Ok(builder)
+}
+
+/// An async client builder with the general proxy and TLS settings applied.
+pub fn client(network: &NetworkConfig) -> Result<reqwest::ClientBuilder> {
+    client_for(network, None)
+}
+
+/// Like `client`, with a provider's own proxy taking precedence.
+pub fn client_for(
+    network: &NetworkConfig,
+    provider_proxy: Option<&str>,
+) -> Result<reqwest::ClientBuilder> {
+    let tls = tls(network)?;
+    let mut builder = reqwest::Client::builder()
+        .no_proxy()
+        .tls_built_in_root_certs(tls.system_roots);
+    for proxy in proxies(&resolve(network, provider_proxy))? {
+        builder = builder.proxy(proxy);
+    }
+    for cert in tls.roots {
+        builder = builder.add_root_certificate(cert);
+    }
+    if let Some(identity) = tls.identity {
+        builder = builder.identity(identity);
+    }
+    Ok(builder)
+}
+
+const PROXY_VARS: &[&str] = &[
+    "HTTP_PROXY",
+    "http_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+    "NO_PROXY",
+    "no_proxy",
+];
+
+/// Read by OpenSSL, which provider clients use on Linux.
+const TLS_VARS: &[&str] = &["SSL_CERT_FILE", "SSL_CERT_DIR"];
+
+/// A PEM file with the CA bundle, preceded by the system roots unless those
+/// are turned off, for `SSL_CERT_FILE`.
+fn provider_ca_file(network: &NetworkConfig) -> Result<Option<PathBuf>> {
+    let Some(bundle) = &network.ca_bundle else {
+        return Ok(None);
+    };
+    let mut pem = String::new();
+    if network.system_roots.unwrap_or(true) {
+        for cert in rustls_native_certs::load_native_certs().certs {
+            let encoded = STANDARD.encode(cert.as_ref());
+            pem.push_str("-----BEGIN CERTIFICATE-----\n");
+            for line in encoded.as_bytes().chunks(64) {
+                pem.push_str(&String::from_utf8_lossy(line));
+                pem.push('\n');
+            }
+            pem.push_str("-----END CERTIFICATE-----\n");
+        }
+    }
+    pem.push_str(&String::from_utf8_lossy(&read_pem(bundle, "CA bundle")?));
+    let path = crate::scratch::file("ca.pem")?;
+    fs::write(&path, pem).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(Some(path))
+}
+
+/// Run `build` with the proxy and CA environment set for `provider`. The llm
+/// crate creates its HTTP client from these variables, and reads them only
+/// then, so the original environment is put back afterwards for commands tai
+/// runs. Client certificates cannot be passed this way.
+pub fn with_provider_env<T>(
+    cfg: &Config,
+    provider: &str,
+    build: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let p = resolve(&cfg.network, provider_proxy(cfg, Some(provider)));
+    tls(&cfg.network)?;
+    let ca_file = provider_ca_file(&cfg.network)?;
+    let saved: Vec<(&str, Option<String>)> = PROXY_VARS
+        .iter()
+        .chain(TLS_VARS)
+        .map(|name| (*name, std::env::var(name).ok()))
+        .collect();
+    let set = |name: &str, value: Option<&str>| match value {
+        Some(v) => std::env::set_var(name, v),
+        None => std::env::remove_var(name),
+    };
+    for name in PROXY_VARS {
+        let value = match name.to_ascii_uppercase().as_str() {
+            "HTTP_PROXY" => p.http.as_deref(),
+            "HTTPS_PROXY" => p.https.as_deref(),
+            "NO_PROXY" => Some(p.no_proxy.as_str()),
+            _ => None,
+        };
+        set(name, value);
+    }
+    if let Some(path) = &ca_file {
+        set("SSL_CERT_FILE", path.to_str());
+        if cfg.network.system_roots == Some(false) {
+            // A directory that does not exist keeps OpenSSL from falling
+            // back to the system certificate directory.
+            let none = std::env::temp_dir().join("tai-no-system-roots");
+            set("SSL_CERT_DIR", none.to_str());
+        }
+    }
+    let out = build();
+    for (name, value) in &saved {
+        set(name, value.as_deref());
+    }
+    if let Some(path) = ca_file {
+        let _ = fs::remove_file(path);
+    }
+    out
+}
+
+/// Git honors the proxy environment variables itself, but not tai's config.
+pub fn git_proxy_args(network: &NetworkConfig) -> Vec<String> {
+    match network.proxy.as_deref().map(str::trim) {
+        Some(url) if !url.is_empty() && url != "direct" => {
+            vec!["-c".into(), format!("http.proxy={}", url)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The innermost cause of a request error ("Connection refused", a proxy's
+/// 407), which reqwest's top-level message leaves out.
+pub fn error_cause(err: &reqwest::Error) -> String {
+    let mut cause: &dyn std::error::Error = err;
+    while let Some(next) = cause.source() {
+        cause = next;
+    }
+    cause.to_string()
+}
@@ -0,0 +1,143 @@
+use serde_json::Value;
+use std::borrow::Cow;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+const PRETTY: u8 = 0;
+const RAW: u8 = 1;
+const JSON: u8 = 2;
+
+/// How the chat session writes to stdout. In raw mode stdout gets only the
+/// final answer; with `--json` it carries one JSON event per line. Either way
+/// everything meant for a human (prompts, command output, notices) goes to
+/// stderr instead.
+static MODE: AtomicU8 = AtomicU8::new(PRETTY);
+
+pub fn enable_raw() {
+    MODE.store(RAW, Ordering::Relaxed);
+}
+
+pub fn enable_json() {
+    MODE.store(JSON, Ordering::Relaxed);
+}
+
+pub fn is_raw() -> bool {
+    MODE.load(Ordering::Relaxed) == RAW
+}
+
+pub fn is_json() -> bool {
+    MODE.load(Ordering::Relaxed) == JSON
+}
+
+/// Off with `--no-color` or NO_COLOR.
+static COLOR: AtomicBool = AtomicBool::new(true);
+
+pub fn disable_color() {
+    COLOR.store(false, Ordering::Relaxed);
+}
+
+pub fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// `text` without its color codes when colors are off. Cursor movement
+/// stays.
+pub fn plain(text: &str) -> Cow<'_, str> {
+    if color() || !text.contains('\x1b') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find("\x1b[") {
+        out.push_str(&rest[..at]);
+        let code = &rest[at + 2..];
+        let end = code
+            .find(|c: char| !c.is_ascii_digit() && c != ';')
+            .unwrap_or(code.len());
+        if code[end..].starts_with('m') {
+            rest = &code[end + 1..];
+        } else {
+            out.push_str("\x1b[");
+            rest = code;
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// `text` as it would read on screen, roughly: escape sequences dropped and
+/// carriage returns turned into line ends.
+pub fn printable(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: parameters up to a final byte in @..~.
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: up to BEL or ESC \.
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\r' => {
+                if chars.peek() != Some(&'\n') {
+                    out.push('\n');
+                }
+            }
+            c if c.is_control() && c != '\n' && c != '\t' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether stdout is reserved for the answer or events.
+pub fn stdout_reserved() -> bool {
+    MODE.load(Ordering::Relaxed) != PRETTY
+}
+
+/// One event: `{"type": kind, ...fields}`.
+pub fn event(kind: &str, fields: Value) -> Value {
+    let mut event = serde_json::Map::new();
+    event.insert("type".into(), Value::String(kind.into()));
+    if let Value::Object(map) = fields {
+        event.extend(map);
+    }
+    Value::Object(event)
+}
+
+/// Write one event line.
+pub fn emit(kind: &str, fields: Value) {
+    let mut out = std::io::stdout().lock();
+    let _ = writeln!(out, "{}", event(kind, fields));
+    let _ = out.flush();
+}
+
+/// `println!` for human-facing notices; moves to stderr in raw and JSON mode
+/// so stdout stays clean.
+#[macro_export]
+macro_rules! say {
+    () => {
+        $crate::say!("")
+    };
+    ($($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        if $crate::output::stdout_reserved() {
+            eprintln!("{}", $crate::output::plain(&line));
+        } else {
+            println!("{}", $crate::output::plain(&line));
+        }
+    }};
+}
@@ -0,0 +1,242 @@
+use anyhow::{Context, Result};
+use nu_ansi_term::{Color as NuColor, Style};
+use std::io::IsTerminal;
+use std::process::Command;
+
+use crate::config::Config;
+use crate::output;
+use crate::prompts;
+use crate::tools::{ask_line, ToolsRegistry};
+
+/// Entries shown at once; a filter narrows the rest down.
+const SHOWN: usize = 20;
+
+/// What the questions typed after `>` can also be.
+const SLASH_COMMANDS: &[(&str, &str)] = &[
+    (
+        "/context",
+        "Show what the next question is sent with, or drop and keep parts of it",
+    ),
+    ("/save", "Write a code block of the last answer to a file"),
+    (
+        "/copy",
+        "Copy code block N of the last answer to the clipboard",
+    ),
+    (
+        "/retry",
+        "Ask the last question again, optionally with another model or temperature",
+    ),
+    (
+        "/branch",
+        "Copy the history into a new named session and continue there",
+    ),
+    ("/session", "Switch to another session, or list them"),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Command,
+    Slash,
+    Tool,
+    Template,
+}
+
+impl Kind {
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Command => "command",
+            Kind::Slash => "slash",
+            Kind::Tool => "tool",
+            Kind::Template => "template",
+        }
+    }
+}
+
+struct Entry {
+    kind: Kind,
+    name: String,
+    about: String,
+}
+
+/// `text` up to the first ". " that starts a new sentence.
+fn first_sentence(text: &str) -> &str {
+    text.match_indices(". ")
+        .find(|(at, _)| text[at + 2..].starts_with(|c: char| c.is_uppercase()))
+        .map_or(text, |(at, _)| &text[..at])
+}
+
+fn entries(cfg: &Config, commands: Vec<(String, String)>) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = commands
+        .into_iter()
+        .map(|(name, about)| Entry {
+            kind: Kind::Command,
+            name,
+            about,
+        })
+        .collect();
+    entries.extend(SLASH_COMMANDS.iter().map(|(name, about)| Entry {
+        kind: Kind::Slash,
+        name: name.to_string(),
+        about: about.to_string(),
+    }));
+    let tools = ToolsRegistry::with_default(cfg);
+    // A tool's description is written for the model; its first sentence
+    // is enough here.
+    entries.extend(tools.describe().into_iter().map(|(name, about)| Entry {
+        kind: Kind::Tool,
+        name: name.to_string(),
+        about: first_sentence(about).to_string(),
+    }));
+    entries.extend(
+        prompts::templates()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, about)| Entry {
+                kind: Kind::Template,
+                name,
+                about,
+            }),
+    );
+    entries
+}
+
+/// How well `query` matches `entry`, lower is better: the name starts with
+/// it, contains it, has its letters in order, or the description contains
+/// it. `None` when it does not match.
+fn score(entry: &Entry, query: &str) -> Option<u8> {
+    let name = entry.name.to_lowercase();
+    let query = query.to_lowercase();
+    if query.is_empty() {
+        return Some(0);
+    }
+    if name
+        .trim_start_matches('/')
+        .starts_with(query.trim_start_matches('/'))
+    {
+        return Some(0);
+    }
+    if name.contains(&query) {
+        return Some(1);
+    }
+    let mut letters = name.chars();
+    if query.chars().all(|q| letters.any(|c| c == q)) {
+        return Some(2);
+    }
+    if entry.about.to_lowercase().contains(&query) {
+        return Some(3);
+    }
+    None
+}
+
+fn matching<'a>(entries: &'a [Entry], query: &str) -> Vec<&'a Entry> {
+    let mut found: Vec<(u8, &Entry)> = entries
+        .iter()
+        .filter_map(|e| score(e, query).map(|s| (s, e)))
+        .collect();
+    // Stable, so entries of one score keep their kind's order.
+    found.sort_by_key(|(s, _)| *s);
+    found.into_iter().map(|(_, e)| e).collect()
+}
+
+fn show(entries: &[&Entry], limit: usize) {
+    let width = entries
+        .iter()
+        .take(limit)
+        .map(|e| e.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (i, entry) in entries.iter().take(limit).enumerate() {
+        let line = format!(
+            "{:>3}  {}  {}  {}",
+            i + 1,
+            Style::new()
+                .dimmed()
+                .paint(format!("{:8}", entry.kind.label())),
+            Style::new()
+                .bold()
+                .paint(format!("{:width$}", entry.name, width = width)),
+            entry.about
+        );
+        println!("{}", output::plain(&line));
+    }
+    if entries.len() > limit {
+        println!(
+            "     … {} more; type to narrow them down",
+            entries.len() - limit
+        );
+    }
+}
+
+/// The `tai` arguments that run `entry`, after asking for what it needs.
+fn arguments(entry: &Entry) -> Result<Vec<String>> {
+    let words =
+        |text: String| -> Vec<String> { text.split_whitespace().map(String::from).collect() };
+    Ok(match entry.kind {
+        Kind::Command => {
+            let rest = ask_line(&format!("tai {} ", entry.name))?;
+            [vec![entry.name.clone()], words(rest)].concat()
+        }
+        Kind::Slash => {
+            let rest = ask_line(&format!("tai {} ", entry.name))?;
+            vec![format!("{} {}", entry.name, rest).trim().to_string()]
+        }
+        Kind::Tool => {
+            let task = ask_line(&format!("What should the model do with {}? ", entry.name))?;
+            if task.is_empty() {
+                return Ok(Vec::new());
+            }
+            vec!["--tools".into(), entry.name.clone(), task]
+        }
+        Kind::Template => {
+            let rest = ask_line(&format!("tai prompt {} ", entry.name))?;
+            [vec!["prompt".into(), entry.name.clone()], words(rest)].concat()
+        }
+    })
+}
+
+/// `tai ?`: every subcommand, slash command, tool, and prompt template
+/// with what it does. On a terminal, typing narrows the list down and a
+/// number runs that entry; otherwise the list is printed.
+pub fn run(cfg: &Config, commands: Vec<(String, String)>) -> Result<()> {
+    let entries = entries(cfg, commands);
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        output::disable_color();
+        show(&entries.iter().collect::<Vec<_>>(), entries.len());
+        return Ok(());
+    }
+    let mut query = String::new();
+    loop {
+        let found = matching(&entries, &query);
+        if found.is_empty() {
+            println!("Nothing matches '{}'", query);
+        } else {
+            show(&found, SHOWN);
+        }
+        let prompt = Style::new()
+            .fg(NuColor::Cyan)
+            .paint("Type to filter, a number to run, or q to quit: ")
+            .to_string();
+        let input = ask_line(&output::plain(&prompt))?;
+        match input.as_str() {
+            "q" => return Ok(()),
+            "" if query.is_empty() => return Ok(()),
+            "" => query.clear(),
+            n => match n.parse::<usize>() {
+                Ok(n) if (1..=found.len().min(SHOWN)).contains(&n) => {
+                    let args = arguments(found[n - 1])?;
+                    if args.is_empty() {
+                        continue;
+                    }
+                    let exe = std::env::current_exe().context("Failed to find tai itself")?;
+                    let status = Command::new(exe)
+                        .args(&args)
+                        .status()
+                        .context("Failed to start tai")?;
+                    std::process::exit(status.code().unwrap_or(1));
+                }
+                _ => query = n.to_string(),
+            },
+        }
+        println!();
+    }
+}
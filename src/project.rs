@@ -0,0 +1,243 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::{get_git_root, Config};
+
+/// Diagnostics output passed on to the model, from the end.
+const MAX_DIAGNOSTICS_CHARS: usize = 8000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectType {
+    Rust,
+    Node,
+    Python,
+    Terraform,
+    Kubernetes,
+}
+
+impl ProjectType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectType::Rust => "rust",
+            ProjectType::Node => "node",
+            ProjectType::Python => "python",
+            ProjectType::Terraform => "terraform",
+            ProjectType::Kubernetes => "kubernetes",
+        }
+    }
+}
+
+/// Directory used for detection: the git root if inside a repository,
+/// otherwise the current directory.
+pub fn project_root() -> Option<PathBuf> {
+    get_git_root().or_else(|| std::env::current_dir().ok())
+}
+
+pub fn detection_enabled(cfg: &Config) -> bool {
+    cfg.project.detect.unwrap_or(true)
+}
+
+pub fn detect_project_types(root: &Path) -> Vec<ProjectType> {
+    let mut out = Vec::new();
+    if root.join("Cargo.toml").is_file() {
+        out.push(ProjectType::Rust);
+    }
+    if root.join("package.json").is_file() {
+        out.push(ProjectType::Node);
+    }
+    if ["pyproject.toml", "setup.py", "requirements.txt", "Pipfile"]
+        .iter()
+        .any(|f| root.join(f).is_file())
+    {
+        out.push(ProjectType::Python);
+    }
+    if ["", "terraform", "infra", "infrastructure"]
+        .iter()
+        .any(|sub| has_file_with_extension(&root.join(sub), "tf"))
+    {
+        out.push(ProjectType::Terraform);
+    }
+    if has_kubernetes_manifests(root) {
+        out.push(ProjectType::Kubernetes);
+    }
+    out
+}
+
+pub fn detect_current(cfg: &Config) -> Vec<ProjectType> {
+    if !detection_enabled(cfg) {
+        return Vec::new();
+    }
+    project_root()
+        .map(|root| detect_project_types(&root))
+        .unwrap_or_default()
+}
+
+fn has_file_with_extension(dir: &Path, ext: &str) -> bool {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .any(|e| e.path().extension().and_then(|s| s.to_str()) == Some(ext))
+        })
+        .unwrap_or(false)
+}
+
+fn has_kubernetes_manifests(root: &Path) -> bool {
+    if ["kustomization.yaml", "kustomization.yml", "Chart.yaml", "skaffold.yaml"]
+        .iter()
+        .any(|f| root.join(f).is_file())
+    {
+        return true;
+    }
+    let mut dirs = vec![root.to_path_buf()];
+    for sub in ["k8s", "kubernetes", "manifests", "deploy", "charts"] {
+        let p = root.join(sub);
+        if p.is_dir() {
+            dirs.push(p);
+        }
+    }
+    dirs.iter().any(|dir| {
+        fs::read_dir(dir)
+            .map(|entries| {
+                entries.filter_map(|e| e.ok()).take(200).any(|e| {
+                    let p = e.path();
+                    let is_yaml = matches!(
+                        p.extension().and_then(|s| s.to_str()),
+                        Some("yaml") | Some("yml")
+                    );
+                    is_yaml && looks_like_manifest(&p)
+                })
+            })
+            .unwrap_or(false)
+    })
+}
+
+fn looks_like_manifest(path: &Path) -> bool {
+    let Ok(content) = fs::read_to_string(path) else {
+        return false;
+    };
+    let head: String = content.lines().take(40).collect::<Vec<_>>().join("\n");
+    head.contains("apiVersion:") && head.contains("kind:")
+}
+
+pub fn node_package_manager(root: &Path) -> &'static str {
+    if root.join("pnpm-lock.yaml").is_file() {
+        "pnpm"
+    } else if root.join("yarn.lock").is_file() {
+        "yarn"
+    } else if root.join("bun.lockb").is_file() || root.join("bun.lock").is_file() {
+        "bun"
+    } else {
+        "npm"
+    }
+}
+
+fn python_tooling(root: &Path) -> &'static str {
+    if root.join("uv.lock").is_file() {
+        "uv (use `uv run`, `uv add`, `uv sync`)"
+    } else if root.join("poetry.lock").is_file() {
+        "Poetry (use `poetry run`, `poetry add`, `poetry install`)"
+    } else if root.join("Pipfile").is_file() {
+        "Pipenv (use `pipenv run`, `pipenv install`)"
+    } else {
+        "pip with a virtual environment (prefer `python -m pip`)"
+    }
+}
+
+/// Bundled context snippet for a detected project type.
+pub fn bundled_context(pt: ProjectType, root: &Path) -> String {
+    match pt {
+        ProjectType::Rust => "This is a Rust project built with Cargo. Use `cargo check` for fast feedback, `cargo clippy` for lints, `cargo test` to run tests, and `cargo fmt` for formatting. Prefer the `cargo` tool over run_shell for these.".to_string(),
+        ProjectType::Node => format!(
+            "This is a Node.js project using {pm}. Use `{pm} install` to install dependencies and `{pm} run <script>` for scripts defined in package.json. Do not mix package managers.",
+            pm = node_package_manager(root)
+        ),
+        ProjectType::Python => format!(
+            "This is a Python project managed with {}. Run tests with pytest if it is configured.",
+            python_tooling(root)
+        ),
+        ProjectType::Terraform => "This directory contains Terraform configuration. Use `terraform fmt`, `terraform validate`, and `terraform plan` before any `apply`. Never run `terraform apply` or `destroy` without explicit user confirmation.".to_string(),
+        ProjectType::Kubernetes => "This project contains Kubernetes manifests. Prefer `kubectl diff` and `kubectl apply --dry-run=server` before applying changes, and always confirm the current context with `kubectl config current-context`.".to_string(),
+    }
+}
+
+/// A command that fails when the project no longer builds or type-checks,
+/// if the project type has a cheap one.
+pub fn diagnostics_command(pt: ProjectType, root: &Path) -> Option<String> {
+    match pt {
+        ProjectType::Rust => Some("cargo check --all-targets".into()),
+        ProjectType::Node => {
+            if root.join("tsconfig.json").is_file() {
+                return Some("npx --no-install tsc --noEmit".into());
+            }
+            let manifest: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(root.join("package.json")).ok()?).ok()?;
+            let scripts = manifest.get("scripts")?.as_object()?;
+            ["typecheck", "lint", "build"]
+                .iter()
+                .find(|s| scripts.contains_key(**s))
+                .map(|s| format!("{} run {}", node_package_manager(root), s))
+        }
+        ProjectType::Python => {
+            Some("python3 -m compileall -q -x '/(\\.?venv|node_modules|\\.git)/' .".into())
+        }
+        ProjectType::Terraform => Some("terraform validate".into()),
+        ProjectType::Kubernetes => None,
+    }
+}
+
+/// The diagnostics of every project type detected in `root`, run one after
+/// another.
+pub fn detected_diagnostics(cfg: &Config, root: &Path) -> Option<String> {
+    let commands: Vec<String> = detect_current(cfg)
+        .into_iter()
+        .filter_map(|pt| diagnostics_command(pt, root))
+        .collect();
+    (!commands.is_empty()).then(|| commands.join(" && "))
+}
+
+/// Run `command` in `root`; `None` when it passes, otherwise the end of its
+/// output.
+pub fn run_diagnostics(command: &str, root: &Path) -> Result<Option<String>> {
+    let output = if cfg!(target_os = "windows") {
+        Command::new("cmd")
+            .args(["/C", command])
+            .current_dir(root)
+            .output()
+    } else {
+        Command::new("sh")
+            .args(["-c", command])
+            .current_dir(root)
+            .output()
+    }
+    .with_context(|| format!("Failed to run `{}`", command))?;
+    if output.status.success() {
+        return Ok(None);
+    }
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let chars = text.chars().count();
+    Ok(Some(
+        text.chars()
+            .skip(chars.saturating_sub(MAX_DIAGNOSTICS_CHARS))
+            .collect(),
+    ))
+}
+
+pub fn bundled_contexts(cfg: &Config) -> Vec<(String, String)> {
+    if !detection_enabled(cfg) {
+        return Vec::new();
+    }
+    let Some(root) = project_root() else {
+        return Vec::new();
+    };
+    detect_project_types(&root)
+        .into_iter()
+        .map(|pt| (format!("project:{}", pt.as_str()), bundled_context(pt, &root)))
+        .collect()
+}
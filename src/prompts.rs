@@ -0,0 +1,166 @@
+use anyhow::{bail, Context, Result};
+use regex::{Captures, Regex};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use crate::config::get_global_config_dir;
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([A-Za-z0-9_]+)\s*\}\}").expect("valid regex"));
+
+fn prompts_dir() -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("prompts"))
+}
+
+fn template_path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        bail!("Invalid prompt name '{}'", name);
+    }
+    Ok(prompts_dir()?.join(format!("{}.tai", name)))
+}
+
+/// Every template in `~/.config/tai/prompts` with its first line, by name.
+pub fn templates() -> Result<Vec<(String, String)>> {
+    let dir = prompts_dir()?;
+    let mut names = BTreeSet::new();
+    if let Ok(entries) = fs::read_dir(&dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "tai") {
+                if let Some(stem) = path.file_stem() {
+                    names.insert(stem.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let body = fs::read_to_string(dir.join(format!("{}.tai", name))).unwrap_or_default();
+            let first = body.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+            let mut summary: String = first.trim().chars().take(70).collect();
+            if first.trim().chars().count() > 70 {
+                summary.push('…');
+            }
+            (name, summary)
+        })
+        .collect())
+}
+
+/// Print every template in `~/.config/tai/prompts` with its first line.
+pub fn list() -> Result<()> {
+    let templates = templates()?;
+    if templates.is_empty() {
+        let dir = prompts_dir()?;
+        println!("No prompt templates in {}", dir.display());
+        println!("Create e.g. {}", dir.join("summarize.tai").display());
+        return Ok(());
+    }
+    let width = templates.iter().map(|(n, _)| n.len()).max().unwrap_or(0);
+    for (name, summary) in templates {
+        println!("{:width$}  {}", name, summary, width = width);
+    }
+    Ok(())
+}
+
+fn read_stdin() -> Result<String> {
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        bail!("Template uses {{{{stdin}}}} but nothing was piped in");
+    }
+    let mut input = String::new();
+    stdin.read_to_string(&mut input)?;
+    Ok(input.trim_end().to_string())
+}
+
+/// The current selection: `TAI_SELECTION` if set, then the X11/Wayland
+/// primary selection on Linux, then the clipboard.
+fn read_selection() -> Result<String> {
+    if let Ok(text) = std::env::var("TAI_SELECTION") {
+        return Ok(text);
+    }
+    let mut cb = arboard::Clipboard::new().context("Failed to access the clipboard")?;
+    #[cfg(target_os = "linux")]
+    {
+        use arboard::{GetExtLinux, LinuxClipboardKind};
+        if let Ok(text) = cb.get().clipboard(LinuxClipboardKind::Primary).text() {
+            if !text.is_empty() {
+                return Ok(text);
+            }
+        }
+    }
+    cb.get_text().context("Nothing selected or copied")
+}
+
+/// Load the template `name` and substitute its placeholders. `{{argN}}` is
+/// the N-th argument (1-based) and `{{args}}` all of them; a template without
+/// argument placeholders gets the arguments appended instead.
+pub fn render(name: &str, args: &[String]) -> Result<String> {
+    let path = template_path(name)?;
+    if !path.exists() {
+        bail!(
+            "No prompt named '{}' (expected {}); run `tai prompt` to list templates",
+            name,
+            path.display()
+        );
+    }
+    let template = fs::read_to_string(&path)?;
+
+    let used: BTreeSet<String> = PLACEHOLDER
+        .captures_iter(&template)
+        .map(|c| c[1].to_string())
+        .collect();
+    let stdin = if used.contains("stdin") {
+        Some(read_stdin()?)
+    } else {
+        None
+    };
+    let selection = if used.contains("selection") {
+        Some(read_selection()?)
+    } else {
+        None
+    };
+
+    let mut missing = Vec::new();
+    let mut rendered = PLACEHOLDER
+        .replace_all(&template, |c: &Captures| {
+            let key = &c[1];
+            let value = match key {
+                "args" => Some(args.join(" ")),
+                "stdin" => stdin.clone(),
+                "selection" => selection.clone(),
+                _ => key
+                    .strip_prefix("arg")
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|i| args.get(i).cloned()),
+            };
+            value.unwrap_or_else(|| {
+                missing.push(key.to_string());
+                c[0].to_string()
+            })
+        })
+        .trim()
+        .to_string();
+    if !missing.is_empty() {
+        bail!(
+            "Prompt '{}' needs a value for {}",
+            name,
+            missing
+                .iter()
+                .map(|m| format!("{{{{{}}}}}", m))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    let takes_args = used.iter().any(|k| k == "args" || k.starts_with("arg"));
+    if !takes_args && !args.is_empty() {
+        rendered.push_str("\n\n");
+        rendered.push_str(&args.join(" "));
+    }
+    Ok(rendered)
+}
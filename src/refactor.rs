@@ -0,0 +1,236 @@
+use anyhow::{bail, Context, Result};
+use ignore::WalkBuilder;
+use nu_ansi_term::Color as NuColor;
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use crate::chat::Check;
+use crate::config::Config;
+use crate::project::{self, project_root};
+use crate::say;
+use crate::tools::{ask_user, restrict_writes};
+
+/// Files listed in the prompt, per group.
+const MAX_LISTED: usize = 100;
+
+/// Files larger than this are not searched for references.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+static WORD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"`([^`]+)`|[A-Za-z_][A-Za-z0-9_]*(::[A-Za-z_][A-Za-z0-9_]*)*").expect("valid regex")
+});
+
+/// Words in `task` that look like code rather than English: anything in
+/// backticks, and names with an underscore, a path separator, or a capital
+/// letter other than at the start of the task.
+fn identifiers(task: &str) -> BTreeSet<String> {
+    WORD.captures_iter(task)
+        .filter_map(|c| {
+            if let Some(quoted) = c.get(1) {
+                return Some(quoted.as_str().trim().to_string());
+            }
+            let word = c.get(0)?;
+            let name = word.as_str();
+            let capital_inside = name.chars().skip(1).any(|ch| ch.is_ascii_uppercase());
+            let capitalized =
+                word.start() > 0 && name.starts_with(|ch: char| ch.is_ascii_uppercase());
+            (name.len() > 2
+                && (name.contains('_') || name.contains("::") || capital_inside || capitalized))
+                .then(|| name.to_string())
+        })
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Every text file of the workspace that mentions one of `names`, with the
+/// number of mentions, most first. Files in `scope` are included either way.
+fn references(names: &BTreeSet<String>, scope: &Path) -> Result<Vec<(PathBuf, usize)>> {
+    let pattern = names
+        .iter()
+        .map(|n| regex::escape(n))
+        .collect::<Vec<_>>()
+        .join("|");
+    let re = (!names.is_empty())
+        .then(|| Regex::new(&format!(r"\b({})\b", pattern)))
+        .transpose()?;
+    let mut found: Vec<(PathBuf, usize)> = WalkBuilder::new(".")
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+        .filter(|e| e.metadata().is_ok_and(|m| m.len() <= MAX_FILE_BYTES))
+        .filter_map(|e| {
+            let path = e
+                .path()
+                .strip_prefix("./")
+                .unwrap_or(e.path())
+                .to_path_buf();
+            let text = fs::read_to_string(e.path()).ok()?;
+            let count = re.as_ref().map_or(0, |re| re.find_iter(&text).count());
+            (count > 0 || path.starts_with(scope)).then_some((path, count))
+        })
+        .collect();
+    found.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(found)
+}
+
+fn list(files: &[&(PathBuf, usize)]) -> String {
+    let mut out: String = files
+        .iter()
+        .take(MAX_LISTED)
+        .map(|(path, count)| match count {
+            0 => format!("- {}\n", path.display()),
+            1 => format!("- {} (1 match)\n", path.display()),
+            n => format!("- {} ({} matches)\n", path.display(), n),
+        })
+        .collect();
+    if files.len() > MAX_LISTED {
+        out.push_str(&format!("- and {} more\n", files.len() - MAX_LISTED));
+    }
+    out
+}
+
+/// `tai refactor`: limit writes to `scope`, and return the request for the
+/// model with the files involved, plus the check that must pass afterwards.
+pub fn prepare(
+    cfg: &Config,
+    scope: &Path,
+    task: &str,
+    verify: Option<&str>,
+    attempts: u32,
+) -> Result<(String, Option<Check>)> {
+    if task.trim().is_empty() {
+        bail!(
+            "Say what to change, e.g. tai refactor --scope src/parser \"rename Token to Lexeme\""
+        );
+    }
+    let cwd = std::env::current_dir()?.canonicalize()?;
+    let canonical = scope
+        .canonicalize()
+        .with_context(|| format!("Scope {} does not exist", scope.display()))?;
+    let scope = canonical
+        .strip_prefix(&cwd)
+        .map(Path::to_path_buf)
+        .map_err(|_| {
+            anyhow::anyhow!("Scope {} is outside the current directory", scope.display())
+        })?;
+    restrict_writes(&canonical)?;
+    let shown_scope = if scope.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        scope.display().to_string()
+    };
+
+    let names = identifiers(task);
+    let files = references(&names, &scope)?;
+    let (inside, outside): (Vec<_>, Vec<_>) =
+        files.iter().partition(|(p, _)| p.starts_with(&scope));
+    if inside.is_empty() {
+        bail!("No files in {}", shown_scope);
+    }
+    say!(
+        "Scope {}: {} file{}{}",
+        shown_scope,
+        inside.len(),
+        if inside.len() == 1 { "" } else { "s" },
+        if outside.is_empty() {
+            String::new()
+        } else {
+            format!(
+                ", {} referencing file{} outside",
+                outside.len(),
+                if outside.len() == 1 { "" } else { "s" }
+            )
+        }
+    );
+
+    let root = project_root().unwrap_or_else(|| cwd.clone());
+    let mut verification = verify
+        .map(str::to_string)
+        .or_else(|| project::detected_diagnostics(cfg, &root));
+    if let Some(command) = verification.clone() {
+        say!("Checking that `{}` passes before the refactor", command);
+        if project::run_diagnostics(&command, &root)?.is_some() {
+            if ask_user(&format!(
+                "`{}` already fails. Refactor without verifying? [y/N] ",
+                command
+            ))? != "y"
+            {
+                bail!(
+                    "`{}` fails before the refactor; fix it first or pass --verify",
+                    command
+                );
+            }
+            verification = None;
+        }
+    } else {
+        eprintln!("Warning: No diagnostics for this project; pass --verify to check the result");
+    }
+
+    let mut prompt = format!("Refactor the code in {}: {}\n\n", shown_scope, task.trim());
+    if names.is_empty() {
+        prompt.push_str(&format!("Files in {}:\n{}", shown_scope, list(&inside)));
+    } else {
+        prompt.push_str(&format!(
+            "Files in {} (matches of {}):\n{}",
+            shown_scope,
+            names
+                .iter()
+                .map(|n| format!("`{}`", n))
+                .collect::<Vec<_>>()
+                .join(", "),
+            list(&inside)
+        ));
+    }
+    if !outside.is_empty() {
+        prompt.push_str(&format!(
+            "\nReferences outside {}, which cannot be changed:\n{}",
+            shown_scope,
+            list(&outside)
+        ));
+    }
+    prompt.push_str(&format!(
+        r#"
+Rules:
+- This is a refactor: keep behavior the same.
+- Read a file before changing it, and change it with patch_file or apply_patch, or write_file for new files. Only files under {scope} can be written.
+- Update every reference under {scope}, including tests, comments, and docs.
+- Do not run commands that rewrite files, such as formatters.
+- If code outside {scope} would have to change, stop and say where instead of working around it.
+- Finish with a list of the files you changed.
+"#,
+        scope = shown_scope
+    ));
+    if let Some(command) = &verification {
+        prompt.push_str(&format!(
+            "- `{}` is run afterwards and must pass.\n",
+            command
+        ));
+    }
+
+    let check = verification.map(|command| {
+        let scope = shown_scope.clone();
+        Check {
+            attempts,
+            run: Box::new(move || {
+                say!("Verifying with `{}`", command);
+                let failure = project::run_diagnostics(&command, &root)?;
+                match &failure {
+                    None => say!("{}", NuColor::Green.paint(format!("`{}` passes; refactor verified", command))),
+                    Some(_) => say!("{}", NuColor::Red.paint(format!("`{}` fails", command))),
+                }
+                Ok(failure.map(|output| {
+                    format!(
+                        "`{}` fails after your changes:\n```\n{}\n```\nFix it, changing only files under {}.",
+                        command,
+                        output.trim_end(),
+                        scope
+                    )
+                }))
+            }),
+        }
+    });
+    Ok((prompt, check))
+}
@@ -0,0 +1,304 @@
+use anyhow::{anyhow, bail, Context, Result};
+use regex::{Captures, Regex};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::sync::LazyLock;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::config::Config;
+use crate::net;
+
+/// Largest request head accepted from the provider client.
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+
+/// Headers that describe one connection rather than the request.
+const HOP_BY_HOP: &[&str] = &[
+    "host",
+    "connection",
+    "keep-alive",
+    "content-length",
+    "transfer-encoding",
+    "accept-encoding",
+    "proxy-connection",
+    "upgrade",
+];
+
+static ENV_REF: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").expect("valid regex"));
+
+/// `providers.<name>.headers` with `${VAR}` references filled in from the
+/// environment, or `None` when the provider has none.
+pub fn headers(cfg: &Config, provider: &str) -> Result<Option<HeaderMap>> {
+    let Some(configured) = cfg
+        .providers
+        .common(provider)
+        .and_then(|c| c.headers.as_ref())
+        .filter(|h| !h.is_empty())
+    else {
+        return Ok(None);
+    };
+    let mut out = HeaderMap::new();
+    for (name, template) in configured {
+        let mut missing = None;
+        let value = ENV_REF.replace_all(template, |caps: &Captures| {
+            std::env::var(&caps[1]).unwrap_or_else(|_| {
+                missing.get_or_insert_with(|| caps[1].to_string());
+                String::new()
+            })
+        });
+        if let Some(var) = missing {
+            bail!(
+                "providers.{}.headers.{} uses ${{{}}}, which is not set",
+                provider,
+                name,
+                var
+            );
+        }
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header name '{}' for {}", name, provider))?;
+        let mut value = HeaderValue::from_str(&value)
+            .with_context(|| format!("Invalid value for header {} of {}", name, provider))?;
+        value.set_sensitive(true);
+        out.insert(name, value);
+    }
+    Ok(Some(out))
+}
+
+/// Start a loopback relay that forwards the provider client's requests to
+/// `target` with `headers` set, and return its base URL. The llm crate has
+/// no way to add headers, so the provider is pointed here instead. Requests
+/// leave through tai's own client, with the proxy and TLS settings.
+///
+/// Any local process can reach the port, so the base URL carries a random
+/// path prefix made for this run, and requests without it are refused.
+pub fn start(cfg: &Config, provider: &str, target: &str, headers: HeaderMap) -> Result<String> {
+    let client = net::client_for(&cfg.network, net::provider_proxy(cfg, Some(provider)))?
+        .build()
+        .context("Failed to build HTTP client")?;
+    let listener =
+        std::net::TcpListener::bind("127.0.0.1:0").context("Failed to start header relay")?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let runtime = tokio::runtime::Handle::try_current()
+        .map_err(|_| anyhow!("Provider headers need an async runtime"))?;
+    let listener = {
+        let _guard = runtime.enter();
+        TcpListener::from_std(listener)?
+    };
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow!("System random number generator failed"))?;
+    let secret: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let prefix = format!("/{}/", secret);
+    let target = target.trim_end_matches('/').to_string();
+    runtime.spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let client = client.clone();
+            let target = target.clone();
+            let prefix = prefix.clone();
+            let headers = headers.clone();
+            tokio::spawn(async move {
+                let _ = serve(stream, &client, &prefix, &target, &headers).await;
+            });
+        }
+    });
+    Ok(format!("http://{}/{}/", addr, secret))
+}
+
+/// Relay one request whose path starts with `prefix`. The response body is
+/// passed on as it arrives, so streamed completions stay streamed; the
+/// connection closes after it.
+async fn serve(
+    stream: TcpStream,
+    client: &reqwest::Client,
+    prefix: &str,
+    target: &str,
+    headers: &HeaderMap,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut head = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        if line == "\r\n" || line == "\n" {
+            break;
+        }
+        head.push(line.trim_end().to_string());
+        if head.iter().map(String::len).sum::<usize>() > MAX_HEAD_BYTES {
+            bail!("Request head too large");
+        }
+    }
+    let request_line = head.first().context("Empty request")?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().context("Missing method")?;
+    let path = parts.next().context("Missing path")?;
+    let Some(path) = path.strip_prefix(prefix) else {
+        let mut stream = reader.into_inner();
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(());
+    };
+    let url = format!("{}/{}", target, path.trim_start_matches('/'));
+
+    let mut forward = HeaderMap::new();
+    let mut length = 0;
+    for line in &head[1..] {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("content-length") {
+            length = value.parse().context("Invalid Content-Length")?;
+        }
+        if HOP_BY_HOP.iter().any(|h| name.eq_ignore_ascii_case(h)) {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            forward.append(name, value);
+        }
+    }
+    // Configured headers replace the client's own, so a gateway key can
+    // stand in for the provider's Authorization header.
+    for (name, value) in headers {
+        forward.insert(name, value.clone());
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    let mut stream = reader.into_inner();
+
+    let method = reqwest::Method::from_bytes(method.as_bytes())?;
+    let mut response = match client
+        .request(method, &url)
+        .headers(forward)
+        .body(body)
+        .send()
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            let message = serde_json::json!({ "error": { "message": format!("{}: {}", e, net::error_cause(&e)) } })
+                .to_string();
+            let reply = format!(
+                "HTTP/1.1 502 Bad Gateway\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                message.len(),
+                message
+            );
+            stream.write_all(reply.as_bytes()).await?;
+            return Ok(());
+        }
+    };
+
+    let status = response.status();
+    let mut reply = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    );
+    for (name, value) in response.headers() {
+        if HOP_BY_HOP.contains(&name.as_str()) {
+            continue;
+        }
+        reply.push_str(&format!(
+            "{}: {}\r\n",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        ));
+    }
+    reply.push_str("Connection: close\r\n\r\n");
+    stream.write_all(reply.as_bytes()).await?;
+    while let Some(chunk) = response.chunk().await? {
+        stream.write_all(&chunk).await?;
+        stream.flush().await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A provider stand-in that answers one request with `ok` and hands
+    /// back the head it got.
+    async fn upstream() -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let got = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if line == "\r\n" || line.is_empty() {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            reader
+                .into_inner()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                .await
+                .unwrap();
+            head
+        });
+        (format!("http://{}", addr), got)
+    }
+
+    /// Send a GET for `path` to the relay at `base` and return the reply.
+    async fn get(base: &str, path: &str) -> String {
+        let addr = base
+            .trim_start_matches("http://")
+            .split('/')
+            .next()
+            .unwrap();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer provider-key\r\n\r\n",
+            path, addr
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        reply
+    }
+
+    #[tokio::test]
+    async fn only_requests_with_the_secret_prefix_are_relayed() {
+        let (target, got) = upstream().await;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer gateway-key"),
+        );
+        let base = start(&Config::default(), "openai", &target, headers).unwrap();
+        let prefix = format!("/{}", base.splitn(4, '/').nth(3).unwrap());
+        assert!(prefix.len() > 30, "{}", prefix);
+
+        assert!(get(&base, "/v1/models").await.starts_with("HTTP/1.1 404"));
+        assert!(get(&base, "/0000/v1/models")
+            .await
+            .starts_with("HTTP/1.1 404"));
+
+        let reply = get(&base, &format!("{}v1/models", prefix)).await;
+        assert!(reply.starts_with("HTTP/1.1 200"), "{}", reply);
+        assert!(reply.ends_with("ok"), "{}", reply);
+        let head = got.await.unwrap().to_ascii_lowercase();
+        assert!(head.starts_with("get /v1/models "), "{}", head);
+        // The configured header replaces the client's own.
+        assert!(
+            head.contains("authorization: bearer gateway-key"),
+            "{}",
+            head
+        );
+        assert!(!head.contains("provider-key"), "{}", head);
+    }
+}
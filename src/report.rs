@@ -0,0 +1,256 @@
+use anyhow::{Context, Result};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::chat;
+use crate::config::{get_global_config_dir, Config};
+use crate::logging::redact;
+use crate::output;
+use crate::tools::{ask_user, UserShell};
+
+const NEW_ISSUE_URL: &str = "https://github.com/patrickjeremic/tai/issues/new";
+
+/// Transcript kept in a report, from its end.
+const TRANSCRIPT_CHARS: usize = 20_000;
+
+/// Longer reports are left out of the new-issue URL, which browsers and
+/// GitHub cut short.
+const MAX_URL_BODY: usize = 6_000;
+
+/// The last panic, recorded by the hook for the report.
+static CRASH: Mutex<Option<Crash>> = Mutex::new(None);
+
+/// The config the run loaded, for the report and `logging.bug_reports`.
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+pub struct Crash {
+    message: String,
+    backtrace: String,
+}
+
+/// What ended the run.
+pub enum Failure<'a> {
+    Error(&'a anyhow::Error),
+    Panic(Crash),
+}
+
+/// Keep each panic's message and backtrace for [`take_crash`], after the
+/// usual message is printed.
+pub fn catch_panics() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default(info);
+        let payload = info.payload();
+        let what = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Box<dyn Any>".to_string());
+        let message = match info.location() {
+            Some(at) => format!("{} at {}", what, at),
+            None => what,
+        };
+        *CRASH.lock().unwrap_or_else(|e| e.into_inner()) = Some(Crash {
+            message,
+            backtrace: Backtrace::force_capture().to_string(),
+        });
+    }));
+}
+
+/// The panic that unwound out of the run.
+pub fn take_crash() -> Option<Crash> {
+    CRASH.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+/// Whether to offer a report: on a terminal, outside `--json`, and unless
+/// `logging.bug_reports = false`.
+pub fn should_offer() -> bool {
+    std::io::stdin().is_terminal()
+        && std::io::stderr().is_terminal()
+        && !output::is_json()
+        && CONFIG
+            .get()
+            .is_none_or(|cfg| cfg.logging.bug_reports != Some(false))
+}
+
+/// The merged config as TOML without empty sections, with values under key-, token-, or
+/// password-like names and anything else that looks secret masked.
+fn redacted_config() -> String {
+    fn mask(value: &mut toml::Value) {
+        match value {
+            toml::Value::Table(table) => {
+                for (key, value) in table.iter_mut() {
+                    if chat::is_sensitive_key(key) && value.is_str() {
+                        *value = toml::Value::String("***".into());
+                    } else {
+                        mask(value);
+                    }
+                }
+                table.retain(|_, value| !matches!(value, toml::Value::Table(t) if t.is_empty()));
+            }
+            toml::Value::Array(items) => items.iter_mut().for_each(mask),
+            _ => {}
+        }
+    }
+    let cfg = CONFIG.get().cloned().unwrap_or_default();
+    match toml::Value::try_from(&cfg) {
+        Ok(mut value) => {
+            mask(&mut value);
+            redact(&toml::to_string(&value).unwrap_or_default()).into_owned()
+        }
+        Err(e) => format!("(could not be written out: {})", e),
+    }
+}
+
+/// The end of this run's transcript, whole lines only.
+fn transcript_tail(path: &Path) -> Option<String> {
+    let text = fs::read_to_string(path).ok()?;
+    let start = match text.len().saturating_sub(TRANSCRIPT_CHARS) {
+        0 => 0,
+        cut => text.as_bytes()[cut..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(text.len(), |at| cut + at + 1),
+    };
+    Some(redact(&text[start..]).into_owned())
+}
+
+fn fenced(lang: &str, text: &str) -> String {
+    format!("```{}\n{}\n```\n", lang, text.trim_end())
+}
+
+fn report(failure: &Failure) -> String {
+    let (title, error, backtrace) = match failure {
+        Failure::Error(e) => {
+            let backtrace = e.backtrace();
+            (
+                format!("Run failed: {:#}", e),
+                format!("{:?}", e),
+                (backtrace.status() == BacktraceStatus::Captured).then(|| backtrace.to_string()),
+            )
+        }
+        Failure::Panic(crash) => (
+            format!("Crash: {}", crash.message),
+            crash.message.clone(),
+            Some(crash.backtrace.clone()),
+        ),
+    };
+    let mut out = format!("# {}\n\n", redact(&title));
+    out.push_str("## What happened\n\n<!-- What did you run, and what did you expect? -->\n\n");
+    out.push_str("## Error\n\n");
+    out.push_str(&fenced("", &redact(&error)));
+    out.push_str("\n## Environment\n\n");
+    out.push_str(&format!(
+        "- tai {} ({} build)\n- {} {}\n- Shell: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        UserShell::detect().name()
+    ));
+    out.push_str("\n## Config\n\n");
+    out.push_str(&fenced("toml", &redacted_config()));
+    out.push_str("\n## Transcript\n\n");
+    match chat::transcript_path().and_then(transcript_tail) {
+        Some(tail) => out.push_str(&fenced("jsonl", &tail)),
+        None => out.push_str("Not recorded; run again with `--transcript` to add one.\n"),
+    }
+    if let Some(backtrace) = backtrace {
+        out.push_str("\n## Backtrace\n\n");
+        out.push_str(&fenced("", &redact(&backtrace)));
+    }
+    out
+}
+
+fn write(text: &str) -> Result<PathBuf> {
+    let dir = get_global_config_dir()?.join("reports");
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!(
+        "{}.md",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+fn open_in_browser(url: &str) -> Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = std::process::Command::new("xdg-open");
+    command
+        .arg(url)
+        .status()
+        .context("Failed to start a browser")?;
+    Ok(())
+}
+
+/// Report with `cfg`, the config this run loaded.
+pub fn keep_config(cfg: &Config) {
+    let _ = CONFIG.set(cfg.clone());
+}
+
+/// Ask whether to write a bug report for `failure`, and then whether to
+/// open a new GitHub issue with it.
+pub fn offer(failure: Failure) {
+    let Ok(answer) = ask_user("Write a bug report for this? [y/N] ") else {
+        return;
+    };
+    if answer != "y" && answer != "yes" {
+        return;
+    }
+    let text = report(&failure);
+    let path = match write(&text) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Warning: {:#}", e);
+            return;
+        }
+    };
+    eprintln!(
+        "Wrote {}. Secrets are masked, but it holds your config and any transcript, so read it before sharing.",
+        path.display()
+    );
+    let Ok(answer) = ask_user("Open a new GitHub issue with it? [y/N] ") else {
+        return;
+    };
+    if answer != "y" && answer != "yes" {
+        return;
+    }
+    let title = text
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches("# ");
+    let body = if text.len() <= MAX_URL_BODY {
+        text.clone()
+    } else {
+        format!(
+            "<!-- The report is too long for a link: paste or attach {} here. -->\n",
+            path.display()
+        )
+    };
+    let url = format!(
+        "{}?title={}&body={}",
+        NEW_ISSUE_URL,
+        utf8_percent_encode(title, NON_ALPHANUMERIC),
+        utf8_percent_encode(&body, NON_ALPHANUMERIC)
+    );
+    if let Err(e) = open_in_browser(&url) {
+        eprintln!("Warning: {:#}; open {} yourself", e, NEW_ISSUE_URL);
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::{bail, Context, Result};
+use nu_ansi_term::{Color as NuColor, Style};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::say;
+use crate::tools::auto_approve_edits;
+
+/// Built-in project kinds and what a starting point for each contains. Any
+/// other kind is passed to the model as written.
+const KINDS: &[(&str, &str)] = &[
+    (
+        "rust-cli",
+        "a Rust command-line program: Cargo.toml (edition 2021, clap with the derive feature, anyhow), src/main.rs with a clap Parser and a main returning anyhow::Result, .gitignore with /target",
+    ),
+    (
+        "rust-lib",
+        "a Rust library crate: Cargo.toml (edition 2021), src/lib.rs with a documented public function and a #[cfg(test)] test module, .gitignore with /target",
+    ),
+    (
+        "python-cli",
+        "a Python command-line package: pyproject.toml (setuptools, a [project.scripts] entry point, requires-python >= 3.9), src/<package>/__init__.py, src/<package>/__main__.py and src/<package>/cli.py using argparse, tests/test_cli.py for pytest, .gitignore for Python",
+    ),
+    (
+        "node-cli",
+        "a Node.js command-line program: package.json (type module, a bin entry, a start script, no dependencies unless the task needs them), bin/<name>.js with a #!/usr/bin/env node shebang, src/index.js, .gitignore with node_modules",
+    ),
+    (
+        "go-cli",
+        "a Go command-line program: go.mod (module named after the project, go 1.21), main.go using the flag package, .gitignore for the binary",
+    ),
+    (
+        "web",
+        "a static web page: index.html, style.css, script.js, no build step",
+    ),
+];
+
+fn describe_kind(kind: &str) -> String {
+    KINDS
+        .iter()
+        .find(|(name, _)| *name == kind)
+        .map(|(_, layout)| layout.to_string())
+        .unwrap_or_else(|| format!("a {} project with the usual layout for it", kind))
+}
+
+/// `tai new`: create the project directory `name`, move into it, and return
+/// the request that has the model write the project there with its file
+/// tools.
+pub fn prepare(kind: &str, name: &Path, description: &str) -> Result<String> {
+    let project = name
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .filter(|n| !n.starts_with('.'))
+        .with_context(|| format!("Invalid project name '{}'", name.display()))?;
+    if name.exists() {
+        let empty = fs::read_dir(name)
+            .with_context(|| format!("{} exists and is not a directory", name.display()))?
+            .next()
+            .is_none();
+        if !empty {
+            bail!("{} already exists and is not empty", name.display());
+        }
+    }
+    fs::create_dir_all(name).with_context(|| format!("Failed to create {}", name.display()))?;
+    std::env::set_current_dir(name)
+        .with_context(|| format!("Failed to enter {}", name.display()))?;
+    // Every file is new, in a directory that was empty.
+    auto_approve_edits();
+
+    let purpose = if description.trim().is_empty() {
+        String::new()
+    } else {
+        format!("\nWhat it is for: {}\n", description.trim())
+    };
+    Ok(format!(
+        r#"Create a new project named "{project}" in the current directory, which is empty.
+{purpose}
+Make it {layout}. Also write a README.md saying what the project does and how to build, run, and test it.
+
+Rules:
+- Create every file with write_file, using paths relative to the current directory.
+- Do not run commands: no generators such as `cargo init` or `npm init`, no installs, no builds.
+- Write working starter code for the purpose above, not placeholders, and keep it small.
+- Use current, stable versions of any dependency.
+- Finish with a short summary of the files you created and the command to run the project."#,
+        layout = describe_kind(kind),
+    ))
+}
+
+/// The files of the project in the current directory, printed after the
+/// model is done so what was created is plain whatever it reported.
+pub fn summary(name: &Path) -> Result<()> {
+    let files: Vec<(PathBuf, usize)> = WalkDir::new(".")
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| {
+            let lines = fs::read_to_string(e.path())
+                .map(|s| s.lines().count())
+                .unwrap_or(0);
+            let path = e.path().strip_prefix(".").unwrap_or(e.path()).to_path_buf();
+            (path, lines)
+        })
+        .collect();
+    say!();
+    if files.is_empty() {
+        say!(
+            "{}",
+            NuColor::Yellow.paint(format!("No files were created in {}", name.display()))
+        );
+        return Ok(());
+    }
+    say!(
+        "{}",
+        Style::new().bold().paint(format!(
+            "Created {} with {} file{}:",
+            name.display(),
+            files.len(),
+            if files.len() == 1 { "" } else { "s" }
+        ))
+    );
+    let width = files
+        .iter()
+        .map(|(p, _)| p.display().to_string().chars().count())
+        .max()
+        .unwrap_or(0);
+    for (path, lines) in files {
+        say!(
+            "  {:width$}  {}",
+            path.display().to_string(),
+            NuColor::DarkGray.paint(format!(
+                "{} line{}",
+                lines,
+                if lines == 1 { "" } else { "s" }
+            )),
+            width = width
+        );
+    }
+    Ok(())
+}
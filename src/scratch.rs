@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+/// Run directories are named this, then the pid of the run that owns them.
+const PREFIX: &str = "tai-run-";
+
+/// This run's directory, once something has needed it.
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Numbers the files handed out, so names never collide within a run.
+static NEXT: AtomicU32 = AtomicU32::new(0);
+
+/// Removes the run directory when dropped. `main` holds it for the whole
+/// run, so the directory goes on any return, error, or panic.
+pub struct Cleanup;
+
+impl Drop for Cleanup {
+    fn drop(&mut self) {
+        remove();
+    }
+}
+
+/// Clear the directories of runs that ended without removing theirs, as
+/// after a crash, and return the guard that removes this run's.
+pub fn start() -> Cleanup {
+    let Ok(entries) = fs::read_dir(std::env::temp_dir()) else {
+        return Cleanup;
+    };
+    let stale: Vec<(PathBuf, Pid)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let pid = name.to_str()?.strip_prefix(PREFIX)?.parse().ok()?;
+            (pid != std::process::id()).then(|| (entry.path(), Pid::from_u32(pid)))
+        })
+        .collect();
+    if stale.is_empty() {
+        return Cleanup;
+    }
+    let mut sys = System::new();
+    let pids: Vec<Pid> = stale.iter().map(|(_, pid)| *pid).collect();
+    sys.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&pids),
+        true,
+        ProcessRefreshKind::nothing(),
+    );
+    for (path, pid) in stale {
+        if sys.process(pid).is_none() {
+            tracing::debug!(path = %path.display(), "removing a stale run directory");
+            let _ = fs::remove_dir_all(path);
+        }
+    }
+    Cleanup
+}
+
+/// Remove this run's directory, for exits that skip the `Cleanup` guard.
+pub fn remove() {
+    if let Some(dir) = DIR.get() {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+/// This run's temp directory, created on first use and readable only by
+/// the user.
+pub fn dir() -> Result<&'static Path> {
+    if let Some(dir) = DIR.get() {
+        return Ok(dir);
+    }
+    let dir = std::env::temp_dir().join(format!("{}{}", PREFIX, std::process::id()));
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(&dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    Ok(DIR.get_or_init(|| dir))
+}
+
+/// A path in the run directory for a file called `name`. Nothing is created.
+pub fn file(name: &str) -> Result<PathBuf> {
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    Ok(dir()?.join(format!("{}-{}", n, name)))
+}
+
+/// Replace `path` with `content` so readers see the old file or the new one,
+/// never part of either. The new file is written in the run directory and
+/// moved into place. Where that crosses file systems, it is copied next to
+/// `path` first, and the copy is removed if it cannot be moved.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("file");
+    let tmp = file(name)?;
+    fs::write(&tmp, content).with_context(|| format!("Failed to write temp {}", tmp.display()))?;
+    if fs::rename(&tmp, path).is_ok() {
+        return Ok(());
+    }
+    let sibling = path.with_file_name(format!(".{}.tai-{}.tmp", name, std::process::id()));
+    let moved = fs::copy(&tmp, &sibling)
+        .with_context(|| format!("Failed to write temp {}", sibling.display()))
+        .and_then(|_| {
+            fs::rename(&sibling, path)
+                .with_context(|| format!("Failed to replace {}", path.display()))
+        });
+    let _ = fs::remove_file(&tmp);
+    if moved.is_err() {
+        let _ = fs::remove_file(&sibling);
+    }
+    moved
+}
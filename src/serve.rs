@@ -0,0 +1,741 @@
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use llm::chat::{ChatMessage, ChatRole, MessageType};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::LocalSet;
+
+use crate::chat::{self, ChatOptions, Failover};
+use crate::config::{
+    apply_profile, forget_config, get_global_config_dir, load_config, resolve_effective_provider,
+    Config, EffectiveProvider,
+};
+use crate::say;
+use crate::tools::{ToolsRegistry, SCHEMA_VERSION};
+
+/// Largest request head accepted from a client.
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+
+/// Longest single line and most lines accepted in a request head.
+const MAX_LINE_BYTES: usize = 8 * 1024;
+const MAX_HEAD_LINES: usize = 100;
+
+/// Largest request body accepted from a client.
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// A name clients can send as the model to get the configured one.
+const DEFAULT_MODEL: &str = "tai";
+
+struct Request {
+    method: String,
+    path: String,
+    /// The headers the checks in [`Guard::check`] look at.
+    authorization: Option<String>,
+    host: Option<String>,
+    origin: Option<String>,
+    body: Vec<u8>,
+}
+
+/// What a request must show to be served. Browsers can reach a port on
+/// 127.0.0.1 from any page, so the port alone proves nothing: requests
+/// need the token from startup, a Host naming this server (against DNS
+/// rebinding), and no Origin (which browsers add to cross-site requests).
+struct Guard {
+    token: String,
+    port: u16,
+}
+
+impl Guard {
+    fn check(&self, request: &Request) -> Result<(), Status> {
+        if request.origin.is_some() {
+            return Err(Status(403, "Requests from web pages are not served".into()));
+        }
+        let host = request.host.as_deref().unwrap_or_default();
+        let name = host
+            .strip_suffix(&format!(":{}", self.port))
+            .unwrap_or(host);
+        if !matches!(name, "127.0.0.1" | "localhost") {
+            return Err(Status(403, format!("Unexpected Host '{}'", host)));
+        }
+        let given = request
+            .authorization
+            .as_deref()
+            .and_then(|a| a.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        // Compared in full whatever differs, so timing does not tell.
+        let same = given.len() == self.token.len()
+            && given
+                .bytes()
+                .zip(self.token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0;
+        if !same {
+            return Err(Status(
+                401,
+                format!("Missing or wrong bearer token; it is in {}", token_hint()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn token_path() -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("serve-token"))
+}
+
+fn token_hint() -> String {
+    token_path()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| "the output of tai serve".into())
+}
+
+/// A new random token, written where local clients can read it and other
+/// users cannot.
+fn new_token() -> Result<String> {
+    let mut bytes = [0u8; 24];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("System random number generator failed"))?;
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let path = token_path()?;
+    let _ = fs::remove_file(&path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&path)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, token.as_bytes()))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(token)
+}
+
+/// `POST /v1/chat/completions`, as far as tai uses it.
+#[derive(Deserialize)]
+struct CompletionRequest {
+    model: Option<String>,
+    /// Not part of the OpenAI API: a provider other than the configured one.
+    provider: Option<String>,
+    messages: Vec<Message>,
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    role: String,
+    #[serde(default)]
+    content: Value,
+}
+
+/// `POST /v1/agent`: one question for the full tool loop.
+#[derive(Deserialize)]
+struct AgentRequest {
+    input: String,
+    provider: Option<String>,
+    model: Option<String>,
+    style: Option<String>,
+    tools: Option<Vec<String>>,
+    profile: Option<String>,
+    /// Directory to run in instead of the one `tai serve` started in. It
+    /// must be inside that one.
+    cwd: Option<PathBuf>,
+    #[serde(default)]
+    stream: bool,
+}
+
+/// Serve the API on `127.0.0.1:port` until Ctrl-C. `defaults` are the
+/// flags `tai serve` was started with; a request can override the
+/// provider, model, style, tools, and profile.
+pub async fn run(port: u16, defaults: ChatOptions) -> Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .await
+        .with_context(|| format!("Failed to listen on port {}", port))?;
+    let guard = Rc::new(Guard {
+        token: new_token()?,
+        port: listener.local_addr()?.port(),
+    });
+    say!(
+        "Serving /v1/chat/completions and /v1/agent on http://{}",
+        listener.local_addr()?
+    );
+    say!(
+        "Send `Authorization: Bearer <token>` with the token in {}",
+        token_hint()
+    );
+    // Agent runs share the working directory, the shell session, and the
+    // terminal approvals are asked in, and every request reads the config
+    // from the working directory, so requests take turns. Sessions are not
+    // `Send`, so connections are served on this thread.
+    let turn = Rc::new(Mutex::new(()));
+    let root = std::env::current_dir()?
+        .canonicalize()
+        .context("Failed to resolve the current directory")?;
+    let root = Rc::new(root);
+    let defaults = Rc::new(defaults);
+    LocalSet::new()
+        .run_until(async move {
+            loop {
+                let (stream, _) = tokio::select! {
+                    accepted = listener.accept() => accepted?,
+                    _ = tokio::signal::ctrl_c() => return Ok(()),
+                };
+                let turn = turn.clone();
+                let root = root.clone();
+                let defaults = defaults.clone();
+                let guard = guard.clone();
+                tokio::task::spawn_local(async move {
+                    if let Err(e) = handle(stream, &guard, &defaults, &root, &turn).await {
+                        tracing::debug!("Request failed: {:#}", e);
+                    }
+                });
+            }
+        })
+        .await
+}
+
+async fn read_request(reader: &mut BufReader<TcpStream>) -> Result<Option<Request>> {
+    let mut head = Vec::new();
+    loop {
+        let mut line = String::new();
+        let limit = (MAX_LINE_BYTES + 1) as u64;
+        if (&mut *reader).take(limit).read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        if !line.ends_with('\n') {
+            bail!("Request header line too long");
+        }
+        if line == "\r\n" || line == "\n" {
+            break;
+        }
+        head.push(line.trim_end().to_string());
+        if head.len() > MAX_HEAD_LINES {
+            bail!("Too many request headers");
+        }
+        if head.iter().map(String::len).sum::<usize>() > MAX_HEAD_BYTES {
+            bail!("Request head too large");
+        }
+    }
+    let request_line = head.first().context("Empty request")?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().context("Missing method")?.to_string();
+    let path = parts.next().context("Missing path")?;
+    // The query string means nothing here.
+    let path = path.split('?').next().unwrap_or(path).to_string();
+    let mut length = 0;
+    let (mut authorization, mut host, mut origin) = (None, None, None);
+    for line in &head[1..] {
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => {
+                    length = value.parse().context("Invalid Content-Length")?;
+                }
+                "authorization" => authorization = Some(value.to_string()),
+                "host" => host = Some(value.to_string()),
+                "origin" => origin = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+    if length > MAX_BODY_BYTES {
+        bail!("Request body too large");
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(Request {
+        method,
+        path,
+        authorization,
+        host,
+        origin,
+        body,
+    }))
+}
+
+/// Serve one request; the connection closes after it.
+async fn handle(
+    stream: TcpStream,
+    guard: &Guard,
+    defaults: &ChatOptions,
+    root: &Path,
+    turn: &Mutex<()>,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+    let Some(request) = read_request(&mut reader).await? else {
+        return Ok(());
+    };
+    let mut stream = reader.into_inner();
+    let _turn = turn.lock().await;
+    let route = guard
+        .check(&request)
+        .map(|()| (request.method.as_str(), request.path.as_str()));
+    let result = match route {
+        Err(status) => Err(status),
+        Ok(("GET", "/v1/models")) => models(&mut stream, defaults).await,
+        Ok(("POST", "/v1/chat/completions")) => match serde_json::from_slice(&request.body) {
+            Ok(body) => completions(&mut stream, defaults, body).await,
+            Err(e) => Err(Status(400, format!("Invalid request: {}", e))),
+        },
+        Ok(("POST", "/v1/agent")) => match serde_json::from_slice(&request.body) {
+            Ok(body) => run_agent(&mut stream, defaults, root, body).await,
+            Err(e) => Err(Status(400, format!("Invalid request: {}", e))),
+        },
+        Ok((_, "/v1/models" | "/v1/chat/completions" | "/v1/agent")) => Err(Status(
+            405,
+            format!("{} is not allowed here", request.method),
+        )),
+        Ok((_, path)) => Err(Status(404, format!("No endpoint at {}", path))),
+    };
+    if let Err(Status(code, message)) = result {
+        respond(
+            &mut stream,
+            code,
+            &json!({ "error": { "message": message, "code": code } }),
+        )
+        .await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// A request that failed before its response started, with the HTTP
+/// status to answer with.
+struct Status(u16, String);
+
+fn reason(code: u16) -> &'static str {
+    match code {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        502 => "Bad Gateway",
+        _ => "Internal Server Error",
+    }
+}
+
+async fn respond(stream: &mut TcpStream, code: u16, body: &Value) -> Result<()> {
+    let body = body.to_string();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        code,
+        reason(code),
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    Ok(())
+}
+
+/// Start a server-sent event stream; it ends when the connection closes.
+async fn start_events(stream: &mut TcpStream) -> Result<()> {
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        )
+        .await?;
+    Ok(())
+}
+
+async fn send_event(stream: &mut TcpStream, data: &str) -> Result<()> {
+    stream
+        .write_all(format!("data: {}\n\n", data).as_bytes())
+        .await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// The provider a request asks for, with [`DEFAULT_MODEL`] or no model
+/// meaning the configured one.
+fn provider_for(
+    defaults: &ChatOptions,
+    provider: Option<String>,
+    model: Option<String>,
+) -> (Option<String>, Option<String>) {
+    let model = model.filter(|m| !m.is_empty() && m != DEFAULT_MODEL);
+    let explicit = provider.is_some();
+    let provider = provider.or_else(|| defaults.provider.clone());
+    // The default model belongs to the default provider.
+    let model = model.or_else(|| {
+        if explicit {
+            None
+        } else {
+            defaults.model.clone()
+        }
+    });
+    (provider, model)
+}
+
+/// The config files as they are now; the server outlives edits to them.
+fn current_config() -> Config {
+    forget_config();
+    load_config().unwrap_or_default()
+}
+
+fn effective(
+    defaults: &ChatOptions,
+    provider: Option<String>,
+    model: Option<String>,
+) -> Result<(Config, EffectiveProvider), Status> {
+    let (provider, model) = provider_for(defaults, provider, model);
+    let mut cfg = current_config();
+    apply_profile(&mut cfg, defaults.profile.as_deref())
+        .map_err(|e| Status(500, format!("{:#}", e)))?;
+    let eff = resolve_effective_provider(&cfg, provider.as_deref(), model.as_deref())
+        .map_err(|e| Status(400, format!("{:#}", e)))?;
+    Ok((cfg, eff))
+}
+
+async fn models(stream: &mut TcpStream, defaults: &ChatOptions) -> Result<(), Status> {
+    let (_, eff) = effective(defaults, None, None)?;
+    respond(
+        stream,
+        200,
+        &json!({
+            "object": "list",
+            "data": [
+                { "id": DEFAULT_MODEL, "object": "model", "owned_by": "tai" },
+                { "id": eff.model, "object": "model", "owned_by": eff.name },
+            ],
+        }),
+    )
+    .await
+    .map_err(|e| Status(500, format!("{:#}", e)))
+}
+
+/// The text of a message's content: a string, or the text parts of an
+/// array of parts.
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// The request's messages as the provider client takes them. There is no
+/// system role there; tai's own system prompt goes as an assistant message
+/// too.
+fn messages(request: &CompletionRequest) -> Vec<ChatMessage> {
+    request
+        .messages
+        .iter()
+        .map(|m| ChatMessage {
+            role: match m.role.as_str() {
+                "user" | "tool" => ChatRole::User,
+                _ => ChatRole::Assistant,
+            },
+            message_type: MessageType::Text,
+            content: content_text(&m.content),
+        })
+        .collect()
+}
+
+fn completion_id() -> String {
+    format!(
+        "chatcmpl-{:x}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+    )
+}
+
+/// A plain completion through tai's provider selection and failover,
+/// without tools.
+async fn completions(
+    stream: &mut TcpStream,
+    defaults: &ChatOptions,
+    request: CompletionRequest,
+) -> Result<(), Status> {
+    if request.messages.is_empty() {
+        return Err(Status(400, "messages is empty".to_string()));
+    }
+    let (cfg, eff) = effective(defaults, request.provider.clone(), request.model.clone())?;
+    let llm = Failover::new(&cfg, &ToolsRegistry::new(), &eff)
+        .map_err(|e| Status(400, format!("{:#}", e)))?;
+    let messages = messages(&request);
+    let id = completion_id();
+    let created = chrono::Utc::now().timestamp();
+    let upstream = |e: anyhow::Error| Status(502, format!("{:#}", e));
+    let broken = |e: anyhow::Error| Status(500, format!("{:#}", e));
+
+    if !request.stream {
+        let response = llm.chat(&messages).await.map_err(upstream)?;
+        let mut body = json!({
+            "id": id,
+            "object": "chat.completion",
+            "created": created,
+            "model": eff.model,
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": response.text().unwrap_or_default() },
+                "finish_reason": "stop",
+            }],
+        });
+        if let Some(usage) = response.usage() {
+            body["usage"] = json!(usage);
+        }
+        return respond(stream, 200, &body).await.map_err(broken);
+    }
+
+    let mut tokens = llm.chat_stream(&messages).await.map_err(upstream)?;
+    start_events(stream).await.map_err(broken)?;
+    let chunk = |delta: Value, finish: Option<&str>| {
+        json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": eff.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish }],
+        })
+        .to_string()
+    };
+    let mut send = async |data: String| send_event(stream, &data).await.map_err(broken);
+    send(chunk(json!({ "role": "assistant" }), None)).await?;
+    while let Some(token) = tokens.next().await {
+        match token {
+            Ok(text) => send(chunk(json!({ "content": text }), None)).await?,
+            Err(e) => {
+                send(json!({ "error": { "message": e.to_string() } }).to_string()).await?;
+                return Ok(());
+            }
+        }
+    }
+    send(chunk(json!({}), Some("stop"))).await?;
+    send("[DONE]".to_string()).await
+}
+
+/// `cwd`, resolved against `root`, if it is `root` or a directory under it.
+fn inside(root: &Path, cwd: &Path) -> Result<PathBuf, Status> {
+    let resolved = root
+        .join(cwd)
+        .canonicalize()
+        .map_err(|e| Status(400, format!("Cannot use {}: {}", cwd.display(), e)))?;
+    if !resolved.starts_with(root) {
+        return Err(Status(
+            403,
+            format!("{} is outside {}", cwd.display(), root.display()),
+        ));
+    }
+    Ok(resolved)
+}
+
+/// Run the full tool loop on one question. Its events stream as they
+/// happen, or come back together with the answer.
+async fn run_agent(
+    stream: &mut TcpStream,
+    defaults: &ChatOptions,
+    root: &Path,
+    request: AgentRequest,
+) -> Result<(), Status> {
+    let broken = |e: anyhow::Error| Status(500, format!("{:#}", e));
+    let (provider, model) = provider_for(defaults, request.provider, request.model);
+    let opts = ChatOptions {
+        provider,
+        model,
+        style: request.style.or_else(|| defaults.style.clone()),
+        tools: request.tools.or_else(|| defaults.tools.clone()),
+        profile: request.profile.or_else(|| defaults.profile.clone()),
+        ..defaults.clone()
+    };
+    if let Some(cwd) = &request.cwd {
+        let cwd = inside(root, cwd)?;
+        std::env::set_current_dir(&cwd)
+            .map_err(|e| Status(400, format!("Cannot use {}: {}", cwd.display(), e)))?;
+    }
+    say!("Agent request: {}", request.input);
+
+    if request.stream {
+        if let Err(e) = start_events(stream).await {
+            let _ = std::env::set_current_dir(root);
+            return Err(broken(e));
+        }
+    }
+    let (events, mut received) = mpsc::unbounded_channel();
+    let run = chat::run_agent(current_config(), opts, request.input, events);
+    let mut collected = Vec::new();
+    let mut connected = true;
+    let relay = async {
+        while let Some(event) = received.recv().await {
+            if !request.stream {
+                collected.push(event);
+            } else if connected {
+                // A client that went away still leaves the run to finish.
+                connected = send_event(stream, &event.to_string()).await.is_ok();
+            }
+        }
+    };
+    let (result, ()) = tokio::join!(run, relay);
+    let _ = std::env::set_current_dir(root);
+
+    if request.stream {
+        let last = match &result {
+            Ok(()) => "[DONE]".to_string(),
+            Err(e) => json!({ "type": "error", "message": format!("{:#}", e) }).to_string(),
+        };
+        return send_event(stream, &last).await.map_err(broken);
+    }
+    if let Err(e) = result {
+        return Err(Status(500, format!("{:#}", e)));
+    }
+    let answer = collected
+        .iter()
+        .rev()
+        .find(|e| e["type"] == "assistant_text")
+        .and_then(|e| e["text"].as_str())
+        .unwrap_or_default()
+        .to_string();
+    respond(
+        stream,
+        200,
+        &json!({
+            "answer": answer,
+            "events": collected,
+            "schema_version": SCHEMA_VERSION,
+        }),
+    )
+    .await
+    .map_err(broken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(host: &str, authorization: Option<&str>, origin: Option<&str>) -> Request {
+        Request {
+            method: "GET".into(),
+            path: "/v1/models".into(),
+            authorization: authorization.map(str::to_string),
+            host: Some(host.to_string()),
+            origin: origin.map(str::to_string),
+            body: Vec::new(),
+        }
+    }
+
+    fn status(result: Result<(), Status>) -> Option<u16> {
+        result.err().map(|Status(code, _)| code)
+    }
+
+    #[test]
+    fn guard_wants_the_token_a_local_host_and_no_origin() {
+        let guard = Guard {
+            token: "secret".into(),
+            port: 8080,
+        };
+        let bearer = Some("Bearer secret");
+        assert_eq!(
+            status(guard.check(&request("localhost:8080", bearer, None))),
+            None
+        );
+        assert_eq!(
+            status(guard.check(&request("127.0.0.1", bearer, None))),
+            None
+        );
+        assert_eq!(
+            status(guard.check(&request("localhost:8080", None, None))),
+            Some(401)
+        );
+        assert_eq!(
+            status(guard.check(&request("localhost:8080", Some("Bearer secreT"), None))),
+            Some(401)
+        );
+        assert_eq!(
+            status(guard.check(&request("localhost:8080", Some("Bearer secret2"), None))),
+            Some(401)
+        );
+        assert_eq!(
+            status(guard.check(&request("evil.example:8080", bearer, None))),
+            Some(403)
+        );
+        assert_eq!(
+            status(guard.check(&request("localhost:9090", bearer, None))),
+            Some(403)
+        );
+        assert_eq!(
+            status(guard.check(&request(
+                "localhost:8080",
+                bearer,
+                Some("https://evil.example")
+            ))),
+            Some(403)
+        );
+    }
+
+    #[test]
+    fn agent_cwd_stays_under_the_root() {
+        let root = std::env::temp_dir().join(format!("tai-serve-{}", std::process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        let root = root.canonicalize().unwrap();
+        assert_eq!(inside(&root, Path::new("sub")).ok(), Some(root.join("sub")));
+        assert_eq!(inside(&root, &root).ok(), Some(root.clone()));
+        assert!(matches!(
+            inside(&root, Path::new("..")),
+            Err(Status(403, _))
+        ));
+        assert!(matches!(inside(&root, Path::new("/")), Err(Status(403, _))));
+        assert!(matches!(
+            inside(&root, Path::new("sub/../..")),
+            Err(Status(403, _))
+        ));
+        assert!(matches!(
+            inside(&root, Path::new("missing")),
+            Err(Status(400, _))
+        ));
+    }
+
+    /// `raw` sent to a fresh connection, as [`read_request`] reads it.
+    async fn read(raw: Vec<u8>) -> Result<Option<Request>> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let mut client = TcpStream::connect(listener.local_addr()?).await?;
+        let (server, _) = listener.accept().await?;
+        client.write_all(&raw).await?;
+        client.shutdown().await?;
+        read_request(&mut BufReader::new(server)).await
+    }
+
+    #[tokio::test]
+    async fn request_heads_are_capped() {
+        let ok = read(
+            b"POST /v1/agent?x=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\n\r\n{}"
+                .to_vec(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            (ok.method.as_str(), ok.path.as_str()),
+            ("POST", "/v1/agent")
+        );
+        assert_eq!(ok.body, b"{}");
+
+        let long = format!(
+            "GET / HTTP/1.1\r\nX: {}\r\n\r\n",
+            "a".repeat(MAX_LINE_BYTES)
+        );
+        assert!(read(long.into_bytes()).await.is_err());
+
+        let many = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X: a\r\n".repeat(MAX_HEAD_LINES)
+        );
+        assert!(read(many.into_bytes()).await.is_err());
+
+        let big = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_BYTES + 1
+        );
+        assert!(read(big.into_bytes()).await.is_err());
+    }
+}
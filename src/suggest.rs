@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Context, Result};
+use llm::chat::ChatMessage;
+use nu_ansi_term::{Color as NuColor, Style};
+use serde_json::json;
+use std::process::Command;
+
+use crate::chat::Failover;
+use crate::config::{apply_profile, resolve_effective_provider, Config};
+use crate::history::History;
+use crate::output;
+use crate::tools::{ask_line, ask_user, missing_programs, Offer, ToolsRegistry, UserShell};
+
+fn build_prompt(task: &str, shell: &UserShell) -> String {
+    let cwd = std::env::current_dir()
+        .map(|d| d.display().to_string())
+        .unwrap_or_default();
+    format!(
+        r#"Write one {shell} command for {os} that does the following:
+
+{task}
+
+Rules:
+- The working directory is {cwd}.
+- Combine steps with pipes or && rather than giving several commands.
+- Output ONLY the command. No code fences, no prompt sign, no explanation."#,
+        shell = shell.name(),
+        os = std::env::consts::OS,
+    )
+}
+
+/// The command without the fences, backticks, or `$ ` models add anyway.
+fn clean_command(raw: &str) -> String {
+    let trimmed = raw.trim();
+    let unfenced = trimmed
+        .strip_prefix("```")
+        .map(|rest| {
+            let rest = rest.split_once('\n').map(|(_, body)| body).unwrap_or("");
+            rest.trim_end().strip_suffix("```").unwrap_or(rest)
+        })
+        .unwrap_or(trimmed)
+        .trim();
+    let unquoted = unfenced
+        .strip_prefix('`')
+        .and_then(|s| s.strip_suffix('`'))
+        .unwrap_or(unfenced);
+    unquoted
+        .strip_prefix("$ ")
+        .unwrap_or(unquoted)
+        .trim()
+        .to_string()
+}
+
+/// Run `command` in the user's own shell, attached to the terminal.
+fn execute(command: &str) -> Result<()> {
+    let mut cmd = if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = Command::new(std::env::var("SHELL").unwrap_or_else(|_| "sh".into()));
+        cmd.arg("-c");
+        cmd
+    };
+    let status = cmd
+        .arg(command)
+        .status()
+        .context("Failed to execute command")?;
+    if !status.success() {
+        let code = status
+            .code()
+            .map(|c| format!("exit {}", c))
+            .unwrap_or_else(|| "terminated by signal".into());
+        eprintln!("{}", NuColor::Red.paint(code));
+    }
+    Ok(())
+}
+
+async fn suggest(llm: &Failover, messages: &[ChatMessage]) -> Result<String> {
+    let response = llm
+        .chat(messages)
+        .await
+        .context("Failed to get a command suggestion")?;
+    let command = clean_command(&response.text().unwrap_or_default());
+    if command.is_empty() {
+        return Err(anyhow!("Provider returned no command"));
+    }
+    Ok(command)
+}
+
+/// `tai -s`: ask for a single shell command for `task` without the tool
+/// loop, then execute, copy, or refine it. In raw and JSON mode the command
+/// is only printed.
+pub async fn run_suggest(
+    mut cfg: Config,
+    task: String,
+    provider: Option<&str>,
+    model: Option<&str>,
+    profile: Option<&str>,
+) -> Result<()> {
+    apply_profile(&mut cfg, profile)?;
+    let eff = resolve_effective_provider(&cfg, provider, model)?;
+    let llm = Failover::new(&cfg, &ToolsRegistry::new(), &eff)?;
+    let shell = UserShell::detect();
+
+    let mut messages = vec![ChatMessage::user()
+        .content(build_prompt(&task, &shell))
+        .build()];
+    let separator = Style::new()
+        .fg(NuColor::Rgb(100, 100, 100))
+        .paint("─".repeat(40));
+    let mut command = suggest(&llm, &messages).await?;
+    if output::is_json() {
+        output::emit("suggestion", json!({ "command": command }));
+        return Ok(());
+    }
+    if output::is_raw() {
+        println!("{}", command);
+        return Ok(());
+    }
+    loop {
+        println!("{}", separator);
+        println!("{}", Style::new().bold().paint(&command));
+        println!("{}", separator);
+        match ask_user("[e]xecute / [c]opy / [r]efine / [a]bort? ")?.as_str() {
+            "e" | "execute" => {
+                let missing = shell
+                    .is_posix()
+                    .then(|| missing_programs(&command, None))
+                    .flatten();
+                if let Some(missing) = missing {
+                    match missing.offer()? {
+                        Offer::Installed | Offer::RunAnyway => {}
+                        Offer::Declined => continue,
+                        Offer::Failed(code) => {
+                            eprintln!(
+                                "{}",
+                                NuColor::Red.paint(format!(
+                                    "install failed{}",
+                                    code.map(|c| format!(" (exit {})", c)).unwrap_or_default()
+                                ))
+                            );
+                            continue;
+                        }
+                    }
+                }
+                History::load(&cfg.history)
+                    .unwrap_or_default()
+                    .add_entry(task, command.clone())?;
+                return execute(&command);
+            }
+            "c" | "copy" => {
+                match arboard::Clipboard::new() {
+                    Ok(mut cb) => match cb.set_text(&command) {
+                        Ok(()) => println!("Command copied to clipboard"),
+                        Err(e) => eprintln!("Failed to copy to clipboard: {}", e),
+                    },
+                    Err(_) => eprintln!("Failed to access clipboard"),
+                }
+                return History::load(&cfg.history)
+                    .unwrap_or_default()
+                    .add_entry(task, command);
+            }
+            "r" | "refine" => {
+                let change = ask_line("What should change? ")?;
+                if change.is_empty() {
+                    continue;
+                }
+                messages.push(ChatMessage::assistant().content(&command).build());
+                messages.push(
+                    ChatMessage::user()
+                        .content(format!("{}\n\nOutput ONLY the revised command.", change))
+                        .build(),
+                );
+                command = suggest(&llm, &messages).await?;
+            }
+            // An empty answer is also what a closed stdin gives.
+            "" | "a" | "abort" => {
+                println!("Aborted");
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+}
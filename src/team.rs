@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use crate::config::{get_global_config_dir, Config, NetworkConfig};
+use crate::net;
+
+const DEFAULT_REFRESH_HOURS: u64 = 24;
+
+/// A team config source is either a git repository (cloned into the cache),
+/// a plain http(s) URL pointing at a config.tai file, or a local directory.
+enum TeamSource {
+    Git(String),
+    Url(String),
+    Local(PathBuf),
+}
+
+fn parse_source(spec: &str) -> TeamSource {
+    let spec = spec.trim();
+    let local = Path::new(spec);
+    if local.is_dir() {
+        return TeamSource::Local(local.to_path_buf());
+    }
+    if spec.starts_with("git@") || spec.starts_with("ssh://") || spec.ends_with(".git") {
+        return TeamSource::Git(spec.to_string());
+    }
+    if spec.starts_with("http://") || spec.starts_with("https://") {
+        return TeamSource::Url(spec.to_string());
+    }
+    TeamSource::Git(spec.to_string())
+}
+
+fn cache_dir() -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("team"))
+}
+
+/// Directory holding the team's `config.tai` and `context/` folder, if any.
+pub fn team_root(spec: &str) -> Result<PathBuf> {
+    match parse_source(spec) {
+        TeamSource::Local(p) => Ok(p),
+        TeamSource::Git(_) => Ok(cache_dir()?.join("repo")),
+        TeamSource::Url(_) => Ok(cache_dir()?),
+    }
+}
+
+pub fn team_context_dir(spec: &str) -> Option<PathBuf> {
+    let dir = team_root(spec).ok()?.join("context");
+    dir.is_dir().then_some(dir)
+}
+
+fn is_stale(spec: &str, refresh_hours: u64) -> bool {
+    let Ok(dir) = cache_dir() else {
+        return true;
+    };
+    let recorded = fs::read_to_string(dir.join(".source")).unwrap_or_default();
+    if recorded.trim() != spec.trim() {
+        return true;
+    }
+    let age = fs::metadata(dir.join(".last_refresh"))
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| SystemTime::now().duration_since(t).ok());
+    match age {
+        Some(age) => age > Duration::from_secs(refresh_hours * 3600),
+        None => true,
+    }
+}
+
+/// Fetch or update the team source into the local cache. Local directories are
+/// used in place and never copied.
+pub fn refresh(spec: &str, network: &NetworkConfig) -> Result<()> {
+    if spec.trim().starts_with('-') {
+        return Err(anyhow!(
+            "Team config source '{}' looks like an option, not a repository",
+            spec.trim()
+        ));
+    }
+    let dir = cache_dir()?;
+    let recorded = fs::read_to_string(dir.join(".source")).unwrap_or_default();
+    if recorded.trim() != spec.trim() && dir.exists() {
+        fs::remove_dir_all(&dir)
+            .with_context(|| format!("Failed to clear team cache at {}", dir.display()))?;
+    }
+    fs::create_dir_all(&dir)?;
+    match parse_source(spec) {
+        TeamSource::Local(_) => {}
+        TeamSource::Git(url) => {
+            let repo = dir.join("repo");
+            let output = if repo.join(".git").exists() {
+                Command::new("git")
+                    .args(net::git_proxy_args(network))
+                    .arg("-C")
+                    .arg(&repo)
+                    .args(["pull", "--ff-only", "--quiet"])
+                    .output()
+            } else {
+                Command::new("git")
+                    .args(net::git_proxy_args(network))
+                    .args(["clone", "--depth", "1", "--quiet", "--", &url])
+                    .arg(&repo)
+                    .output()
+            }
+            .context("Failed to run git")?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "git failed for team config {}: {}",
+                    url,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        }
+        TeamSource::Url(url) => {
+            let client = net::blocking_client_for(network, None)?
+                .timeout(Duration::from_secs(10))
+                .build()?;
+            let resp = client
+                .get(&url)
+                .send()
+                .with_context(|| format!("Failed to fetch team config from {}", url))?;
+            if !resp.status().is_success() {
+                return Err(anyhow!(
+                    "Fetching team config from {} returned {}",
+                    url,
+                    resp.status()
+                ));
+            }
+            let body = resp.text()?;
+            toml::from_str::<Config>(&body)
+                .with_context(|| format!("Team config at {} is not valid", url))?;
+            fs::write(dir.join("config.tai"), body)?;
+        }
+    }
+    fs::write(dir.join(".source"), spec.trim())?;
+    fs::write(dir.join(".last_refresh"), "")?;
+    Ok(())
+}
+
+/// Load the team config layer, refreshing the cache when it is older than the
+/// configured interval. A failed refresh falls back to the cached copy.
+pub fn load_team_config(
+    spec: &str,
+    refresh_hours: Option<u64>,
+    network: &NetworkConfig,
+) -> Option<Config> {
+    let refresh_hours = refresh_hours.unwrap_or(DEFAULT_REFRESH_HOURS);
+    if !matches!(parse_source(spec), TeamSource::Local(_)) && is_stale(spec, refresh_hours) {
+        if let Err(e) = refresh(spec, network) {
+            eprintln!("Warning: Failed to refresh team config: {:#}", e);
+        }
+    }
+    let path = team_root(spec).ok()?.join("config.tai");
+    let content = fs::read_to_string(&path).ok()?;
+    match toml::from_str::<Config>(&content) {
+        Ok(mut cfg) => {
+            cfg.core.team_config = None;
+            cfg.core.team_refresh_hours = None;
+            Some(cfg)
+        }
+        Err(e) => {
+            eprintln!(
+                "Warning: Ignoring invalid team config {}: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
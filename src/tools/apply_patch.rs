@@ -0,0 +1,494 @@
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use llm::builder::ParamBuilder;
+
+use super::approval::{approve_edit, FileChange};
+use super::dir::resolve_writable;
+use super::seen;
+use super::{results, Tool};
+use crate::config::PolicyConfig;
+use crate::scratch;
+
+/// Context lines per hunk end that may mismatch, by default.
+const DEFAULT_FUZZ: usize = 2;
+
+static HUNK_HEADER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^@@ -(\d+)(?:,\d+)? \+\d+(?:,\d+)? @@").expect("valid regex"));
+
+/// One `@@` section of a diff.
+struct Hunk {
+    /// 1-based line of the old file the hunk starts at; 0 before the first.
+    old_start: usize,
+    /// `' '` context, `'-'` removed, or `'+'` added, with the line's text.
+    lines: Vec<(char, String)>,
+    /// The new side ends with `\ No newline at end of file`.
+    new_no_eol: bool,
+}
+
+impl Hunk {
+    fn context_at_start(&self) -> usize {
+        self.lines.iter().take_while(|(k, _)| *k == ' ').count()
+    }
+
+    fn context_at_end(&self) -> usize {
+        self.lines
+            .iter()
+            .rev()
+            .take_while(|(k, _)| *k == ' ')
+            .count()
+    }
+}
+
+/// The hunks for one file; a `None` side is `/dev/null`.
+struct FilePatch {
+    old: Option<String>,
+    new: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+fn header_path(text: &str) -> Option<String> {
+    let path = text.split('\t').next().unwrap_or(text).trim();
+    (path != "/dev/null").then(|| path.to_string())
+}
+
+/// The files and hunks of a unified diff. Line counts in hunk headers are
+/// not trusted, since models often get them wrong: a hunk runs until the
+/// next header. Text between files, such as `diff --git` and `index` lines,
+/// is skipped.
+fn parse(text: &str) -> Result<Vec<FilePatch>> {
+    let lines: Vec<&str> = text.lines().collect();
+    let is_file_header = |i: usize| {
+        lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "))
+    };
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if is_file_header(i) {
+            files.push(FilePatch {
+                old: header_path(&lines[i][4..]),
+                new: header_path(&lines[i + 1][4..]),
+                hunks: Vec::new(),
+            });
+            i += 2;
+            continue;
+        }
+        let Some(header) = HUNK_HEADER.captures(lines[i]) else {
+            i += 1;
+            continue;
+        };
+        let file = files
+            .last_mut()
+            .ok_or_else(|| anyhow!("Line {}: hunk before any ---/+++ file header", i + 1))?;
+        let mut hunk = Hunk {
+            old_start: header[1].parse()?,
+            lines: Vec::new(),
+            new_no_eol: false,
+        };
+        // Empty lines are context whose leading space was lost, unless they
+        // only separate this hunk from what follows. A new file has no
+        // context, so there they are added lines.
+        let bare = if file.old.is_none() { '+' } else { ' ' };
+        let mut bare_tail = 0;
+        i += 1;
+        while i < lines.len() && !lines[i].starts_with("@@") && !is_file_header(i) {
+            let line = lines[i];
+            if line.starts_with('\\') {
+                if hunk.lines.last().is_some_and(|(k, _)| *k != '-') {
+                    hunk.new_no_eol = true;
+                }
+            } else if line.is_empty() {
+                hunk.lines.push((bare, String::new()));
+                bare_tail += 1;
+            } else if let Some(kind @ (' ' | '-' | '+')) = line.chars().next() {
+                hunk.lines.push((kind, line[1..].to_string()));
+                bare_tail = 0;
+            } else {
+                break;
+            }
+            i += 1;
+        }
+        hunk.lines.truncate(hunk.lines.len() - bare_tail);
+        file.hunks.push(hunk);
+    }
+    if files.is_empty() {
+        bail!(
+            "No ---/+++ file headers found; pass a unified diff as produced by diff -u or git diff"
+        );
+    }
+
+    // git's a/ and b/ prefixes.
+    for file in &mut files {
+        let git_style = file.old.as_deref().is_none_or(|p| p.starts_with("a/"))
+            && file.new.as_deref().is_none_or(|p| p.starts_with("b/"));
+        if git_style {
+            for path in [&mut file.old, &mut file.new].into_iter().flatten() {
+                path.drain(..2);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Where a hunk matched.
+struct Placement {
+    /// 0-based line in the file.
+    at: usize,
+    /// Context lines left out at each end.
+    fuzz: usize,
+    /// Matched only with whitespace ignored.
+    loose: bool,
+}
+
+fn matches_at(lines: &[String], at: usize, old: &[&str], loose: bool) -> bool {
+    old.iter().enumerate().all(|(j, want)| {
+        let have = &lines[at + j];
+        if loose {
+            have.split_whitespace().eq(want.split_whitespace())
+        } else {
+            have == want
+        }
+    })
+}
+
+/// Find `hunk` in `lines` at or after `floor`, nearest to `expected` first.
+fn place(
+    hunk: &Hunk,
+    lines: &[String],
+    expected: usize,
+    floor: usize,
+    max_fuzz: usize,
+) -> Option<Placement> {
+    let lead = hunk.context_at_start();
+    let trail = hunk.context_at_end().min(hunk.lines.len() - lead);
+    for fuzz in 0..=max_fuzz {
+        if fuzz > lead.max(trail) {
+            break;
+        }
+        let (skip_start, skip_end) = (fuzz.min(lead), fuzz.min(trail));
+        let old: Vec<&str> = hunk.lines[skip_start..hunk.lines.len() - skip_end]
+            .iter()
+            .filter(|(k, _)| *k != '+')
+            .map(|(_, t)| t.as_str())
+            .collect();
+        let want = expected + skip_start;
+        if old.is_empty() {
+            return Some(Placement {
+                at: want.clamp(floor, lines.len()),
+                fuzz,
+                loose: false,
+            });
+        }
+        if old.len() > lines.len() {
+            continue;
+        }
+        let last = lines.len() - old.len();
+        if floor > last {
+            continue;
+        }
+        let want = want.clamp(floor, last);
+        for loose in [false, true] {
+            // want, want+1, want-1, want+2, ...
+            for distance in 0..=(last - floor) {
+                let candidates = [want.checked_add(distance), want.checked_sub(distance)];
+                for at in candidates.into_iter().flatten() {
+                    if at >= floor && at <= last && matches_at(lines, at, &old, loose) {
+                        return Some(Placement { at, fuzz, loose });
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// `content` with `hunks` applied, if all of them apply, and a report per
+/// hunk either way.
+fn apply(
+    content: &str,
+    hunks: &[Hunk],
+    max_fuzz: usize,
+) -> (Option<String>, Vec<results::HunkReport>) {
+    let crlf = content.contains("\r\n");
+    let mut eol = content.is_empty() || content.ends_with('\n');
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut reports = Vec::new();
+    let mut all_applied = true;
+    // How far the file has moved from the line numbers in the headers.
+    let mut offset: isize = 0;
+    let mut floor = 0;
+    for (n, hunk) in hunks.iter().enumerate() {
+        let expected = (hunk.old_start.saturating_sub(1) as isize + offset).max(0) as usize;
+        let Some(Placement { at, fuzz, loose }) = place(hunk, &lines, expected, floor, max_fuzz)
+        else {
+            all_applied = false;
+            let first = hunk
+                .lines
+                .iter()
+                .find(|(k, t)| *k != '+' && !t.trim().is_empty())
+                .map(|(_, t)| t.trim().to_string())
+                .unwrap_or_default();
+            reports.push(results::HunkReport {
+                hunk: n + 1,
+                applied: false,
+                line: hunk.old_start,
+                error: Some(format!(
+                    "Context not found near line {}: {}",
+                    hunk.old_start, first
+                )),
+                ..results::HunkReport::default()
+            });
+            continue;
+        };
+        let lead = hunk.context_at_start().min(fuzz);
+        let trail = hunk.context_at_end().min(fuzz).min(hunk.lines.len() - lead);
+        let body = &hunk.lines[lead..hunk.lines.len() - trail];
+        let old_len = body.iter().filter(|(k, _)| *k != '+').count();
+        let mut replacement = Vec::new();
+        let mut line = at;
+        for (kind, text) in body {
+            match kind {
+                // The file's own context, which may differ in whitespace.
+                ' ' => {
+                    replacement.push(lines[line].clone());
+                    line += 1;
+                }
+                '-' => line += 1,
+                _ => replacement.push(text.clone()),
+            }
+        }
+        if at + old_len == lines.len() {
+            eol = !hunk.new_no_eol;
+        }
+        let intended = expected + lead;
+        let new_len = replacement.len();
+        lines.splice(at..at + old_len, replacement);
+        offset += at as isize - intended as isize + new_len as isize - old_len as isize;
+        floor = at + new_len;
+
+        reports.push(results::HunkReport {
+            hunk: n + 1,
+            applied: true,
+            line: at + 1,
+            offset: (at != intended).then(|| at as isize - intended as isize),
+            fuzz: (fuzz > 0).then_some(fuzz),
+            whitespace_ignored: loose.then_some(true),
+            error: None,
+        });
+    }
+    if !all_applied {
+        return (None, reports);
+    }
+    let mut out = lines.join(if crlf { "\r\n" } else { "\n" });
+    if eol && !lines.is_empty() {
+        out.push_str(if crlf { "\r\n" } else { "\n" });
+    }
+    (Some(out), reports)
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn write(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    scratch::write_atomic(path, content.as_bytes())?;
+    seen::saw(path, content.as_bytes());
+    Ok(())
+}
+
+/// What applying one file's hunks comes to.
+enum Outcome {
+    Write(PathBuf, String),
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+        content: String,
+    },
+    Delete(PathBuf),
+}
+
+pub struct ApplyPatchTool(pub(super) PolicyConfig);
+impl Tool for ApplyPatchTool {
+    fn name(&self) -> &'static str {
+        "apply_patch"
+    }
+    fn description(&self) -> &'static str {
+        "Apply a unified diff (diff -u or git diff output) to one or more files. Hunks are found by their context, so line numbers may be off; context that still does not match exactly is tried with whitespace ignored, then with up to `fuzz` context lines dropped at each end of the hunk. Either every hunk applies and all files are written, or nothing is written and the result says which hunks failed. Handles new files (--- /dev/null), deletions (+++ /dev/null), and renames. Returns per-file, per-hunk results; use dry_run to check a patch without writing."
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        &["patch"]
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("patch")
+                .type_of("string")
+                .description("The unified diff, with ---/+++ headers and @@ hunks"),
+            ParamBuilder::new("dry_run")
+                .type_of("boolean")
+                .description("Report what would apply without writing (default false)"),
+            ParamBuilder::new("fuzz")
+                .type_of("integer")
+                .description("Context lines per hunk end that may mismatch (default 2)"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let patch = args
+            .get("patch")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'patch'"))?;
+        let dry_run = args
+            .get("dry_run")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let fuzz = args
+            .get("fuzz")
+            .and_then(|v| v.as_u64())
+            .map_or(DEFAULT_FUZZ, |n| n as usize);
+
+        let mut outcomes = Vec::new();
+        let mut files = Vec::new();
+        let mut declined = false;
+        let (mut applied, mut failed) = (0, 0);
+        for file in parse(patch)? {
+            let (status, path, source) = match (&file.old, &file.new) {
+                (None, None) => bail!("A file header has /dev/null on both sides"),
+                (None, Some(new)) => {
+                    let path = resolve_writable(new, true)?;
+                    if path.exists() && !read(&path)?.is_empty() {
+                        bail!(
+                            "{} already exists; diff against it instead of /dev/null",
+                            new
+                        );
+                    }
+                    ("created", path, String::new())
+                }
+                (Some(old), None) => {
+                    let path = resolve_writable(old, false)?;
+                    let source = read(&path)?;
+                    ("deleted", path, source)
+                }
+                (Some(old), Some(new)) => {
+                    let path = resolve_writable(old, false)?;
+                    let source = read(&path)?;
+                    (
+                        if old == new { "modified" } else { "renamed" },
+                        path,
+                        source,
+                    )
+                }
+            };
+            let (result, hunks) = apply(&source, &file.hunks, fuzz);
+            let original = (status != "created").then(|| source.clone());
+            let ok = hunks.iter().filter(|h| h.applied).count();
+            applied += ok;
+            failed += hunks.len() - ok;
+            let shown = file
+                .new
+                .as_ref()
+                .or(file.old.as_ref())
+                .cloned()
+                .unwrap_or_default();
+            files.push(results::PatchedFile {
+                path: shown,
+                status: if result.is_some() { status } else { "failed" }.to_string(),
+                hunks,
+            });
+            let Some(content) = result else {
+                continue;
+            };
+            let outcome = match (status, &file.new) {
+                ("deleted", _) => {
+                    if !content.trim().is_empty() {
+                        bail!(
+                            "The diff deletes {} but leaves lines in it",
+                            file.old.as_deref().unwrap_or_default()
+                        );
+                    }
+                    Outcome::Delete(path)
+                }
+                ("renamed", Some(new)) => {
+                    let to = resolve_writable(new, true)?;
+                    if to.exists() {
+                        bail!("Cannot rename to {}: it already exists", new);
+                    }
+                    Outcome::Move {
+                        from: path,
+                        to,
+                        content,
+                    }
+                }
+                _ => Outcome::Write(path, content),
+            };
+            outcomes.push((original, outcome));
+        }
+
+        let mut written = failed == 0 && !dry_run;
+        if written {
+            let changes: Vec<FileChange> = outcomes
+                .iter()
+                .map(|(old, outcome)| {
+                    let old = old.as_deref();
+                    match outcome {
+                        Outcome::Write(path, content) => FileChange {
+                            path,
+                            old,
+                            new: Some(content),
+                            moved_to: None,
+                        },
+                        Outcome::Move { from, to, content } => FileChange {
+                            path: from,
+                            old,
+                            new: Some(content),
+                            moved_to: Some(to),
+                        },
+                        Outcome::Delete(path) => FileChange {
+                            path,
+                            old,
+                            new: None,
+                            moved_to: None,
+                        },
+                    }
+                })
+                .collect();
+            written = approve_edit(&self.0, self.name(), &changes)?;
+            declined = !written;
+        }
+        if written {
+            for (_, outcome) in &outcomes {
+                match outcome {
+                    Outcome::Write(path, content) => write(path, content)?,
+                    Outcome::Move { from, to, content } => {
+                        write(to, content)?;
+                        fs::remove_file(from)
+                            .with_context(|| format!("Failed to remove {}", from.display()))?;
+                        seen::forget(from);
+                    }
+                    Outcome::Delete(path) => {
+                        fs::remove_file(path)
+                            .with_context(|| format!("Failed to remove {}", path.display()))?;
+                        seen::forget(path);
+                    }
+                }
+            }
+        }
+        results::to_value(results::ApplyPatch {
+            dry_run,
+            written,
+            hunks_applied: applied,
+            hunks_failed: failed,
+            files,
+            reason: declined.then(|| "The user declined this change".to_string()),
+            hint: (failed > 0).then(|| {
+                "Nothing was written. Read the files again and resend the whole patch with the failed hunks' context fixed.".to_string()
+            }),
+        })
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::{bail, Result};
+use nu_ansi_term::{Color as NuColor, Style};
+use similar::{ChangeTag, TextDiff};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::ask_user;
+use crate::config::PolicyConfig;
+use crate::say;
+
+/// Diff lines shown per change before the rest is summarized.
+const MAX_DIFF_LINES: usize = 400;
+
+/// Set by `--auto-approve-edits` and by commands that review every change
+/// at the end, so their edits are not asked about one by one.
+static AUTO_APPROVE: AtomicBool = AtomicBool::new(false);
+
+/// Apply file edits without asking, unless the policy denies them.
+pub(crate) fn auto_approve_edits() {
+    AUTO_APPROVE.store(true, Ordering::Relaxed);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Approval {
+    Ask,
+    Auto,
+    Deny,
+}
+
+impl Approval {
+    fn parse(value: &str, key: &str) -> Result<Self> {
+        match value {
+            "ask" => Ok(Self::Ask),
+            "auto" => Ok(Self::Auto),
+            "deny" => Ok(Self::Deny),
+            other => bail!("Unknown {} '{}'; use ask, auto, or deny", key, other),
+        }
+    }
+
+    /// What applies to `tool`: its entry in `policy.approve`, then
+    /// `policy.edits`, then asking.
+    fn of(policy: &PolicyConfig, tool: &str) -> Result<Self> {
+        if let Some(value) = policy.approve.as_ref().and_then(|a| a.get(tool)) {
+            return Self::parse(value, &format!("policy.approve.{}", tool));
+        }
+        match &policy.edits {
+            Some(value) => Self::parse(value, "policy.edits"),
+            None => Ok(Self::Ask),
+        }
+    }
+}
+
+/// One file an edit touches; `None` is a file that does not exist before
+/// or after it.
+pub(super) struct FileChange<'a> {
+    pub path: &'a Path,
+    pub old: Option<&'a str>,
+    pub new: Option<&'a str>,
+    /// Where the file moves to, for renames.
+    pub moved_to: Option<&'a Path>,
+}
+
+pub(super) fn print_diff(change: &FileChange) {
+    let heading = match (change.old, change.new, change.moved_to) {
+        (None, _, _) => format!("create {}", change.path.display()),
+        (_, None, _) => format!("delete {}", change.path.display()),
+        (_, _, Some(to)) => format!("rename {} -> {}", change.path.display(), to.display()),
+        _ => format!("modify {}", change.path.display()),
+    };
+    say!("{}", Style::new().bold().paint(heading));
+    if change.new.is_none() {
+        return;
+    }
+
+    let old = change.old.unwrap_or_default();
+    let new = change.new.unwrap_or_default();
+    let diff = TextDiff::from_lines(old, new);
+    let mut shown = 0;
+    let mut hidden = 0;
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        if shown >= MAX_DIFF_LINES {
+            hidden += hunk.iter_changes().count();
+            continue;
+        }
+        say!("{}", NuColor::Cyan.paint(hunk.header().to_string()));
+        for line in hunk.iter_changes() {
+            if shown >= MAX_DIFF_LINES {
+                hidden += 1;
+                continue;
+            }
+            shown += 1;
+            let text = line.value().trim_end_matches(['\n', '\r']);
+            match line.tag() {
+                ChangeTag::Insert => say!("{}", NuColor::Green.paint(format!("+{}", text))),
+                ChangeTag::Delete => say!("{}", NuColor::Red.paint(format!("-{}", text))),
+                ChangeTag::Equal => say!(" {}", text),
+            }
+            if line.missing_newline() {
+                say!(
+                    "{}",
+                    NuColor::DarkGray.paint("\\ No newline at end of file")
+                );
+            }
+        }
+    }
+    if hidden > 0 {
+        say!(
+            "{}",
+            NuColor::DarkGray.paint(format!("… {} more diff lines", hidden))
+        );
+    }
+}
+
+/// Whether `tool` may make `changes`: shows their diff and asks, unless
+/// the policy or `--auto-approve-edits` settles it. A denied tool is an
+/// error, so the model stops trying it.
+pub(super) fn approve_edit(
+    policy: &PolicyConfig,
+    tool: &str,
+    changes: &[FileChange],
+) -> Result<bool> {
+    let approval = Approval::of(policy, tool)?;
+    if approval == Approval::Deny {
+        bail!("{} is not allowed to change files by the edit policy", tool);
+    }
+    if changes.is_empty() || approval == Approval::Auto || AUTO_APPROVE.load(Ordering::Relaxed) {
+        return Ok(true);
+    }
+    for change in changes {
+        print_diff(change);
+    }
+    if ask_user("Apply this change? [Y/n] ")? == "n" {
+        say!("Change not applied");
+        return Ok(false);
+    }
+    Ok(true)
+}
@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::process::Command;
+use std::time::Duration;
+
+use llm::builder::ParamBuilder;
+use llm::chat::ParameterProperty;
+
+use super::dir::{working_dir, workspace_root};
+use super::process::{run_captured, tail};
+use super::{ask_user, results, Tool};
+use crate::say;
+
+const SUBCOMMANDS: &[&str] = &[
+    "check", "build", "test", "clippy", "fmt", "tree", "metadata", "doc",
+];
+const MAX_OUTPUT_CHARS: usize = 20_000;
+
+/// Runs a whitelisted set of cargo subcommands. Only registered in Rust projects.
+pub struct CargoTool;
+
+impl Tool for CargoTool {
+    fn name(&self) -> &'static str {
+        "cargo"
+    }
+    fn description(&self) -> &'static str {
+        "Run a cargo subcommand (check, build, test, clippy, fmt, tree, metadata, doc) in the current Rust project and return its exit status and output."
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        &["subcommand"]
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("subcommand")
+                .type_of("string")
+                .description(format!("One of: {}", SUBCOMMANDS.join(", "))),
+            ParamBuilder::new("args")
+                .type_of("array")
+                .items(ParameterProperty {
+                    property_type: "string".into(),
+                    description: "argument".into(),
+                    items: None,
+                    enum_list: None,
+                })
+                .description("Extra arguments (e.g. [\"--workspace\", \"--\", \"-D\", \"warnings\"])"),
+            ParamBuilder::new("timeout_sec")
+                .type_of("integer")
+                .description("Optional timeout in seconds (defaults to 600)"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let sub = args
+            .get("subcommand")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'subcommand'"))?;
+        if !SUBCOMMANDS.contains(&sub) {
+            return Err(anyhow!("Unsupported cargo subcommand: {}", sub));
+        }
+        let extra: Vec<String> = args
+            .get("args")
+            .and_then(|v| v.as_array())
+            .map(|arr| {
+                arr.iter()
+                    .filter_map(|a| a.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let timeout = args
+            .get("timeout_sec")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(600);
+        let display = std::iter::once(format!("cargo {}", sub))
+            .chain(extra.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let choice = ask_user(&format!("Run `{}`? [Y/n] ", display))?;
+        if choice == "n" {
+            say!("Command execution cancelled");
+            return results::to_value(results::NotRun::new(&display));
+        }
+
+        let mut cmd = Command::new("cargo");
+        cmd.arg(sub)
+            .args(&extra)
+            .current_dir(working_dir(&workspace_root()?))
+            .env("CARGO_TERM_COLOR", "never");
+        let captured = run_captured(cmd, Duration::from_secs(timeout))?;
+        let (stdout, stdout_truncated) = tail(&captured.stdout, MAX_OUTPUT_CHARS);
+        let (stderr, stderr_truncated) = tail(&captured.stderr, MAX_OUTPUT_CHARS);
+
+        results::to_value(match captured.status {
+            Some(status) => results::Cargo {
+                command: display,
+                executed: true,
+                exit_status: status.code(),
+                success: Some(status.success()),
+                stdout: Some(stdout),
+                stderr,
+                truncated: Some(stdout_truncated || stderr_truncated),
+                error: None,
+                terminated: None,
+            },
+            None => results::Cargo {
+                command: display,
+                executed: false,
+                exit_status: None,
+                success: None,
+                stdout: None,
+                stderr,
+                truncated: None,
+                error: Some(format!("timeout after {}s", timeout)),
+                terminated: Some(captured.killed),
+            },
+        })
+    }
+
+    fn print_result(&self, result: &Value) {
+        use nu_ansi_term::{Color as NuColor, Style};
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        if let Some(err) = result.get("error").and_then(|v| v.as_str()) {
+            println!("{}: {}", result_label, err);
+            return;
+        }
+        match result.get("success").and_then(|v| v.as_bool()) {
+            Some(true) => println!("{}: ok", result_label),
+            Some(false) => {
+                let stderr = result.get("stderr").and_then(|v| v.as_str()).unwrap_or("");
+                let (shown, _) = tail(stderr, 2_000);
+                println!("{}: failed\n{}", result_label, shown);
+            }
+            None => println!("{}: command not executed", result_label),
+        }
+    }
+}
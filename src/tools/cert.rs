@@ -0,0 +1,441 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use llm::builder::ParamBuilder;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring as ring_provider, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::certification_request::X509CertificationRequest;
+use x509_parser::extensions::{GeneralName, ParsedExtension};
+use x509_parser::objects::{oid2sn, oid_registry};
+use x509_parser::pem::Pem;
+use x509_parser::prelude::FromDer;
+use x509_parser::x509::{AlgorithmIdentifier, SubjectPublicKeyInfo};
+
+use super::results::{self, CertObject, Certificate, Csr, InspectCert, KeyInfo, Tls, Validation};
+use super::Tool;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parses certificates, CSRs, and public keys from files or a live TLS
+/// endpoint, and validates certificate chains against the system roots.
+pub struct InspectCertTool;
+
+fn algorithm_name(alg: &AlgorithmIdentifier) -> String {
+    oid2sn(&alg.algorithm, oid_registry())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|_| alg.algorithm.to_id_string())
+}
+
+fn key_info(spki: &SubjectPublicKeyInfo) -> KeyInfo {
+    let bits = spki.parsed().map(|k| k.key_size()).unwrap_or(0);
+    KeyInfo {
+        algorithm: algorithm_name(&spki.algorithm),
+        bits: (bits > 0).then_some(bits),
+    }
+}
+
+fn general_name(name: &GeneralName) -> String {
+    match name {
+        GeneralName::DNSName(s) => format!("DNS:{}", s),
+        GeneralName::RFC822Name(s) => format!("email:{}", s),
+        GeneralName::URI(s) => format!("URI:{}", s),
+        GeneralName::IPAddress(b) => match b.len() {
+            4 => format!("IP:{}", std::net::Ipv4Addr::new(b[0], b[1], b[2], b[3])),
+            16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(b);
+                format!("IP:{}", std::net::Ipv6Addr::from(octets))
+            }
+            _ => name.to_string(),
+        },
+        other => other.to_string(),
+    }
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, der)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn timestamp(ts: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp(ts, 0).map(|t| t.to_rfc3339())
+}
+
+fn describe_certificate(cert: &X509Certificate, der: &[u8]) -> Certificate {
+    let sans: Vec<String> = cert
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|ext| ext.value.general_names.iter().map(general_name).collect())
+        .unwrap_or_default();
+    let not_before = cert.validity().not_before.timestamp();
+    let not_after = cert.validity().not_after.timestamp();
+    let now = chrono::Utc::now().timestamp();
+    Certificate {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        serial: cert.raw_serial_as_string(),
+        not_before: timestamp(not_before),
+        not_after: timestamp(not_after),
+        days_remaining: (not_after - now).div_euclid(86_400),
+        expired: now > not_after,
+        not_yet_valid: now < not_before,
+        self_signed: cert.subject() == cert.issuer(),
+        is_ca: cert.is_ca(),
+        san: sans,
+        key: key_info(cert.public_key()),
+        signature_algorithm: algorithm_name(&cert.signature_algorithm),
+        sha256_fingerprint: fingerprint(der),
+    }
+}
+
+fn describe_csr(csr: &X509CertificationRequest) -> Csr {
+    let info = &csr.certification_request_info;
+    let sans: Vec<String> = csr
+        .requested_extensions()
+        .into_iter()
+        .flatten()
+        .filter_map(|ext| match ext {
+            ParsedExtension::SubjectAlternativeName(san) => Some(san),
+            _ => None,
+        })
+        .flat_map(|san| san.general_names.iter().map(general_name))
+        .collect();
+    Csr {
+        subject: info.subject.to_string(),
+        san: sans,
+        key: key_info(&info.subject_pki),
+        signature_algorithm: algorithm_name(&csr.signature_algorithm),
+    }
+}
+
+/// Parse every object in a PEM bundle or a single DER blob. Certificates are
+/// also returned as raw DER (in file order) for chain validation.
+fn parse_objects(data: &[u8]) -> Result<(Vec<CertObject>, Vec<CertificateDer<'static>>)> {
+    let mut objects = Vec::new();
+    let mut certs = Vec::new();
+    let pems: Vec<Pem> = Pem::iter_from_buffer(data).filter_map(|p| p.ok()).collect();
+    let blocks: Vec<(Option<String>, Vec<u8>)> = if pems.is_empty() {
+        vec![(None, data.to_vec())]
+    } else {
+        pems.into_iter()
+            .map(|p| (Some(p.label), p.contents))
+            .collect()
+    };
+    for (label, der) in blocks {
+        match label.as_deref() {
+            Some(l) if l.contains("PRIVATE KEY") => {
+                objects.push(CertObject::PrivateKey {
+                    label: l.to_string(),
+                    note: "private key contents are not inspected".into(),
+                });
+                continue;
+            }
+            Some("CERTIFICATE") | Some("TRUSTED CERTIFICATE") | None => {
+                if let Ok((_, cert)) = X509Certificate::from_der(&der) {
+                    objects.push(CertObject::Certificate(describe_certificate(&cert, &der)));
+                    certs.push(CertificateDer::from(der.clone()));
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        if let Ok((_, csr)) = X509CertificationRequest::from_der(&der) {
+            objects.push(CertObject::Csr(describe_csr(&csr)));
+        } else if let Ok((_, spki)) = SubjectPublicKeyInfo::from_der(&der) {
+            objects.push(CertObject::PublicKey(key_info(&spki)));
+        } else {
+            objects.push(CertObject::Unknown {
+                label,
+                error: "not a certificate, CSR, or public key".into(),
+            });
+        }
+    }
+    if objects.is_empty() {
+        return Err(anyhow!("No certificates, CSRs, or keys found"));
+    }
+    Ok((objects, certs))
+}
+
+fn signature_algorithms() -> WebPkiSupportedAlgorithms {
+    ring_provider::default_provider().signature_verification_algorithms
+}
+
+/// Validate `chain` (leaf first) against the bundled Mozilla roots plus the
+/// platform trust store, and optionally check the hostname.
+fn verify_chain(chain: &[CertificateDer<'static>], host: Option<&str>) -> Validation {
+    let native = rustls_native_certs::load_native_certs();
+    let mut anchors: Vec<_> = webpki_roots::TLS_SERVER_ROOTS.to_vec();
+    anchors.extend(native.certs.iter().filter_map(|c| {
+        webpki::anchor_from_trusted_cert(c)
+            .ok()
+            .map(|a| a.to_owned())
+    }));
+    let leaf = match webpki::EndEntityCert::try_from(&chain[0]) {
+        Ok(leaf) => leaf,
+        Err(e) => {
+            return Validation {
+                trusted: false,
+                error: Some(format!("{:?}", e)),
+                roots_checked: None,
+                hostname: None,
+                hostname_match: None,
+            }
+        }
+    };
+    let trusted = leaf.verify_for_usage(
+        signature_algorithms().all,
+        &anchors,
+        &chain[1..],
+        UnixTime::now(),
+        webpki::KeyUsage::server_auth(),
+        None,
+        None,
+    );
+    let mut out = Validation {
+        trusted: trusted.is_ok(),
+        error: trusted.err().map(|e| format!("{:?}", e)),
+        roots_checked: Some(anchors.len()),
+        hostname: None,
+        hostname_match: None,
+    };
+    if let Some(h) = host {
+        if let Ok(name) = ServerName::try_from(h) {
+            let matched = leaf.verify_is_valid_for_subject_name(&name);
+            out.hostname = Some(h.to_string());
+            out.hostname_match = Some(matched.is_ok());
+        }
+    }
+    out
+}
+
+/// Accepts any server certificate so the chain can be inspected even when it
+/// is broken; validation happens separately in `verify_chain`.
+#[derive(Debug)]
+struct CaptureVerifier(WebPkiSupportedAlgorithms);
+
+impl ServerCertVerifier for CaptureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0)
+    }
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0)
+    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_schemes()
+    }
+}
+
+/// Split "host", "host:port", "[v6]:port", or a URL into host and port.
+fn parse_endpoint(endpoint: &str) -> Result<(String, u16)> {
+    let rest = endpoint
+        .split_once("://")
+        .map(|(_, r)| r)
+        .unwrap_or(endpoint);
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let (host, port) = if let Some(v6) = authority.strip_prefix('[') {
+        let (h, tail) = v6
+            .split_once(']')
+            .ok_or_else(|| anyhow!("Invalid IPv6 endpoint: {}", endpoint))?;
+        (h.to_string(), tail.strip_prefix(':'))
+    } else {
+        match authority.rsplit_once(':') {
+            Some((h, p)) => (h.to_string(), Some(p)),
+            None => (authority.to_string(), None),
+        }
+    };
+    let port = match port {
+        Some(p) => p
+            .parse()
+            .with_context(|| format!("Invalid port in {}", endpoint))?,
+        None => 443,
+    };
+    if host.is_empty() {
+        return Err(anyhow!("Missing host in {}", endpoint));
+    }
+    Ok((host, port))
+}
+
+fn fetch_chain(host: &str, port: u16, sni: &str) -> Result<(Vec<CertificateDer<'static>>, Tls)> {
+    let provider = Arc::new(ring_provider::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(CaptureVerifier(
+            provider.signature_verification_algorithms,
+        )))
+        .with_no_client_auth();
+    let server_name = ServerName::try_from(sni.to_string())
+        .map_err(|_| anyhow!("Invalid server name: {}", sni))?;
+    let mut conn = rustls::ClientConnection::new(Arc::new(config), server_name)?;
+
+    let addr = (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {}", host))?
+        .next()
+        .ok_or_else(|| anyhow!("No address for {}", host))?;
+    let mut sock = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {}:{}", host, port))?;
+    sock.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    sock.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+    while conn.is_handshaking() {
+        conn.complete_io(&mut sock)
+            .with_context(|| format!("TLS handshake with {}:{} failed", host, port))?;
+    }
+    let chain: Vec<CertificateDer<'static>> = conn
+        .peer_certificates()
+        .map(|c| c.to_vec())
+        .unwrap_or_default();
+    let tls = Tls {
+        address: addr.to_string(),
+        protocol: conn.protocol_version().map(|v| format!("{:?}", v)),
+        cipher_suite: conn
+            .negotiated_cipher_suite()
+            .map(|c| format!("{:?}", c.suite())),
+    };
+    conn.send_close_notify();
+    let _ = conn.complete_io(&mut sock);
+    let _ = sock.flush();
+    Ok((chain, tls))
+}
+
+impl Tool for InspectCertTool {
+    fn name(&self) -> &'static str {
+        "inspect_cert"
+    }
+    fn description(&self) -> &'static str {
+        "Inspect X.509 certificates, CSRs, and public keys from a PEM/DER file or a live TLS endpoint: subject, issuer, SANs, validity and days to expiry, key type/size, fingerprint, and chain validation against system roots (plus hostname match for endpoints). Private keys are never read out."
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("path")
+                .type_of("string")
+                .description("PEM or DER file (certificate, bundle, CSR, or public key)"),
+            ParamBuilder::new("endpoint")
+                .type_of("string")
+                .description("Live TLS endpoint: host, host:port, or URL (default port 443)"),
+            ParamBuilder::new("server_name")
+                .type_of("string")
+                .description("SNI/hostname to present and verify (defaults to the endpoint host)"),
+            ParamBuilder::new("verify")
+                .type_of("boolean")
+                .description("Validate the certificate chain (default true)"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let verify = args.get("verify").and_then(|v| v.as_bool()).unwrap_or(true);
+        if let Some(endpoint) = args.get("endpoint").and_then(|v| v.as_str()) {
+            let (host, port) = parse_endpoint(endpoint)?;
+            let sni = args
+                .get("server_name")
+                .and_then(|v| v.as_str())
+                .unwrap_or(&host)
+                .to_string();
+            let (chain, tls) = fetch_chain(&host, port, &sni)?;
+            if chain.is_empty() {
+                return Err(anyhow!("{}:{} presented no certificates", host, port));
+            }
+            let objects = chain
+                .iter()
+                .filter_map(|der| {
+                    X509Certificate::from_der(der)
+                        .ok()
+                        .map(|(_, c)| CertObject::Certificate(describe_certificate(&c, der)))
+                })
+                .collect();
+            let validation = verify.then(|| verify_chain(&chain, Some(&sni)));
+            return results::to_value(InspectCert {
+                source: format!("{}:{}", host, port),
+                tls: Some(tls),
+                objects,
+                validation,
+            });
+        }
+
+        let path = args
+            .get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Provide either 'path' or 'endpoint'"))?;
+        // Only public metadata leaves this tool, so system locations such as
+        // /etc/ssl are allowed in addition to the workspace.
+        let data = std::fs::read(path).with_context(|| format!("Failed reading {}", path))?;
+        let (objects, certs) = parse_objects(&data)?;
+        let validation = (verify && !certs.is_empty()).then(|| {
+            let host = args.get("server_name").and_then(|v| v.as_str());
+            verify_chain(&certs, host)
+        });
+        results::to_value(InspectCert {
+            source: path.to_string(),
+            tls: None,
+            objects,
+            validation,
+        })
+    }
+
+    fn print_result(&self, result: &Value) {
+        use nu_ansi_term::{Color as NuColor, Style};
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let source = result.get("source").and_then(|v| v.as_str()).unwrap_or("");
+        println!("{}: {}", result_label, source);
+        for obj in result
+            .get("objects")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let kind = obj.get("kind").and_then(|v| v.as_str()).unwrap_or("?");
+            let subject = obj.get("subject").and_then(|v| v.as_str()).unwrap_or("");
+            match obj.get("days_remaining").and_then(|v| v.as_i64()) {
+                Some(days) if days < 0 => println!(
+                    "  {} {} {}",
+                    kind,
+                    subject,
+                    NuColor::Red.paint(format!("(expired {} days ago)", -days))
+                ),
+                Some(days) => println!("  {} {} (expires in {} days)", kind, subject, days),
+                None => println!("  {} {}", kind, subject),
+            }
+        }
+        if let Some(v) = result.get("validation").filter(|v| !v.is_null()) {
+            let trusted = v.get("trusted").and_then(|t| t.as_bool()).unwrap_or(false);
+            let host_ok = v.get("hostname_match").and_then(|t| t.as_bool());
+            let status = match (trusted, host_ok) {
+                (true, Some(false)) => NuColor::Yellow.paint("trusted, hostname mismatch"),
+                (true, _) => NuColor::Green.paint("trusted"),
+                (false, _) => NuColor::Red.paint("not trusted"),
+            };
+            println!("  chain: {}", status);
+        }
+    }
+}
@@ -0,0 +1,585 @@
+use anyhow::{anyhow, Result};
+use nu_ansi_term::{Color as NuColor, Style};
+use serde_json::{json, Value};
+use std::process::Command;
+use std::time::Duration;
+
+use llm::builder::ParamBuilder;
+use llm::chat::ParameterProperty;
+
+use super::process::{in_path, run_captured, tail};
+use super::{ask_user, results, Tool};
+use crate::say;
+
+const DEFAULT_LIMIT: u64 = 50;
+const TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_STRING: usize = 2_000;
+
+/// AWS services whose describe/list/get operations may run unasked.
+const AWS_SERVICES: &[&str] = &[
+    "apigateway",
+    "autoscaling",
+    "cloudformation",
+    "cloudfront",
+    "cloudwatch",
+    "dynamodb",
+    "ec2",
+    "ecr",
+    "ecs",
+    "eks",
+    "elb",
+    "elbv2",
+    "iam",
+    "lambda",
+    "logs",
+    "rds",
+    "route53",
+    "s3api",
+    "secretsmanager",
+    "sns",
+    "sqs",
+    "ssm",
+    "sts",
+];
+const AWS_READ_PREFIXES: &[&str] = &[
+    "describe-",
+    "list-",
+    "get-",
+    "lookup-",
+    "search-",
+    "filter-",
+];
+/// Operations that are technically reads but return credentials or secrets.
+const AWS_DENY: &[&str] = &[
+    "get-secret-value",
+    "get-password-data",
+    "get-login-password",
+    "get-session-token",
+    "get-authorization-token",
+    "get-federation-token",
+    "get-parameter",
+    "get-parameters",
+    "get-parameters-by-path",
+    "get-parameter-history",
+];
+/// gcloud command groups whose read verbs may run unasked.
+const GCLOUD_GROUPS: &[&str] = &[
+    "compute instances",
+    "compute disks",
+    "compute networks",
+    "compute firewall-rules",
+    "compute addresses",
+    "compute regions",
+    "compute zones",
+    "container clusters",
+    "run services",
+    "functions",
+    "sql instances",
+    "storage buckets",
+    "iam service-accounts",
+    "projects",
+    "logging",
+];
+const GCLOUD_READ_VERBS: &[&str] = &["list", "describe", "read", "get-iam-policy"];
+/// az command groups whose read verbs may run unasked.
+const AZ_GROUPS: &[&str] = &[
+    "vm",
+    "vmss",
+    "group",
+    "aks",
+    "webapp",
+    "functionapp",
+    "network vnet",
+    "network nsg",
+    "network public-ip",
+    "network lb",
+    "storage account",
+    "sql server",
+    "monitor activity-log",
+    "resource",
+];
+const AZ_READ_VERBS: &[&str] = &["list", "show"];
+
+/// Inspection of AWS, GCP, and Azure through their CLIs. Queries outside
+/// the read-only allowlists ask the user first, as run_shell does.
+pub struct CloudTool {
+    available: Vec<&'static str>,
+}
+
+impl CloudTool {
+    /// Returns the tool if at least one cloud CLI is installed.
+    pub fn detect() -> Option<Self> {
+        let available: Vec<&'static str> = [("aws", "aws"), ("gcp", "gcloud"), ("azure", "az")]
+            .iter()
+            .filter(|(_, bin)| in_path(bin))
+            .map(|(name, _)| *name)
+            .collect();
+        (!available.is_empty()).then_some(Self { available })
+    }
+}
+
+fn str_arg<'a>(args: &'a Value, key: &str) -> Option<&'a str> {
+    args.get(key)
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+}
+
+/// The command words at the front of `args`, up to the first flag. The
+/// service, group, and verb are read from fixed places among them, so flag
+/// values never stand in for them.
+fn command_words(args: &[String]) -> Vec<&str> {
+    args.iter()
+        .map(|s| s.as_str())
+        .take_while(|s| !s.starts_with('-'))
+        .collect()
+}
+
+/// The verb after one of `groups` at the start of `words`, if any.
+fn verb_after<'a>(words: &[&'a str], groups: &[&str]) -> Option<&'a str> {
+    groups.iter().find_map(|group| {
+        let group: Vec<&str> = group.split(' ').collect();
+        (words.len() > group.len() && words[..group.len()] == group[..]).then(|| words[group.len()])
+    })
+}
+
+/// Whether `args` is a query known to be read-only and free of secrets.
+/// Anything else needs the user's approval.
+fn is_read_only(provider: &str, args: &[String]) -> bool {
+    let words = command_words(args);
+    match provider {
+        "aws" => match words[..] {
+            [service, op, ..] => {
+                AWS_SERVICES.contains(&service)
+                    && AWS_READ_PREFIXES.iter().any(|p| op.starts_with(p))
+                    && !AWS_DENY.contains(&op)
+            }
+            _ => false,
+        },
+        "gcp" => verb_after(&words, GCLOUD_GROUPS).is_some_and(|v| GCLOUD_READ_VERBS.contains(&v)),
+        "azure" => verb_after(&words, AZ_GROUPS).is_some_and(|v| AZ_READ_VERBS.contains(&v)),
+        _ => false,
+    }
+}
+
+fn binary(provider: &str) -> &'static str {
+    match provider {
+        "aws" => "aws",
+        "gcp" => "gcloud",
+        _ => "az",
+    }
+}
+
+fn cli_for(provider: &str, mut args: Vec<String>) -> (Command, String) {
+    let bin = binary(provider);
+    match bin {
+        "aws" => args.extend(["--output".into(), "json".into()]),
+        "gcloud" => args.push("--format=json".into()),
+        _ => args.extend(["-o".into(), "json".into()]),
+    }
+    let display = format!("{} {}", bin, args.join(" "));
+    let mut cmd = Command::new(bin);
+    cmd.args(&args).env("AWS_PAGER", "");
+    (cmd, display)
+}
+
+fn run_json(provider: &str, args: Vec<String>) -> Result<(Value, String)> {
+    let (cmd, display) = cli_for(provider, args);
+    let captured = run_captured(cmd, TIMEOUT)?;
+    match captured.status {
+        Some(status) if status.success() => {
+            let value = if captured.stdout.trim().is_empty() {
+                Value::Null
+            } else {
+                serde_json::from_str(&captured.stdout).unwrap_or(Value::String(captured.stdout))
+            };
+            Ok((value, display))
+        }
+        Some(_) => Err(anyhow!(
+            "`{}` failed: {}",
+            display,
+            tail(captured.stderr.trim(), 1_000).0
+        )),
+        None => Err(anyhow!(
+            "`{}` timed out after {}s",
+            display,
+            TIMEOUT.as_secs()
+        )),
+    }
+}
+
+/// Cap arrays at `limit` items and long strings at MAX_STRING characters.
+fn clamp(v: Value, limit: usize, truncated: &mut bool) -> Value {
+    match v {
+        Value::Array(arr) => {
+            if arr.len() > limit {
+                *truncated = true;
+            }
+            Value::Array(
+                arr.into_iter()
+                    .take(limit)
+                    .map(|x| clamp(x, limit, truncated))
+                    .collect(),
+            )
+        }
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, x)| (k, clamp(x, limit, truncated)))
+                .collect(),
+        ),
+        Value::String(s) if s.len() > MAX_STRING => {
+            *truncated = true;
+            Value::String(s.chars().take(MAX_STRING).collect())
+        }
+        other => other,
+    }
+}
+
+fn last_segment(v: &Value) -> Value {
+    v.as_str()
+        .map(|s| json!(s.rsplit('/').next().unwrap_or(s)))
+        .unwrap_or(Value::Null)
+}
+
+fn list_instances(provider: &str, args: &Value, limit: u64) -> Result<(Vec<Value>, String)> {
+    match provider {
+        "aws" => {
+            let mut a: Vec<String> = vec![
+                "ec2".into(),
+                "describe-instances".into(),
+                "--max-items".into(),
+                limit.to_string(),
+            ];
+            if let Some(r) = str_arg(args, "region") {
+                a.extend(["--region".into(), r.into()]);
+            }
+            let (v, display) = run_json(provider, a)?;
+            let mut items = Vec::new();
+            for res in v["Reservations"].as_array().into_iter().flatten() {
+                for i in res["Instances"].as_array().into_iter().flatten() {
+                    let name = i["Tags"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .find(|t| t["Key"] == "Name")
+                        .map(|t| t["Value"].clone())
+                        .unwrap_or(Value::Null);
+                    items.push(json!({
+                        "id": i["InstanceId"],
+                        "name": name,
+                        "state": i["State"]["Name"],
+                        "type": i["InstanceType"],
+                        "zone": i["Placement"]["AvailabilityZone"],
+                        "private_ip": i["PrivateIpAddress"],
+                        "public_ip": i["PublicIpAddress"],
+                        "launched": i["LaunchTime"],
+                    }));
+                }
+            }
+            Ok((items, display))
+        }
+        "gcp" => {
+            let mut a: Vec<String> = vec![
+                "compute".into(),
+                "instances".into(),
+                "list".into(),
+                "--limit".into(),
+                limit.to_string(),
+            ];
+            if let Some(p) = str_arg(args, "project") {
+                a.push(format!("--project={}", p));
+            }
+            let (v, display) = run_json(provider, a)?;
+            let items = v
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|i| {
+                    let nic = &i["networkInterfaces"][0];
+                    json!({
+                        "name": i["name"],
+                        "zone": last_segment(&i["zone"]),
+                        "status": i["status"],
+                        "type": last_segment(&i["machineType"]),
+                        "private_ip": nic["networkIP"],
+                        "public_ip": nic["accessConfigs"][0]["natIP"],
+                        "created": i["creationTimestamp"],
+                    })
+                })
+                .collect();
+            Ok((items, display))
+        }
+        _ => {
+            let mut a: Vec<String> = vec!["vm".into(), "list".into(), "-d".into()];
+            if let Some(rg) = str_arg(args, "resource_group") {
+                a.extend(["--resource-group".into(), rg.into()]);
+            }
+            if let Some(sub) = str_arg(args, "project") {
+                a.extend(["--subscription".into(), sub.into()]);
+            }
+            let (v, display) = run_json(provider, a)?;
+            let items = v
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|i| {
+                    json!({
+                        "name": i["name"],
+                        "resource_group": i["resourceGroup"],
+                        "zone": i["location"],
+                        "status": i["powerState"],
+                        "type": i["hardwareProfile"]["vmSize"],
+                        "private_ip": i["privateIps"],
+                        "public_ip": i["publicIps"],
+                    })
+                })
+                .collect();
+            Ok((items, display))
+        }
+    }
+}
+
+fn logs(provider: &str, args: &Value, limit: u64) -> Result<(Vec<Value>, String)> {
+    let since = args
+        .get("since_minutes")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(60);
+    let filter = str_arg(args, "filter");
+    match provider {
+        "aws" => {
+            let group = str_arg(args, "log_group")
+                .ok_or_else(|| anyhow!("'log_group' is required for AWS logs"))?;
+            let start = chrono::Utc::now() - chrono::Duration::minutes(since as i64);
+            let mut a: Vec<String> = vec![
+                "logs".into(),
+                "filter-log-events".into(),
+                "--log-group-name".into(),
+                group.into(),
+                "--start-time".into(),
+                start.timestamp_millis().to_string(),
+                "--max-items".into(),
+                limit.to_string(),
+            ];
+            if let Some(f) = filter {
+                a.extend(["--filter-pattern".into(), f.into()]);
+            }
+            if let Some(r) = str_arg(args, "region") {
+                a.extend(["--region".into(), r.into()]);
+            }
+            let (v, display) = run_json(provider, a)?;
+            let items = v["events"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|e| {
+                    let ts = e["timestamp"]
+                        .as_i64()
+                        .and_then(chrono::DateTime::from_timestamp_millis)
+                        .map(|t| json!(t.to_rfc3339()))
+                        .unwrap_or(Value::Null);
+                    json!({ "timestamp": ts, "stream": e["logStreamName"], "message": e["message"] })
+                })
+                .collect();
+            Ok((items, display))
+        }
+        "gcp" => {
+            let mut a: Vec<String> = vec![
+                "logging".into(),
+                "read".into(),
+                filter.unwrap_or("").into(),
+                format!("--freshness={}m", since),
+                format!("--limit={}", limit),
+            ];
+            if let Some(p) = str_arg(args, "project") {
+                a.push(format!("--project={}", p));
+            }
+            let (v, display) = run_json(provider, a)?;
+            let items = v
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|e| {
+                    let message = if e["textPayload"].is_string() {
+                        e["textPayload"].clone()
+                    } else if e["jsonPayload"]["message"].is_string() {
+                        e["jsonPayload"]["message"].clone()
+                    } else if !e["jsonPayload"].is_null() {
+                        json!(e["jsonPayload"].to_string())
+                    } else {
+                        e["protoPayload"]["methodName"].clone()
+                    };
+                    json!({
+                        "timestamp": e["timestamp"],
+                        "severity": e["severity"],
+                        "resource": e["resource"]["type"],
+                        "message": message,
+                    })
+                })
+                .collect();
+            Ok((items, display))
+        }
+        _ => {
+            let hours = since.div_ceil(60).max(1);
+            let mut a: Vec<String> = vec![
+                "monitor".into(),
+                "activity-log".into(),
+                "list".into(),
+                "--offset".into(),
+                format!("{}h", hours),
+                "--max-events".into(),
+                limit.to_string(),
+            ];
+            if let Some(rg) = str_arg(args, "resource_group") {
+                a.extend(["--resource-group".into(), rg.into()]);
+            }
+            let (v, display) = run_json(provider, a)?;
+            let items = v
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|e| {
+                    json!({
+                        "timestamp": e["eventTimestamp"],
+                        "operation": e["operationName"]["localizedValue"],
+                        "status": e["status"]["value"],
+                        "caller": e["caller"],
+                        "resource": e["resourceId"],
+                    })
+                })
+                .collect();
+            Ok((items, display))
+        }
+    }
+}
+
+impl Tool for CloudTool {
+    fn name(&self) -> &'static str {
+        "cloud"
+    }
+    fn description(&self) -> &'static str {
+        "Cloud inspection via the aws, gcloud, or az CLIs: list compute instances, fetch recent logs, or run a describe/list/get/show query. Returns normalized JSON with pagination limits. Queries that are not known to be read-only are shown to the user for approval first. Prefer run_shell for anything that modifies cloud resources."
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        &["provider", "action"]
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("provider")
+                .type_of("string")
+                .description(format!(
+                    "Cloud provider; installed: {}",
+                    self.available.join(", ")
+                )),
+            ParamBuilder::new("action")
+                .type_of("string")
+                .description("One of: list_instances, logs, read"),
+            ParamBuilder::new("args")
+                .type_of("array")
+                .items(ParameterProperty {
+                    property_type: "string".into(),
+                    description: "argument".into(),
+                    items: None,
+                    enum_list: None,
+                })
+                .description("For action=read: CLI arguments after the binary, e.g. [\"ec2\", \"describe-vpcs\"], [\"compute\", \"networks\", \"list\"], [\"network\", \"vnet\", \"list\"]"),
+            ParamBuilder::new("region")
+                .type_of("string")
+                .description("AWS region"),
+            ParamBuilder::new("project")
+                .type_of("string")
+                .description("GCP project or Azure subscription"),
+            ParamBuilder::new("resource_group")
+                .type_of("string")
+                .description("Azure resource group"),
+            ParamBuilder::new("log_group")
+                .type_of("string")
+                .description("AWS CloudWatch log group (required for AWS logs)"),
+            ParamBuilder::new("filter")
+                .type_of("string")
+                .description("Log filter (CloudWatch filter pattern or Cloud Logging filter)"),
+            ParamBuilder::new("since_minutes")
+                .type_of("integer")
+                .description("How far back to read logs (default 60)"),
+            ParamBuilder::new("limit")
+                .type_of("integer")
+                .description("Maximum items to return (default 50)"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let provider = str_arg(&args, "provider").ok_or_else(|| anyhow!("Missing 'provider'"))?;
+        let provider = match provider {
+            "gcloud" | "google" => "gcp",
+            "az" => "azure",
+            other => other,
+        };
+        if !self.available.contains(&provider) {
+            return Err(anyhow!(
+                "Cloud provider '{}' is not available (installed: {})",
+                provider,
+                self.available.join(", ")
+            ));
+        }
+        let action = str_arg(&args, "action").ok_or_else(|| anyhow!("Missing 'action'"))?;
+        let limit = args
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, 500);
+        let mut truncated = false;
+        let (result, display) = match action {
+            "list_instances" => {
+                let (items, display) = list_instances(provider, &args, limit)?;
+                (Value::Array(items), display)
+            }
+            "logs" => {
+                let (items, display) = logs(provider, &args, limit)?;
+                (Value::Array(items), display)
+            }
+            "read" => {
+                let cli_args: Vec<String> = args
+                    .get("args")
+                    .and_then(|v| v.as_array())
+                    .map(|arr| {
+                        arr.iter()
+                            .filter_map(|a| a.as_str().map(|s| s.to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if !is_read_only(provider, &cli_args) {
+                    let command = format!("{} {}", binary(provider), cli_args.join(" "));
+                    say!(
+                        "{}: {}",
+                        Style::new()
+                            .fg(NuColor::Yellow)
+                            .paint("not a known read-only query"),
+                        command
+                    );
+                    if ask_user("Do you want to execute this command? [Y/n] ")? == "n" {
+                        say!("Command execution cancelled");
+                        return Err(anyhow!("The user declined to run `{}`", command));
+                    }
+                }
+                run_json(provider, cli_args)?
+            }
+            other => return Err(anyhow!("Unknown action: {}", other)),
+        };
+        let result = clamp(result, limit as usize, &mut truncated);
+        let count = result.as_array().map(|a| a.len());
+        results::to_value(results::Cloud {
+            provider: provider.to_string(),
+            action: action.to_string(),
+            command: display,
+            count,
+            truncated,
+            result,
+        })
+    }
+
+    fn print_result(&self, result: &Value) {
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let command = result.get("command").and_then(|v| v.as_str()).unwrap_or("");
+        match result.get("count").and_then(|v| v.as_u64()) {
+            Some(n) => println!("{}: {} items from `{}`", result_label, n, command),
+            None => println!("{}: `{}` ok", result_label, command),
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::{anyhow, bail, Context, Result};
+use ignore::WalkBuilder;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use llm::builder::ParamBuilder;
+use tree_sitter::{Query, QueryCursor, StreamingIterator};
+
+use super::dir::resolve_path;
+use super::languages::{grammar, parse, signature, Grammar, MAX_PARSE_BYTES};
+use super::{results, Tool};
+
+const DEFAULT_MAX_RESULTS: usize = 100;
+
+/// Reference lines longer than this are cut.
+const MAX_SNIPPET_CHARS: usize = 200;
+
+const KINDS: &[&str] = &["definition", "reference", "impl"];
+const ITEMS: &[&str] = &["function", "type", "module", "constant", "macro"];
+
+/// The bare name in a type as written: `Foo` in `&mut foo::Foo<T>`,
+/// `*Foo`, or `pkg.Foo[T]`.
+fn base_name(text: &str) -> &str {
+    let text = text.trim_start_matches(['&', '*', ' ']);
+    let text = text.strip_prefix("mut ").unwrap_or(text);
+    let end = text.find(['<', '[', '(']).unwrap_or(text.len());
+    let path = &text[..end];
+    path.rsplit(['.', ':']).next().unwrap_or(path).trim()
+}
+
+fn line_at(source: &str, row: usize) -> String {
+    let line = source.lines().nth(row).unwrap_or_default().trim();
+    if line.chars().count() > MAX_SNIPPET_CHARS {
+        format!(
+            "{}…",
+            line.chars().take(MAX_SNIPPET_CHARS).collect::<String>()
+        )
+    } else {
+        line.to_string()
+    }
+}
+
+/// The queries of one grammar, compiled on first use in a search.
+struct Queries {
+    grammar: &'static Grammar,
+    definitions: Query,
+    impls: Query,
+    identifiers: Query,
+}
+
+impl Queries {
+    fn new(grammar: &'static Grammar) -> Result<Self> {
+        let language = (grammar.language)();
+        let compile = |source: &str| {
+            Query::new(&language, source).map_err(|e| anyhow!("Invalid query: {}", e))
+        };
+        Ok(Self {
+            grammar,
+            definitions: compile(grammar.definitions)?,
+            impls: compile(grammar.impls)?,
+            identifiers: compile(grammar.identifiers)?,
+        })
+    }
+}
+
+/// One hit in a file; `row` and `column` are 0-based.
+struct Match {
+    row: usize,
+    column: usize,
+    /// What a definition defines: function, type, and so on.
+    item: Option<String>,
+    snippet: String,
+}
+
+struct Search<'a> {
+    name: &'a str,
+    kind: &'a str,
+    item: Option<&'a str>,
+}
+
+impl Search<'_> {
+    /// Matches of the search in one file, in order.
+    fn file(&self, queries: &Queries, source: &str) -> Result<Vec<Match>> {
+        let bytes = source.as_bytes();
+        let tree = parse(queries.grammar, bytes)?;
+        let root = tree.root_node();
+        let mut cursor = QueryCursor::new();
+        let mut found = Vec::new();
+
+        // Definitions are also what references leave out.
+        let mut defined = BTreeSet::new();
+        if self.kind != "impl" {
+            let query = &queries.definitions;
+            let mut matches = cursor.matches(query, root, bytes);
+            while let Some(m) = matches.next() {
+                let mut name = None;
+                let mut item = None;
+                for capture in m.captures {
+                    match query.capture_names()[capture.index as usize] {
+                        "name" => name = Some(capture.node),
+                        kind => item = Some((kind, capture.node)),
+                    }
+                }
+                let (Some(name), Some((kind, node))) = (name, item) else {
+                    continue;
+                };
+                if name.utf8_text(bytes).ok() != Some(self.name) {
+                    continue;
+                }
+                defined.insert(name.start_byte());
+                if self.kind == "definition" && self.item.is_none_or(|i| i == kind) {
+                    found.push(Match {
+                        row: node.start_position().row,
+                        column: name.start_position().column,
+                        item: Some(kind.to_string()),
+                        snippet: signature(node, bytes),
+                    });
+                }
+            }
+        }
+
+        match self.kind {
+            "impl" => {
+                let query = &queries.impls;
+                let mut matches = cursor.matches(query, root, bytes);
+                while let Some(m) = matches.next() {
+                    let name = m
+                        .captures
+                        .iter()
+                        .find(|c| query.capture_names()[c.index as usize] == "name");
+                    let node = m
+                        .captures
+                        .iter()
+                        .find(|c| query.capture_names()[c.index as usize] == "impl");
+                    let (Some(name), Some(node)) = (name, node) else {
+                        continue;
+                    };
+                    if name.node.utf8_text(bytes).map(base_name) == Ok(self.name) {
+                        found.push(Match {
+                            row: node.node.start_position().row,
+                            column: node.node.start_position().column,
+                            item: None,
+                            snippet: signature(node.node, bytes),
+                        });
+                    }
+                }
+            }
+            "reference" => {
+                let mut captures = cursor.captures(&queries.identifiers, root, bytes);
+                while let Some((m, index)) = captures.next() {
+                    let node = m.captures[*index].node;
+                    if node.utf8_text(bytes).ok() == Some(self.name)
+                        && !defined.contains(&node.start_byte())
+                    {
+                        let at = node.start_position();
+                        found.push(Match {
+                            row: at.row,
+                            column: at.column,
+                            item: None,
+                            snippet: line_at(source, at.row),
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+        found.sort_by_key(|m| (m.row, m.column));
+        // Both impl patterns can match one block, as in `impl Foo for Foo`.
+        found.dedup_by_key(|m| (m.row, m.column));
+        Ok(found)
+    }
+}
+
+/// Structural search: where a symbol is defined, used, or implemented,
+/// from tree-sitter parses rather than text, so comments, strings, and
+/// longer names containing it do not match.
+pub struct CodeSearchTool;
+
+impl Tool for CodeSearchTool {
+    fn name(&self) -> &'static str {
+        "code_search"
+    }
+    fn description(&self) -> &'static str {
+        "Find where a symbol is defined, referenced, or implemented in Rust, Python, JavaScript, and Go files, using syntax trees instead of text. kind 'definition' finds functions, methods, types, classes, modules, and constants named `name` (narrow it with item, e.g. item 'function' for \"the function named parse_config\"); 'reference' finds every use of the identifier outside its definitions, skipping comments and strings; 'impl' finds impl blocks for a Rust type or trait, Go methods on a type, and Python or JavaScript classes extending a class (\"all impl blocks for Foo\"). Returns file, 1-based line, and a one-line snippet per match. Prefer it over grep for code symbols."
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        &["name"]
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("name")
+                .type_of("string")
+                .description("The identifier, e.g. parse_config or Foo, without a path or generics"),
+            ParamBuilder::new("kind")
+                .type_of("string")
+                .description("definition (default), reference, or impl"),
+            ParamBuilder::new("item")
+                .type_of("string")
+                .description("For definitions only: function (including methods), type (struct, enum, trait, class, type alias), module, constant, or macro"),
+            ParamBuilder::new("root")
+                .type_of("string")
+                .description("Root directory to search (default '.')"),
+            ParamBuilder::new("max_results")
+                .type_of("integer")
+                .description("Maximum results to return (default 100)"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let name = args
+            .get("name")
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| anyhow!("Missing 'name'"))?;
+        let kind = args
+            .get("kind")
+            .and_then(|v| v.as_str())
+            .unwrap_or("definition");
+        if !KINDS.contains(&kind) {
+            bail!("Unknown kind '{}'; use one of {}", kind, KINDS.join(", "));
+        }
+        let item = args.get("item").and_then(|v| v.as_str());
+        if let Some(item) = item {
+            if !ITEMS.contains(&item) {
+                bail!("Unknown item '{}'; use one of {}", item, ITEMS.join(", "));
+            }
+        }
+        let root_s = args.get("root").and_then(|v| v.as_str()).unwrap_or(".");
+        let max_results = args
+            .get("max_results")
+            .and_then(|v| v.as_u64())
+            .map_or(DEFAULT_MAX_RESULTS, |n| n as usize);
+        let root = resolve_path(root_s, false)?;
+        let search = Search { name, kind, item };
+
+        let mut files: Vec<PathBuf> = WalkBuilder::new(&root)
+            .build()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+            .filter(|e| e.metadata().is_ok_and(|m| m.len() <= MAX_PARSE_BYTES))
+            .map(|e| e.into_path())
+            .filter(|p| grammar(p).is_some())
+            .collect();
+        files.sort();
+
+        let mut compiled: Vec<Queries> = Vec::new();
+        let mut matches = Vec::new();
+        let mut searched = 0;
+        let mut truncated = false;
+        for path in &files {
+            let Ok(source) = fs::read_to_string(path) else {
+                continue;
+            };
+            searched += 1;
+            // Only files that mention the name at all are parsed.
+            if !source.contains(name) {
+                continue;
+            }
+            let Some(grammar) = grammar(path) else {
+                continue;
+            };
+            let queries = match compiled
+                .iter()
+                .position(|q| std::ptr::eq(q.grammar, grammar))
+            {
+                Some(i) => &compiled[i],
+                None => {
+                    compiled.push(Queries::new(grammar)?);
+                    &compiled[compiled.len() - 1]
+                }
+            };
+            let found = search
+                .file(queries, &source)
+                .with_context(|| format!("Failed to search {}", path.display()))?;
+            let file = path
+                .strip_prefix(&root)
+                .unwrap_or(path)
+                .display()
+                .to_string();
+            for Match {
+                row,
+                column,
+                item,
+                snippet,
+            } in found
+            {
+                if matches.len() == max_results {
+                    truncated = true;
+                    break;
+                }
+                matches.push(results::CodeMatch {
+                    file: file.clone(),
+                    line: row + 1,
+                    column: column + 1,
+                    snippet,
+                    item,
+                });
+            }
+            if truncated {
+                break;
+            }
+        }
+
+        results::to_value(results::CodeSearch {
+            root: root.display().to_string(),
+            name: name.to_string(),
+            kind: kind.to_string(),
+            files_searched: searched,
+            count: matches.len(),
+            results: matches,
+            truncated: truncated.then(|| {
+                format!(
+                    "Stopped at {} results; narrow the search with root or item",
+                    max_results
+                )
+            }),
+        })
+    }
+}
@@ -0,0 +1,536 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, Offset, SubsecRound,
+    TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use croner::Cron;
+use serde_json::Value;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use llm::builder::ParamBuilder;
+use llm::chat::ParameterProperty;
+
+use super::results::{self, Convert, ConvertBytes, ConvertCron, ConvertTime, Converted, ZoneTime};
+use super::Tool;
+
+/// Common abbreviations mapped to a representative zone. Abbreviations are
+/// ambiguous, so results always report the zone that was actually used.
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("PST", "America/Los_Angeles"),
+    ("PDT", "America/Los_Angeles"),
+    ("MST", "America/Denver"),
+    ("MDT", "America/Denver"),
+    ("CST", "America/Chicago"),
+    ("CDT", "America/Chicago"),
+    ("EST", "America/New_York"),
+    ("EDT", "America/New_York"),
+    ("BST", "Europe/London"),
+    ("CET", "Europe/Berlin"),
+    ("CEST", "Europe/Berlin"),
+    ("EET", "Europe/Helsinki"),
+    ("IST", "Asia/Kolkata"),
+    ("JST", "Asia/Tokyo"),
+    ("AEST", "Australia/Sydney"),
+];
+
+const SIZE_UNITS: &[(&str, f64)] = &[
+    ("b", 1.0),
+    ("kb", 1e3),
+    ("mb", 1e6),
+    ("gb", 1e9),
+    ("tb", 1e12),
+    ("pb", 1e15),
+    ("k", 1e3),
+    ("m", 1e6),
+    ("g", 1e9),
+    ("t", 1e12),
+    ("p", 1e15),
+    ("kib", 1024.0),
+    ("mib", 1048576.0),
+    ("gib", 1073741824.0),
+    ("tib", 1099511627776.0),
+    ("pib", 1125899906842624.0),
+    ("ki", 1024.0),
+    ("mi", 1048576.0),
+    ("gi", 1073741824.0),
+    ("ti", 1099511627776.0),
+    ("pi", 1125899906842624.0),
+];
+
+/// Exact time zone, byte size, and cron calculations done locally.
+pub struct ConvertTool;
+
+#[derive(Clone)]
+enum Zone {
+    Utc,
+    Local,
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl Zone {
+    fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("utc") || s.eq_ignore_ascii_case("z") || s == "GMT" {
+            return Ok(Zone::Utc);
+        }
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        if let Some((_, name)) = ABBREVIATIONS
+            .iter()
+            .find(|(abbr, _)| abbr.eq_ignore_ascii_case(s))
+        {
+            return Ok(Zone::Named(Tz::from_str(name).map_err(|e| anyhow!(e))?));
+        }
+        if let Some(tz) = chrono_tz::TZ_VARIANTS
+            .iter()
+            .find(|tz| tz.name().eq_ignore_ascii_case(s))
+        {
+            return Ok(Zone::Named(*tz));
+        }
+        let offset = s
+            .trim_start_matches("UTC")
+            .trim_start_matches("GMT")
+            .to_string();
+        if let Ok(fixed) = FixedOffset::from_str(&offset) {
+            return Ok(Zone::Fixed(fixed));
+        }
+        Err(anyhow!("Unknown time zone: {}", s))
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Zone::Utc => "UTC".into(),
+            Zone::Local => iana_time_zone::get_timezone().unwrap_or_else(|_| "local".into()),
+            Zone::Named(tz) => tz.name().into(),
+            Zone::Fixed(o) => o.to_string(),
+        }
+    }
+
+    fn localize(&self, naive: NaiveDateTime) -> Result<DateTime<Utc>> {
+        let dt = match self {
+            Zone::Utc => Some(Utc.from_utc_datetime(&naive)),
+            Zone::Local => Local
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(|d| d.with_timezone(&Utc)),
+            Zone::Named(tz) => tz
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(|d| d.with_timezone(&Utc)),
+            Zone::Fixed(o) => o
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(|d| d.with_timezone(&Utc)),
+        };
+        dt.ok_or_else(|| anyhow!("{} does not exist in {}", naive, self.name()))
+    }
+
+    fn describe(&self, t: DateTime<Utc>) -> ZoneTime {
+        fn fmt<Z: TimeZone>(d: DateTime<Z>) -> (String, String, String)
+        where
+            Z::Offset: Display,
+        {
+            (
+                d.to_rfc3339(),
+                d.format("%a %Y-%m-%d %H:%M:%S %Z").to_string(),
+                d.offset().fix().to_string(),
+            )
+        }
+        let (rfc3339, readable, offset) = match self {
+            Zone::Utc => fmt(t),
+            Zone::Local => fmt(t.with_timezone(&Local)),
+            Zone::Named(tz) => fmt(t.with_timezone(tz)),
+            Zone::Fixed(o) => fmt(t.with_timezone(o)),
+        };
+        ZoneTime {
+            zone: self.name(),
+            rfc3339,
+            readable,
+            utc_offset: offset,
+        }
+    }
+}
+
+fn parse_instant(input: &str, zone: &Zone) -> Result<DateTime<Utc>> {
+    let s = input.trim();
+    if s.is_empty() || s.eq_ignore_ascii_case("now") {
+        return Ok(Utc::now());
+    }
+    if let Ok(n) = s.parse::<i64>() {
+        // Pick the epoch unit from the magnitude: s, ms, µs, or ns.
+        let abs = n.unsigned_abs();
+        let dt = if abs >= 1_000_000_000_000_000_000 {
+            DateTime::from_timestamp_nanos(n)
+        } else if abs >= 1_000_000_000_000_000 {
+            DateTime::from_timestamp_micros(n).context("timestamp out of range")?
+        } else if abs >= 100_000_000_000 {
+            DateTime::from_timestamp_millis(n).context("timestamp out of range")?
+        } else {
+            DateTime::from_timestamp(n, 0).context("timestamp out of range")?
+        };
+        return Ok(dt);
+    }
+    if let Ok(f) = s.parse::<f64>() {
+        let secs = f.trunc() as i64;
+        let nanos = ((f.fract()) * 1e9).round() as u32;
+        return DateTime::from_timestamp(secs, nanos).context("timestamp out of range");
+    }
+    if let Ok(d) = DateTime::parse_from_rfc3339(s) {
+        return Ok(d.with_timezone(&Utc));
+    }
+    if let Ok(d) = DateTime::parse_from_rfc2822(s) {
+        return Ok(d.with_timezone(&Utc));
+    }
+    for fmt in [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+        "%Y/%m/%d %H:%M:%S",
+        "%d.%m.%Y %H:%M",
+    ] {
+        if let Ok(n) = NaiveDateTime::parse_from_str(s, fmt) {
+            return zone.localize(n);
+        }
+    }
+    for fmt in ["%Y-%m-%d", "%Y/%m/%d", "%d.%m.%Y"] {
+        if let Ok(d) = NaiveDate::parse_from_str(s, fmt) {
+            return zone.localize(d.and_hms_opt(0, 0, 0).unwrap_or_default());
+        }
+    }
+    Err(anyhow!("Could not parse time: {}", s))
+}
+
+fn humanize(secs: i64) -> String {
+    let (sign, mut rest) = if secs < 0 {
+        ("ago", -secs)
+    } else {
+        ("from now", secs)
+    };
+    let mut parts = Vec::new();
+    for (unit, size) in [("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)] {
+        if rest >= size {
+            parts.push(format!("{}{}", rest / size, unit));
+            rest %= size;
+        }
+        if parts.len() == 2 {
+            break;
+        }
+    }
+    if parts.is_empty() {
+        "now".into()
+    } else {
+        format!("{} {}", parts.join(" "), sign)
+    }
+}
+
+fn convert_time(args: &Value) -> Result<ConvertTime> {
+    let input = args.get("input").and_then(|v| v.as_str()).unwrap_or("now");
+    let from = Zone::parse(
+        args.get("from_tz")
+            .and_then(|v| v.as_str())
+            .unwrap_or("UTC"),
+    )?;
+    let mut targets: Vec<Zone> = Vec::new();
+    match args.get("to_tz") {
+        Some(Value::String(s)) => targets.push(Zone::parse(s)?),
+        Some(Value::Array(arr)) => {
+            for z in arr.iter().filter_map(|z| z.as_str()) {
+                targets.push(Zone::parse(z)?);
+            }
+        }
+        _ => targets.extend([Zone::Utc, Zone::Local]),
+    }
+    let t = parse_instant(input, &from)?;
+    Ok(ConvertTime {
+        epoch_seconds: t.timestamp(),
+        epoch_millis: t.timestamp_millis(),
+        iso_week: format!("{}-W{:02}", t.iso_week().year(), t.iso_week().week()),
+        relative: humanize(t.timestamp() - Utc::now().timestamp()),
+        zones: targets.iter().map(|z| z.describe(t)).collect(),
+    })
+}
+
+fn parse_size(term: &str) -> Result<(f64, bool)> {
+    let term = term.trim().replace(['_', ','], "");
+    let split = term
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(term.len());
+    let (num, unit) = term.split_at(split);
+    let value: f64 = num
+        .parse()
+        .with_context(|| format!("Invalid number in '{}'", term))?;
+    let unit = unit.trim().to_ascii_lowercase();
+    if unit.is_empty() {
+        return Ok((value, false));
+    }
+    let unit = unit
+        .strip_suffix("ytes")
+        .map(|u| format!("{}b", u))
+        .unwrap_or(unit);
+    let factor = SIZE_UNITS
+        .iter()
+        .find(|(u, _)| *u == unit)
+        .map(|(_, f)| *f)
+        .ok_or_else(|| anyhow!("Unknown size unit '{}'", unit))?;
+    Ok((value * factor, true))
+}
+
+/// Evaluate `a + b - c * n / m` where terms are sizes or plain numbers,
+/// with the usual precedence. Returns the value and whether it is a size.
+fn eval_size_expr(expr: &str) -> Result<(f64, bool)> {
+    let mut terms: Vec<(char, String)> = Vec::new();
+    let mut current = String::new();
+    let mut op = '+';
+    for c in expr.chars() {
+        if matches!(c, '+' | '-' | '*' | '/' | 'x') && !current.trim().is_empty() {
+            terms.push((op, current.trim().to_string()));
+            current.clear();
+            op = if c == 'x' { '*' } else { c };
+        } else {
+            current.push(c);
+        }
+    }
+    if current.trim().is_empty() {
+        return Err(anyhow!("Incomplete expression: {}", expr));
+    }
+    terms.push((op, current.trim().to_string()));
+
+    let mut sums: Vec<(char, f64, bool)> = Vec::new();
+    for (op, term) in terms {
+        let (v, is_size) = parse_size(&term)?;
+        match op {
+            '*' | '/' => {
+                let last = sums
+                    .last_mut()
+                    .ok_or_else(|| anyhow!("Expression starts with {}", op))?;
+                if op == '*' {
+                    if last.2 && is_size {
+                        return Err(anyhow!("Cannot multiply two sizes"));
+                    }
+                    last.1 *= v;
+                    last.2 |= is_size;
+                } else {
+                    if v == 0.0 {
+                        return Err(anyhow!("Division by zero"));
+                    }
+                    last.1 /= v;
+                    last.2 = last.2 && !is_size;
+                }
+            }
+            _ => sums.push((op, v, is_size)),
+        }
+    }
+    let is_size = sums.iter().any(|(_, _, s)| *s);
+    if is_size && sums.iter().any(|(_, _, s)| !*s) {
+        return Err(anyhow!("Cannot add or subtract a plain number and a size"));
+    }
+    let total = sums
+        .iter()
+        .map(|(op, v, _)| if *op == '-' { -v } else { *v })
+        .sum();
+    Ok((total, is_size))
+}
+
+fn format_size(bytes: f64, base: f64, units: &[&str]) -> String {
+    let mut value = bytes;
+    let mut i = 0;
+    while value.abs() >= base && i < units.len() - 1 {
+        value /= base;
+        i += 1;
+    }
+    format!("{:.2} {}", value, units[i])
+}
+
+fn convert_bytes(args: &Value) -> Result<ConvertBytes> {
+    let input = args
+        .get("input")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("Missing 'input' (e.g. '1.5GiB + 200MB')"))?;
+    let (value, is_size) = eval_size_expr(input)?;
+    if !is_size {
+        return Ok(ConvertBytes {
+            ratio: Some(value),
+            ..Default::default()
+        });
+    }
+    let mut out = ConvertBytes {
+        bytes: Some(value.round() as i64),
+        decimal: Some(format_size(
+            value,
+            1000.0,
+            &["B", "kB", "MB", "GB", "TB", "PB"],
+        )),
+        binary: Some(format_size(
+            value,
+            1024.0,
+            &["B", "KiB", "MiB", "GiB", "TiB", "PiB"],
+        )),
+        ..Default::default()
+    };
+    if let Some(unit) = args.get("to_unit").and_then(|v| v.as_str()) {
+        let (factor, _) = parse_size(&format!("1{}", unit))?;
+        out.converted = Some(Converted {
+            unit: unit.to_string(),
+            value: value / factor,
+        });
+    }
+    Ok(out)
+}
+
+fn next_runs<Z: TimeZone + Copy>(cron: &Cron, start: DateTime<Z>, count: usize) -> Vec<String>
+where
+    Z::Offset: Display,
+{
+    cron.iter_after(start)
+        .take(count)
+        .map(|d| d.to_rfc3339())
+        .collect()
+}
+
+fn convert_cron(args: &Value) -> Result<ConvertCron> {
+    let expr = args
+        .get("input")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("Missing 'input' cron expression"))?;
+    let cron = Cron::from_str(expr).map_err(|e| anyhow!("Invalid cron expression: {}", e))?;
+    let count = args
+        .get("count")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(5)
+        .clamp(1, 50) as usize;
+    let zone = Zone::parse(
+        args.get("to_tz")
+            .and_then(|v| v.as_str())
+            .unwrap_or("local"),
+    )?;
+    let now = Utc::now().trunc_subsecs(0);
+    let runs = match &zone {
+        Zone::Utc => next_runs(&cron, now, count),
+        Zone::Local => next_runs(&cron, now.with_timezone(&Local), count),
+        Zone::Named(tz) => next_runs(&cron, now.with_timezone(tz), count),
+        Zone::Fixed(o) => next_runs(&cron, now.with_timezone(o), count),
+    };
+    Ok(ConvertCron {
+        expression: expr.to_string(),
+        description: cron.describe(),
+        zone: zone.name(),
+        next_runs: runs,
+    })
+}
+
+impl Tool for ConvertTool {
+    fn name(&self) -> &'static str {
+        "convert"
+    }
+    fn description(&self) -> &'static str {
+        "Exact local conversions: 'time' converts epochs/dates between time zones, 'bytes' evaluates size arithmetic like '1.5GiB + 200MB' or '10TB / 3' (decimal and binary units), 'cron' explains a cron expression and lists its next run times. Use this instead of calculating these yourself."
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        &["kind"]
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("kind")
+                .type_of("string")
+                .description("One of: time, bytes, cron"),
+            ParamBuilder::new("input")
+                .type_of("string")
+                .description("time: epoch (s/ms/µs/ns), RFC 3339, 'YYYY-MM-DD HH:MM', or 'now'; bytes: size expression; cron: 5 or 6 field expression"),
+            ParamBuilder::new("from_tz")
+                .type_of("string")
+                .description("Zone for time inputs without an offset (IANA name, abbreviation, '+05:30', or 'local'; default UTC)"),
+            ParamBuilder::new("to_tz")
+                .type_of("array")
+                .items(ParameterProperty {
+                    property_type: "string".into(),
+                    description: "time zone".into(),
+                    items: None,
+                    enum_list: None,
+                })
+                .description("Target zones for time (default UTC and local); the first is used for cron run times"),
+            ParamBuilder::new("to_unit")
+                .type_of("string")
+                .description("Unit to express a bytes result in (e.g. MiB, GB)"),
+            ParamBuilder::new("count")
+                .type_of("integer")
+                .description("Number of upcoming cron runs (default 5)"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let kind = args
+            .get("kind")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'kind'"))?;
+        let result = match kind {
+            "time" => Convert::Time(convert_time(&args)?),
+            "bytes" => Convert::Bytes(convert_bytes(&args)?),
+            "cron" => {
+                let mut args = args.clone();
+                if let Some(Value::Array(zones)) = args.get("to_tz").cloned() {
+                    args["to_tz"] = zones.first().cloned().unwrap_or(Value::Null);
+                }
+                Convert::Cron(convert_cron(&args)?)
+            }
+            other => return Err(anyhow!("Unknown kind: {}", other)),
+        };
+        results::to_value(result)
+    }
+
+    fn print_result(&self, result: &Value) {
+        use nu_ansi_term::{Color as NuColor, Style};
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        match result.get("kind").and_then(|v| v.as_str()) {
+            Some("time") => {
+                println!(
+                    "{}: epoch {}",
+                    result_label,
+                    result.get("epoch_seconds").unwrap_or(&Value::Null)
+                );
+                for z in result
+                    .get("zones")
+                    .and_then(|v| v.as_array())
+                    .into_iter()
+                    .flatten()
+                {
+                    println!(
+                        "  {}",
+                        z.get("readable").and_then(|v| v.as_str()).unwrap_or("")
+                    );
+                }
+            }
+            Some("bytes") => match result.get("ratio") {
+                Some(r) => println!("{}: {}", result_label, r),
+                None => println!(
+                    "{}: {} bytes ({} / {})",
+                    result_label,
+                    result.get("bytes").unwrap_or(&Value::Null),
+                    result.get("decimal").and_then(|v| v.as_str()).unwrap_or(""),
+                    result.get("binary").and_then(|v| v.as_str()).unwrap_or("")
+                ),
+            },
+            _ => {
+                println!(
+                    "{}: {}",
+                    result_label,
+                    result
+                        .get("description")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("")
+                );
+                if let Some(next) = result
+                    .get("next_runs")
+                    .and_then(|v| v.as_array())
+                    .and_then(|a| a.first())
+                    .and_then(|v| v.as_str())
+                {
+                    println!("  next: {}", next);
+                }
+            }
+        }
+    }
+}
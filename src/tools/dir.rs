@@ -1,7 +1,9 @@
 use anyhow::{anyhow, Context, Result};
-use serde_json::{json, Value};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use std::time::SystemTime;
 
 use globset::{Glob, GlobSet, GlobSetBuilder};
@@ -9,7 +11,73 @@ use llm::builder::ParamBuilder;
 use llm::chat::ParameterProperty;
 use walkdir::WalkDir;
 
-use super::Tool;
+use super::results::{self, Glob as GlobResult, ListDir, PathInfo};
+use super::{units, Tool};
+use crate::config::{get_git_root, WorkspaceConfig};
+use crate::net::expand_home;
+
+/// `workspace.root` as configured, resolved when the registry is built.
+static WORKSPACE_ROOT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Take the workspace root from `cfg` for the tools built now; unset, it is
+/// the git root, or the current directory outside a repository.
+pub(super) fn set_workspace_root(cfg: &WorkspaceConfig) -> Result<()> {
+    let root = match &cfg.root {
+        Some(root) => {
+            let root = expand_home(root);
+            Some(
+                root.canonicalize()
+                    .with_context(|| format!("workspace.root {} not found", root.display()))?,
+            )
+        }
+        None => None,
+    };
+    *WORKSPACE_ROOT.lock().unwrap_or_else(|e| e.into_inner()) = root;
+    Ok(())
+}
+
+/// The directory the tools are confined to.
+pub(crate) fn workspace_root() -> Result<PathBuf> {
+    if let Some(root) = WORKSPACE_ROOT
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+    {
+        return Ok(root);
+    }
+    let root = match get_git_root() {
+        Some(root) => root,
+        None => std::env::current_dir().context("Failed to determine current directory")?,
+    };
+    root.canonicalize()
+        .with_context(|| format!("Failed to canonicalize {}", root.display()))
+}
+
+/// Where relative paths start and commands run: the current directory when
+/// it is in the workspace, the root otherwise.
+pub(super) fn working_dir(root: &Path) -> PathBuf {
+    std::env::current_dir()
+        .ok()
+        .and_then(|dir| dir.canonicalize().ok())
+        .filter(|dir| dir.starts_with(root))
+        .unwrap_or_else(|| root.to_path_buf())
+}
+
+/// `path` with `.` and `..` resolved without touching the file system, for
+/// paths that may not exist.
+pub(super) fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for part in path.components() {
+        match part {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
 
 pub(super) fn resolve_path(p: &str, allow_nonexistent: bool) -> Result<PathBuf> {
     let root = workspace_root()?;
@@ -17,40 +85,109 @@ pub(super) fn resolve_path(p: &str, allow_nonexistent: bool) -> Result<PathBuf>
     let abs = if candidate.is_absolute() {
         candidate.to_path_buf()
     } else {
-        root.join(candidate)
+        working_dir(&root).join(candidate)
     };
-    let canonical = if allow_nonexistent {
-        if let Some(parent) = abs.parent() {
-            let can_parent = parent
-                .canonicalize()
-                .with_context(|| format!("Failed to canonicalize parent of {}", abs.display()))?;
-            can_parent.join(abs.file_name().unwrap_or_default())
-        } else {
-            abs.canonicalize()
-                .with_context(|| format!("Failed to canonicalize {}", abs.display()))?
+    let canonical = match abs.canonicalize() {
+        Ok(canonical) => canonical,
+        Err(_) if allow_nonexistent => canonical_missing(&abs)?,
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to canonicalize {}", abs.display()))
         }
-    } else {
-        abs.canonicalize()
-            .with_context(|| format!("Failed to canonicalize {}", abs.display()))?
     };
-    if !is_within(&root.canonicalize()?, &canonical) {
-        return Err(anyhow!("Path escapes workspace root"));
+    if !canonical.starts_with(&root) {
+        return Err(anyhow!(
+            "{} is outside the workspace root {}",
+            canonical.display(),
+            root.display()
+        ));
+    }
+    Ok(canonical)
+}
+
+/// `path`, which does not exist yet, below its nearest existing ancestor's
+/// canonical form, so files can be created in directories that are not
+/// there yet.
+fn canonical_missing(path: &Path) -> Result<PathBuf> {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    while !existing.exists() {
+        missing.push(
+            existing
+                .file_name()
+                .with_context(|| format!("Invalid path {}", path.display()))?,
+        );
+        existing = existing
+            .parent()
+            .with_context(|| format!("Invalid path {}", path.display()))?;
     }
+    let mut canonical = existing
+        .canonicalize()
+        .with_context(|| format!("Failed to canonicalize {}", existing.display()))?;
+    canonical.extend(missing.iter().rev());
     Ok(canonical)
 }
 
-fn workspace_root() -> Result<PathBuf> {
-    std::env::current_dir().context("Failed to determine current directory")
+/// The only directory file tools may write in, when set.
+static WRITE_SCOPE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Keep the file tools' writes to `dir` for the rest of the session.
+pub(crate) fn restrict_writes(dir: &Path) -> Result<()> {
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("Failed to canonicalize {}", dir.display()))?;
+    WRITE_SCOPE
+        .set(dir)
+        .map_err(|_| anyhow!("Writes are already restricted"))
 }
 
-fn is_within(root: &Path, path: &Path) -> bool {
-    let Ok(root_c) = root.canonicalize() else {
-        return false;
-    };
-    let Ok(path_c) = path.canonicalize() else {
-        return false;
-    };
-    path_c.starts_with(root_c)
+/// What each file held before a file tool first wrote it,
+/// `None` for files that did not exist, once recording is turned on.
+static ORIGINALS: Mutex<Option<BTreeMap<PathBuf, Option<Vec<u8>>>>> = Mutex::new(None);
+
+/// Keep the contents of files from before they are changed, from now on.
+pub(crate) fn record_changes() {
+    let mut originals = ORIGINALS.lock().unwrap_or_else(|e| e.into_inner());
+    originals.get_or_insert_with(BTreeMap::new);
+}
+
+/// Note what `path` holds now, unless it was noted before or recording is
+/// off.
+pub(crate) fn remember(path: &Path) {
+    let mut originals = ORIGINALS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(originals) = originals.as_mut() {
+        originals
+            .entry(path.to_path_buf())
+            .or_insert_with(|| fs::read(path).ok());
+    }
+}
+
+/// The files that differ from what they held when first noted, with what
+/// they held.
+pub(crate) fn recorded_changes() -> Vec<(PathBuf, Option<Vec<u8>>)> {
+    let originals = ORIGINALS.lock().unwrap_or_else(|e| e.into_inner());
+    originals
+        .iter()
+        .flatten()
+        .filter(|(path, original)| fs::read(path).ok() != **original)
+        .map(|(path, original)| (path.clone(), original.clone()))
+        .collect()
+}
+
+/// Like `resolve_path`, for a path about to be written: it must also lie in
+/// the write scope, if there is one.
+pub(super) fn resolve_writable(p: &str, allow_nonexistent: bool) -> Result<PathBuf> {
+    let path = resolve_path(p, allow_nonexistent)?;
+    if let Some(scope) = WRITE_SCOPE.get() {
+        if !path.starts_with(scope) {
+            return Err(anyhow!(
+                "{} is outside the writable scope {}; only files there may be changed",
+                path.display(),
+                scope.display()
+            ));
+        }
+    }
+    remember(&path);
+    Ok(path)
 }
 
 pub struct ListDirTool;
@@ -191,15 +328,45 @@ impl Tool for ListDirTool {
                 }
             }
         }
-        Ok(json!({ "path": path.display().to_string(), "count": items.len(), "items": items }))
+        results::to_value(ListDir {
+            path: path.display().to_string(),
+            count: items.len(),
+            items,
+        })
+    }
+
+    fn print_result(&self, result: &Value) {
+        let items = result
+            .get("items")
+            .and_then(|v| v.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let files: Vec<u64> = items
+            .iter()
+            .filter(|item| item["type"] == "file")
+            .filter_map(|item| item["size"].as_u64())
+            .collect();
+        println!(
+            "{}: {} entries in {}, {} in {} files",
+            result_label(),
+            units::count(items.len() as u64),
+            result["path"].as_str().unwrap_or("."),
+            units::size(files.iter().sum()),
+            units::count(files.len() as u64)
+        );
     }
 }
 
+fn result_label() -> impl std::fmt::Display {
+    use nu_ansi_term::{Color as NuColor, Style};
+    Style::new().fg(NuColor::LightMagenta).paint("result")
+}
+
 fn fmt_time(t: SystemTime) -> String {
     chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
 }
 
-fn path_info(p: &Path) -> Result<Value> {
+fn path_info(p: &Path) -> Result<PathInfo> {
     let md = fs::symlink_metadata(p).with_context(|| format!("stat failed for {}", p.display()))?;
     let file_type = if md.is_dir() {
         "dir"
@@ -219,14 +386,14 @@ fn path_info(p: &Path) -> Result<Value> {
     } else {
         String::from("")
     };
-    Ok(json!({
-        "path": p.display().to_string(),
-        "type": file_type,
-        "size": size,
-        "modified": modified.map(fmt_time),
-        "created": created.map(fmt_time),
-        "mode": mode,
-    }))
+    Ok(PathInfo {
+        path: p.display().to_string(),
+        kind: file_type.to_string(),
+        size,
+        modified: modified.map(fmt_time),
+        created: created.map(fmt_time),
+        mode,
+    })
 }
 
 pub struct StatTool;
@@ -251,7 +418,21 @@ impl Tool for StatTool {
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("Missing 'path'"))?;
         let path = resolve_path(path_s, false)?;
-        path_info(&path)
+        results::to_value(path_info(&path)?)
+    }
+
+    fn print_result(&self, result: &Value) {
+        let size = match result["type"].as_str() {
+            Some("dir") => String::new(),
+            _ => format!(", {}", units::size(result["size"].as_u64().unwrap_or(0))),
+        };
+        println!(
+            "{}: {}{}, modified {}",
+            result_label(),
+            result["type"].as_str().unwrap_or("other"),
+            size,
+            result["modified"].as_str().unwrap_or("unknown")
+        );
     }
 }
 
@@ -290,18 +471,74 @@ impl Tool for GlobTool {
         let glob = Glob::new(pattern)
             .with_context(|| format!("bad glob {}", pattern))?
             .compile_matcher();
-        let mut results = Vec::new();
+        let mut paths = Vec::new();
         for entry in WalkDir::new(&root).into_iter().filter_map(|e| e.ok()) {
             let p = entry.path();
             if p.is_file() && glob.is_match(p) {
-                results.push(p.display().to_string());
-                if results.len() >= limit {
+                paths.push(p.display().to_string());
+                if paths.len() >= limit {
                     break;
                 }
             }
         }
-        Ok(
-            json!({ "root": root.display().to_string(), "pattern": pattern, "count": results.len(), "paths": results }),
-        )
+        results::to_value(GlobResult {
+            root: root.display().to_string(),
+            pattern: pattern.to_string(),
+            count: paths.len(),
+            paths,
+        })
+    }
+}
+
+/// Held by tests that set the workspace root, which is shared by the process.
+#[cfg(test)]
+pub(super) static WORKSPACE_TEST: Mutex<()> = Mutex::new(());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_stay_in_the_workspace_and_writes_in_their_scope() {
+        let _lock = WORKSPACE_TEST.lock().unwrap_or_else(|e| e.into_inner());
+        let base = std::env::temp_dir().join(format!("tai-dir-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(base.join("ws").join("sub")).unwrap();
+        let base = base.canonicalize().unwrap();
+        let root = base.join("ws");
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(base.join("outside.txt"), "secret").unwrap();
+        set_workspace_root(&WorkspaceConfig {
+            root: Some(root.display().to_string()),
+            ..WorkspaceConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(resolve_path("a.txt", false).unwrap(), root.join("a.txt"));
+        assert!(resolve_path("../outside.txt", false).is_err());
+        assert!(resolve_path("sub/../../outside.txt", true).is_err());
+        assert!(resolve_path(&base.join("outside.txt").to_string_lossy(), false).is_err());
+        assert!(resolve_path("missing/new.txt", false).is_err());
+        assert_eq!(
+            resolve_path("missing/new.txt", true).unwrap(),
+            root.join("missing").join("new.txt")
+        );
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(base.join("outside.txt"), root.join("link")).unwrap();
+            assert!(resolve_path("link", false).is_err());
+        }
+
+        restrict_writes(&root.join("sub")).unwrap();
+        assert!(restrict_writes(&root).is_err());
+        assert_eq!(
+            resolve_writable("sub/new.txt", true).unwrap(),
+            root.join("sub").join("new.txt")
+        );
+        assert!(resolve_writable("a.txt", false).is_err());
+        // Reading outside the write scope is still fine.
+        assert!(resolve_path("a.txt", false).is_ok());
+
+        set_workspace_root(&WorkspaceConfig::default()).unwrap();
     }
 }
@@ -1,14 +1,20 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
 use llm::builder::ParamBuilder;
-use reqwest::blocking::{Client, Response};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use serde_json::{json, Value};
+use reqwest::Response;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
-use super::Tool;
+use super::results::{self, FetchUrl};
+use super::{units, Tool};
+use crate::config::NetworkConfig;
+use crate::net;
 
-pub struct FetchUrlTool;
+pub struct FetchUrlTool(pub(super) NetworkConfig);
 
 impl Tool for FetchUrlTool {
     fn name(&self) -> &'static str {
@@ -42,103 +48,108 @@ impl Tool for FetchUrlTool {
     fn required_params(&self) -> &'static [&'static str] {
         &["url"]
     }
-    fn execute_blocking(&self, args: Value) -> Result<Value> {
-        let url = args
-            .get("url")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow!("Missing 'url'"))?;
-        if !(url.starts_with("http://") || url.starts_with("https://")) {
-            return Err(anyhow!("Only http/https URLs are allowed"));
-        }
-        let method = args
-            .get("method")
-            .and_then(|v| v.as_str())
-            .unwrap_or("GET")
-            .to_uppercase();
-        let timeout = args
-            .get("timeout_sec")
-            .and_then(|v| v.as_u64())
-            .unwrap_or(10);
-        let max_bytes = args
-            .get("max_bytes")
-            .and_then(|v| v.as_u64())
-            .unwrap_or(200_000) as usize;
-        let body = args
-            .get("body")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string());
+    fn execute(self: Arc<Self>, args: Value) -> BoxFuture<'static, Result<Value>> {
+        Box::pin(fetch(args, self.0.clone()))
+    }
+
+    fn print_result(&self, result: &Value) {
+        use nu_ansi_term::{Color as NuColor, Style};
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let Some(status) = result.get("status") else {
+            println!("{}: Error", result_label);
+            return;
+        };
+        let text = result.get("text").and_then(|v| v.as_str()).unwrap_or("");
+        let truncated = result.get("truncated").and_then(|v| v.as_bool()) == Some(true);
+        println!(
+            "{}: {}, {}{}",
+            result_label,
+            status,
+            units::size(text.len() as u64),
+            if truncated { " (truncated)" } else { "" }
+        );
+    }
+}
+
+async fn fetch(args: Value, network: NetworkConfig) -> Result<Value> {
+    let url = args
+        .get("url")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("Missing 'url'"))?;
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(anyhow!("Only http/https URLs are allowed"));
+    }
+    let method = args
+        .get("method")
+        .and_then(|v| v.as_str())
+        .unwrap_or("GET")
+        .to_uppercase();
+    let timeout = args
+        .get("timeout_sec")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(10);
+    let max_bytes = args
+        .get("max_bytes")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(200_000) as usize;
+    let body = args
+        .get("body")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
 
-        let mut headers = HeaderMap::new();
-        if let Some(hv) = args.get("headers").and_then(|v| v.as_object()) {
-            for (k, v) in hv {
-                if let Some(val_str) = v.as_str() {
-                    if let Ok(name) = HeaderName::from_str(k) {
-                        if let Ok(val) = HeaderValue::from_str(val_str) {
-                            headers.insert(name, val);
-                        }
+    let mut headers = HeaderMap::new();
+    if let Some(hv) = args.get("headers").and_then(|v| v.as_object()) {
+        for (k, v) in hv {
+            if let Some(val_str) = v.as_str() {
+                if let Ok(name) = HeaderName::from_str(k) {
+                    if let Ok(val) = HeaderValue::from_str(val_str) {
+                        headers.insert(name, val);
                     }
                 }
             }
         }
+    }
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(timeout))
-            .connect_timeout(Duration::from_secs(timeout))
-            .build()?;
-
-        let req_builder = match method.as_str() {
-            "GET" => client.get(url),
-            "POST" => client.post(url),
-            "PUT" => client.put(url),
-            "PATCH" => client.patch(url),
-            "DELETE" => client.delete(url),
-            "HEAD" => client.head(url),
-            _ => return Err(anyhow!("Unsupported method")),
-        };
-        let mut req = req_builder.headers(headers);
-        if let Some(b) = body {
-            req = req.body(b);
-        }
+    let client = net::client(&network)?
+        .timeout(Duration::from_secs(timeout))
+        .connect_timeout(Duration::from_secs(timeout))
+        .build()?;
 
-        let resp: Response = req
-            .send()
-            .with_context(|| format!("Request failed for {}", url))?;
-        let status = resp.status().as_u16();
-        let final_url = resp.url().to_string();
-        let mut resp_headers = serde_json::Map::new();
-        for (name, value) in resp.headers().iter() {
-            resp_headers.insert(name.to_string(), json!(value.to_str().unwrap_or("")));
-        }
-        let mut text = resp.text().unwrap_or_default();
-        let truncated = text.len() > max_bytes;
-        if truncated {
-            text.truncate(max_bytes);
-        }
-        Ok(json!({
-            "url": url,
-            "final_url": final_url,
-            "status": status,
-            "headers": resp_headers,
-            "truncated": truncated,
-            "text": text,
-        }))
+    let req_builder = match method.as_str() {
+        "GET" => client.get(url),
+        "POST" => client.post(url),
+        "PUT" => client.put(url),
+        "PATCH" => client.patch(url),
+        "DELETE" => client.delete(url),
+        "HEAD" => client.head(url),
+        _ => return Err(anyhow!("Unsupported method")),
+    };
+    let mut req = req_builder.headers(headers);
+    if let Some(b) = body {
+        req = req.body(b);
     }
 
-    fn print_result(&self, result: &Value) {
-        use nu_ansi_term::{Color as NuColor, Style};
-        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
-        println!(
-            "{}: {}",
-            result_label,
-            result
-                .get("status")
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| "Error".to_string()),
-            // result
-            //     .get("final_url")
-            //     .or_else(|| result.get("url"))
-            //     .map(|s| s.to_string())
-            //     .unwrap_or_else(|| "unknown".to_string())
-        );
+    let resp: Response = req
+        .send()
+        .await
+        .map_err(|e| anyhow!("Request failed for {}: {}", url, net::error_cause(&e)))?;
+    let status = resp.status().as_u16();
+    let final_url = resp.url().to_string();
+    let mut resp_headers = BTreeMap::new();
+    for (name, value) in resp.headers().iter() {
+        resp_headers.insert(name.to_string(), value.to_str().unwrap_or("").to_string());
+    }
+    let mut text = resp.text().await.unwrap_or_default();
+    let truncated = text.len() > max_bytes;
+    if truncated {
+        text.truncate(max_bytes);
     }
+    results::to_value(FetchUrl {
+        url: url.to_string(),
+        final_url,
+        status,
+        headers: resp_headers,
+        truncated,
+        text,
+    })
 }
@@ -1,8 +1,7 @@
 use anyhow::{anyhow, Context, Result};
-use serde_json::{json, Value};
+use serde_json::Value;
 use std::fs;
 use std::io::Read;
-use std::path::{Path, PathBuf};
 
 use globset::{Glob, GlobSetBuilder};
 use ignore::WalkBuilder;
@@ -10,8 +9,14 @@ use llm::builder::ParamBuilder;
 use llm::chat::ParameterProperty;
 use regex::RegexBuilder;
 
-use crate::tools::dir::resolve_path;
+use crate::config::PolicyConfig;
+use crate::scratch;
+use crate::tools::dir::{resolve_path, resolve_writable};
 
+use super::approval::{approve_edit, FileChange};
+use super::format::Formatters;
+use super::results::{self, Grep, GrepMatch, PatchFile, ReadFile, WriteFile};
+use super::seen;
 use super::Tool;
 
 pub struct ReadFileTool;
@@ -53,6 +58,7 @@ impl Tool for ReadFileTool {
         fs::File::open(&path)
             .and_then(|mut f| f.read_to_string(&mut s))
             .with_context(|| format!("Failed reading {}", path.display()))?;
+        seen::saw(&path, s.as_bytes());
         let lines: Vec<&str> = s.lines().collect();
         let total_lines = lines.len();
         let start = offset.min(total_lines);
@@ -61,17 +67,17 @@ impl Tool for ReadFileTool {
             None => total_lines,
         };
         let slice = lines[start..end].join("\n");
-        Ok(json!({
-            "path": path.display().to_string(),
-            "start": start,
-            "end": end,
-            "total_lines": total_lines,
-            "content": slice,
-        }))
+        results::to_value(ReadFile {
+            path: path.display().to_string(),
+            start,
+            end,
+            total_lines,
+            content: slice,
+        })
     }
 }
 
-pub struct WriteFileTool;
+pub struct WriteFileTool(pub(super) PolicyConfig, pub(super) Formatters);
 impl Tool for WriteFileTool {
     fn name(&self) -> &'static str {
         "write_file"
@@ -112,7 +118,30 @@ impl Tool for WriteFileTool {
             .get("create_parents")
             .and_then(|v| v.as_bool())
             .unwrap_or(true);
-        let path = resolve_path(path_s, true)?;
+        let path = resolve_writable(path_s, true)?;
+        if let Some(conflict) = seen::conflict(&path, self.name()) {
+            return results::to_value(conflict);
+        }
+        let old = fs::read(&path)
+            .ok()
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+        if old.as_deref() != Some(content) {
+            let change = FileChange {
+                path: &path,
+                old: old.as_deref(),
+                new: Some(content),
+                moved_to: None,
+            };
+            if !approve_edit(&self.0, self.name(), &[change])? {
+                return results::to_value(WriteFile {
+                    path: path.display().to_string(),
+                    bytes: None,
+                    written: Some(false),
+                    reason: Some("The user declined this change".to_string()),
+                    formatted: None,
+                });
+            }
+        }
         if let Some(parent) = path.parent() {
             if create_parents {
                 fs::create_dir_all(parent)
@@ -120,32 +149,24 @@ impl Tool for WriteFileTool {
             }
         }
         if atomic {
-            let file_name = path.file_name().and_then(|s| s.to_str()).unwrap_or("tmp");
-            let mut suffix = 0u32;
-            let tmp = loop {
-                let candidate = parent_join(&path, &format!(".{}.tmp.{}", file_name, suffix));
-                if !candidate.exists() {
-                    break candidate;
-                }
-                suffix += 1;
-            };
-            fs::write(&tmp, content)
-                .with_context(|| format!("Failed to write temp file {}", tmp.display()))?;
-            fs::rename(&tmp, &path)
-                .with_context(|| format!("Failed to replace {}", path.display()))?;
+            scratch::write_atomic(&path, content.as_bytes())?;
         } else {
             fs::write(&path, content)
                 .with_context(|| format!("Failed to write {}", path.display()))?;
         }
-        Ok(json!({ "path": path.display().to_string(), "bytes": content.len() }))
+        let formatted = self.1.run(&path, content);
+        seen::refresh(&path);
+        results::to_value(WriteFile {
+            path: path.display().to_string(),
+            bytes: Some(content.len()),
+            written: None,
+            reason: None,
+            formatted,
+        })
     }
 }
 
-fn parent_join(path: &Path, file: &str) -> PathBuf {
-    path.parent().unwrap_or_else(|| Path::new("")).join(file)
-}
-
-pub struct PatchFileTool;
+pub struct PatchFileTool(pub(super) PolicyConfig, pub(super) Formatters);
 impl Tool for PatchFileTool {
     fn name(&self) -> &'static str {
         "patch_file"
@@ -180,12 +201,15 @@ impl Tool for PatchFileTool {
             .get("path")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("Missing 'path'"))?;
-        let path = resolve_path(path_s, false)?;
+        let path = resolve_writable(path_s, false)?;
         let replacements = args
             .get("replacements")
             .and_then(|v| v.as_array())
             .ok_or_else(|| anyhow!("Missing 'replacements'"))?;
         let atomic = args.get("atomic").and_then(|v| v.as_bool()).unwrap_or(true);
+        if let Some(conflict) = seen::conflict(&path, self.name()) {
+            return results::to_value(conflict);
+        }
         let mut content = String::new();
         fs::File::open(&path)
             .and_then(|mut f| f.read_to_string(&mut content))
@@ -219,36 +243,49 @@ impl Tool for PatchFileTool {
                 counts.push(0);
             }
         }
+        let total = counts.iter().sum::<usize>();
         if updated == content {
-            return Ok(json!({
-                "path": path.display().to_string(),
-                "changed": false,
-                "replacements": counts,
-                "total_replacements": counts.iter().sum::<usize>(),
-            }));
+            return results::to_value(PatchFile {
+                path: path.display().to_string(),
+                changed: false,
+                replacements: Some(counts),
+                total_replacements: Some(total),
+                reason: None,
+                formatted: None,
+            });
+        }
+        let change = FileChange {
+            path: &path,
+            old: Some(&content),
+            new: Some(&updated),
+            moved_to: None,
+        };
+        if !approve_edit(&self.0, self.name(), &[change])? {
+            return results::to_value(PatchFile {
+                path: path.display().to_string(),
+                changed: false,
+                replacements: None,
+                total_replacements: None,
+                reason: Some("The user declined this change".to_string()),
+                formatted: None,
+            });
         }
         if atomic {
-            let tmp = parent_join(
-                &path,
-                &format!(
-                    ".{}.patch.tmp",
-                    path.file_name().and_then(|s| s.to_str()).unwrap_or("file")
-                ),
-            );
-            fs::write(&tmp, updated.as_bytes())
-                .with_context(|| format!("Failed to write temp {}", tmp.display()))?;
-            fs::rename(&tmp, &path)
-                .with_context(|| format!("Failed to replace {}", path.display()))?;
+            scratch::write_atomic(&path, updated.as_bytes())?;
         } else {
             fs::write(&path, updated.as_bytes())
                 .with_context(|| format!("Failed to write {}", path.display()))?;
         }
-        Ok(json!({
-            "path": path.display().to_string(),
-            "changed": true,
-            "replacements": counts,
-            "total_replacements": counts.iter().sum::<usize>(),
-        }))
+        let formatted = self.1.run(&path, &updated);
+        seen::refresh(&path);
+        results::to_value(PatchFile {
+            path: path.display().to_string(),
+            changed: true,
+            replacements: Some(counts),
+            total_replacements: Some(total),
+            reason: None,
+            formatted,
+        })
     }
 }
 
@@ -355,7 +392,7 @@ impl Tool for GrepTool {
             .build()
             .with_context(|| "Invalid regex pattern")?;
 
-        let mut results = Vec::new();
+        let mut matches = Vec::new();
         let walker = WalkBuilder::new(&root)
             .hidden(false)
             .ignore(true)
@@ -364,7 +401,7 @@ impl Tool for GrepTool {
             .git_exclude(true)
             .build();
         for dent in walker {
-            if results.len() >= max_results {
+            if matches.len() >= max_results {
                 break;
             }
             let dent = match dent {
@@ -401,21 +438,24 @@ impl Tool for GrepTool {
             };
             for (lineno, line) in text.lines().enumerate() {
                 if re.is_match(line) {
-                    results.push(json!({
-                        "file": p.strip_prefix(&root).unwrap_or(p).display().to_string(),
-                        "abs_path": p.display().to_string(),
-                        "line": lineno + 1,
-                        "match": line,
-                    }));
-                    if results.len() >= max_results {
+                    matches.push(GrepMatch {
+                        file: p.strip_prefix(&root).unwrap_or(p).display().to_string(),
+                        abs_path: p.display().to_string(),
+                        line: lineno + 1,
+                        text: line.to_string(),
+                    });
+                    if matches.len() >= max_results {
                         break;
                     }
                 }
             }
         }
-        Ok(
-            json!({ "root": root.display().to_string(), "pattern": pattern, "count": results.len(), "results": results }),
-        )
+        results::to_value(Grep {
+            root: root.display().to_string(),
+            pattern: pattern.to_string(),
+            count: matches.len(),
+            results: matches,
+        })
     }
 }
 
@@ -0,0 +1,243 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use globset::Glob;
+use nu_ansi_term::Style;
+
+use super::approval::{print_diff, FileChange};
+use super::ask_user;
+use super::process::{in_path, run_with_input, Captured};
+use super::results::Formatted;
+use crate::config::ConventionsConfig;
+use crate::project::project_root;
+use crate::say;
+use crate::scratch;
+
+/// Formatted files up to this size go back to the model whole; larger ones
+/// as the diff the formatter made.
+const MAX_CONTENT_CHARS: usize = 20_000;
+
+/// How long a formatter may run before it is killed.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Whether the user let the project's own formatters run, by program, as
+/// answered once per run.
+static ALLOWED: Mutex<Option<HashMap<PathBuf, bool>>> = Mutex::new(None);
+
+/// Formatters by file extension, for files `[conventions.formatters]` does
+/// not cover. They read the file on stdin, so none of them touches other
+/// files, e.g. rustfmt the modules a `main.rs` declares. `prettier` only
+/// runs from the project's own node_modules, and only once the user allowed
+/// it, since the project decides what that program does.
+const BUILT_IN: &[(&[&str], &str, &[&str])] = &[
+    (&["rs"], "rustfmt", &["--edition", "2021"]),
+    (&["go"], "gofmt", &[]),
+    (&["py"], "black", &["-q", "-"]),
+    (
+        &[
+            "js", "jsx", "ts", "tsx", "mjs", "cjs", "json", "css", "scss", "md", "yaml", "yml",
+            "html", "vue",
+        ],
+        "prettier",
+        &["--stdin-filepath"],
+    ),
+];
+
+/// One formatter run: built-in ones filter stdin to stdout, configured ones
+/// rewrite the file.
+struct Run {
+    program: PathBuf,
+    args: Vec<String>,
+    stdin: bool,
+}
+
+fn format(run: &Run, written: &str) -> Result<Captured> {
+    let mut command = Command::new(&run.program);
+    command.args(&run.args);
+    let input = run.stdin.then(|| written.as_bytes().to_vec());
+    run_with_input(command, input, TIMEOUT)
+}
+
+/// Ask once per run whether `program`, which came with the project, may
+/// format files.
+fn allowed(program: &Path) -> bool {
+    let mut allowed = ALLOWED.lock().unwrap_or_else(|e| e.into_inner());
+    let allowed = allowed.get_or_insert_with(HashMap::new);
+    if let Some(&answer) = allowed.get(program) {
+        return answer;
+    }
+    let answer = ask_user(&format!(
+        "Format edited files with the project's {}? [y/N] ",
+        program.display()
+    ))
+    .is_ok_and(|choice| choice == "y");
+    allowed.insert(program.to_path_buf(), answer);
+    answer
+}
+
+fn glob_matches(glob: &str, path: &str) -> bool {
+    Glob::new(glob).is_ok_and(|g| g.compile_matcher().is_match(path))
+}
+
+/// Runs the project's formatter on files `write_file` and `patch_file`
+/// changed.
+#[derive(Clone)]
+pub(super) struct Formatters {
+    enabled: bool,
+    /// `{file}`-templated commands by glob.
+    custom: BTreeMap<String, String>,
+}
+
+impl Formatters {
+    pub(super) fn new(conventions: &ConventionsConfig) -> Self {
+        Self {
+            enabled: conventions.format_on_write.unwrap_or(true),
+            custom: conventions.formatters.clone().unwrap_or_default(),
+        }
+    }
+
+    /// The program and arguments that format `path`, if any.
+    fn command_for(&self, path: &Path) -> Option<Run> {
+        let file = path.display().to_string();
+        let relative = project_root()
+            .and_then(|root| {
+                path.strip_prefix(root)
+                    .ok()
+                    .map(|p| p.display().to_string())
+            })
+            .unwrap_or_else(|| file.clone());
+        let name = path.file_name()?.to_string_lossy();
+        if let Some((_, command)) = self
+            .custom
+            .iter()
+            .find(|(glob, _)| glob_matches(glob, &relative) || glob_matches(glob, &name))
+        {
+            let mut words = command
+                .split_whitespace()
+                .map(|w| w.replace("{file}", &file));
+            let program = PathBuf::from(words.next()?);
+            let mut args: Vec<String> = words.collect();
+            if !command.contains("{file}") {
+                args.push(file);
+            }
+            return Some(Run {
+                program,
+                args,
+                stdin: false,
+            });
+        }
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        let (_, program, args) = BUILT_IN
+            .iter()
+            .find(|(exts, _, _)| exts.contains(&ext.as_str()))?;
+        let program = if *program == "prettier" {
+            let local = project_root()?.join("node_modules/.bin/prettier");
+            (local.is_file() && allowed(&local)).then_some(local)?
+        } else if in_path(program) {
+            PathBuf::from(program)
+        } else {
+            return None;
+        };
+        let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        if args.last().is_some_and(|a| a == "--stdin-filepath") {
+            args.push(file);
+        }
+        Some(Run {
+            program,
+            args,
+            stdin: true,
+        })
+    }
+
+    /// Format `path` after `written` went into it. Shows what the formatter
+    /// changed, and returns what the tool result gets: the formatter, and
+    /// the file as it is now or the diff, or why formatting failed.
+    pub(super) fn run(&self, path: &Path, written: &str) -> Option<Formatted> {
+        if !self.enabled {
+            return None;
+        }
+        let run = self.command_for(path)?;
+        let by = run
+            .program
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let output = match format(&run, written) {
+            Ok(output) => output,
+            Err(e) => {
+                tracing::debug!("Failed to run {}: {:#}", run.program.display(), e);
+                return None;
+            }
+        };
+        let Some(status) = output.status else {
+            return Some(Formatted {
+                failed: Some(format!("timed out after {}s", TIMEOUT.as_secs())),
+                ..Formatted::by(by)
+            });
+        };
+        if !status.success() {
+            let stderr = output.stderr;
+            return Some(Formatted {
+                failed: Some(
+                    stderr
+                        .trim()
+                        .lines()
+                        .take(20)
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+                ..Formatted::by(by)
+            });
+        }
+        let formatted = if run.stdin {
+            output.stdout
+        } else {
+            fs::read_to_string(path).ok()?
+        };
+        if run.stdin && formatted != written {
+            if let Err(e) = scratch::write_atomic(path, formatted.as_bytes()) {
+                return Some(Formatted {
+                    failed: Some(format!("{:#}", e)),
+                    ..Formatted::by(by)
+                });
+            }
+        }
+        if formatted == written {
+            return Some(Formatted {
+                changed: Some(false),
+                ..Formatted::by(by)
+            });
+        }
+        say!(
+            "{}",
+            Style::new().dimmed().paint(format!("formatted by {}:", by))
+        );
+        print_diff(&FileChange {
+            path,
+            old: Some(written),
+            new: Some(&formatted),
+            moved_to: None,
+        });
+        if formatted.len() <= MAX_CONTENT_CHARS {
+            return Some(Formatted {
+                changed: Some(true),
+                content: Some(formatted),
+                ..Formatted::by(by)
+            });
+        }
+        let diff = similar::TextDiff::from_lines(written, &formatted)
+            .unified_diff()
+            .context_radius(2)
+            .to_string();
+        Some(Formatted {
+            changed: Some(true),
+            diff: Some(diff),
+            ..Formatted::by(by)
+        })
+    }
+}
@@ -0,0 +1,306 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use llm::builder::ParamBuilder;
+use nu_ansi_term::{Color as NuColor, Style};
+
+use super::process::{tail, Limits, ProcessTree};
+use super::units;
+use super::{results, Tool};
+use crate::scratch;
+
+const MAX_LOG_BYTES: usize = 32 * 1024;
+
+/// A command started by `run_shell` with `background: true`.
+struct Job {
+    id: u32,
+    command: String,
+    child: Child,
+    tree: ProcessTree,
+    log: PathBuf,
+    started: Instant,
+    exit: Option<ExitStatus>,
+}
+
+impl Job {
+    fn refresh(&mut self) {
+        if self.exit.is_none() {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                self.exit = Some(status);
+                self.tree.release();
+            }
+        }
+    }
+
+    fn summary(&self) -> results::Job {
+        results::Job {
+            id: self.id,
+            command: self.command.clone(),
+            pid: self.child.id(),
+            running: self.exit.is_none(),
+            exit_status: self.exit.and_then(|s| s.code()),
+            uptime_sec: self.started.elapsed().as_secs(),
+            log: self.log.display().to_string(),
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        if self.exit.is_none() {
+            self.tree.kill();
+            let _ = self.child.wait();
+        }
+        let _ = fs::remove_file(&self.log);
+    }
+}
+
+/// Background jobs of this session. Anything still running when the session
+/// ends is stopped with it.
+#[derive(Default)]
+pub(super) struct Jobs {
+    jobs: Mutex<Vec<Job>>,
+}
+
+impl Jobs {
+    /// Spawn `cmd` with its output going to a log file. `cmd` should already
+    /// be in its own process group.
+    pub(super) fn start(
+        &self,
+        command: &str,
+        mut cmd: Command,
+        limits: &Limits,
+    ) -> Result<results::Job> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let id = jobs.iter().map(|j| j.id).max().unwrap_or(0) + 1;
+        let log = scratch::file(&format!("job-{}.log", id))?;
+        let file = fs::File::create(&log)
+            .with_context(|| format!("Failed to create {}", log.display()))?;
+        let child = cmd
+            .stdin(Stdio::null())
+            .stdout(file.try_clone()?)
+            .stderr(file)
+            .spawn()
+            .context("Failed to start background command")?;
+        let job = Job {
+            id,
+            command: command.to_string(),
+            tree: ProcessTree::new(child.id(), limits),
+            child,
+            log,
+            started: Instant::now(),
+            exit: None,
+        };
+        let summary = job.summary();
+        jobs.push(job);
+        Ok(summary)
+    }
+
+    pub(super) fn status(&self, id: Option<u32>) -> Result<Value> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = Vec::new();
+        for job in jobs.iter_mut().filter(|j| id.is_none_or(|id| j.id == id)) {
+            job.refresh();
+            out.push(job.summary());
+        }
+        if let (Some(id), true) = (id, out.is_empty()) {
+            return Err(anyhow!("No background job with id {}", id));
+        }
+        results::to_value(results::ProcessStatus { jobs: out })
+    }
+
+    pub(super) fn logs(&self, id: u32, lines: usize) -> Result<results::ProcessLogs> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let job = jobs
+            .iter_mut()
+            .find(|j| j.id == id)
+            .ok_or_else(|| anyhow!("No background job with id {}", id))?;
+        job.refresh();
+        let content = fs::read(&job.log)
+            .map(|b| String::from_utf8_lossy(&b).to_string())
+            .unwrap_or_default();
+        let all: Vec<&str> = content.lines().collect();
+        let start = all.len().saturating_sub(lines);
+        let (output, truncated) = tail(&all[start..].join("\n"), MAX_LOG_BYTES);
+        Ok(results::ProcessLogs {
+            id,
+            running: job.exit.is_none(),
+            exit_status: job.exit.and_then(|s| s.code()),
+            total_lines: all.len(),
+            output,
+            truncated: truncated || start > 0,
+        })
+    }
+
+    pub(super) fn stop(&self, id: u32) -> Result<Value> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let job = jobs
+            .iter_mut()
+            .find(|j| j.id == id)
+            .ok_or_else(|| anyhow!("No background job with id {}", id))?;
+        job.refresh();
+        if job.exit.is_some() {
+            return results::to_value(results::ProcessStop {
+                id,
+                stopped: false,
+                terminated: None,
+                exit_status: job.exit.and_then(|s| s.code()),
+                note: Some("Job had already exited".to_string()),
+            });
+        }
+        let killed = job.tree.kill();
+        let _ = job.child.kill();
+        job.exit = job.child.wait().ok();
+        results::to_value(results::ProcessStop {
+            id,
+            stopped: true,
+            terminated: Some(killed),
+            exit_status: None,
+            note: None,
+        })
+    }
+}
+
+fn job_id(args: &Value) -> Result<u32> {
+    args.get("id")
+        .and_then(|v| v.as_u64())
+        .map(|id| id as u32)
+        .ok_or_else(|| anyhow!("Missing 'id'"))
+}
+
+pub struct ProcessStatusTool(pub(super) Arc<Jobs>);
+
+impl Tool for ProcessStatusTool {
+    fn name(&self) -> &'static str {
+        "process_status"
+    }
+    fn description(&self) -> &'static str {
+        "List background jobs started with run_shell (background=true): whether each is still running, its exit status, uptime, and log file."
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![ParamBuilder::new("id")
+            .type_of("integer")
+            .description("Only report this job")]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let id = args.get("id").and_then(|v| v.as_u64()).map(|id| id as u32);
+        self.0.status(id)
+    }
+
+    fn print_result(&self, result: &Value) {
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let jobs = result
+            .get("jobs")
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default();
+        if jobs.is_empty() {
+            println!("{}: no background jobs", result_label);
+            return;
+        }
+        println!("{}:", result_label);
+        for job in jobs {
+            let state = if job["running"].as_bool().unwrap_or(false) {
+                NuColor::Green.paint("running".to_string())
+            } else {
+                match job["exit_status"].as_i64() {
+                    Some(code) => NuColor::Red.paint(format!("exit {}", code)),
+                    None => NuColor::Red.paint("terminated".to_string()),
+                }
+            };
+            println!(
+                "  [{}] {} {}  {}",
+                job["id"],
+                state,
+                units::duration(job["uptime_sec"].as_f64().unwrap_or(0.0)),
+                job["command"].as_str().unwrap_or("")
+            );
+        }
+    }
+}
+
+pub struct ProcessLogsTool(pub(super) Arc<Jobs>);
+
+impl Tool for ProcessLogsTool {
+    fn name(&self) -> &'static str {
+        "process_logs"
+    }
+    fn description(&self) -> &'static str {
+        "Read the most recent output (stdout and stderr combined) of a background job started with run_shell."
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        &["id"]
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("id")
+                .type_of("integer")
+                .description("Job id returned by run_shell"),
+            ParamBuilder::new("lines")
+                .type_of("integer")
+                .description("Number of trailing lines to return (defaults to 50)"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let lines = args.get("lines").and_then(|v| v.as_u64()).unwrap_or(50) as usize;
+        results::to_value(self.0.logs(job_id(&args)?, lines)?)
+    }
+
+    fn print_result(&self, result: &Value) {
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let output = result.get("output").and_then(|v| v.as_str()).unwrap_or("");
+        println!(
+            "{}: {} lines of output",
+            result_label,
+            units::count(output.lines().count() as u64)
+        );
+        if !output.is_empty() {
+            println!("{}", Style::new().dimmed().paint(output));
+        }
+    }
+}
+
+pub struct ProcessStopTool(pub(super) Arc<Jobs>);
+
+impl Tool for ProcessStopTool {
+    fn name(&self) -> &'static str {
+        "process_stop"
+    }
+    fn description(&self) -> &'static str {
+        "Stop a background job started with run_shell, together with every process it started."
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        &["id"]
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![ParamBuilder::new("id")
+            .type_of("integer")
+            .description("Job id returned by run_shell")]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        self.0.stop(job_id(&args)?)
+    }
+
+    fn print_result(&self, result: &Value) {
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let id = result.get("id").and_then(|v| v.as_u64()).unwrap_or(0);
+        if result.get("stopped").and_then(|v| v.as_bool()) == Some(true) {
+            let killed = result
+                .get("terminated")
+                .and_then(|v| v.as_array())
+                .map(|a| a.len())
+                .unwrap_or(0);
+            println!(
+                "{}: stopped job {} ({} processes)",
+                result_label, id, killed
+            );
+        } else {
+            println!("{}: job {} had already exited", result_label, id);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser, Tree};
+
+/// Files larger than this are not parsed.
+pub(super) const MAX_PARSE_BYTES: u64 = 512 * 1024;
+
+/// Signatures longer than this are cut.
+const MAX_SIGNATURE_CHARS: usize = 120;
+
+/// What the code tools know about one language, in tree-sitter terms.
+pub(super) struct Grammar {
+    pub language: fn() -> Language,
+    /// Kinds listed as symbols.
+    pub items: &'static [&'static str],
+    /// Items whose body members are listed under them.
+    pub containers: &'static [&'static str],
+    /// Kinds that wrap an item, and the field holding it.
+    pub wrappers: &'static [(&'static str, &'static str)],
+    /// Query for definitions: `@name` in a node captured as `@function`,
+    /// `@type`, `@module`, `@constant`, or `@macro`.
+    pub definitions: &'static str,
+    /// Query for what implements or extends a type: `@name` for the type in
+    /// a node captured as `@impl`.
+    pub impls: &'static str,
+    /// Query for every identifier, captured as `@name`.
+    pub identifiers: &'static str,
+}
+
+pub(super) const RUST: Grammar = Grammar {
+    language: || tree_sitter_rust::LANGUAGE.into(),
+    items: &[
+        "function_item",
+        "function_signature_item",
+        "struct_item",
+        "enum_item",
+        "union_item",
+        "trait_item",
+        "impl_item",
+        "mod_item",
+        "type_item",
+        "const_item",
+        "static_item",
+        "macro_definition",
+    ],
+    containers: &["impl_item", "trait_item", "mod_item"],
+    wrappers: &[],
+    definitions: r#"
+        (function_item name: (identifier) @name) @function
+        (function_signature_item name: (identifier) @name) @function
+        (struct_item name: (type_identifier) @name) @type
+        (enum_item name: (type_identifier) @name) @type
+        (union_item name: (type_identifier) @name) @type
+        (trait_item name: (type_identifier) @name) @type
+        (type_item name: (type_identifier) @name) @type
+        (mod_item name: (identifier) @name) @module
+        (const_item name: (identifier) @name) @constant
+        (static_item name: (identifier) @name) @constant
+        (macro_definition name: (identifier) @name) @macro
+    "#,
+    impls: r#"
+        (impl_item type: (_) @name) @impl
+        (impl_item trait: (_) @name) @impl
+    "#,
+    identifiers: "[(identifier) (type_identifier) (field_identifier)] @name",
+};
+
+pub(super) const PYTHON: Grammar = Grammar {
+    language: || tree_sitter_python::LANGUAGE.into(),
+    items: &["function_definition", "class_definition"],
+    containers: &["class_definition"],
+    wrappers: &[("decorated_definition", "definition")],
+    definitions: r#"
+        (function_definition name: (identifier) @name) @function
+        (class_definition name: (identifier) @name) @type
+        (module (expression_statement (assignment left: (identifier) @name) @constant))
+    "#,
+    impls:
+        "(class_definition superclasses: (argument_list [(identifier) (attribute)] @name)) @impl",
+    identifiers: "(identifier) @name",
+};
+
+pub(super) const JAVASCRIPT: Grammar = Grammar {
+    language: || tree_sitter_javascript::LANGUAGE.into(),
+    items: &[
+        "function_declaration",
+        "generator_function_declaration",
+        "class_declaration",
+        "method_definition",
+        "lexical_declaration",
+        "variable_declaration",
+    ],
+    containers: &["class_declaration"],
+    wrappers: &[("export_statement", "declaration")],
+    definitions: r#"
+        (function_declaration name: (identifier) @name) @function
+        (generator_function_declaration name: (identifier) @name) @function
+        (method_definition name: (property_identifier) @name) @function
+        (class_declaration name: (identifier) @name) @type
+        (variable_declarator name: (identifier) @name) @constant
+    "#,
+    impls: "(class_declaration (class_heritage (_) @name)) @impl",
+    identifiers: "[(identifier) (property_identifier) (shorthand_property_identifier)] @name",
+};
+
+pub(super) const GO: Grammar = Grammar {
+    language: || tree_sitter_go::LANGUAGE.into(),
+    items: &[
+        "function_declaration",
+        "method_declaration",
+        "type_declaration",
+    ],
+    containers: &[],
+    wrappers: &[],
+    definitions: r#"
+        (function_declaration name: (identifier) @name) @function
+        (method_declaration name: (field_identifier) @name) @function
+        (type_spec name: (type_identifier) @name) @type
+        (const_spec name: (identifier) @name) @constant
+        (var_spec name: (identifier) @name) @constant
+    "#,
+    impls: "(method_declaration receiver: (parameter_list (parameter_declaration type: (_) @name))) @impl",
+    identifiers: "[(identifier) (type_identifier) (field_identifier)] @name",
+};
+
+pub(super) fn grammar(path: &Path) -> Option<&'static Grammar> {
+    match path.extension()?.to_str()? {
+        "rs" => Some(&RUST),
+        "py" | "pyi" => Some(&PYTHON),
+        "js" | "mjs" | "cjs" | "jsx" => Some(&JAVASCRIPT),
+        "go" => Some(&GO),
+        _ => None,
+    }
+}
+
+pub(super) fn parse(grammar: &Grammar, source: &[u8]) -> Result<Tree> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&(grammar.language)())
+        .context("Failed to load grammar")?;
+    parser.parse(source, None).context("Failed to parse")
+}
+
+/// The declaration of `node` without its body, on one line.
+pub(super) fn signature(node: Node, source: &[u8]) -> String {
+    let end = node
+        .child_by_field_name("body")
+        .map(|body| body.start_byte())
+        .unwrap_or(node.end_byte());
+    let text = String::from_utf8_lossy(&source[node.start_byte()..end]);
+    let head = if node.child_by_field_name("body").is_some() {
+        &text[..]
+    } else {
+        text.lines().next().unwrap_or_default()
+    };
+    // Rejoined, a wrapped parameter list reads `( a, b, )`.
+    let flat = head
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("( ", "(")
+        .replace(", )", ")")
+        .replace(" )", ")");
+    let flat = flat.trim_end_matches(['{', ':', '=', ';', ' ']);
+    if flat.chars().count() > MAX_SIGNATURE_CHARS {
+        format!(
+            "{}…",
+            flat.chars().take(MAX_SIGNATURE_CHARS).collect::<String>()
+        )
+    } else {
+        flat.to_string()
+    }
+}
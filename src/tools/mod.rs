@@ -1,15 +1,82 @@
 use anyhow::{anyhow, Context, Result};
+use futures::future::BoxFuture;
 use serde_json::Value;
+use std::sync::{Arc, RwLock};
 
 use llm::builder::{FunctionBuilder, LLMBuilder, ParamBuilder};
 use llm::ToolCall;
 
+use crate::config::Config;
+use crate::output;
+use crate::project::{self, ProjectType};
+
+mod apply_patch;
+mod approval;
+mod cargo;
+mod cert;
+mod cloud;
+mod code_search;
+mod convert;
 mod dir;
 mod fetch;
 mod file;
+mod format;
+mod jobs;
+mod languages;
+mod packages;
+mod plugin;
+mod ports;
+mod process;
+mod pty;
+mod quoting;
+mod remember;
+mod repeats;
+mod repo_map;
+mod results;
+mod sandbox;
+mod secret;
+mod seen;
 mod shell;
+mod syntax;
+mod terraform;
+mod transform;
+pub(crate) mod units;
+
+pub(crate) use approval::auto_approve_edits;
+pub(crate) use dir::{record_changes, recorded_changes, remember, restrict_writes};
+pub(crate) use packages::{missing_programs, Offer};
+pub(crate) use process::handle_interrupts;
+pub(crate) use repo_map::repo_map;
+pub(crate) use results::SCHEMA_VERSION;
+pub(crate) use syntax::UserShell;
+
+/// Tools that read or compute without changing anything or running commands
+/// the user has not been asked about; "read-only" in `[tools]` lists.
+const READ_ONLY: &[&str] = &[
+    "read_file",
+    "list_dir",
+    "stat",
+    "glob",
+    "grep",
+    "repo_map",
+    "code_search",
+    "process_status",
+    "process_logs",
+    "ports",
+    "inspect_cert",
+    "generate_secret",
+    "transform",
+    "convert",
+    "terraform_plan",
+];
+
+/// Whether `name` is in `list`, directly or through "read-only".
+fn listed(list: &[String], name: &str) -> bool {
+    list.iter()
+        .any(|entry| entry == name || (entry == "read-only" && READ_ONLY.contains(&name)))
+}
 
-pub trait Tool: Send + Sync {
+pub trait Tool: Send + Sync + 'static {
     fn name(&self) -> &'static str;
     fn description(&self) -> &'static str;
     fn required_params(&self) -> &'static [&'static str] {
@@ -33,7 +100,31 @@ pub trait Tool: Send + Sync {
         };
         builder.function(fb)
     }
-    fn execute_blocking(&self, args: Value) -> Result<Value>;
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let _ = args;
+        Err(anyhow!(
+            "{} does not support blocking execution",
+            self.name()
+        ))
+    }
+
+    /// Execute the tool. Tools doing their own async I/O override this; the
+    /// default runs `execute_blocking` on tokio's blocking pool. Dropping the
+    /// returned future cancels the call where the tool supports it.
+    fn execute(self: Arc<Self>, args: Value) -> BoxFuture<'static, Result<Value>> {
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || self.execute_blocking(args))
+                .await
+                .context("Tool task panicked")?
+        })
+    }
+
+    /// Check the arguments before the call is shown to the user. A returned
+    /// problem goes straight back to the model so it can correct the call.
+    fn preflight(&self, args: &Value) -> Option<String> {
+        let _ = args;
+        None
+    }
 
     /// Format and print the result of this tool execution.
     /// Default implementation prints JSON, tools can override for custom formatting.
@@ -42,15 +133,57 @@ pub trait Tool: Send + Sync {
         use nu_ansi_term::{Color as NuColor, Style};
         let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
         let pretty = serde_json::to_string_pretty(result).unwrap_or_else(|_| "{}".into());
-        println!("{}:\n{}", result_label, pretty);
+        crate::say!("{}:\n{}", result_label, pretty);
     }
 
     #[cfg(not(debug_assertions))]
     fn print_result(&self, _result: &Value) {}
 }
 
+/// Answers the questions tools ask, in place of the terminal.
+pub(crate) type Prompter = Box<dyn Fn(&str) -> Result<String> + Send + Sync>;
+
+/// Set while `tai tui` has the terminal and asks the questions itself.
+static PROMPTER: RwLock<Option<Prompter>> = RwLock::new(None);
+
+/// Send the questions [`ask_line`] gets to `prompter`, or back to the
+/// terminal with `None`.
+pub(crate) fn route_prompts(prompter: Option<Prompter>) {
+    *PROMPTER.write().unwrap_or_else(|e| e.into_inner()) = prompter;
+}
+
+/// Print a question and return the user's trimmed, lowercased answer.
+pub(crate) fn ask_user(question: &str) -> Result<String> {
+    Ok(ask_line(question)?.to_lowercase())
+}
+
+/// Print a question and return the user's answer trimmed but as typed.
+pub(crate) fn ask_line(question: &str) -> Result<String> {
+    if let Some(prompter) = PROMPTER.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        return Ok(prompter(question)?.trim().to_string());
+    }
+    if output::stdout_reserved() {
+        eprint!("{}", question);
+    } else {
+        print!("{}", question);
+        std::io::Write::flush(&mut std::io::stdout()).context("Failed to flush stdout")?;
+    }
+    let mut input = String::new();
+    std::io::stdin()
+        .read_line(&mut input)
+        .context("Failed to read user input")?;
+    Ok(input.trim().to_string())
+}
+
+/// Async variant of [`ask_user`] that does not block the runtime.
+pub(crate) async fn ask_user_async(question: String) -> Result<String> {
+    tokio::task::spawn_blocking(move || ask_user(&question))
+        .await
+        .context("Prompt task panicked")?
+}
+
 pub struct ToolsRegistry {
-    tools: Vec<Box<dyn Tool>>,
+    tools: Vec<Arc<dyn Tool>>,
 }
 
 impl Default for ToolsRegistry {
@@ -63,21 +196,80 @@ impl ToolsRegistry {
     pub fn new() -> Self {
         Self { tools: Vec::new() }
     }
-    pub fn with_default() -> Self {
+    pub fn with_default(cfg: &Config) -> Self {
+        if let Err(e) = dir::set_workspace_root(&cfg.workspace) {
+            eprintln!("Warning: {:#}; using the default workspace root", e);
+        }
         let mut reg = Self::new();
         reg.register(Box::new(file::ReadFileTool));
-        reg.register(Box::new(file::WriteFileTool));
-        reg.register(Box::new(file::PatchFileTool));
+        let formatters = format::Formatters::new(&cfg.conventions);
+        reg.register(Box::new(file::WriteFileTool(
+            cfg.policy.clone(),
+            formatters.clone(),
+        )));
+        reg.register(Box::new(file::PatchFileTool(
+            cfg.policy.clone(),
+            formatters,
+        )));
+        reg.register(Box::new(apply_patch::ApplyPatchTool(cfg.policy.clone())));
         reg.register(Box::new(dir::ListDirTool));
         reg.register(Box::new(dir::StatTool));
         reg.register(Box::new(dir::GlobTool));
         reg.register(Box::new(file::GrepTool));
-        reg.register(Box::new(shell::ShellCommandTool));
-        reg.register(Box::new(fetch::FetchUrlTool));
+        reg.register(Box::new(repo_map::RepoMapTool));
+        reg.register(Box::new(code_search::CodeSearchTool));
+        let jobs = Arc::new(jobs::Jobs::default());
+        reg.register(Box::new(shell::ShellCommandTool::new(
+            cfg.sandbox.clone(),
+            cfg.policy.clone(),
+            cfg.workspace.clone(),
+            jobs.clone(),
+            cfg.history.enabled != Some(false),
+        )));
+        reg.register(Box::new(jobs::ProcessStatusTool(jobs.clone())));
+        reg.register(Box::new(jobs::ProcessLogsTool(jobs.clone())));
+        reg.register(Box::new(jobs::ProcessStopTool(jobs)));
+        reg.register(Box::new(ports::PortsTool));
+        reg.register(Box::new(fetch::FetchUrlTool(cfg.network.clone())));
+        reg.register(Box::new(cert::InspectCertTool));
+        reg.register(Box::new(secret::GenerateSecretTool));
+        reg.register(Box::new(transform::TransformTool));
+        reg.register(Box::new(convert::ConvertTool));
+        reg.register(Box::new(remember::RememberTool));
+        if let Some(cloud) = cloud::CloudTool::detect() {
+            reg.register(Box::new(cloud));
+        }
+        for pt in project::detect_current(cfg) {
+            match pt {
+                ProjectType::Rust => reg.register(Box::new(cargo::CargoTool)),
+                ProjectType::Terraform => reg.register(Box::new(terraform::TerraformPlanTool)),
+                _ => {}
+            }
+        }
+        for tool in plugin::load(&cfg.tools) {
+            if reg.find(tool.name()).is_some() {
+                eprintln!(
+                    "Warning: Skipping plugin {}: a built-in tool has that name",
+                    tool.name()
+                );
+            } else {
+                reg.register(Box::new(tool));
+            }
+        }
+        let tools = &cfg.tools;
+        for name in tools.enabled.iter().chain(&tools.disabled).flatten() {
+            if name != "read-only" && reg.find(name).is_none() {
+                tracing::debug!(tool = %name, "[tools] names a tool that is not available here");
+            }
+        }
+        reg.tools.retain(|t| {
+            tools.enabled.as_ref().is_none_or(|l| listed(l, t.name()))
+                && !tools.disabled.as_ref().is_some_and(|l| listed(l, t.name()))
+        });
         reg
     }
     pub fn register(&mut self, tool: Box<dyn Tool>) {
-        self.tools.push(tool);
+        self.tools.push(Arc::from(tool));
     }
     pub fn apply_to_builder(&self, mut builder: LLMBuilder) -> LLMBuilder {
         for t in &self.tools {
@@ -85,22 +277,30 @@ impl ToolsRegistry {
         }
         builder
     }
-    pub fn find(&self, name: &str) -> Option<&dyn Tool> {
-        for t in &self.tools {
-            if t.name() == name {
-                return Some(t.as_ref());
-            }
-        }
-        None
+    /// Name and description of each registered tool.
+    pub fn describe(&self) -> Vec<(&'static str, &'static str)> {
+        self.tools
+            .iter()
+            .map(|t| (t.name(), t.description()))
+            .collect()
+    }
+    pub fn find(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.iter().find(|t| t.name() == name).cloned()
     }
-    pub fn handle_tool_call(&self, call: &ToolCall) -> Result<(Value, &dyn Tool)> {
+    pub fn preflight(&self, call: &ToolCall) -> Option<String> {
+        let args: Value = serde_json::from_str(&call.function.arguments).ok()?;
+        self.find(&call.function.name)?.preflight(&args)
+    }
+
+    pub async fn handle_tool_call(&self, call: &ToolCall) -> Result<(Value, Arc<dyn Tool>)> {
         let name = &call.function.name;
         let args: Value = serde_json::from_str(&call.function.arguments)
             .with_context(|| format!("Failed parsing tool args for {}", name))?;
         let tool = self
             .find(name)
             .ok_or_else(|| anyhow!("Unknown tool: {}", name))?;
-        let result = tool.execute_blocking(args)?;
+        let result = tool.clone().execute(args).await?;
+        results::validate(name, &result)?;
         Ok((result, tool))
     }
 }
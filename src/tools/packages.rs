@@ -0,0 +1,358 @@
+use anyhow::{Context, Result};
+use nu_ansi_term::{Color as NuColor, Style};
+use std::ffi::OsStr;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use super::{ask_user, quoting, results};
+use crate::{output, say};
+
+/// Package managers by the program that runs them, with their install
+/// command. The first one found is used, so native ones come before the
+/// ones that may sit next to them.
+const MANAGERS: &[(&str, &str)] = &[
+    ("apt-get", "sudo apt-get install -y {}"),
+    ("dnf", "sudo dnf install -y {}"),
+    ("yum", "sudo yum install -y {}"),
+    ("pacman", "sudo pacman -S --needed --noconfirm {}"),
+    ("zypper", "sudo zypper install -y {}"),
+    ("apk", "sudo apk add {}"),
+    ("xbps-install", "sudo xbps-install -y {}"),
+    ("brew", "brew install {}"),
+    ("port", "sudo port install {}"),
+    ("nix-env", "nix-env -iA nixpkgs.{}"),
+];
+
+/// Programs whose package is not named after them: the programs, the usual
+/// package, and the package managers that name it differently.
+type Package = (
+    &'static [&'static str],
+    &'static str,
+    &'static [(&'static str, &'static str)],
+);
+
+const PACKAGES: &[Package] = &[
+    (&["rg"], "ripgrep", &[]),
+    (&["fd"], "fd", &[("apt-get", "fd-find"), ("dnf", "fd-find")]),
+    (
+        &["ag"],
+        "the_silver_searcher",
+        &[("apt-get", "silversearcher-ag")],
+    ),
+    (&["http", "https"], "httpie", &[]),
+    (
+        &["convert", "magick", "identify"],
+        "imagemagick",
+        &[
+            ("dnf", "ImageMagick"),
+            ("yum", "ImageMagick"),
+            ("zypper", "ImageMagick"),
+        ],
+    ),
+    (&["node"], "nodejs", &[("brew", "node")]),
+    (
+        &["pip", "pip3"],
+        "python3-pip",
+        &[
+            ("pacman", "python-pip"),
+            ("apk", "py3-pip"),
+            ("brew", "python"),
+        ],
+    ),
+    (
+        &["python3"],
+        "python3",
+        &[("pacman", "python"), ("brew", "python")],
+    ),
+    (
+        &["nc"],
+        "netcat",
+        &[
+            ("apt-get", "netcat-openbsd"),
+            ("dnf", "nmap-ncat"),
+            ("pacman", "openbsd-netcat"),
+        ],
+    ),
+    (
+        &["dig", "nslookup", "host"],
+        "bind-utils",
+        &[
+            ("apt-get", "dnsutils"),
+            ("pacman", "bind"),
+            ("brew", "bind"),
+            ("apk", "bind-tools"),
+        ],
+    ),
+    (
+        &["ip", "ss"],
+        "iproute2",
+        &[("dnf", "iproute"), ("yum", "iproute")],
+    ),
+    (&["ifconfig", "netstat"], "net-tools", &[]),
+    (&["7z"], "p7zip", &[("apt-get", "p7zip-full")]),
+    (&["gpg"], "gnupg", &[("dnf", "gnupg2"), ("yum", "gnupg2")]),
+    (&["ffprobe"], "ffmpeg", &[]),
+    (
+        &["pdftotext", "pdfinfo"],
+        "poppler-utils",
+        &[("pacman", "poppler"), ("brew", "poppler")],
+    ),
+    (
+        &["psql"],
+        "postgresql",
+        &[
+            ("apt-get", "postgresql-client"),
+            ("apk", "postgresql-client"),
+            ("brew", "libpq"),
+        ],
+    ),
+    (&["redis-cli"], "redis", &[("apt-get", "redis-tools")]),
+    (&["docker"], "docker", &[("apt-get", "docker.io")]),
+    (&["aws"], "awscli", &[("pacman", "aws-cli")]),
+    (&["kubectl"], "kubectl", &[("brew", "kubernetes-cli")]),
+    (&["gh"], "gh", &[("pacman", "github-cli")]),
+    (
+        &["sqlite3"],
+        "sqlite3",
+        &[
+            ("dnf", "sqlite"),
+            ("yum", "sqlite"),
+            ("pacman", "sqlite"),
+            ("brew", "sqlite"),
+            ("apk", "sqlite"),
+        ],
+    ),
+    (
+        &["shellcheck"],
+        "shellcheck",
+        &[("dnf", "ShellCheck"), ("yum", "ShellCheck")],
+    ),
+];
+
+/// Shell builtins and keywords, which are never on PATH.
+const BUILTINS: &[&str] = &[
+    ".", ":", "[", "[[", "]]", "{", "}", "!", "alias", "bg", "break", "case", "cd", "command",
+    "continue", "declare", "do", "done", "echo", "elif", "else", "esac", "eval", "exec", "exit",
+    "export", "false", "fc", "fg", "fi", "for", "function", "getopts", "hash", "if", "in", "jobs",
+    "kill", "let", "local", "printf", "popd", "pushd", "pwd", "read", "readonly", "return",
+    "select", "set", "shift", "source", "test", "then", "time", "times", "trap", "true", "type",
+    "typeset", "ulimit", "umask", "unalias", "unset", "until", "wait", "while",
+];
+
+/// Installers, including language ones. A command that runs one may be
+/// installing what it uses next, so it is not checked.
+const INSTALLERS: &[&str] = &[
+    "apt",
+    "apt-get",
+    "dnf",
+    "yum",
+    "pacman",
+    "zypper",
+    "apk",
+    "xbps-install",
+    "brew",
+    "port",
+    "nix",
+    "nix-env",
+    "snap",
+    "flatpak",
+    "pip",
+    "pip3",
+    "pipx",
+    "uv",
+    "conda",
+    "mamba",
+    "npm",
+    "npx",
+    "yarn",
+    "pnpm",
+    "cargo",
+    "go",
+    "gem",
+    "winget",
+    "choco",
+    "scoop",
+];
+
+/// The package manager on this machine, as `(program, install command)`.
+fn manager() -> Option<(&'static str, &'static str)> {
+    static FOUND: OnceLock<Option<(&str, &str)>> = OnceLock::new();
+    *FOUND.get_or_init(|| {
+        let path = std::env::var_os("PATH")?;
+        MANAGERS
+            .iter()
+            .find(|(program, _)| on_path(program, &path))
+            .copied()
+    })
+}
+
+fn on_path(program: &str, path: &OsStr) -> bool {
+    std::env::split_paths(path).any(|dir| dir.join(program).is_file())
+}
+
+/// The package `manager` installs `program` from.
+fn package<'a>(program: &'a str, manager: &str) -> &'a str {
+    PACKAGES
+        .iter()
+        .find(|(programs, _, _)| programs.contains(&program))
+        .map(|(_, default, named)| {
+            named
+                .iter()
+                .find(|(m, _)| *m == manager)
+                .map_or(*default, |(_, name)| *name)
+        })
+        .unwrap_or(program)
+}
+
+/// What the user chose when a command needed programs that are missing.
+pub(crate) enum Offer {
+    Installed,
+    RunAnyway,
+    Declined,
+    /// The install command ran and failed, with its exit code.
+    Failed(Option<i32>),
+}
+
+/// Programs a command runs that are not installed, and the command that
+/// installs them with this machine's package manager, if it has one.
+pub(crate) struct Missing {
+    programs: Vec<String>,
+    install: Option<String>,
+}
+
+/// The programs `command` needs that are on neither `path` nor, without
+/// one, the PATH tai runs with. `None` when nothing is missing.
+pub(crate) fn missing_programs(command: &str, path: Option<&OsStr>) -> Option<Missing> {
+    if cfg!(target_os = "windows") {
+        return None;
+    }
+    let env_path = std::env::var_os("PATH").unwrap_or_default();
+    let path = path.unwrap_or(&env_path);
+    let words = quoting::program_words(command);
+    if words.iter().any(|w| INSTALLERS.contains(&w.as_str())) {
+        return None;
+    }
+    let mut programs: Vec<String> = Vec::new();
+    for word in words {
+        if !word.contains('/')
+            && !BUILTINS.contains(&word.as_str())
+            && !on_path(&word, path)
+            && !programs.contains(&word)
+        {
+            programs.push(word);
+        }
+    }
+    if programs.is_empty() {
+        return None;
+    }
+    let install = manager().map(|(name, template)| {
+        let mut packages: Vec<&str> = Vec::new();
+        for program in &programs {
+            let package = package(program, name);
+            if !packages.contains(&package) {
+                packages.push(package);
+            }
+        }
+        let command = template.replace("{}", &packages.join(" "));
+        if is_root() {
+            command.trim_start_matches("sudo ").to_string()
+        } else {
+            command
+        }
+    });
+    Some(Missing { programs, install })
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions.
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+impl Missing {
+    fn names(&self) -> String {
+        self.programs.join(", ")
+    }
+
+    fn verb(&self) -> &'static str {
+        if self.programs.len() == 1 {
+            "is"
+        } else {
+            "are"
+        }
+    }
+
+    fn declined(&self) -> Offer {
+        say!("Not installing {}; the command was not run", self.names());
+        Offer::Declined
+    }
+
+    /// Show what is missing and the command that installs it as a step of
+    /// its own, and run that command if the user approves. Its output goes
+    /// to the terminal, where `sudo` can ask for a password.
+    pub(crate) fn offer(&self) -> Result<Offer> {
+        say!(
+            "{}: {} {} not installed",
+            Style::new().fg(NuColor::Yellow).bold().paint("missing"),
+            self.names(),
+            self.verb()
+        );
+        let Some(install) = &self.install else {
+            say!("No supported package manager was found to install it with");
+            return Ok(match ask_user("Run the command anyway? [y/N] ")?.as_str() {
+                "y" | "yes" => Offer::RunAnyway,
+                _ => self.declined(),
+            });
+        };
+        say!(
+            "{}: {}",
+            Style::new().fg(NuColor::Cyan).bold().paint("install"),
+            install
+        );
+        match ask_user("Install first? [y/N/r=run without installing] ")?.as_str() {
+            "y" | "yes" => {}
+            "r" => return Ok(Offer::RunAnyway),
+            _ => return Ok(self.declined()),
+        }
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(install);
+        if output::stdout_reserved() {
+            cmd.stdout(Stdio::from(std::io::stderr()));
+        }
+        let status = cmd
+            .status()
+            .with_context(|| format!("Failed to run {}", install))?;
+        if !status.success() {
+            return Ok(Offer::Failed(status.code()));
+        }
+        Ok(Offer::Installed)
+    }
+
+    /// The tool result for a command that was not run because of what is
+    /// missing.
+    pub(super) fn not_run(&self, command: &str, offer: &Offer) -> results::RunShell {
+        let error = match offer {
+            Offer::Failed(code) => format!(
+                "Installing {} failed ({}), so the command was not run",
+                self.names(),
+                code.map_or_else(|| "killed".to_string(), |c| format!("exit {}", c))
+            ),
+            _ => format!(
+                "{} {} not installed and the user chose not to install it, so the command was not run",
+                self.names(),
+                self.verb()
+            ),
+        };
+        results::RunShell {
+            missing: Some(self.programs.clone()),
+            install_command: self.install.clone(),
+            error: Some(error),
+            ..results::RunShell::not_run(command)
+        }
+    }
+}
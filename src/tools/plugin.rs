@@ -0,0 +1,202 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+use std::fs;
+use std::process::Command;
+use std::time::Duration;
+
+use llm::builder::ParamBuilder;
+use llm::chat::ParameterProperty;
+
+use super::dir::{working_dir, workspace_root};
+use super::process::{run_with_input, tail};
+use super::{ask_user, Tool};
+use crate::config::{PluginConfig, ToolsConfig};
+use crate::net::expand_home;
+use crate::say;
+
+/// How much of a failing plugin's stderr goes into the error.
+const MAX_STDERR_CHARS: usize = 2_000;
+
+/// A tool declared in `[tools.plugins]`: an external program that reads
+/// the call's arguments as JSON on stdin and prints its result as JSON on
+/// stdout.
+pub struct PluginTool {
+    // Tool names and descriptions are `&'static str`; a plugin's live as
+    // long as the process, so they are leaked once when it is loaded.
+    name: &'static str,
+    description: &'static str,
+    required: &'static [&'static str],
+    properties: Vec<(String, Value)>,
+    command: String,
+    args: Vec<String>,
+    timeout: Duration,
+    confirm: bool,
+}
+
+/// The plugins in `cfg`. One that cannot be loaded is skipped with a warning
+/// rather than keeping tai from starting.
+pub(super) fn load(cfg: &ToolsConfig) -> Vec<PluginTool> {
+    let Some(plugins) = &cfg.plugins else {
+        return Vec::new();
+    };
+    plugins
+        .iter()
+        .filter_map(|(name, plugin)| match PluginTool::new(name, plugin) {
+            Ok(tool) => Some(tool),
+            Err(e) => {
+                eprintln!("Warning: Skipping plugin {}: {:#}", name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The schema as configured: inline, or the path of a JSON file.
+fn read_schema(schema: Option<&Value>) -> Result<Value> {
+    match schema {
+        None => Ok(json!({ "type": "object", "properties": {} })),
+        Some(Value::String(path)) => {
+            let path = expand_home(path);
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read schema {}", path.display()))?;
+            serde_json::from_str(&text)
+                .with_context(|| format!("Invalid JSON in schema {}", path.display()))
+        }
+        Some(schema @ Value::Object(_)) => Ok(schema.clone()),
+        Some(_) => bail!("schema must be a path or a table"),
+    }
+}
+
+/// A JSON schema property as far as tool definitions carry it: its type,
+/// description, item type, and allowed values.
+fn property(schema: &Value) -> ParameterProperty {
+    ParameterProperty {
+        property_type: schema["type"].as_str().unwrap_or("string").to_string(),
+        description: schema["description"].as_str().unwrap_or("").to_string(),
+        items: schema.get("items").map(|items| Box::new(property(items))),
+        enum_list: enum_values(schema),
+    }
+}
+
+fn enum_values(schema: &Value) -> Option<Vec<String>> {
+    let values = schema.get("enum")?.as_array()?;
+    Some(
+        values
+            .iter()
+            .map(|v| v.as_str().map_or_else(|| v.to_string(), str::to_string))
+            .collect(),
+    )
+}
+
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+impl PluginTool {
+    fn new(name: &str, cfg: &PluginConfig) -> Result<Self> {
+        if name.is_empty()
+            || name.len() > 64
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            bail!("tool names may only use letters, digits, '_' and '-'");
+        }
+        let command = cfg.command.clone().context("command is not set")?;
+        let schema = read_schema(cfg.schema.as_ref())?;
+        let properties = match schema.get("properties") {
+            Some(Value::Object(map)) => map.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            Some(_) => bail!("schema properties must be an object"),
+            None => Vec::new(),
+        };
+        let required: Vec<&'static str> = schema["required"]
+            .as_array()
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|n| n.as_str())
+                    .map(|n| leak(n.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            name: leak(name.to_string()),
+            description: leak(
+                cfg.description
+                    .clone()
+                    .unwrap_or_else(|| format!("Run the {} plugin", name)),
+            ),
+            required: Box::leak(required.into_boxed_slice()),
+            properties,
+            command,
+            args: cfg.args.clone().unwrap_or_default(),
+            timeout: Duration::from_secs(cfg.timeout_sec.unwrap_or(60)),
+            confirm: cfg.confirm.unwrap_or(true),
+        })
+    }
+}
+
+impl Tool for PluginTool {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn description(&self) -> &'static str {
+        self.description
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        self.required
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        self.properties
+            .iter()
+            .map(|(name, schema)| {
+                let p = property(schema);
+                let mut builder = ParamBuilder::new(name)
+                    .type_of(p.property_type)
+                    .description(p.description);
+                if let Some(items) = p.items {
+                    builder = builder.items(*items);
+                }
+                if let Some(values) = p.enum_list {
+                    builder = builder.enum_values(values);
+                }
+                builder
+            })
+            .collect()
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        if self.confirm {
+            let choice = ask_user(&format!(
+                "Run plugin {} ({})? [Y/n] ",
+                self.name, self.command
+            ))?;
+            if choice == "n" {
+                say!("Plugin not run");
+                return Ok(json!({ "executed": false }));
+            }
+        }
+        let mut cmd = Command::new(expand_home(&self.command));
+        cmd.args(&self.args)
+            .current_dir(working_dir(&workspace_root()?));
+        let captured = run_with_input(cmd, Some(serde_json::to_vec(&args)?), self.timeout)
+            .with_context(|| format!("Failed to run plugin {}", self.name))?;
+        let Some(status) = captured.status else {
+            bail!("{} timed out after {}s", self.name, self.timeout.as_secs());
+        };
+        if !status.success() {
+            let (stderr, _) = tail(captured.stderr.trim(), MAX_STDERR_CHARS);
+            bail!(
+                "{} failed ({}): {}",
+                self.name,
+                status
+                    .code()
+                    .map_or_else(|| "killed".to_string(), |c| format!("exit {}", c)),
+                stderr
+            );
+        }
+        serde_json::from_str(captured.stdout.trim()).map_err(|e| {
+            let (output, _) = tail(captured.stdout.trim(), 200);
+            anyhow!("{} printed invalid JSON ({}): {}", self.name, e, output)
+        })
+    }
+}
@@ -0,0 +1,302 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+use llm::builder::ParamBuilder;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+
+use super::{results, Tool};
+
+/// Lists listening sockets and the processes that own them, read from the OS
+/// rather than by running and parsing `lsof`/`ss` in the shell.
+pub struct PortsTool;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Socket {
+    port: u16,
+    protocol: &'static str,
+    address: String,
+    pid: Option<u32>,
+}
+
+#[cfg(target_os = "linux")]
+mod os {
+    use super::Socket;
+    use anyhow::Result;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const TCP_LISTEN: &str = "0A";
+    const UDP_UNCONNECTED: &str = "07";
+
+    fn parse_addr(hex: &str) -> Option<(String, u16)> {
+        let (ip, port) = hex.split_once(':')?;
+        let port = u16::from_str_radix(port, 16).ok()?;
+        // The kernel prints each 32-bit word in host byte order.
+        let words: Vec<u32> = (0..ip.len() / 8)
+            .map(|i| u32::from_str_radix(&ip[i * 8..i * 8 + 8], 16))
+            .collect::<Result<_, _>>()
+            .ok()?;
+        let ip = match words.as_slice() {
+            [w] => Ipv4Addr::from(w.to_le_bytes()).to_string(),
+            [a, b, c, d] => {
+                let mut octets = [0u8; 16];
+                for (i, w) in [a, b, c, d].into_iter().enumerate() {
+                    octets[i * 4..i * 4 + 4].copy_from_slice(&w.to_le_bytes());
+                }
+                Ipv6Addr::from(octets).to_string()
+            }
+            _ => return None,
+        };
+        Some((ip, port))
+    }
+
+    /// Socket inode to pid, for the processes whose fds we may read.
+    fn socket_owners() -> HashMap<u64, u32> {
+        let mut owners = HashMap::new();
+        let Ok(procs) = fs::read_dir("/proc") else {
+            return owners;
+        };
+        for entry in procs.flatten() {
+            let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+                continue;
+            };
+            let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+                continue;
+            };
+            for fd in fds.flatten() {
+                let Ok(target) = fs::read_link(fd.path()) else {
+                    continue;
+                };
+                let target = target.to_string_lossy();
+                if let Some(inode) = target
+                    .strip_prefix("socket:[")
+                    .and_then(|s| s.strip_suffix(']'))
+                    .and_then(|s| s.parse().ok())
+                {
+                    owners.entry(inode).or_insert(pid);
+                }
+            }
+        }
+        owners
+    }
+
+    pub(super) fn sockets() -> Result<Vec<Socket>> {
+        let owners = socket_owners();
+        let mut out = Vec::new();
+        for (file, protocol, state) in [
+            ("tcp", "tcp", TCP_LISTEN),
+            ("tcp6", "tcp", TCP_LISTEN),
+            ("udp", "udp", UDP_UNCONNECTED),
+            ("udp6", "udp", UDP_UNCONNECTED),
+        ] {
+            let Ok(table) = fs::read_to_string(format!("/proc/net/{}", file)) else {
+                continue;
+            };
+            for line in table.lines().skip(1) {
+                let cols: Vec<&str> = line.split_whitespace().collect();
+                if cols.len() < 10 || cols[3] != state {
+                    continue;
+                }
+                let Some((address, port)) = parse_addr(cols[1]) else {
+                    continue;
+                };
+                let inode: u64 = cols[9].parse().unwrap_or(0);
+                out.push(Socket {
+                    port,
+                    protocol,
+                    address,
+                    pid: owners.get(&inode).copied(),
+                });
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn split_addr(s: &str) -> Option<(String, u16)> {
+    let (ip, port) = s.rsplit_once(':')?;
+    let ip = ip.trim_start_matches('[').trim_end_matches(']');
+    Some((ip.replace('*', "0.0.0.0"), port.parse().ok()?))
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+mod os {
+    use super::{split_addr, Socket};
+    use anyhow::{Context, Result};
+    use std::process::Command;
+
+    /// Parses `lsof -F` output: a `p` line per process, then `P`/`n` lines
+    /// per socket.
+    pub(super) fn sockets() -> Result<Vec<Socket>> {
+        let out = Command::new("lsof")
+            .args(["-nP", "-iTCP", "-sTCP:LISTEN", "-iUDP", "-FpPn"])
+            .output()
+            .context("Failed to run lsof")?;
+        let text = String::from_utf8_lossy(&out.stdout);
+        let mut sockets = Vec::new();
+        let mut pid = None;
+        let mut protocol = "tcp";
+        for line in text.lines() {
+            let (tag, value) = line.split_at(line.len().min(1));
+            match tag {
+                "p" => pid = value.parse().ok(),
+                "P" => protocol = if value == "UDP" { "udp" } else { "tcp" },
+                "n" if !value.contains("->") => {
+                    if let Some((address, port)) = split_addr(value) {
+                        sockets.push(Socket {
+                            port,
+                            protocol,
+                            address,
+                            pid,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(sockets)
+    }
+}
+
+#[cfg(windows)]
+mod os {
+    use super::{split_addr, Socket};
+    use anyhow::{Context, Result};
+    use std::process::Command;
+
+    pub(super) fn sockets() -> Result<Vec<Socket>> {
+        let out = Command::new("netstat")
+            .arg("-ano")
+            .output()
+            .context("Failed to run netstat")?;
+        let text = String::from_utf8_lossy(&out.stdout);
+        let mut sockets = Vec::new();
+        for line in text.lines() {
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            let (protocol, pid) = match cols.as_slice() {
+                ["TCP", _, _, "LISTENING", pid] => ("tcp", pid),
+                ["UDP", _, _, pid] => ("udp", pid),
+                _ => continue,
+            };
+            if let Some((address, port)) = split_addr(cols[1]) {
+                sockets.push(Socket {
+                    port,
+                    protocol,
+                    address,
+                    pid: pid.parse().ok(),
+                });
+            }
+        }
+        Ok(sockets)
+    }
+}
+
+impl Tool for PortsTool {
+    fn name(&self) -> &'static str {
+        "ports"
+    }
+    fn description(&self) -> &'static str {
+        "List listening TCP ports and bound UDP sockets with the owning process (pid, name, command line). Use this to answer what is running on a port instead of lsof, netstat, or ss."
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("port")
+                .type_of("integer")
+                .description("Only report sockets on this port"),
+            ParamBuilder::new("protocol")
+                .type_of("string")
+                .description("tcp, udp, or all (default all)"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let port = args.get("port").and_then(|v| v.as_u64());
+        let protocol = args
+            .get("protocol")
+            .and_then(|v| v.as_str())
+            .unwrap_or("all")
+            .to_ascii_lowercase();
+        if !matches!(protocol.as_str(), "tcp" | "udp" | "all") {
+            return Err(anyhow!("Unknown protocol '{}'", protocol));
+        }
+        let sockets: BTreeSet<Socket> = os::sockets()?
+            .into_iter()
+            .filter(|s| port.is_none_or(|p| u64::from(s.port) == p))
+            .filter(|s| protocol == "all" || s.protocol == protocol)
+            .collect();
+
+        let mut sys = System::new();
+        sys.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::nothing().with_cmd(UpdateKind::OnlyIfNotSet),
+        );
+        let hidden = sockets.iter().any(|s| s.pid.is_none());
+        let list: Vec<results::Listener> = sockets
+            .iter()
+            .map(|s| {
+                let process = s.pid.and_then(|pid| sys.process(Pid::from_u32(pid)));
+                results::Listener {
+                    protocol: s.protocol.to_string(),
+                    address: s.address.clone(),
+                    port: s.port,
+                    pid: s.pid,
+                    process: process.map(|p| p.name().to_string_lossy().to_string()),
+                    command: process.map(|p| {
+                        p.cmd()
+                            .iter()
+                            .map(|a| a.to_string_lossy())
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    }),
+                }
+            })
+            .collect();
+        results::to_value(results::Ports {
+            count: list.len(),
+            sockets: list,
+            note: hidden.then(|| {
+                "Some sockets belong to processes of other users; run tai with elevated privileges to see their owners".to_string()
+            }),
+        })
+    }
+
+    fn print_result(&self, result: &Value) {
+        use nu_ansi_term::{Color as NuColor, Style};
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let sockets = result
+            .get("sockets")
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default();
+        if sockets.is_empty() {
+            println!("{}: no listening sockets", result_label);
+            return;
+        }
+        println!("{}:", result_label);
+        for s in sockets {
+            let owner = match (s["pid"].as_u64(), s["process"].as_str()) {
+                (Some(pid), Some(name)) => format!("{} ({})", name, pid),
+                (Some(pid), None) => pid.to_string(),
+                _ => Style::new().dimmed().paint("unknown").to_string(),
+            };
+            let address = s["address"].as_str().unwrap_or("");
+            let endpoint = if address.contains(':') {
+                format!("[{}]:{}", address, s["port"])
+            } else {
+                format!("{}:{}", address, s["port"])
+            };
+            println!(
+                "  {:<4} {:>22}  {}",
+                s["protocol"].as_str().unwrap_or(""),
+                endpoint,
+                owner
+            );
+        }
+        if let Some(note) = result.get("note").and_then(|v| v.as_str()) {
+            println!("  {}", NuColor::Yellow.paint(note));
+        }
+    }
+}
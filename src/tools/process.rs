@@ -0,0 +1,475 @@
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+pub(super) struct Captured {
+    /// `None` when the process was killed after the timeout.
+    pub status: Option<ExitStatus>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Processes killed when the timeout hit, as `pid name`.
+    pub killed: Vec<String>,
+}
+
+/// Run a non-interactive process, draining stdout/stderr on background threads
+/// so large outputs cannot fill the pipes and stall the child.
+pub(super) fn run_captured(cmd: Command, timeout: Duration) -> Result<Captured> {
+    run_with_input(cmd, None, timeout)
+}
+
+/// [`run_captured`], with `input` written to the process's stdin and then
+/// closed.
+pub(super) fn run_with_input(
+    mut cmd: Command,
+    input: Option<Vec<u8>>,
+    timeout: Duration,
+) -> Result<Captured> {
+    new_group(&mut cmd);
+    let mut child = cmd
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {:?}", cmd.get_program()))?;
+    let mut tree = ProcessTree::new(child.id(), &Limits::default());
+    let mut stdout = child.stdout.take().context("stdout unavailable")?;
+    let mut stderr = child.stderr.take().context("stderr unavailable")?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        // A child that exits without reading makes the write fail; its
+        // status says more than the broken pipe would.
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+    }
+    let out_reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stdout.read_to_end(&mut buf);
+        buf
+    });
+    let err_reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf);
+        buf
+    });
+
+    let start = Instant::now();
+    let mut killed = Vec::new();
+    let status = loop {
+        if let Some(status) = child.try_wait().context("wait failed")? {
+            tree.release();
+            break Some(status);
+        }
+        if start.elapsed() >= timeout {
+            killed = tree.kill();
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    let stdout = out_reader.join().unwrap_or_default();
+    let stderr = err_reader.join().unwrap_or_default();
+    Ok(Captured {
+        status,
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        killed,
+    })
+}
+
+/// Start `cmd` in its own process group on Unix, so that [`ProcessTree`] can
+/// kill everything it spawns rather than just the direct child. On Windows
+/// the grouping happens after spawn, via a job object.
+pub(super) fn new_group(cmd: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+        unix::forward_signals();
+    }
+    #[cfg(not(unix))]
+    let _ = cmd;
+}
+
+/// The chat session handles Ctrl-C itself by cancelling the running step,
+/// which kills the command's tree; stop forwarding SIGINT and dying on it.
+/// Must be called before the first command starts.
+pub(crate) fn handle_interrupts() {
+    #[cfg(unix)]
+    unix::SIGINT_HANDLED.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Resource caps for a spawned command; `None` is unlimited.
+#[derive(Clone, Copy, Default)]
+pub(super) struct Limits {
+    pub cpu_seconds: Option<u64>,
+    pub memory_bytes: Option<u64>,
+    /// New processes allowed on top of those the user already runs.
+    pub processes: Option<u64>,
+}
+
+impl Limits {
+    pub(super) fn is_empty(&self) -> bool {
+        self.cpu_seconds.is_none() && self.memory_bytes.is_none() && self.processes.is_none()
+    }
+}
+
+/// Apply `limits` to `cmd` as rlimits set in the child before exec. On
+/// Windows they are set on the job object by [`ProcessTree::new`] instead.
+pub(super) fn apply_limits(cmd: &mut Command, limits: &Limits) {
+    #[cfg(unix)]
+    if !limits.is_empty() {
+        unix::set_rlimits(cmd, limits);
+    }
+    #[cfg(not(unix))]
+    let _ = (cmd, limits);
+}
+
+/// A spawned command together with everything it started. Killed as a whole
+/// on timeout, or when dropped before [`ProcessTree::release`]; a command
+/// that exits normally keeps any background jobs it launched.
+pub(super) struct ProcessTree {
+    pid: u32,
+    live: bool,
+    #[cfg(unix)]
+    slot: Option<usize>,
+    #[cfg(windows)]
+    job: Option<windows::Job>,
+}
+
+impl ProcessTree {
+    pub(super) fn new(pid: u32, limits: &Limits) -> Self {
+        let _ = limits;
+        Self {
+            pid,
+            live: pid != 0,
+            #[cfg(unix)]
+            slot: unix::register(pid),
+            #[cfg(windows)]
+            job: windows::Job::attach(pid, limits),
+        }
+    }
+
+    /// Kill every process in the tree. Returns `pid name` for each one.
+    pub(super) fn kill(&mut self) -> Vec<String> {
+        if !self.live {
+            return Vec::new();
+        }
+        self.live = false;
+        #[cfg(unix)]
+        let killed = unix::kill_group(self.pid);
+        #[cfg(windows)]
+        let killed = match &self.job {
+            Some(job) => job.terminate(),
+            None => Vec::new(),
+        };
+        #[cfg(not(any(unix, windows)))]
+        let killed = Vec::new();
+        self.release();
+        killed
+    }
+
+    /// The command exited on its own; stop tracking it.
+    pub(super) fn release(&mut self) {
+        self.live = false;
+        #[cfg(unix)]
+        if let Some(slot) = self.slot.take() {
+            unix::unregister(slot);
+        }
+        #[cfg(windows)]
+        self.job.take();
+    }
+}
+
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::process::Command;
+    use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+    use std::sync::Once;
+
+    /// Process groups of running commands. Their own group keeps them from
+    /// seeing the terminal's Ctrl-C, so the handler below forwards it.
+    static GROUPS: [AtomicI32; 16] = [const { AtomicI32::new(0) }; 16];
+
+    extern "C" fn forward(sig: libc::c_int) {
+        for group in &GROUPS {
+            let pgid = group.load(Ordering::Relaxed);
+            if pgid > 0 {
+                // SAFETY: killpg, signal, and raise are async-signal-safe.
+                unsafe { libc::killpg(pgid, sig) };
+            }
+        }
+        unsafe {
+            libc::signal(sig, libc::SIG_DFL);
+            libc::raise(sig);
+        }
+    }
+
+    pub(super) static SIGINT_HANDLED: AtomicBool = AtomicBool::new(false);
+
+    /// Pass SIGINT, SIGTERM, and SIGHUP on to running commands, then die as
+    /// before. Signals that were ignored (e.g. under nohup) stay ignored.
+    pub(super) fn forward_signals() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            for sig in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+                if sig == libc::SIGINT && SIGINT_HANDLED.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let handler = forward as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                // SAFETY: installs a handler that only calls async-signal-safe functions.
+                unsafe {
+                    if libc::signal(sig, handler) == libc::SIG_IGN {
+                        libc::signal(sig, libc::SIG_IGN);
+                    }
+                }
+            }
+        });
+    }
+
+    pub(super) fn register(pid: u32) -> Option<usize> {
+        let pgid = i32::try_from(pid).ok().filter(|p| *p > 0)?;
+        GROUPS.iter().position(|g| {
+            g.compare_exchange(0, pgid, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        })
+    }
+
+    pub(super) fn unregister(slot: usize) {
+        GROUPS[slot].store(0, Ordering::Relaxed);
+    }
+
+    /// Members of process group `pgid` as `pid name`.
+    fn members(pgid: u32) -> Vec<String> {
+        let Ok(out) = Command::new("ps")
+            .args(["-A", "-o", "pid=", "-o", "pgid=", "-o", "comm="])
+            .output()
+        else {
+            return Vec::new();
+        };
+        String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let pid = fields.next()?;
+                let group: u32 = fields.next()?.parse().ok()?;
+                let name = fields.collect::<Vec<_>>().join(" ");
+                (group == pgid).then(|| format!("{} {}", pid, name))
+            })
+            .collect()
+    }
+
+    /// Processes currently owned by the user, so the process limit counts
+    /// only what the command itself starts.
+    fn user_processes() -> u64 {
+        // SAFETY: getuid cannot fail.
+        let uid = unsafe { libc::getuid() };
+        Command::new("ps")
+            .args(["-U", &uid.to_string(), "-o", "pid="])
+            .output()
+            .map(|out| String::from_utf8_lossy(&out.stdout).lines().count() as u64)
+            .unwrap_or(0)
+    }
+
+    pub(super) fn set_rlimits(cmd: &mut Command, limits: &super::Limits) {
+        use std::os::unix::process::CommandExt;
+        let cpu = limits.cpu_seconds;
+        let memory = limits.memory_bytes;
+        let processes = limits.processes.map(|n| user_processes() + n);
+        let apply = move || {
+            let set = |resource, value: Option<u64>| -> std::io::Result<()> {
+                let Some(value) = value else {
+                    return Ok(());
+                };
+                let mut current = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                // SAFETY: plain syscalls on a valid rlimit struct.
+                unsafe {
+                    if libc::getrlimit(resource, &mut current) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    // Lowering the hard limit too keeps the command from
+                    // raising it again with `ulimit`.
+                    let value = (value as libc::rlim_t).min(current.rlim_max);
+                    let limit = libc::rlimit {
+                        rlim_cur: value,
+                        rlim_max: value,
+                    };
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            };
+            set(libc::RLIMIT_CPU, cpu)?;
+            set(libc::RLIMIT_AS, memory)?;
+            set(libc::RLIMIT_NPROC, processes)?;
+            Ok(())
+        };
+        // SAFETY: the closure only calls getrlimit/setrlimit, which are
+        // async-signal-safe, and does not allocate.
+        unsafe { cmd.pre_exec(apply) };
+    }
+
+    pub(super) fn kill_group(pgid: u32) -> Vec<String> {
+        let Ok(id) = i32::try_from(pgid) else {
+            return Vec::new();
+        };
+        let killed = members(pgid);
+        // SAFETY: plain syscall; the group leader has not been reaped yet, so
+        // the id still refers to our command's group.
+        unsafe { libc::killpg(id, libc::SIGKILL) };
+        killed
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectBasicProcessIdList,
+        JobObjectExtendedLimitInformation, QueryInformationJobObject, SetInformationJobObject,
+        TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_ACTIVE_PROCESS,
+        JOB_OBJECT_LIMIT_JOB_MEMORY, JOB_OBJECT_LIMIT_PROCESS_TIME,
+    };
+
+    use super::Limits;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, PROCESS_SET_QUOTA, PROCESS_TERMINATE,
+    };
+
+    /// Job object holding the command; children join it automatically.
+    /// Stored as an integer so the tree stays `Send`.
+    pub(super) struct Job(isize);
+
+    /// `JOBOBJECT_BASIC_PROCESS_ID_LIST` with room for more than one id.
+    #[repr(C)]
+    struct ProcessIds {
+        assigned: u32,
+        listed: u32,
+        ids: [usize; 256],
+    }
+
+    impl Job {
+        pub(super) fn attach(pid: u32, limits: &Limits) -> Option<Self> {
+            // SAFETY: every handle is checked before use and closed once.
+            unsafe {
+                let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+                if job.is_null() {
+                    return None;
+                }
+                let process = OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, 0, pid);
+                let assigned = !process.is_null() && AssignProcessToJobObject(job, process) != 0;
+                if !process.is_null() {
+                    CloseHandle(process);
+                }
+                if !assigned {
+                    CloseHandle(job);
+                    return None;
+                }
+                let job = Job(job as isize);
+                if !limits.is_empty() {
+                    job.set_limits(limits);
+                }
+                Some(job)
+            }
+        }
+
+        fn set_limits(&self, limits: &Limits) {
+            // SAFETY: the struct is plain data, so all-zero is a valid value,
+            // and it is passed with its exact size.
+            unsafe {
+                let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+                let basic = &mut info.BasicLimitInformation;
+                if let Some(secs) = limits.cpu_seconds {
+                    // In 100ns units.
+                    basic.PerProcessUserTimeLimit = (secs as i64).saturating_mul(10_000_000);
+                    basic.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+                }
+                if let Some(n) = limits.processes {
+                    // The job starts with the command itself.
+                    basic.ActiveProcessLimit = u32::try_from(n + 1).unwrap_or(u32::MAX);
+                    basic.LimitFlags |= JOB_OBJECT_LIMIT_ACTIVE_PROCESS;
+                }
+                if let Some(bytes) = limits.memory_bytes {
+                    info.JobMemoryLimit = usize::try_from(bytes).unwrap_or(usize::MAX);
+                    info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_MEMORY;
+                }
+                SetInformationJobObject(
+                    self.handle(),
+                    JobObjectExtendedLimitInformation,
+                    &info as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION as *const _,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                );
+            }
+        }
+
+        fn handle(&self) -> HANDLE {
+            self.0 as HANDLE
+        }
+
+        pub(super) fn terminate(&self) -> Vec<String> {
+            let mut list = ProcessIds {
+                assigned: 0,
+                listed: 0,
+                ids: [0; 256],
+            };
+            // SAFETY: the buffer is a valid, correctly sized id list.
+            unsafe {
+                let ok = QueryInformationJobObject(
+                    self.handle(),
+                    JobObjectBasicProcessIdList,
+                    &mut list as *mut ProcessIds as *mut _,
+                    std::mem::size_of::<ProcessIds>() as u32,
+                    std::ptr::null_mut(),
+                );
+                TerminateJobObject(self.handle(), 1);
+                if ok == 0 {
+                    return Vec::new();
+                }
+            }
+            list.ids[..(list.listed as usize).min(list.ids.len())]
+                .iter()
+                .map(|pid| pid.to_string())
+                .collect()
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the handle came from CreateJobObjectW and is closed once.
+            unsafe { CloseHandle(self.handle()) };
+        }
+    }
+}
+
+/// Keep the last `max` bytes of `s` (on a char boundary).
+pub(super) fn tail(s: &str, max: usize) -> (String, bool) {
+    if s.len() <= max {
+        return (s.to_string(), false);
+    }
+    let mut start = s.len() - max;
+    while !s.is_char_boundary(start) {
+        start += 1;
+    }
+    (s[start..].to_string(), true)
+}
+
+pub(super) fn in_path(bin: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(bin).is_file()))
+        .unwrap_or(false)
+}
@@ -0,0 +1,244 @@
+use anyhow::Result;
+use std::process::{Command, ExitStatus};
+
+use super::process::Limits;
+
+/// How much of an interactive session is kept for the tool result.
+const TRANSCRIPT_BYTES: usize = 8 * 1024;
+
+/// What an interactive command left behind.
+pub(super) struct Session {
+    pub status: ExitStatus,
+    /// The end of what it printed, without terminal escape codes.
+    pub transcript: String,
+    pub truncated: bool,
+}
+
+/// Whether commands can be attached to the user's terminal: on Unix, with
+/// stdin and stdout both a terminal that output is not reserved on.
+pub(super) fn available() -> bool {
+    use std::io::IsTerminal;
+    cfg!(unix)
+        && std::io::stdin().is_terminal()
+        && std::io::stdout().is_terminal()
+        && !crate::output::stdout_reserved()
+}
+
+/// Run `cmd` on a pseudo-terminal attached to the user's terminal until it
+/// exits, with the terminal in raw mode so keys, Ctrl-C included, go to the
+/// command. There is no timeout; the user ends the command.
+pub(super) fn run(cmd: Command, limits: &Limits) -> Result<Session> {
+    #[cfg(unix)]
+    return unix::run(cmd, limits);
+    #[cfg(not(unix))]
+    {
+        let _ = (cmd, limits);
+        anyhow::bail!("Interactive commands are only supported on Unix")
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use anyhow::{Context, Result};
+    use std::io::Write;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    use super::super::process::{Limits, ProcessTree};
+    use super::{Session, TRANSCRIPT_BYTES};
+    use crate::output::printable;
+
+    /// How often the loop looks for an exited command or a resized terminal.
+    const TICK: Duration = Duration::from_millis(100);
+
+    fn check(ret: libc::c_int, what: &str) -> Result<libc::c_int> {
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error()).context(what.to_string());
+        }
+        Ok(ret)
+    }
+
+    fn window_size(fd: RawFd) -> Option<libc::winsize> {
+        // SAFETY: TIOCGWINSZ fills the winsize it is given.
+        unsafe {
+            let mut size: libc::winsize = std::mem::zeroed();
+            (libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) == 0).then_some(size)
+        }
+    }
+
+    /// The user's terminal in raw mode; its settings come back on drop.
+    struct RawMode(libc::termios);
+
+    impl RawMode {
+        fn enable() -> Result<Self> {
+            // SAFETY: tcgetattr and tcsetattr on stdin with valid termios.
+            unsafe {
+                let mut saved: libc::termios = std::mem::zeroed();
+                check(
+                    libc::tcgetattr(libc::STDIN_FILENO, &mut saved),
+                    "Failed to read the terminal settings",
+                )?;
+                let mut raw = saved;
+                libc::cfmakeraw(&mut raw);
+                check(
+                    libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw),
+                    "Failed to put the terminal in raw mode",
+                )?;
+                Ok(Self(saved))
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: restores the settings read in `enable`.
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+        }
+    }
+
+    fn read(fd: RawFd, buf: &mut [u8]) -> isize {
+        // SAFETY: reads into a buffer of the given length.
+        unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) }
+    }
+
+    fn write_all(fd: RawFd, mut data: &[u8]) {
+        while !data.is_empty() {
+            // SAFETY: writes from a buffer of the given length.
+            let n = unsafe { libc::write(fd, data.as_ptr().cast(), data.len()) };
+            if n <= 0 {
+                return;
+            }
+            data = &data[n as usize..];
+        }
+    }
+
+    pub(super) fn run(mut cmd: Command, limits: &Limits) -> Result<Session> {
+        let (mut master, mut slave) = (0, 0);
+        let mut size = window_size(libc::STDOUT_FILENO);
+        // SAFETY: openpty fills both descriptors; the name is not asked for.
+        check(
+            unsafe {
+                libc::openpty(
+                    &mut master,
+                    &mut slave,
+                    std::ptr::null_mut(),
+                    std::ptr::null(),
+                    size.as_ref()
+                        .map_or(std::ptr::null(), |s| s as *const libc::winsize),
+                )
+            },
+            "Failed to open a pseudo-terminal",
+        )?;
+        // SAFETY: both descriptors were just opened and are owned here.
+        let (master, slave) =
+            unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        cmd.stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // A session of its own makes the pseudo-terminal its controlling
+        // terminal, so Ctrl-C and job control reach it; it also makes the
+        // command a group leader, as ProcessTree expects.
+        // SAFETY: setsid and ioctl are async-signal-safe.
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut child = cmd.spawn().context("Failed to execute command")?;
+        // The parent's copies of the slave must close, or reading the master
+        // never ends.
+        drop(cmd);
+        let mut tree = ProcessTree::new(child.id(), limits);
+
+        let raw = RawMode::enable()?;
+        let pty = master.as_raw_fd();
+        let mut transcript = Vec::new();
+        let mut truncated = false;
+        let mut buf = [0u8; 4096];
+        let mut stdin_open = true;
+        let mut exited = None;
+        loop {
+            if let (Some(now), Some(last)) = (window_size(libc::STDOUT_FILENO), &mut size) {
+                if (now.ws_row, now.ws_col) != (last.ws_row, last.ws_col) {
+                    // SAFETY: TIOCSWINSZ reads the winsize it is given.
+                    unsafe { libc::ioctl(pty, libc::TIOCSWINSZ, &now) };
+                    *last = now;
+                }
+            }
+            let mut fds = [
+                libc::pollfd {
+                    fd: pty,
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: if stdin_open { libc::STDIN_FILENO } else { -1 },
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            // Once the command exited, only what it left is read.
+            let wait = if exited.is_some() {
+                0
+            } else {
+                TICK.as_millis()
+            };
+            // SAFETY: polls the two entries of `fds`.
+            let ready = unsafe { libc::poll(fds.as_mut_ptr(), 2, wait as libc::c_int) };
+            if ready < 0
+                && std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted
+            {
+                break;
+            }
+            if fds[0].revents & libc::POLLIN != 0 {
+                let n = read(pty, &mut buf);
+                if n <= 0 {
+                    break;
+                }
+                let out = &buf[..n as usize];
+                let mut stdout = std::io::stdout().lock();
+                let _ = stdout.write_all(out);
+                let _ = stdout.flush();
+                transcript.extend_from_slice(out);
+                if transcript.len() > 2 * TRANSCRIPT_BYTES {
+                    transcript.drain(..transcript.len() - TRANSCRIPT_BYTES);
+                    truncated = true;
+                }
+                continue;
+            } else if fds[0].revents & (libc::POLLHUP | libc::POLLERR) != 0 {
+                break;
+            }
+            if fds[1].revents & libc::POLLIN != 0 {
+                let n = read(libc::STDIN_FILENO, &mut buf);
+                if n <= 0 {
+                    stdin_open = false;
+                } else {
+                    write_all(pty, &buf[..n as usize]);
+                }
+            }
+            if exited.is_some() {
+                break;
+            }
+            exited = child.try_wait().context("wait failed")?;
+        }
+        let status = match exited {
+            Some(status) => status,
+            None => child.wait().context("wait failed")?,
+        };
+        tree.release();
+        drop(raw);
+
+        let text = printable(&String::from_utf8_lossy(&transcript));
+        let (transcript, cut) = super::super::process::tail(text.trim_end(), TRANSCRIPT_BYTES);
+        Ok(Session {
+            status,
+            transcript,
+            truncated: truncated || cut,
+        })
+    }
+}
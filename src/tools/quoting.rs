@@ -0,0 +1,364 @@
+/// Commands where a word that splits or expands unexpectedly touches files.
+const FILE_COMMANDS: &[&str] = &["rm", "mv", "cp", "chmod", "chown", "chgrp", "ln", "rsync"];
+
+/// Arguments whose value is a pattern meant for the program, not the shell.
+const PATTERN_FLAGS: &[&str] = &[
+    "-name",
+    "-iname",
+    "-path",
+    "-ipath",
+    "-wholename",
+    "--include",
+    "--exclude",
+];
+
+/// Words that precede the actual program name.
+const PREFIXES: &[&str] = &[
+    "{", "if", "then", "elif", "else", "do", "while", "until", "!", "time", "sudo", "nohup",
+    "exec", "command", "env",
+];
+
+/// Commands whose arguments are not word-split in a way that matters here.
+const SKIP_COMMANDS: &[&str] = &[
+    "echo", "printf", "for", "case", "[[", "export", "local", "readonly", "declare",
+];
+
+pub(super) struct Hazard {
+    pub message: String,
+    /// Byte ranges to wrap in quotes, with the quote style to use.
+    edits: Vec<(usize, usize, Quote)>,
+}
+
+#[derive(Clone, Copy)]
+enum Quote {
+    Double,
+    Single,
+    /// `"${NAME:?}"`, which aborts when the variable is empty or unset.
+    Required,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Expansion {
+    Var,
+    Subst,
+}
+
+#[derive(Default)]
+struct Word {
+    start: usize,
+    end: usize,
+    /// Unquoted `$NAME`, `${...}`, `$(...)`, or backtick spans.
+    expansions: Vec<(usize, usize, Expansion)>,
+    quoted: bool,
+    glob: bool,
+    /// First word after `;`, `|`, `&`, `(`, or a newline.
+    starts_command: bool,
+}
+
+fn matching(bytes: &[u8], mut i: usize, open: u8, close: u8) -> usize {
+    let mut depth = 0;
+    while i < bytes.len() {
+        if bytes[i] == open {
+            depth += 1;
+        } else if bytes[i] == close {
+            depth -= 1;
+            if depth == 0 {
+                return i + 1;
+            }
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Length of the expansion starting at the `$` at `i`, if it is one.
+fn expansion_at(bytes: &[u8], i: usize) -> Option<(usize, Expansion)> {
+    match bytes.get(i + 1)? {
+        b'(' => Some((matching(bytes, i + 1, b'(', b')'), Expansion::Subst)),
+        b'{' => Some((matching(bytes, i + 1, b'{', b'}'), Expansion::Var)),
+        b'@' | b'*' | b'0'..=b'9' => Some((i + 2, Expansion::Var)),
+        c if c.is_ascii_alphabetic() || *c == b'_' => {
+            let mut j = i + 1;
+            while j < bytes.len() && (bytes[j].is_ascii_alphanumeric() || bytes[j] == b'_') {
+                j += 1;
+            }
+            Some((j, Expansion::Var))
+        }
+        _ => None,
+    }
+}
+
+fn lex(command: &str) -> Vec<Word> {
+    let bytes = command.as_bytes();
+    let mut words = Vec::new();
+    let mut word: Option<Word> = None;
+    let mut next_starts = true;
+    let mut i = 0;
+    macro_rules! current {
+        () => {
+            word.get_or_insert_with(|| Word {
+                start: i,
+                starts_command: std::mem::replace(&mut next_starts, false),
+                ..Default::default()
+            })
+        };
+    }
+    while i < bytes.len() {
+        let c = bytes[i];
+        match c {
+            b' ' | b'\t' | b';' | b'|' | b'&' | b'(' | b')' | b'<' | b'>' | b'\n' => {
+                if let Some(mut w) = word.take() {
+                    w.end = i;
+                    words.push(w);
+                }
+                // `2>&1` and `>&2` are redirections, not a new command.
+                let redirect = c == b'&' && i > 0 && matches!(bytes[i - 1], b'>' | b'<');
+                if matches!(c, b';' | b'|' | b'&' | b'(' | b'\n') && !redirect {
+                    next_starts = true;
+                }
+                i += 1;
+            }
+            b'\'' => {
+                current!().quoted = true;
+                i = command[i + 1..]
+                    .find('\'')
+                    .map(|p| i + p + 2)
+                    .unwrap_or(bytes.len());
+            }
+            b'"' => {
+                current!().quoted = true;
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += match bytes[i] {
+                        b'\\' => 2,
+                        b'$' => expansion_at(bytes, i).map(|(e, _)| e - i).unwrap_or(1),
+                        _ => 1,
+                    };
+                }
+                i += 1;
+            }
+            b'\\' => {
+                current!();
+                i += 2;
+            }
+            b'`' => {
+                let end = command[i + 1..]
+                    .find('`')
+                    .map(|p| i + p + 2)
+                    .unwrap_or(bytes.len());
+                current!().expansions.push((i, end, Expansion::Subst));
+                i = end;
+            }
+            b'$' => match expansion_at(bytes, i) {
+                Some((end, kind)) => {
+                    current!().expansions.push((i, end, kind));
+                    i = end;
+                }
+                None => {
+                    current!();
+                    i += 1;
+                }
+            },
+            b'*' | b'?' | b'[' => {
+                current!().glob = true;
+                i += 1;
+            }
+            _ => {
+                current!();
+                i += 1;
+            }
+        }
+    }
+    if let Some(mut w) = word.take() {
+        w.end = bytes.len().min(i);
+        words.push(w);
+    }
+    words
+}
+
+fn is_assignment(text: &str) -> bool {
+    text.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Words in `command` that look like paths: absolute, from the home
+/// directory, or climbing with `..`, including the values of `--flag=path`
+/// options and assignments. Program names are left out, and so are words
+/// with expansions, whose value is only known when the shell runs them.
+pub(super) fn path_words(command: &str) -> Vec<String> {
+    lex(command)
+        .iter()
+        .filter(|w| !w.starts_command && w.expansions.is_empty())
+        .filter_map(|w| {
+            let text: String = command[w.start..w.end]
+                .chars()
+                .filter(|c| !matches!(c, '\'' | '"' | '\\'))
+                .collect();
+            let value = match text.split_once('=') {
+                Some((_, value)) if text.starts_with('-') || is_assignment(&text) => value,
+                _ => &text,
+            };
+            let is_path = value.starts_with('/')
+                || value == "~"
+                || value.starts_with("~/")
+                || value.split('/').any(|part| part == "..");
+            is_path.then(|| value.to_string())
+        })
+        .collect()
+}
+
+/// The program each simple command in `command` runs, after assignments
+/// and prefixes like `sudo` with their flags. Names written with quotes or
+/// expansions, case patterns, and functions the command defines are left
+/// out, and a command with a here-document yields nothing, since its body
+/// is not commands.
+pub(super) fn program_words(command: &str) -> Vec<String> {
+    if command.contains("<<") {
+        return Vec::new();
+    }
+    let bytes = command.as_bytes();
+    let mut programs = Vec::new();
+    let mut defined = Vec::new();
+    let mut pending = false;
+    for w in lex(command) {
+        let text = &command[w.start..w.end];
+        if w.starts_command {
+            pending = true;
+        }
+        if !pending || is_assignment(text) || PREFIXES.contains(&text) || text.starts_with('-') {
+            continue;
+        }
+        match bytes.get(w.end) {
+            // `pattern)` in a case statement.
+            Some(b')') => continue,
+            // `name() {`, a function definition.
+            Some(b'(') => {
+                defined.push(text);
+                pending = false;
+                continue;
+            }
+            _ => {}
+        }
+        pending = false;
+        if w.expansions.is_empty() && !w.quoted {
+            programs.push(text.to_string());
+        }
+    }
+    programs.retain(|p| !defined.contains(&p.as_str()));
+    programs
+}
+
+/// Find common quoting mistakes in an `sh` command, each with a mechanical
+/// fix. This is a lexer-level heuristic rather than a parser: it catches the
+/// classic footguns but does not prove a command safe.
+pub(super) fn analyze(command: &str) -> Vec<Hazard> {
+    let words = lex(command);
+    let mut hazards = Vec::new();
+    let mut program = "";
+    let mut skip = false;
+    for (idx, w) in words.iter().enumerate() {
+        let text = &command[w.start..w.end];
+        if w.starts_command {
+            program = "";
+        }
+        if program.is_empty() {
+            if is_assignment(text) || PREFIXES.contains(&text) {
+                continue;
+            }
+            program = text.rsplit('/').next().unwrap_or(text);
+            skip = SKIP_COMMANDS.contains(&program);
+            continue;
+        }
+        if skip || is_assignment(text) {
+            continue;
+        }
+        let previous = idx
+            .checked_sub(1)
+            .map(|p| &command[words[p].start..words[p].end])
+            .unwrap_or("");
+        let file_command = FILE_COMMANDS.contains(&program);
+
+        for &(start, end, kind) in &w.expansions {
+            let expansion = &command[start..end];
+            let hazard = match kind {
+                Expansion::Subst if file_command => Hazard {
+                    message: format!(
+                        "`{}` in {} arguments is unquoted: an empty or multi-word result touches unintended files",
+                        expansion, program
+                    ),
+                    edits: vec![(start, end, Quote::Double)],
+                },
+                Expansion::Subst => continue,
+                Expansion::Var
+                    if file_command
+                        && start == w.start
+                        && command[end..w.end].starts_with('/') =>
+                {
+                    Hazard {
+                        message: format!(
+                            "if {} is empty, `{}` refers to `{}`",
+                            expansion,
+                            text,
+                            &command[end..w.end]
+                        ),
+                        edits: vec![(start, end, Quote::Required)],
+                    }
+                }
+                Expansion::Var => Hazard {
+                    message: format!(
+                        "{} is unquoted: a value with spaces or glob characters splits into several arguments",
+                        expansion
+                    ),
+                    edits: vec![(start, end, Quote::Double)],
+                },
+            };
+            hazards.push(hazard);
+        }
+
+        if w.glob && !w.quoted && w.expansions.is_empty() {
+            if PATTERN_FLAGS.contains(&previous) {
+                hazards.push(Hazard {
+                    message: format!(
+                        "pattern `{}` after {} is expanded by the shell before {} sees it",
+                        text, previous, program
+                    ),
+                    edits: vec![(w.start, w.end, Quote::Single)],
+                });
+            } else if !text.contains('*') && text != "[" && text != "]" {
+                hazards.push(Hazard {
+                    message: format!(
+                        "`{}` contains glob characters; quote it if it is a literal name",
+                        text
+                    ),
+                    edits: vec![(w.start, w.end, Quote::Single)],
+                });
+            }
+        }
+    }
+    hazards
+}
+
+/// Apply every hazard's quoting fix.
+pub(super) fn fix(command: &str, hazards: &[Hazard]) -> String {
+    let mut edits: Vec<(usize, usize, Quote)> = hazards
+        .iter()
+        .flat_map(|h| h.edits.iter().copied())
+        .collect();
+    edits.sort_by_key(|e| std::cmp::Reverse(e.0));
+    let mut out = command.to_string();
+    for (start, end, quote) in edits {
+        let inner = &command[start..end];
+        let replacement = match quote {
+            Quote::Double => format!("\"{}\"", inner),
+            Quote::Single => format!("'{}'", inner),
+            Quote::Required => {
+                let name = inner.trim_start_matches("${").trim_start_matches('$');
+                let name = name.trim_end_matches('}');
+                format!("\"${{{}:?}}\"", name)
+            }
+        };
+        out.replace_range(start..end, &replacement);
+    }
+    out
+}
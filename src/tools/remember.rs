@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+
+use llm::builder::ParamBuilder;
+
+use super::{ask_user, results, Tool};
+use crate::config::project_instructions_path;
+use crate::say;
+
+/// Appends a fact to the project's `TAI.md` so later sessions start with it.
+pub struct RememberTool;
+
+impl Tool for RememberTool {
+    fn name(&self) -> &'static str {
+        "remember"
+    }
+    fn description(&self) -> &'static str {
+        "Persist a fact or preference about this project (e.g. \"we use pnpm, not npm\") to its TAI.md instructions file, which is loaded into every future session. Only use when the user asks you to remember something. The user approves each entry."
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        &["fact"]
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![ParamBuilder::new("fact")
+            .type_of("string")
+            .description("The fact as one short, self-contained sentence")]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let fact = args
+            .get("fact")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'fact'"))?
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if fact.is_empty() {
+            return Err(anyhow!("'fact' is empty"));
+        }
+        let path = project_instructions_path()?;
+        let existing = fs::read_to_string(&path).unwrap_or_default();
+        let entry = format!("- {}", fact);
+        if existing.lines().any(|l| l.trim() == entry) {
+            return results::to_value(results::Remember {
+                path: path.display().to_string(),
+                fact,
+                remembered: true,
+                note: Some("Already present".to_string()),
+            });
+        }
+
+        let choice = ask_user(&format!(
+            "Remember in {}: \"{}\"? [Y/n] ",
+            path.display(),
+            fact
+        ))?;
+        if choice == "n" {
+            say!("Not remembered");
+            return results::to_value(results::Remember {
+                path: path.display().to_string(),
+                fact,
+                remembered: false,
+                note: None,
+            });
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let mut text = String::new();
+        if existing.is_empty() {
+            text.push_str("# Project instructions\n\n");
+        } else if !existing.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&entry);
+        text.push('\n');
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut f| f.write_all(text.as_bytes()))
+            .with_context(|| format!("Failed writing {}", path.display()))?;
+
+        results::to_value(results::Remember {
+            path: path.display().to_string(),
+            fact,
+            remembered: true,
+            note: None,
+        })
+    }
+
+    fn print_result(&self, result: &Value) {
+        use nu_ansi_term::{Color as NuColor, Style};
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let path = result.get("path").and_then(|v| v.as_str()).unwrap_or("");
+        match result.get("remembered").and_then(|v| v.as_bool()) {
+            Some(true) => println!("{}: saved to {}", result_label, path),
+            _ => println!("{}: not saved", result_label),
+        }
+    }
+}
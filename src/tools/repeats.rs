@@ -0,0 +1,198 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use nu_ansi_term::{Color as NuColor, Style};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use super::process::tail;
+use super::results;
+use super::units;
+use crate::config::get_global_config_dir;
+use crate::logging::redact;
+use crate::say;
+
+/// Runs kept in the log; the oldest go first.
+const MAX_LOGGED: usize = 200;
+
+/// How long a logged run from another invocation counts as recent.
+const RECENT_MINUTES: i64 = 10;
+
+/// Output kept per run, from its end.
+const OUTPUT_CHARS: usize = 2_000;
+
+/// Output lines shown in the notice.
+const SHOWN_LINES: usize = 5;
+
+/// One finished `run_shell` command.
+#[derive(Clone, Serialize, Deserialize)]
+struct Run {
+    at: DateTime<Utc>,
+    cwd: String,
+    /// With secrets masked, as it is in the log.
+    command: String,
+    exit_status: Option<i32>,
+    elapsed_sec: f64,
+    output: String,
+}
+
+/// The commands run in this session, and in the log those run recently by
+/// other invocations, so a repeat can be pointed out before it runs again.
+pub(super) struct Runs {
+    /// Whether runs are written to and read from the log.
+    log: bool,
+    session: Mutex<Vec<Run>>,
+}
+
+/// An earlier run of the command about to run.
+pub(super) struct Earlier {
+    run: Run,
+    this_session: bool,
+}
+
+fn log_path() -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("commands.jsonl"))
+}
+
+fn read_log() -> Vec<Run> {
+    let Ok(text) = log_path().and_then(|p| Ok(fs::read_to_string(p)?)) else {
+        return Vec::new();
+    };
+    text.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn append_log(run: &Run) -> Result<()> {
+    let mut runs = read_log();
+    runs.push(run.clone());
+    let keep = runs.len().saturating_sub(MAX_LOGGED);
+    let mut text = String::new();
+    for run in &runs[keep..] {
+        text.push_str(&serde_json::to_string(run)?);
+        text.push('\n');
+    }
+    let path = log_path()?;
+    fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+impl Runs {
+    pub(super) fn new(log: bool) -> Self {
+        Self {
+            log,
+            session: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Keep the result of `command`, run in `cwd`, if it ran to the end.
+    pub(super) fn record(&self, command: &str, cwd: &str, result: &results::RunShell) {
+        if !result.executed || result.background.is_some() {
+            return;
+        }
+        let output = result.output.as_deref().unwrap_or_default();
+        let run = Run {
+            at: Utc::now(),
+            cwd: cwd.to_string(),
+            command: redact(command.trim()).into_owned(),
+            exit_status: result.exit_status,
+            elapsed_sec: result.elapsed_sec.unwrap_or_default(),
+            output: redact(&tail(output, OUTPUT_CHARS).0).into_owned(),
+        };
+        if self.log {
+            if let Err(e) = append_log(&run) {
+                tracing::debug!("Failed to log command: {:#}", e);
+            }
+        }
+        self.session
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(run);
+    }
+
+    /// The last run of `command` in `cwd` in this session, or else a recent
+    /// one in the log.
+    pub(super) fn find(&self, command: &str, cwd: &str) -> Option<Earlier> {
+        let command = redact(command.trim());
+        let same = |run: &&Run| run.command == command && run.cwd == cwd;
+        let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(run) = session.iter().rev().find(same) {
+            return Some(Earlier {
+                run: run.clone(),
+                this_session: true,
+            });
+        }
+        if !self.log {
+            return None;
+        }
+        let since = Utc::now() - chrono::Duration::minutes(RECENT_MINUTES);
+        read_log()
+            .into_iter()
+            .rev()
+            .take_while(|run| run.at >= since)
+            .find(|run| same(&run))
+            .map(|run| Earlier {
+                run,
+                this_session: false,
+            })
+    }
+}
+
+impl Earlier {
+    fn ago(&self) -> String {
+        let secs = (Utc::now() - self.run.at).num_milliseconds().max(0) as f64 / 1000.0;
+        let when = if secs < 1.0 {
+            "just now".to_string()
+        } else {
+            format!("{} ago", units::duration(secs.round()))
+        };
+        format!(
+            "{}{}",
+            when,
+            if self.this_session {
+                " in this session"
+            } else {
+                ""
+            }
+        )
+    }
+
+    /// Show when the command last ran, how it ended, and the end of its
+    /// output.
+    pub(super) fn show(&self) {
+        let status = match self.run.exit_status {
+            Some(0) => NuColor::Green.paint("exit 0".to_string()),
+            Some(code) => NuColor::Red.paint(format!("exit {}", code)),
+            None => NuColor::Red.paint("terminated by signal".to_string()),
+        };
+        say!(
+            "{}: {}, {} after {}",
+            Style::new().fg(NuColor::Yellow).paint("already ran"),
+            self.ago(),
+            status,
+            units::duration(self.run.elapsed_sec)
+        );
+        let lines: Vec<&str> = self.run.output.trim_end().lines().collect();
+        for line in &lines[lines.len().saturating_sub(SHOWN_LINES)..] {
+            say!("  {}", Style::new().dimmed().paint(*line));
+        }
+    }
+
+    /// The tool result when the user skips the repeat: the earlier run's.
+    pub(super) fn skipped(&self, command: &str) -> results::RunShell {
+        results::RunShell {
+            skipped_repeat: Some(true),
+            note: Some(
+                "The user chose not to run this again; this is the result of the earlier run"
+                    .to_string(),
+            ),
+            earlier: Some(results::EarlierRun {
+                ran: self.ago(),
+                exit_status: self.run.exit_status,
+                elapsed_sec: self.run.elapsed_sec,
+                output: self.run.output.clone(),
+            }),
+            ..results::RunShell::not_run(command)
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use anyhow::{Context, Result};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use llm::builder::ParamBuilder;
+use tree_sitter::Node;
+
+use super::dir::resolve_path;
+use super::languages::{grammar, parse, signature, Grammar, MAX_PARSE_BYTES};
+use super::{results, Tool};
+use crate::config::get_global_config_dir;
+
+/// Files listed in one map by default.
+const DEFAULT_LIMIT: usize = 500;
+
+/// Symbols listed per file; the rest are counted.
+const MAX_SYMBOLS_PER_FILE: usize = 40;
+
+/// Members of members (a method in an impl in a module) are listed down to
+/// this depth.
+const MAX_DEPTH: usize = 2;
+
+#[derive(Clone, Deserialize, Serialize)]
+struct Symbol {
+    /// 1-based.
+    line: usize,
+    /// 0 for top-level items, 1 for their members, and so on.
+    depth: usize,
+    text: String,
+}
+
+fn collect(grammar: &Grammar, parent: Node, source: &[u8], depth: usize, out: &mut Vec<Symbol>) {
+    let mut cursor = parent.walk();
+    for child in parent.named_children(&mut cursor) {
+        let node = grammar
+            .wrappers
+            .iter()
+            .find(|(kind, _)| *kind == child.kind())
+            .and_then(|(_, field)| child.child_by_field_name(field))
+            .unwrap_or(child);
+        if !grammar.items.contains(&node.kind()) {
+            continue;
+        }
+        let body = node.child_by_field_name("body");
+        // `mod name;` only points at another file, which is listed anyway.
+        if node.kind() == "mod_item" && body.is_none() {
+            continue;
+        }
+        out.push(Symbol {
+            line: node.start_position().row + 1,
+            depth,
+            text: signature(node, source),
+        });
+        if depth < MAX_DEPTH && grammar.containers.contains(&node.kind()) {
+            if let Some(body) = body {
+                collect(grammar, body, source, depth + 1, out);
+            }
+        }
+    }
+}
+
+fn symbols(grammar: &Grammar, source: &[u8]) -> Result<Vec<Symbol>> {
+    let tree = parse(grammar, source)?;
+    let mut out = Vec::new();
+    collect(grammar, tree.root_node(), source, 0, &mut out);
+    Ok(out)
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedFile {
+    modified_ms: u128,
+    size: u64,
+    symbols: Vec<Symbol>,
+}
+
+/// Symbols of each file of one workspace, by path relative to it.
+#[derive(Default, Deserialize, Serialize)]
+struct Cache {
+    files: BTreeMap<String, CachedFile>,
+}
+
+fn cache_path(root: &Path) -> Result<PathBuf> {
+    Ok(get_global_config_dir()?.join("index").join(format!(
+        "repo-map-{:x}.json",
+        md5::compute(root.display().to_string())
+    )))
+}
+
+/// The map of `path` the tool returns: up to `limit` files with their
+/// symbols, as text under `map`, and counts.
+pub(crate) fn repo_map(path: &str, limit: usize) -> Result<Value> {
+    let dir = resolve_path(path, false)?;
+    let root = resolve_path(".", false)?;
+
+    let cache_file = cache_path(&root)?;
+    let mut cache: Cache = fs::read_to_string(&cache_file)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    let mut files: Vec<(String, PathBuf, u128, u64)> = WalkBuilder::new(&dir)
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            let modified = meta
+                .modified()
+                .ok()?
+                .duration_since(UNIX_EPOCH)
+                .ok()?
+                .as_millis();
+            let rel = e.path().strip_prefix(&root).ok()?.display().to_string();
+            Some((rel, e.into_path(), modified, meta.len()))
+        })
+        .collect();
+    files.sort();
+    let total = files.len();
+
+    // Entries under `dir` that are gone are dropped; others stay for
+    // the next call on another directory.
+    let prefix = dir.strip_prefix(&root).unwrap_or(&dir).to_path_buf();
+    let before = cache.files.len();
+    cache.files.retain(|rel, _| {
+        !Path::new(rel).starts_with(&prefix) || files.iter().any(|(r, ..)| r == rel)
+    });
+    let mut changed = cache.files.len() != before;
+
+    let mut parsed = 0;
+    let mut map = String::new();
+    let mut symbol_count = 0;
+    for (rel, path, modified, size) in files.iter().take(limit) {
+        map.push_str(rel);
+        map.push('\n');
+        let Some(grammar) = grammar(path) else {
+            continue;
+        };
+        if *size > MAX_PARSE_BYTES {
+            continue;
+        }
+        let fresh = cache
+            .files
+            .get(rel)
+            .is_some_and(|c| c.modified_ms == *modified && c.size == *size);
+        if !fresh {
+            let found = fs::read(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|source| symbols(grammar, &source))
+                .unwrap_or_default();
+            cache.files.insert(
+                rel.clone(),
+                CachedFile {
+                    modified_ms: *modified,
+                    size: *size,
+                    symbols: found,
+                },
+            );
+            parsed += 1;
+            changed = true;
+        }
+        let file_symbols = &cache.files[rel].symbols;
+        symbol_count += file_symbols.len();
+        for symbol in file_symbols.iter().take(MAX_SYMBOLS_PER_FILE) {
+            map.push_str(&format!(
+                "{:indent$}{}: {}\n",
+                "",
+                symbol.line,
+                symbol.text,
+                indent = 2 + symbol.depth * 2
+            ));
+        }
+        if file_symbols.len() > MAX_SYMBOLS_PER_FILE {
+            map.push_str(&format!(
+                "  … {} more\n",
+                file_symbols.len() - MAX_SYMBOLS_PER_FILE
+            ));
+        }
+    }
+    if changed {
+        if let Some(parent) = cache_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&cache_file, serde_json::to_string(&cache)?)
+            .with_context(|| format!("Failed to write {}", cache_file.display()))?;
+    }
+
+    results::to_value(results::RepoMap {
+        path: dir.display().to_string(),
+        files: total.min(limit),
+        symbols: symbol_count,
+        parsed,
+        map,
+        truncated: (total > limit).then(|| {
+            format!(
+                "Listed {} of {} files; pass a subdirectory as path to see the rest",
+                limit, total
+            )
+        }),
+    })
+}
+
+/// An outline of the workspace for orientation: its files, with the
+/// top-level symbols of Rust, Python, JavaScript, and Go files.
+pub struct RepoMapTool;
+
+impl Tool for RepoMapTool {
+    fn name(&self) -> &'static str {
+        "repo_map"
+    }
+    fn description(&self) -> &'static str {
+        "Outline the workspace: every file (respecting .gitignore), and for Rust, Python, JavaScript, and Go files their functions, types, classes, and methods with 1-based line numbers. Use it first to find your way around a large or unfamiliar repository instead of reading files one by one, then read_file the lines you need."
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("path")
+                .type_of("string")
+                .description("Directory to outline (default '.')"),
+            ParamBuilder::new("limit")
+                .type_of("integer")
+                .description("Maximum files to list (default 500)"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let path = args.get("path").and_then(|v| v.as_str()).unwrap_or(".");
+        let limit = args
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .map_or(DEFAULT_LIMIT, |n| n as usize);
+        repo_map(path, limit)
+    }
+
+    fn print_result(&self, result: &Value) {
+        use nu_ansi_term::{Color as NuColor, Style};
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let count = |key: &str| result.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+        println!(
+            "{}: {} files, {} symbols ({} files parsed, the rest cached)",
+            result_label,
+            count("files"),
+            count("symbols"),
+            count("parsed")
+        );
+    }
+}
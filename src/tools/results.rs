@@ -0,0 +1,917 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+/// Raised when a result shape below changes in a way that breaks readers:
+/// a field removed, renamed, or given another type. New optional fields
+/// keep the version.
+pub(crate) const SCHEMA_VERSION: u32 = 2;
+
+/// `read_file`: lines `start..end` of the file.
+#[derive(Serialize)]
+pub(super) struct ReadFile {
+    pub path: String,
+    pub start: usize,
+    pub end: usize,
+    pub total_lines: usize,
+    pub content: String,
+}
+
+/// `write_file`: `bytes` when written; `written: false` and a reason when
+/// the user declined.
+#[derive(Serialize)]
+pub(super) struct WriteFile {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub written: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<Formatted>,
+}
+
+/// `patch_file`: how often each replacement matched.
+#[derive(Serialize)]
+pub(super) struct PatchFile {
+    pub path: String,
+    pub changed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replacements: Option<Vec<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_replacements: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<Formatted>,
+}
+
+/// What the formatter did after `write_file` or `patch_file`: the file as
+/// it is now, or the diff for large ones, or why it failed.
+#[derive(Serialize)]
+pub(super) struct Formatted {
+    pub by: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed: Option<String>,
+}
+
+impl Formatted {
+    pub fn by(by: String) -> Self {
+        Self {
+            by,
+            changed: None,
+            content: None,
+            diff: None,
+            failed: None,
+        }
+    }
+}
+
+/// `write_file` or `patch_file` not applied because the file changed since
+/// it was read; `content` is the file now, absent when it was deleted.
+#[derive(Serialize)]
+pub(super) struct Conflict {
+    pub path: String,
+    pub changed: bool,
+    pub conflict: bool,
+    pub reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(super) struct GrepMatch {
+    pub file: String,
+    pub abs_path: String,
+    pub line: usize,
+    #[serde(rename = "match")]
+    pub text: String,
+}
+
+/// `grep`: the matching lines, up to the limit.
+#[derive(Serialize)]
+pub(super) struct Grep {
+    pub root: String,
+    pub pattern: String,
+    pub count: usize,
+    pub results: Vec<GrepMatch>,
+}
+
+/// `stat`, and each entry of `list_dir`.
+#[derive(Serialize)]
+pub(super) struct PathInfo {
+    pub path: String,
+    /// dir, file, symlink, or other.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub size: u64,
+    pub modified: Option<String>,
+    pub created: Option<String>,
+    pub mode: String,
+}
+
+/// `list_dir`: the entries, up to the limit.
+#[derive(Serialize)]
+pub(super) struct ListDir {
+    pub path: String,
+    pub count: usize,
+    pub items: Vec<PathInfo>,
+}
+
+/// `glob`: the matching files, up to the limit.
+#[derive(Serialize)]
+pub(super) struct Glob {
+    pub root: String,
+    pub pattern: String,
+    pub count: usize,
+    pub paths: Vec<String>,
+}
+
+/// `fetch_url`: the response, its body cut at the size limit.
+#[derive(Serialize)]
+pub(super) struct FetchUrl {
+    pub url: String,
+    pub final_url: String,
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub truncated: bool,
+    pub text: String,
+}
+
+/// A public key's algorithm and, where it has one, its size.
+#[derive(Serialize)]
+pub(super) struct KeyInfo {
+    pub algorithm: String,
+    pub bits: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub(super) struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub not_before: Option<String>,
+    pub not_after: Option<String>,
+    pub days_remaining: i64,
+    pub expired: bool,
+    pub not_yet_valid: bool,
+    pub self_signed: bool,
+    pub is_ca: bool,
+    pub san: Vec<String>,
+    pub key: KeyInfo,
+    pub signature_algorithm: String,
+    pub sha256_fingerprint: String,
+}
+
+#[derive(Serialize)]
+pub(super) struct Csr {
+    pub subject: String,
+    pub san: Vec<String>,
+    pub key: KeyInfo,
+    pub signature_algorithm: String,
+}
+
+/// One object found in a certificate file, by `kind`.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(super) enum CertObject {
+    Certificate(Certificate),
+    Csr(Csr),
+    PublicKey(KeyInfo),
+    PrivateKey {
+        label: String,
+        note: String,
+    },
+    Unknown {
+        label: Option<String>,
+        error: String,
+    },
+}
+
+/// The TLS session an endpoint's chain came from.
+#[derive(Serialize)]
+pub(super) struct Tls {
+    pub address: String,
+    pub protocol: Option<String>,
+    pub cipher_suite: Option<String>,
+}
+
+/// Whether a chain leads to a trusted root, and matches the hostname when
+/// one was given.
+#[derive(Serialize)]
+pub(super) struct Validation {
+    pub trusted: bool,
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roots_checked: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname_match: Option<bool>,
+}
+
+/// `inspect_cert`: what a file or endpoint holds; `tls` for endpoints.
+#[derive(Serialize)]
+pub(super) struct InspectCert {
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Tls>,
+    pub objects: Vec<CertObject>,
+    pub validation: Option<Validation>,
+}
+
+/// An instant as one time zone shows it.
+#[derive(Serialize)]
+pub(super) struct ZoneTime {
+    pub zone: String,
+    pub rfc3339: String,
+    pub readable: String,
+    pub utc_offset: String,
+}
+
+#[derive(Serialize)]
+pub(super) struct ConvertTime {
+    pub epoch_seconds: i64,
+    pub epoch_millis: i64,
+    pub iso_week: String,
+    pub relative: String,
+    pub zones: Vec<ZoneTime>,
+}
+
+/// A size expression's value; only `ratio` when the units cancel out.
+#[derive(Serialize, Default)]
+pub(super) struct ConvertBytes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ratio: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decimal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub converted: Option<Converted>,
+}
+
+#[derive(Serialize)]
+pub(super) struct Converted {
+    pub unit: String,
+    pub value: f64,
+}
+
+#[derive(Serialize)]
+pub(super) struct ConvertCron {
+    pub expression: String,
+    pub description: String,
+    pub zone: String,
+    pub next_runs: Vec<String>,
+}
+
+/// `convert`, by `kind`.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(super) enum Convert {
+    Time(ConvertTime),
+    Bytes(ConvertBytes),
+    Cron(ConvertCron),
+}
+
+/// `transform`: `output` for hashes and encodings, with `binary` and
+/// `encoding` for decoded bytes; `header`, `claims`, and `times` for a
+/// decoded JWT.
+#[derive(Serialize, Default)]
+pub(super) struct Transform {
+    pub operation: String,
+    pub input_bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claims: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub times: Option<BTreeMap<String, String>>,
+    /// Absent when the token has no `exp` claim.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expired: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature_verified: Option<bool>,
+}
+
+impl Transform {
+    pub fn output(output: String) -> Self {
+        Self {
+            output: Some(output),
+            ..Self::default()
+        }
+    }
+}
+
+/// `cloud`: what the CLI returned, as its own JSON for `read` and as
+/// normalized items otherwise; `count` when it is a list. The values in
+/// `result` are the provider's, so only the envelope is checked.
+#[derive(Serialize)]
+pub(super) struct Cloud {
+    pub provider: String,
+    pub action: String,
+    pub command: String,
+    pub count: Option<usize>,
+    pub truncated: bool,
+    pub result: Value,
+}
+
+#[derive(Serialize)]
+pub(super) struct CodeMatch {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub snippet: String,
+    /// What a definition defines: function, type, and so on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item: Option<String>,
+}
+
+/// `code_search`: definitions or references of a name, up to the limit;
+/// `truncated` says how to narrow the search when the limit was hit.
+#[derive(Serialize)]
+pub(super) struct CodeSearch {
+    pub root: String,
+    pub name: String,
+    pub kind: String,
+    pub files_searched: usize,
+    pub count: usize,
+    pub results: Vec<CodeMatch>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<String>,
+}
+
+/// A listening socket and, when it can be seen, the process that owns it.
+#[derive(Serialize)]
+pub(super) struct Listener {
+    pub protocol: String,
+    pub address: String,
+    pub port: u16,
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+    pub command: Option<String>,
+}
+
+/// `ports`: the listening sockets; `note` when some owners are hidden.
+#[derive(Serialize)]
+pub(super) struct Ports {
+    pub count: usize,
+    pub sockets: Vec<Listener>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// How one hunk of a patch applied: where, and how loosely, or why not.
+#[derive(Serialize, Default)]
+pub(super) struct HunkReport {
+    pub hunk: usize,
+    pub applied: bool,
+    pub line: usize,
+    /// Lines away from where the header put it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<isize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fuzz: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whitespace_ignored: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(super) struct PatchedFile {
+    pub path: String,
+    pub status: String,
+    pub hunks: Vec<HunkReport>,
+}
+
+/// `apply_patch`: every file and hunk; `hint` when some failed and
+/// nothing was written.
+#[derive(Serialize)]
+pub(super) struct ApplyPatch {
+    pub dry_run: bool,
+    pub written: bool,
+    pub hunks_applied: usize,
+    pub hunks_failed: usize,
+    pub files: Vec<PatchedFile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+/// `repo_map`: the outline as text, a file per line with its symbols
+/// indented below; `truncated` when files were left out.
+#[derive(Serialize)]
+pub(super) struct RepoMap {
+    pub path: String,
+    pub files: usize,
+    pub symbols: usize,
+    /// Files parsed in this call rather than taken from the cache.
+    pub parsed: usize,
+    pub map: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<String>,
+}
+
+/// `generate_secret`: what was made, but never the value itself.
+#[derive(Serialize)]
+pub(super) struct GenerateSecret {
+    pub kind: String,
+    pub length: usize,
+    pub entropy_bits: f64,
+    pub shown_to_user: bool,
+    pub copied_to_clipboard: bool,
+    pub note: String,
+}
+
+/// `remember`: where the fact went, or that the user declined.
+#[derive(Serialize)]
+pub(super) struct Remember {
+    pub path: String,
+    pub fact: String,
+    pub remembered: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// A command tool's result when the user declined to run it.
+#[derive(Serialize)]
+pub(super) struct NotRun {
+    pub command: String,
+    pub executed: bool,
+}
+
+impl NotRun {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            executed: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(super) struct PlannedChange {
+    pub address: String,
+    #[serde(rename = "type")]
+    pub resource_type: String,
+    pub action: String,
+    pub risks: Vec<String>,
+    pub replace_paths: Value,
+}
+
+/// `terraform_plan`: changes counted by action, the risky ones, and the
+/// first changes in full.
+#[derive(Serialize)]
+pub(super) struct TerraformPlan {
+    pub dir: String,
+    pub terraform_version: Option<String>,
+    pub summary: BTreeMap<String, usize>,
+    pub risky: Vec<String>,
+    pub changes: Vec<PlannedChange>,
+    pub changes_truncated: bool,
+}
+
+/// `cargo`: the output of a run that ended; `error` and the killed
+/// processes when it timed out.
+#[derive(Serialize)]
+pub(super) struct Cargo {
+    pub command: String,
+    pub executed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+    pub stderr: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terminated: Option<Vec<String>>,
+}
+
+/// A background job started by `run_shell`; `exit_status` is null while
+/// it runs or when a signal ended it.
+#[derive(Serialize)]
+pub(super) struct Job {
+    pub id: u32,
+    pub command: String,
+    pub pid: u32,
+    pub running: bool,
+    pub exit_status: Option<i32>,
+    pub uptime_sec: u64,
+    pub log: String,
+}
+
+/// `process_status`: the jobs asked about, or all of them.
+#[derive(Serialize)]
+pub(super) struct ProcessStatus {
+    pub jobs: Vec<Job>,
+}
+
+/// `process_logs`: the last lines of a job's output.
+#[derive(Serialize)]
+pub(super) struct ProcessLogs {
+    pub id: u32,
+    pub running: bool,
+    pub exit_status: Option<i32>,
+    pub total_lines: usize,
+    pub output: String,
+    pub truncated: bool,
+}
+
+/// `process_stop`: the processes it killed, or a note and the exit status
+/// when the job had already exited.
+#[derive(Serialize)]
+pub(super) struct ProcessStop {
+    pub id: u32,
+    pub stopped: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terminated: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// How `run_shell` confined a command; only `mode` when it did not.
+#[derive(Serialize)]
+pub(super) struct Sandbox {
+    pub mode: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub writable: Option<Vec<String>>,
+}
+
+/// The `[policy]` limits a command ran under, those that are set.
+#[derive(Serialize)]
+pub(super) struct ShellLimits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_cpu_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_memory_mb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_mb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_processes: Option<u64>,
+}
+
+/// An earlier run of a command the user chose not to repeat.
+#[derive(Serialize)]
+pub(super) struct EarlierRun {
+    pub ran: String,
+    pub exit_status: Option<i32>,
+    pub elapsed_sec: f64,
+    pub output: String,
+}
+
+/// `run_shell`. Every result has `command` and `executed`; which others
+/// it has depends on how the command ran: to the end, in the background,
+/// interactively, or not at all (declined, copied, skipped as a repeat,
+/// or missing a program).
+#[derive(Serialize, Default)]
+pub(super) struct RunShell {
+    pub command: String,
+    pub executed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_truncated: Option<bool>,
+    /// Absent when a signal ended the command or it is still running.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<Sandbox>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limits: Option<ShellLimits>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd_reset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_reset: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adapted_command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adaptations: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quoting_warnings: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executed_command: Option<String>,
+    /// Why it did not run or did not finish.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The processes killed when it timed out or printed too much.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terminated: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interactive: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime_sec: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copied: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped_repeat: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub earlier: Option<EarlierRun>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install_command: Option<String>,
+}
+
+impl RunShell {
+    /// A result for `command` that did not run.
+    pub fn not_run(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+/// `result` as JSON, for a tool's `execute_blocking`.
+pub(super) fn to_value<T: Serialize>(result: T) -> Result<Value> {
+    serde_json::to_value(result).context("Failed to encode the tool result")
+}
+
+/// The published shape of every built-in tool's results, kept by hand
+/// apart from the types above so that a change to one of them shows up as
+/// a mismatch instead of passing silently.
+const SCHEMA: &str = include_str!("results.schema.json");
+
+static PARSED: LazyLock<Value> =
+    LazyLock::new(|| serde_json::from_str(SCHEMA).expect("results.schema.json is valid JSON"));
+
+fn type_matches(name: &str, value: &Value) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => false,
+    }
+}
+
+/// Check `value` against `schema` at `at`, with the subset of JSON Schema
+/// the file uses: `$ref` into `$defs`, `anyOf`, `const`, `enum`, `type`,
+/// `minimum`, `properties`, `required`, `additionalProperties`, and `items`.
+fn check(schema: &Value, value: &Value, at: &str) -> Result<()> {
+    if let Some(name) = schema["$ref"].as_str() {
+        let name = name.trim_start_matches("#/$defs/");
+        let def = PARSED["$defs"]
+            .get(name)
+            .ok_or_else(|| anyhow!("no definition {}", name))?;
+        return check(def, value, at);
+    }
+    if let Some(options) = schema["anyOf"].as_array() {
+        let mut errors = Vec::new();
+        for option in options {
+            match check(option, value, at) {
+                Ok(()) => return Ok(()),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+        bail!("{}", errors.join("; or "));
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            bail!("{} is not {}", at, expected);
+        }
+    }
+    if let Some(allowed) = schema["enum"].as_array() {
+        if !allowed.contains(value) {
+            bail!("{} is not one of {}", at, schema["enum"]);
+        }
+    }
+    let types = match &schema["type"] {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|t| type_matches(t, value)) {
+        bail!("{} is not {}", at, types.join(" or "));
+    }
+    if let (Some(minimum), Some(n)) = (schema["minimum"].as_f64(), value.as_f64()) {
+        if n < minimum {
+            bail!("{} is below {}", at, minimum);
+        }
+    }
+    if let Some(fields) = value.as_object() {
+        let properties = schema["properties"].as_object();
+        for name in schema["required"].as_array().into_iter().flatten() {
+            let name = name.as_str().unwrap_or_default();
+            if !fields.contains_key(name) {
+                bail!("{} has no {}", at, name);
+            }
+        }
+        for (name, field) in fields {
+            let field_at = format!("{}.{}", at, name);
+            match properties.and_then(|p| p.get(name)) {
+                Some(property) => check(property, field, &field_at)?,
+                None => match &schema["additionalProperties"] {
+                    Value::Bool(false) => bail!("{} is not expected", field_at),
+                    extra @ Value::Object(_) => check(extra, field, &field_at)?,
+                    _ => {}
+                },
+            }
+        }
+    }
+    if let (Some(items), Some(elements)) = (schema.get("items"), value.as_array()) {
+        for (i, element) in elements.iter().enumerate() {
+            check(items, element, &format!("{}[{}]", at, i))?;
+        }
+    }
+    Ok(())
+}
+
+/// Check `value` against the published shape of `tool`'s results. Every
+/// built-in tool has one; a result that does not match is a bug in the
+/// tool and is not passed on. Plugins' results are their own and are not
+/// checked.
+pub(super) fn validate(tool: &str, value: &Value) -> Result<()> {
+    let Some(schema) = PARSED["tools"].get(tool) else {
+        return Ok(());
+    };
+    check(schema, value, "result").map_err(|e| {
+        anyhow!(
+            "The {} result does not match schema version {}: {}",
+            tool,
+            SCHEMA_VERSION,
+            e
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::tools::ToolsRegistry;
+    use serde_json::json;
+
+    #[test]
+    fn schema_file_has_the_current_version() {
+        assert_eq!(PARSED["schema_version"], json!(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn every_built_in_tool_has_a_schema() {
+        let _lock = crate::tools::dir::WORKSPACE_TEST
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let tools = ToolsRegistry::with_default(&Config::default());
+        for (name, _) in tools.describe() {
+            assert!(
+                PARSED["tools"].get(name).is_some(),
+                "{} has no schema",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn results_built_by_the_tools_match() {
+        let read = to_value(ReadFile {
+            path: "/w/a.rs".into(),
+            start: 0,
+            end: 1,
+            total_lines: 1,
+            content: "fn main() {}".into(),
+        })
+        .unwrap();
+        validate("read_file", &read).unwrap();
+
+        let mut formatted = Formatted::by("rustfmt".into());
+        formatted.changed = Some(false);
+        let write = to_value(WriteFile {
+            path: "/w/a.rs".into(),
+            bytes: Some(12),
+            written: None,
+            reason: None,
+            formatted: Some(formatted),
+        })
+        .unwrap();
+        validate("write_file", &write).unwrap();
+
+        let conflict = to_value(Conflict {
+            path: "/w/a.rs".into(),
+            changed: false,
+            conflict: true,
+            reason: "changed on disk".into(),
+            content: None,
+        })
+        .unwrap();
+        validate("write_file", &conflict).unwrap();
+        validate("patch_file", &conflict).unwrap();
+
+        validate("cargo", &to_value(NotRun::new("cargo test")).unwrap()).unwrap();
+        validate("run_shell", &to_value(RunShell::not_run("ls")).unwrap()).unwrap();
+        validate(
+            "convert",
+            &to_value(Convert::Bytes(ConvertBytes {
+                ratio: Some(0.5),
+                ..ConvertBytes::default()
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+        let cert = to_value(InspectCert {
+            source: "key.pem".into(),
+            tls: None,
+            objects: vec![CertObject::PrivateKey {
+                label: "PRIVATE KEY".into(),
+                note: "not shown".into(),
+            }],
+            validation: None,
+        })
+        .unwrap();
+        validate("inspect_cert", &cert).unwrap();
+    }
+
+    #[test]
+    fn mismatches_are_rejected() {
+        let read = json!({
+            "path": "a.rs", "start": 0, "end": 1, "total_lines": 1, "content": "x"
+        });
+        validate("read_file", &read).unwrap();
+
+        let mut missing = read.clone();
+        missing.as_object_mut().unwrap().remove("content");
+        let extra = json!({
+            "path": "a.rs", "start": 0, "end": 1, "total_lines": 1, "content": "x", "lines": 1
+        });
+        let wrong_type = json!({
+            "path": "a.rs", "start": "0", "end": 1, "total_lines": 1, "content": "x"
+        });
+        let negative = json!({
+            "path": "a.rs", "start": -1, "end": 1, "total_lines": 1, "content": "x"
+        });
+        let null = json!({
+            "path": null, "start": 0, "end": 1, "total_lines": 1, "content": "x"
+        });
+        for bad in [missing, extra, wrong_type, negative, null] {
+            let e = validate("read_file", &bad).unwrap_err().to_string();
+            assert!(e.contains("schema version"), "{}", e);
+        }
+
+        let unknown_kind = json!({ "kind": "length", "ratio": 1.0 });
+        assert!(validate("convert", &unknown_kind).is_err());
+        let bad_item = json!({ "jobs": [{ "id": 1 }] });
+        assert!(validate("process_status", &bad_item).is_err());
+    }
+
+    #[test]
+    fn plugin_results_are_not_checked() {
+        validate("my_plugin", &json!({ "anything": [1, 2, 3] })).unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::process::Command;
+
+use super::process::in_path;
+use super::results;
+use crate::config::SandboxConfig;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Off,
+    ReadOnly,
+    Workspace,
+}
+
+impl Mode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Off => "off",
+            Mode::ReadOnly => "read-only",
+            Mode::Workspace => "workspace",
+        }
+    }
+}
+
+fn parse_mode(cfg: &SandboxConfig) -> Result<Mode> {
+    match cfg.mode.as_deref().unwrap_or("off") {
+        "off" | "none" | "" => Ok(Mode::Off),
+        "read-only" | "readonly" => Ok(Mode::ReadOnly),
+        "workspace" => Ok(Mode::Workspace),
+        other => Err(anyhow!("Unknown sandbox mode: {}", other)),
+    }
+}
+
+fn pick_backend(cfg: &SandboxConfig) -> Result<&'static str> {
+    let requested = cfg.backend.as_deref().unwrap_or("auto");
+    let candidates: &[&'static str] = match requested {
+        "auto" => {
+            if cfg!(target_os = "macos") {
+                &["sandbox-exec"]
+            } else {
+                &["bwrap", "firejail"]
+            }
+        }
+        "bwrap" => &["bwrap"],
+        "firejail" => &["firejail"],
+        "sandbox-exec" => &["sandbox-exec"],
+        other => return Err(anyhow!("Unknown sandbox backend: {}", other)),
+    };
+    candidates
+        .iter()
+        .copied()
+        .find(|b| in_path(b))
+        .ok_or_else(|| {
+            anyhow!(
+                "Sandbox is enabled but no backend is available (looked for {})",
+                candidates.join(", ")
+            )
+        })
+}
+
+fn bwrap_command(mode: Mode, root: &Path, cwd: &Path, network: bool, command: &str) -> Command {
+    let mut cmd = Command::new("bwrap");
+    cmd.args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"])
+        .args(["--tmpfs", "/tmp", "--die-with-parent"]);
+    if mode == Mode::Workspace {
+        cmd.arg("--bind").arg(root).arg(root);
+    }
+    if !network {
+        cmd.arg("--unshare-net");
+    }
+    cmd.arg("--chdir").arg(cwd);
+    cmd.args(["sh", "-c", command]);
+    cmd
+}
+
+fn firejail_command(mode: Mode, root: &Path, network: bool, command: &str) -> Command {
+    let mut cmd = Command::new("firejail");
+    cmd.args(["--quiet", "--read-only=/", "--private-tmp"]);
+    if mode == Mode::Workspace {
+        cmd.arg(format!("--read-write={}", root.display()));
+    }
+    if !network {
+        cmd.arg("--net=none");
+    }
+    cmd.args(["sh", "-c", command]);
+    cmd
+}
+
+fn sandbox_exec_command(mode: Mode, root: &Path, network: bool, command: &str) -> Command {
+    let mut profile = String::from(
+        "(version 1)(allow default)(deny file-write*)(allow file-write* (literal \"/dev/null\") (subpath \"/dev/fd\") (subpath \"/private/tmp\") (subpath \"/private/var/folders\"))",
+    );
+    if mode == Mode::Workspace {
+        profile.push_str(&format!(
+            "(allow file-write* (subpath \"{}\"))",
+            root.display().to_string().replace('"', "\\\"")
+        ));
+    }
+    if !network {
+        profile.push_str("(deny network*)");
+    }
+    let mut cmd = Command::new("sandbox-exec");
+    cmd.args(["-p", &profile, "sh", "-c", command]);
+    cmd
+}
+
+/// Build the process used to run `command` in `cwd`, wrapped in the
+/// configured sandbox backend, which in workspace mode may write in `root`.
+/// Also returns a description of the sandbox for the tool result.
+pub(super) fn build_command(
+    cfg: &SandboxConfig,
+    command: &str,
+    root: &Path,
+    cwd: &Path,
+) -> Result<(Command, results::Sandbox)> {
+    let mode = parse_mode(cfg)?;
+    if mode == Mode::Off {
+        let mut cmd = if cfg!(target_os = "windows") {
+            let mut c = Command::new("cmd");
+            c.args(["/C", command]);
+            c
+        } else {
+            let mut c = Command::new("sh");
+            c.args(["-c", command]);
+            c
+        };
+        cmd.current_dir(cwd);
+        return Ok((
+            cmd,
+            results::Sandbox {
+                mode: "off".to_string(),
+                backend: None,
+                network: None,
+                writable: None,
+            },
+        ));
+    }
+    let backend = pick_backend(cfg)?;
+    let network = cfg.network.unwrap_or(true);
+    let mut cmd = match backend {
+        "bwrap" => bwrap_command(mode, root, cwd, network, command),
+        "firejail" => firejail_command(mode, root, network, command),
+        _ => sandbox_exec_command(mode, root, network, command),
+    };
+    cmd.current_dir(cwd);
+    let writable = match mode {
+        Mode::Workspace => vec![root.display().to_string(), "/tmp".to_string()],
+        _ => vec!["/tmp".to_string()],
+    };
+    Ok((
+        cmd,
+        results::Sandbox {
+            mode: mode.as_str().to_string(),
+            backend: Some(backend.to_string()),
+            network: Some(network),
+            writable: Some(writable),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(cmd: &Command) -> Vec<String> {
+        std::iter::once(cmd.get_program())
+            .chain(cmd.get_args())
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// Whether `args` holds `pair` next to each other.
+    fn has(args: &[String], pair: &[&str]) -> bool {
+        args.windows(pair.len()).any(|w| w == pair)
+    }
+
+    #[test]
+    fn bwrap_binds_only_the_workspace_writable() {
+        let root = Path::new("/work/repo");
+        let cwd = Path::new("/work/repo/src");
+        let read_only = argv(&bwrap_command(Mode::ReadOnly, root, cwd, false, "ls"));
+        assert!(has(&read_only, &["--ro-bind", "/", "/"]));
+        assert!(!read_only.contains(&"--bind".to_string()));
+        assert!(read_only.contains(&"--unshare-net".to_string()));
+        assert!(has(&read_only, &["--chdir", "/work/repo/src"]));
+        assert!(read_only.ends_with(&["sh".into(), "-c".into(), "ls".into()]));
+
+        let workspace = argv(&bwrap_command(Mode::Workspace, root, cwd, true, "ls"));
+        assert!(has(&workspace, &["--bind", "/work/repo", "/work/repo"]));
+        assert!(!workspace.contains(&"--unshare-net".to_string()));
+    }
+
+    #[test]
+    fn firejail_and_sandbox_exec_follow_the_mode() {
+        let root = Path::new("/work/repo");
+        let read_only = argv(&firejail_command(Mode::ReadOnly, root, false, "ls"));
+        assert!(read_only.contains(&"--read-only=/".to_string()));
+        assert!(read_only.contains(&"--net=none".to_string()));
+        assert!(!read_only.iter().any(|a| a.starts_with("--read-write")));
+        let workspace = argv(&firejail_command(Mode::Workspace, root, true, "ls"));
+        assert!(workspace.contains(&"--read-write=/work/repo".to_string()));
+        assert!(!workspace.contains(&"--net=none".to_string()));
+
+        let profile =
+            |mode, network| argv(&sandbox_exec_command(mode, root, network, "ls"))[2].clone();
+        assert!(profile(Mode::ReadOnly, false).contains("(deny file-write*)"));
+        assert!(profile(Mode::ReadOnly, false).contains("(deny network*)"));
+        assert!(!profile(Mode::ReadOnly, true).contains("/work/repo"));
+        assert!(profile(Mode::Workspace, true).contains("(subpath \"/work/repo\")"));
+        // A quote in the root cannot end the profile string early.
+        let quoted = argv(&sandbox_exec_command(
+            Mode::Workspace,
+            Path::new("/a\")(allow network*"),
+            false,
+            "ls",
+        ))[2]
+            .clone();
+        assert!(quoted.contains("(subpath \"/a\\\")(allow network*\")"));
+    }
+
+    #[test]
+    fn modes_and_backends_are_checked() {
+        let cfg = |mode: &str, backend: &str| SandboxConfig {
+            mode: Some(mode.into()),
+            backend: Some(backend.into()),
+            ..SandboxConfig::default()
+        };
+        assert!(parse_mode(&cfg("strict", "auto")).is_err());
+        assert!(pick_backend(&cfg("workspace", "docker")).is_err());
+        let (cmd, sandbox) =
+            build_command(&cfg("off", "auto"), "ls", Path::new("/"), Path::new("/")).unwrap();
+        assert_eq!(sandbox.mode, "off");
+        assert!(sandbox.backend.is_none());
+        if cfg!(unix) {
+            assert_eq!(argv(&cmd), ["sh", "-c", "ls"]);
+        }
+    }
+}
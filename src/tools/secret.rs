@@ -0,0 +1,204 @@
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::Value;
+
+use llm::builder::ParamBuilder;
+
+use super::{results, Tool};
+use crate::say;
+
+const WORDLIST: &str = include_str!("wordlist.txt");
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!@#$%^&*()-_=+[]{};:,.?/~";
+
+/// Generates passwords, passphrases, UUIDs, and random tokens locally. The
+/// secret is shown only to the user; the model just learns that it was made.
+pub struct GenerateSecretTool;
+
+fn random_bytes(n: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; n];
+    SystemRandom::new()
+        .fill(&mut buf)
+        .map_err(|_| anyhow!("System random number generator failed"))?;
+    Ok(buf)
+}
+
+/// Uniform index in `0..n` (rejection sampling avoids modulo bias).
+fn random_index(n: usize) -> Result<usize> {
+    let n = n as u32;
+    let zone = u32::MAX - (u32::MAX % n);
+    loop {
+        let b = random_bytes(4)?;
+        let v = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        if v < zone {
+            return Ok((v % n) as usize);
+        }
+    }
+}
+
+fn password(length: usize, symbols: bool) -> Result<(String, f64)> {
+    let mut classes = vec![LOWER, UPPER, DIGITS];
+    if symbols {
+        classes.push(SYMBOLS);
+    }
+    let alphabet: Vec<char> = classes.concat().chars().collect();
+    // Redraw until every character class is present so the result passes
+    // common password policies.
+    loop {
+        let pw: String = (0..length)
+            .map(|_| random_index(alphabet.len()).map(|i| alphabet[i]))
+            .collect::<Result<_>>()?;
+        if length < classes.len() || classes.iter().all(|c| pw.chars().any(|ch| c.contains(ch))) {
+            return Ok((pw, length as f64 * (alphabet.len() as f64).log2()));
+        }
+    }
+}
+
+fn passphrase(words: usize, separator: &str) -> Result<(String, f64)> {
+    let list: Vec<&str> = WORDLIST.lines().filter(|l| !l.is_empty()).collect();
+    let picked: Vec<&str> = (0..words)
+        .map(|_| random_index(list.len()).map(|i| list[i]))
+        .collect::<Result<_>>()?;
+    Ok((
+        picked.join(separator),
+        words as f64 * (list.len() as f64).log2(),
+    ))
+}
+
+fn uuid_v4() -> Result<String> {
+    let mut b = random_bytes(16)?;
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let hex: String = b.iter().map(|x| format!("{:02x}", x)).collect();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ))
+}
+
+impl Tool for GenerateSecretTool {
+    fn name(&self) -> &'static str {
+        "generate_secret"
+    }
+    fn description(&self) -> &'static str {
+        "Generate a password, passphrase, UUID, or random hex/base64 token locally with a secure RNG. The value is shown directly to the user (and optionally copied to the clipboard) and is NOT returned to you, so never try to repeat it; just tell the user it was generated."
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        &["kind"]
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("kind")
+                .type_of("string")
+                .description("One of: password, passphrase, uuid, hex, base64"),
+            ParamBuilder::new("length")
+                .type_of("integer")
+                .description("Characters for password (default 24); bytes of randomness for hex/base64 (default 32)"),
+            ParamBuilder::new("words")
+                .type_of("integer")
+                .description("Number of words for passphrase (default 6)"),
+            ParamBuilder::new("symbols")
+                .type_of("boolean")
+                .description("Include symbols in passwords (default true)"),
+            ParamBuilder::new("separator")
+                .type_of("string")
+                .description("Passphrase word separator (default '-')"),
+            ParamBuilder::new("copy")
+                .type_of("boolean")
+                .description("Also copy the secret to the clipboard (default false)"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let kind = args
+            .get("kind")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'kind'"))?;
+        let length = args.get("length").and_then(|v| v.as_u64());
+        let (secret, entropy) = match kind {
+            "password" => {
+                let len = length.unwrap_or(24).clamp(4, 1024) as usize;
+                let symbols = args
+                    .get("symbols")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(true);
+                password(len, symbols)?
+            }
+            "passphrase" => {
+                let words = args
+                    .get("words")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(6)
+                    .clamp(3, 64) as usize;
+                let sep = args
+                    .get("separator")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("-");
+                passphrase(words, sep)?
+            }
+            "uuid" => (uuid_v4()?, 122.0),
+            "hex" | "base64" => {
+                let bytes = length.unwrap_or(32).clamp(1, 4096) as usize;
+                let raw = random_bytes(bytes)?;
+                let s = if kind == "hex" {
+                    raw.iter().map(|b| format!("{:02x}", b)).collect()
+                } else {
+                    base64::engine::general_purpose::STANDARD.encode(&raw)
+                };
+                (s, bytes as f64 * 8.0)
+            }
+            other => return Err(anyhow!("Unknown kind: {}", other)),
+        };
+
+        use nu_ansi_term::{Color as NuColor, Style};
+        say!(
+            "{}: {}",
+            Style::new().fg(NuColor::LightMagenta).paint(kind),
+            Style::new().bold().paint(&secret)
+        );
+        let copied = args.get("copy").and_then(|v| v.as_bool()).unwrap_or(false)
+            && match arboard::Clipboard::new() {
+                Ok(mut cb) => match cb.set_text(&secret) {
+                    Ok(()) => {
+                        say!("Copied to clipboard");
+                        true
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to copy to clipboard: {}", e);
+                        false
+                    }
+                },
+                Err(_) => {
+                    eprintln!("Failed to access clipboard");
+                    false
+                }
+            };
+
+        results::to_value(results::GenerateSecret {
+            kind: kind.to_string(),
+            length: secret.chars().count(),
+            entropy_bits: entropy.floor(),
+            shown_to_user: true,
+            copied_to_clipboard: copied,
+            note:
+                "The value was displayed only to the user and is intentionally not included here."
+                    .to_string(),
+        })
+    }
+
+    fn print_result(&self, result: &Value) {
+        use nu_ansi_term::{Color as NuColor, Style};
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let bits = result
+            .get("entropy_bits")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0);
+        println!("{}: ~{} bits of entropy", result_label, bits);
+    }
+}
@@ -0,0 +1,75 @@
+use nu_ansi_term::Color as NuColor;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::results::Conflict;
+use crate::say;
+
+/// Files as this session last read or wrote them, by a digest of their
+/// content, to notice when the user or another program changes one in
+/// between.
+static SEEN: Mutex<Option<HashMap<PathBuf, md5::Digest>>> = Mutex::new(None);
+
+/// Remember `path` as holding `content`.
+pub(super) fn saw(path: &Path, content: &[u8]) {
+    SEEN.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(path.to_path_buf(), md5::compute(content));
+}
+
+/// Remember `path` as it is on disk now, e.g. after a formatter ran on it.
+pub(super) fn refresh(path: &Path) {
+    match fs::read(path) {
+        Ok(content) => saw(path, &content),
+        Err(_) => forget(path),
+    }
+}
+
+pub(super) fn forget(path: &Path) {
+    if let Some(seen) = SEEN.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        seen.remove(path);
+    }
+}
+
+/// When `path` changed since this session last read or wrote it: a warning,
+/// and the result for the model to return instead of editing, with the
+/// file as it is now. The file then counts as read, so an edit made after
+/// looking at it goes through.
+pub(super) fn conflict(path: &Path, tool: &str) -> Option<Conflict> {
+    let mut guard = SEEN.lock().unwrap_or_else(|e| e.into_inner());
+    let seen = guard.as_mut()?;
+    let known = seen.get(path)?;
+    let current = fs::read(path).ok();
+    if current.as_ref().map(md5::compute).as_ref() == Some(known) {
+        return None;
+    }
+    say!(
+        "{}",
+        NuColor::Yellow.paint(format!(
+            "{} changed since it was last read; {} was not applied.",
+            path.display(),
+            tool
+        ))
+    );
+    let mut result = Conflict {
+        path: path.display().to_string(),
+        changed: false,
+        conflict: true,
+        reason: "The file changed since you last read or wrote it, by the user, another program, or a command. Redo the edit against the content below, keeping those changes.".to_string(),
+        content: None,
+    };
+    match current {
+        Some(bytes) => {
+            result.content = Some(String::from_utf8_lossy(&bytes).into_owned());
+            seen.insert(path.to_path_buf(), md5::compute(&bytes));
+        }
+        None => {
+            result.reason = "The file was deleted since you last read it.".to_string();
+            seen.remove(path);
+        }
+    }
+    Some(result)
+}
@@ -1,14 +1,187 @@
 use anyhow::{anyhow, Context, Result};
+use futures::future::BoxFuture;
 use nu_ansi_term::{Color as NuColor, Style};
-use serde_json::{json, Value};
+use serde_json::Value;
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
 
 use llm::builder::ParamBuilder;
 
-use super::Tool;
+use super::dir::{normalize, working_dir, workspace_root};
+use super::jobs::Jobs;
+use super::packages::{self, Offer};
+use super::process::{self, tail, Limits, ProcessTree};
+use super::pty;
+use super::quoting;
+use super::repeats::Runs;
+use super::sandbox;
+use super::syntax::{self, UserShell};
+use super::units;
+use super::{ask_user_async, results, Tool};
+use crate::config::{PolicyConfig, SandboxConfig, WorkspaceConfig};
+use crate::net::expand_home;
+use crate::{output, say};
 
-pub struct ShellCommandTool;
+/// How much of each output stream is kept for the tool result. Everything is
+/// still shown to the user as it streams.
+const OUTPUT_TAIL_BYTES: usize = 32 * 1024;
+
+/// Accumulates streamed output, keeping only the most recent bytes.
+#[derive(Default)]
+struct TailBuffer {
+    buf: String,
+    truncated: bool,
+}
+
+impl TailBuffer {
+    fn push(&mut self, s: &str) {
+        self.buf.push_str(s);
+        if self.buf.len() > 2 * OUTPUT_TAIL_BYTES {
+            self.buf = tail(&self.buf, OUTPUT_TAIL_BYTES).0;
+            self.truncated = true;
+        }
+    }
+
+    fn finish(self) -> (String, bool) {
+        let (buf, cut) = tail(&self.buf, OUTPUT_TAIL_BYTES);
+        (buf, cut || self.truncated)
+    }
+}
+
+/// Working directory and exported environment carried from one `run_shell`
+/// call to the next, so `cd`, `export`, and activated virtualenvs persist for
+/// the rest of the session.
+#[derive(Default)]
+struct ShellState {
+    cwd: Option<String>,
+    /// Output of `export -p`, which is valid shell input to restore it.
+    exports: Option<String>,
+}
+
+pub struct ShellCommandTool {
+    sandbox: SandboxConfig,
+    policy: PolicyConfig,
+    workspace: WorkspaceConfig,
+    jobs: Arc<Jobs>,
+    state: Mutex<ShellState>,
+    marker: String,
+    user_shell: UserShell,
+    runs: Runs,
+}
+
+impl ShellCommandTool {
+    pub(super) fn new(
+        sandbox: SandboxConfig,
+        policy: PolicyConfig,
+        workspace: WorkspaceConfig,
+        jobs: Arc<Jobs>,
+        log_runs: bool,
+    ) -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        Self {
+            sandbox,
+            policy,
+            workspace,
+            jobs,
+            state: Mutex::new(ShellState::default()),
+            marker: format!("__tai_state_{}_{}", std::process::id(), nanos),
+            user_shell: UserShell::detect(),
+            runs: Runs::new(log_runs),
+        }
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            cpu_seconds: self.policy.max_cpu_seconds,
+            memory_bytes: self.policy.max_memory_mb.map(|mb| mb * 1024 * 1024),
+            processes: self.policy.max_processes,
+        }
+    }
+
+    /// Wrap `command` so it starts from the saved state and, with
+    /// `track_state`, on exit prints the marker followed by the new cwd and
+    /// exports on stdout. Going through stdout rather than a state file keeps
+    /// this working inside sandboxes with a private /tmp.
+    fn wrap(&self, command: &str, track_state: bool) -> String {
+        if cfg!(target_os = "windows") {
+            return command.to_string();
+        }
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut script = String::new();
+        if let Some(exports) = &state.exports {
+            script.push_str(exports);
+            script.push('\n');
+        }
+        if let Some(cwd) = &state.cwd {
+            script.push_str(&format!("cd {} || exit 1\n", shell_quote(cwd)));
+        }
+        if track_state {
+            script.push_str(&format!(
+                "trap 'printf \"%s\\n\" {}; pwd; export -p' EXIT\n",
+                self.marker
+            ));
+        }
+        script.push_str(command);
+        script
+    }
+
+    /// Store the state printed after the marker; the first line is the cwd.
+    /// A cwd outside `root` is not kept, so the next command starts back in
+    /// the workspace.
+    fn save_state(&self, captured: &str, root: &Path) -> Option<String> {
+        let (cwd, exports) = captured.split_once('\n')?;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.cwd = Path::new(cwd).starts_with(root).then(|| cwd.to_string());
+        state.exports = Some(exports.to_string());
+        Some(cwd.to_string())
+    }
+
+    /// PATH as earlier commands exported it, if they did.
+    fn session_path(&self) -> Option<String> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.exports.as_deref()?.lines().find_map(|line| {
+            let value = line
+                .strip_prefix("export PATH=")
+                .or_else(|| line.strip_prefix("declare -x PATH="))?;
+            Some(value.trim_matches(|c| c == '"' || c == '\'').to_string())
+        })
+    }
+
+    /// Where the next command starts: the directory the last one left, or
+    /// `base`.
+    fn start_dir(&self, base: &Path) -> PathBuf {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .cwd
+            .as_ref()
+            .map_or_else(|| base.to_path_buf(), PathBuf::from)
+    }
+}
+
+/// The first path `command` names outside `root`, with relative paths taken
+/// from `cwd`. Device files and the temp directory are allowed, since
+/// commands use them without meaning to reach outside.
+fn path_outside(command: &str, root: &Path, cwd: &Path) -> Option<PathBuf> {
+    quoting::path_words(command).into_iter().find_map(|word| {
+        let path = normalize(&cwd.join(expand_home(if word == "~" { "~/" } else { &word })));
+        let allowed = path.starts_with(root)
+            || path.starts_with("/dev")
+            || path.starts_with(std::env::temp_dir());
+        (!allowed).then_some(path)
+    })
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
 
 impl Tool for ShellCommandTool {
     fn name(&self) -> &'static str {
@@ -42,13 +215,111 @@ impl Tool for ShellCommandTool {
         vec![
             ParamBuilder::new("command")
                 .type_of("string")
-                .description(format!("The exact shell command to execute ({shell})")),
+                .description(format!("The exact shell command to execute ({shell}). The working directory and exported variables carry over between calls")),
             ParamBuilder::new("timeout_sec")
                 .type_of("integer")
                 .description("Optional timeout in seconds (defaults to 120)"),
+            ParamBuilder::new("reset")
+                .type_of("boolean")
+                .description("Discard the working directory and environment carried over from earlier commands before running this one"),
+            ParamBuilder::new("background")
+                .type_of("boolean")
+                .description("Start a long-running command (dev server, tail -f, watcher) and return at once with a job id. Check on it with process_status/process_logs and end it with process_stop. Its cd/export changes do not carry over"),
+            ParamBuilder::new("interactive")
+                .type_of("boolean")
+                .description("Attach the command to the user's terminal so they can type into it, for commands that need input (ssh, sudo password prompts, top, vim, interactive installers). Runs until the user ends it, with no timeout; the result holds only the end of its output. Its cd/export changes do not carry over"),
         ]
     }
-    fn execute_blocking(&self, args: Value) -> Result<Value> {
+    fn preflight(&self, args: &Value) -> Option<String> {
+        let command = args.get("command")?.as_str()?;
+        let adapted = syntax::to_posix(command, &self.user_shell);
+        let to_check = adapted.as_ref().map(|(c, _)| c.as_str()).unwrap_or(command);
+        syntax::check_posix(to_check).map(|e| {
+            format!(
+                "Syntax error, so the command was not shown to the user. Fix it and call run_shell again: {}",
+                e
+            )
+        })
+    }
+    fn execute(self: Arc<Self>, args: Value) -> BoxFuture<'static, Result<Value>> {
+        Box::pin(async move { results::to_value(self.run(args).await?) })
+    }
+
+    fn print_result(&self, result: &Value) {
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let executed = result
+            .get("executed")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let copied = result
+            .get("copied")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let elapsed = result
+            .get("elapsed_sec")
+            .and_then(|v| v.as_f64())
+            .map(|s| format!(" after {}", units::duration(s)))
+            .unwrap_or_default();
+
+        // The output itself was streamed to the terminal while the command ran.
+        if copied {
+            println!("{}: command copied to clipboard", result_label);
+        } else if result.get("skipped_repeat").is_some() {
+            println!(
+                "{}: not run again; the earlier result was used",
+                result_label
+            );
+        } else if result.get("background").is_some() {
+            let id = result.get("id").and_then(|v| v.as_u64()).unwrap_or(0);
+            match result.get("running").and_then(|v| v.as_bool()) {
+                Some(false) => println!(
+                    "{}: {}",
+                    result_label,
+                    NuColor::Red.paint(format!(
+                        "job {} exited right away{}",
+                        id,
+                        result
+                            .get("exit_status")
+                            .and_then(|v| v.as_i64())
+                            .map(|c| format!(" (exit {})", c))
+                            .unwrap_or_default()
+                    ))
+                ),
+                _ => println!("{}: job {} running", result_label, id),
+            }
+        } else if executed {
+            let status = match result.get("exit_status").and_then(|v| v.as_i64()) {
+                Some(0) => NuColor::Green.paint("exit 0".to_string()),
+                Some(code) => NuColor::Red.paint(format!("exit {}", code)),
+                None => NuColor::Red.paint("terminated by signal".to_string()),
+            };
+            println!("{}: {}{}", result_label, status, elapsed);
+        } else if let Some(err) = result.get("error").and_then(|v| v.as_str()) {
+            let killed: Vec<&str> = result
+                .get("terminated")
+                .and_then(|v| v.as_array())
+                .map(|a| a.iter().filter_map(|p| p.as_str()).collect())
+                .unwrap_or_default();
+            if killed.is_empty() {
+                println!("{}: {}", result_label, err);
+            } else {
+                println!(
+                    "{}: {}; killed {} process{}: {}",
+                    result_label,
+                    err,
+                    killed.len(),
+                    if killed.len() == 1 { "" } else { "es" },
+                    killed.join(", ")
+                );
+            }
+        } else {
+            println!("{}: command not executed", result_label);
+        }
+    }
+}
+
+impl ShellCommandTool {
+    async fn run(&self, args: Value) -> Result<results::RunShell> {
         let command = args
             .get("command")
             .and_then(|v| v.as_str())
@@ -58,132 +329,388 @@ impl Tool for ShellCommandTool {
             .get("timeout_sec")
             .and_then(|v| v.as_u64())
             .unwrap_or(120);
+        let reset = args.get("reset").and_then(|v| v.as_bool()).unwrap_or(false);
+        let interactive = args
+            .get("interactive")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if interactive && !pty::available() {
+            return Ok(results::RunShell {
+                error: Some("interactive commands need tai to run in a terminal on Unix; run it without interactive, or ask the user to run it themselves".to_string()),
+                ..results::RunShell::not_run(&command)
+            });
+        }
+        if reset {
+            *self.state.lock().unwrap_or_else(|e| e.into_inner()) = ShellState::default();
+        }
+        let adapted = syntax::to_posix(&command, &self.user_shell);
+        if let Some((adapted_command, notes)) = &adapted {
+            say!(
+                "{}: {}\n  {}",
+                Style::new()
+                    .fg(NuColor::Yellow)
+                    .paint(format!("adapted from {} syntax", self.user_shell.name())),
+                adapted_command,
+                Style::new().dimmed().paint(notes.join("; "))
+            );
+        }
+        let mut to_run = adapted
+            .as_ref()
+            .map(|(c, _)| c.clone())
+            .unwrap_or_else(|| command.clone());
 
-        // println!("> {}", command);
-        print!("Do you want to execute this command? [Y/n/c] ");
-        std::io::Write::flush(&mut std::io::stdout()).context("Failed to flush stdout")?;
-        let mut input = String::new();
-        std::io::stdin()
-            .read_line(&mut input)
-            .context("Failed to read user input")?;
-        let choice = input.trim().to_lowercase();
+        let root = workspace_root()?;
+        let base = working_dir(&root);
+        if self.workspace.confine_shell.unwrap_or(false) && !cfg!(target_os = "windows") {
+            if let Some(path) = path_outside(&to_run, &root, &self.start_dir(&base)) {
+                anyhow::bail!(
+                    "The command names {}, which is outside the workspace root {}. Keep to files in the workspace",
+                    path.display(),
+                    root.display()
+                );
+            }
+        }
+
+        let path = self.session_path();
+        if let Some(missing) = packages::missing_programs(&to_run, path.as_deref().map(OsStr::new))
+        {
+            let (missing, offer) = tokio::task::spawn_blocking(move || {
+                let offer = missing.offer();
+                offer.map(|offer| (missing, offer))
+            })
+            .await
+            .context("Prompt task panicked")??;
+            if matches!(offer, Offer::Declined | Offer::Failed(_)) {
+                return Ok(missing.not_run(&command, &offer));
+            }
+        }
+
+        let hazards = if cfg!(target_os = "windows") {
+            Vec::new()
+        } else {
+            quoting::analyze(&to_run)
+        };
+        let quoted = (!hazards.is_empty()).then(|| quoting::fix(&to_run, &hazards));
+        for hazard in &hazards {
+            say!(
+                "{}: {}",
+                Style::new().fg(NuColor::Yellow).paint("warning"),
+                hazard.message
+            );
+        }
+        if let Some(quoted) = &quoted {
+            say!(
+                "{}: {}",
+                Style::new().fg(NuColor::Green).paint("quoted"),
+                quoted
+            );
+        }
+        let background = args
+            .get("background")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let ran_in = self.start_dir(&base).display().to_string();
+        let earlier = if background || interactive {
+            None
+        } else {
+            self.runs.find(&to_run, &ran_in)
+        };
+        if let Some(earlier) = &earlier {
+            earlier.show();
+        }
+        let mut choices = String::from("Y/n/c");
+        if quoted.is_some() {
+            choices.push_str("/q=run quoted");
+        }
+        if earlier.is_some() {
+            choices.push_str("/s=skip, use earlier result");
+        }
+        let question = format!("Do you want to execute this command? [{}] ", choices);
+
+        let choice = ask_user_async(question).await?;
+        if let Some(earlier) = earlier.filter(|_| choice == "s") {
+            say!("Not run again");
+            return Ok(earlier.skipped(&command));
+        }
         if choice == "c" {
+            // The user pastes into their own shell, so hand over that syntax:
+            // the original when it was written for it, otherwise a translation.
+            let for_paste = match &adapted {
+                Some(_) => command.clone(),
+                None => syntax::for_user_shell(&command, &self.user_shell),
+            };
             if let Ok(mut cb) = arboard::Clipboard::new() {
-                if let Err(e) = cb.set_text(&command) {
+                if let Err(e) = cb.set_text(&for_paste) {
                     eprintln!("Failed to copy to clipboard: {}", e);
                 } else {
-                    println!("Command copied to clipboard");
+                    say!("Command copied to clipboard");
                 }
             } else {
                 eprintln!("Failed to access clipboard");
             }
-            return Ok(json!({
-                "command": command,
-                "executed": false,
-                "copied": true
-            }));
+            return Ok(results::RunShell {
+                copied: Some(true),
+                ..results::RunShell::not_run(&command)
+            });
         }
         if choice == "n" {
-            println!("Command execution cancelled");
-            return Ok(json!({
-                "command": command,
-                "executed": false
-            }));
-        }
-
-        print!("\x1B[1A\x1B[2K\r");
-        print!("\x1B[2K\r");
-
-        let mut child = if cfg!(target_os = "windows") {
-            std::process::Command::new("cmd")
-                .args(["/C", &command])
-                .stdin(Stdio::null())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-        } else {
-            std::process::Command::new("sh")
-                .args(["-c", &command])
-                .stdin(Stdio::null())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-        }
-        .context("Failed to execute command")?;
-
-        let start = std::time::Instant::now();
-        let status_output = loop {
-            if let Some(status) = child.try_wait().context("wait failed")? {
-                let output = child.wait_with_output().context("output failed")?;
-                break Ok((status, output));
-            }
-            if start.elapsed().as_secs() >= timeout {
-                let _ = child.kill();
-                break Err(anyhow!("timeout after {}s", timeout));
+            say!("Command execution cancelled");
+            return Ok(results::RunShell::not_run(&command));
+        }
+        let use_quoted = choice == "q" && quoted.is_some();
+        if let Some(q) = quoted.filter(|_| use_quoted) {
+            to_run = q;
+        }
+        let (mut command_process, sandbox_info) = sandbox::build_command(
+            &self.sandbox,
+            &self.wrap(&to_run, !background && !interactive),
+            &root,
+            &base,
+        )?;
+        // An interactive command gets a session of its own instead, which
+        // also makes it a group leader.
+        if !interactive {
+            process::new_group(&mut command_process);
+        }
+        let limits = self.limits();
+        process::apply_limits(&mut command_process, &limits);
+
+        if !output::stdout_reserved() {
+            print!("\x1B[1A\x1B[2K\r");
+            print!("\x1B[2K\r");
+        }
+
+        if interactive {
+            let start = Instant::now();
+            let session = tokio::task::spawn_blocking(move || pty::run(command_process, &limits))
+                .await
+                .context("Interactive command panicked")??;
+            if !session.transcript.is_empty() {
+                say!();
             }
-            std::thread::sleep(Duration::from_millis(500));
-        };
+            return Ok(results::RunShell {
+                command,
+                executed: true,
+                interactive: Some(true),
+                exit_status: session.status.code(),
+                output: Some(session.transcript),
+                output_truncated: Some(session.truncated),
+                elapsed_sec: Some((start.elapsed().as_secs_f64() * 10.0).round() / 10.0),
+                sandbox: Some(sandbox_info),
+                ..results::RunShell::default()
+            });
+        }
 
-        match status_output {
-            Ok((status, output)) => {
-                let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                let combined = if stderr.is_empty() {
-                    stdout.clone()
-                } else if stdout.is_empty() {
-                    stderr.clone()
-                } else {
-                    format!("{}\n{}", stdout, stderr)
-                };
-                println!("{}", combined);
-                Ok(json!({
-                    "command": command,
-                    "executed": true,
-                    "exit_status": status.code(),
-                    "stdout": stdout,
-                    "stderr": stderr,
-                    "output": combined,
-                }))
+        if background {
+            let job = self.jobs.start(&to_run, command_process, &limits)?;
+            say!(
+                "{}: job {} (pid {}), output in {}",
+                Style::new()
+                    .fg(NuColor::Cyan)
+                    .paint("started in background"),
+                job.id,
+                job.pid,
+                job.log
+            );
+            let mut result = results::RunShell {
+                command,
+                executed: true,
+                background: Some(true),
+                id: Some(job.id),
+                pid: Some(job.pid),
+                running: Some(job.running),
+                exit_status: job.exit_status,
+                uptime_sec: Some(job.uptime_sec),
+                log: Some(job.log),
+                sandbox: Some(sandbox_info),
+                ..results::RunShell::default()
+            };
+            // Give commands that fail right away (bad flag, port in use) a
+            // moment, so the model hears about it now rather than later.
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            if let Ok(logs) = self.jobs.logs(job.id, 20) {
+                result.running = Some(logs.running);
+                result.exit_status = logs.exit_status;
+                result.output = Some(logs.output);
             }
-            Err(e) => Ok(json!({
-                "command": command,
-                "executed": false,
-                "error": e.to_string(),
-            })),
+            return Ok(result);
         }
-    }
 
-    fn print_result(&self, result: &Value) {
-        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
-        let executed = result
-            .get("executed")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
-        let copied = result
-            .get("copied")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
+        // The process tree ties the lifetime of everything the command starts
+        // to this future, so a timeout or a cancelled tool call never leaves
+        // orphans running; kill_on_drop covers the direct child.
+        let mut child = tokio::process::Command::from(command_process)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to execute command")?;
+        let mut tree = ProcessTree::new(child.id().unwrap_or(0), &limits);
+        let mut out_reader = BufReader::new(child.stdout.take().context("stdout unavailable")?);
+        let mut err_reader = BufReader::new(child.stderr.take().context("stderr unavailable")?);
 
-        if copied {
-            println!("{}: command copied to clipboard", result_label);
-        } else if executed {
-            let output = result.get("output").and_then(|v| v.as_str()).unwrap_or("");
-            if !output.is_empty() {
-                println!("{}:\n{}", result_label, output);
-            } else {
-                let stdout = result.get("stdout").and_then(|v| v.as_str()).unwrap_or("");
-                let stderr = result.get("stderr").and_then(|v| v.as_str()).unwrap_or("");
-                if !stdout.is_empty() {
-                    println!("{} (stdout):\n{}", result_label, stdout);
+        let start = Instant::now();
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(timeout);
+        let mut stdout = TailBuffer::default();
+        let mut stderr = TailBuffer::default();
+        let mut combined = TailBuffer::default();
+        let (mut out_line, mut err_line) = (Vec::new(), Vec::new());
+        let (mut out_done, mut err_done) = (false, false);
+        let mut state_output: Option<String> = None;
+
+        // read_until keeps partial reads in the buffer when another branch wins,
+        // so lines are only handled once they are complete.
+        let mut timed_out = false;
+        let output_limit = self.policy.max_output_mb.map(|mb| mb * 1024 * 1024);
+        let mut streamed: u64 = 0;
+        let mut output_exceeded = false;
+        while !(out_done && err_done) {
+            tokio::select! {
+                n = out_reader.read_until(b'\n', &mut out_line), if !out_done => {
+                    if n.unwrap_or(0) == 0 {
+                        out_done = true;
+                    } else {
+                        let mut line = String::from_utf8_lossy(&out_line).to_string();
+                        out_line.clear();
+                        if let Some(state) = state_output.as_mut() {
+                            state.push_str(&line);
+                            continue;
+                        }
+                        // The marker follows the command's own output, possibly on
+                        // the same line when that output lacks a trailing newline.
+                        if let Some(prefix) = line.strip_suffix(&format!("{}\n", self.marker)) {
+                            state_output = Some(String::new());
+                            line = prefix.to_string();
+                        }
+                        if output::stdout_reserved() {
+                            eprint!("{}", line);
+                        } else {
+                            print!("{}", line);
+                            let _ = std::io::stdout().flush();
+                        }
+                        stdout.push(&line);
+                        combined.push(&line);
+                        streamed += line.len() as u64;
+                        if output_limit.is_some_and(|max| streamed > max) {
+                            output_exceeded = true;
+                            break;
+                        }
+                    }
                 }
-                if !stderr.is_empty() {
-                    println!("{} (stderr):\n{}", result_label, stderr);
+                n = err_reader.read_until(b'\n', &mut err_line), if !err_done => {
+                    if n.unwrap_or(0) == 0 {
+                        err_done = true;
+                    } else {
+                        let line = String::from_utf8_lossy(&err_line).to_string();
+                        eprint!("{}", line);
+                        stderr.push(&line);
+                        combined.push(&line);
+                        err_line.clear();
+                        streamed += line.len() as u64;
+                        if output_limit.is_some_and(|max| streamed > max) {
+                            output_exceeded = true;
+                            break;
+                        }
+                    }
+                }
+                _ = tokio::time::sleep_until(deadline) => {
+                    timed_out = true;
+                    break;
                 }
             }
-        } else if let Some(err) = result.get("error").and_then(|v| v.as_str()) {
-            println!("{}: {}", result_label, err);
+        }
+        let status = if timed_out || output_exceeded {
+            None
         } else {
-            println!("{}: command not executed", result_label);
+            tokio::time::timeout_at(deadline, child.wait())
+                .await
+                .ok()
+                .transpose()
+                .context("wait failed")?
+        };
+        let killed = match status {
+            Some(_) => {
+                tree.release();
+                Vec::new()
+            }
+            None => {
+                let killed = tree.kill();
+                let _ = child.kill().await;
+                killed
+            }
+        };
+        if !combined.buf.is_empty() && !combined.buf.ends_with('\n') {
+            say!();
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+        let cwd = state_output.and_then(|s| self.save_state(&s, &root));
+
+        let (stdout, _) = stdout.finish();
+        let (stderr, _) = stderr.finish();
+        let (output, truncated) = combined.finish();
+        let mut result = results::RunShell {
+            command,
+            stdout: Some(stdout),
+            stderr: Some(stderr),
+            output: Some(output),
+            output_truncated: Some(truncated),
+            elapsed_sec: Some((elapsed * 10.0).round() / 10.0),
+            sandbox: Some(sandbox_info),
+            ..results::RunShell::default()
+        };
+        if let Some(cwd) = cwd {
+            if !Path::new(&cwd).starts_with(&root) {
+                result.cwd_reset = Some(format!(
+                    "{} is outside the workspace root; the next command starts in {}",
+                    cwd,
+                    base.display()
+                ));
+            }
+            result.cwd = Some(cwd);
+        }
+        if let Some((adapted_command, notes)) = adapted {
+            result.adapted_command = Some(adapted_command);
+            result.adaptations = Some(notes);
+        }
+        if !hazards.is_empty() {
+            result.quoting_warnings = Some(hazards.iter().map(|h| h.message.clone()).collect());
+            if use_quoted {
+                result.executed_command = Some(to_run.clone());
+            }
+        }
+        if reset {
+            result.session_reset = Some(true);
+        }
+        let policy = &self.policy;
+        let limits = [
+            policy.max_cpu_seconds,
+            policy.max_memory_mb,
+            policy.max_output_mb,
+            policy.max_processes,
+        ];
+        if limits.iter().any(Option::is_some) {
+            result.limits = Some(results::ShellLimits {
+                max_cpu_seconds: policy.max_cpu_seconds,
+                max_memory_mb: policy.max_memory_mb,
+                max_output_mb: policy.max_output_mb,
+                max_processes: policy.max_processes,
+            });
+        }
+        match status {
+            Some(status) => {
+                result.executed = true;
+                result.exit_status = status.code();
+            }
+            None => {
+                result.error = Some(match self.policy.max_output_mb {
+                    Some(mb) if output_exceeded => format!("output exceeded the {} MiB limit", mb),
+                    _ => format!("timeout after {}s", timeout),
+                });
+                result.terminated = Some(killed);
+            }
         }
+        self.runs.record(&to_run, &ran_in, &result);
+        Ok(result)
     }
 }
@@ -0,0 +1,235 @@
+use regex::Regex;
+use std::process::Command;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use super::process::run_captured;
+
+/// The user's interactive shell. `run_shell` always executes with `sh -c`,
+/// so commands written for fish, nushell, or PowerShell need adapting first.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum UserShell {
+    Posix(String),
+    Fish,
+    Nu,
+    PowerShell,
+}
+
+impl UserShell {
+    pub(crate) fn detect() -> Self {
+        let from_env = std::env::var("SHELL").ok().and_then(|s| {
+            std::path::Path::new(&s)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+        });
+        match from_env.as_deref() {
+            Some("fish") => UserShell::Fish,
+            Some("nu") => UserShell::Nu,
+            Some("pwsh") | Some("powershell") => UserShell::PowerShell,
+            Some(other) => UserShell::Posix(other.to_string()),
+            None if std::env::var_os("PSModulePath").is_some() => UserShell::PowerShell,
+            None => UserShell::Posix("sh".into()),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            UserShell::Posix(name) => name,
+            UserShell::Fish => "fish",
+            UserShell::Nu => "nushell",
+            UserShell::PowerShell => "PowerShell",
+        }
+    }
+
+    pub(crate) fn is_posix(&self) -> bool {
+        matches!(self, UserShell::Posix(_))
+    }
+}
+
+static FISH_SET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^set\s+(?:-[a-zA-Z]*x[a-zA-Z]*|--export)(?:\s+-[a-zA-Z]+)*\s+([A-Za-z_][A-Za-z0-9_]*)\s+(.+)$")
+        .expect("valid regex")
+});
+static FISH_ERASE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^set\s+(?:-[a-zA-Z]*e[a-zA-Z]*|--erase)\s+([A-Za-z_][A-Za-z0-9_]*)$")
+        .expect("valid regex")
+});
+static ENV_ASSIGN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:\$env[:.]|let-env\s+)([A-Za-z_][A-Za-z0-9_]*)\s*=\s*(.+)$")
+        .expect("valid regex")
+});
+static ENV_REF: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$env[:.]([A-Za-z_][A-Za-z0-9_]*)").expect("valid regex"));
+static POSIX_EXPORT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^export\s+([A-Za-z_][A-Za-z0-9_]*)=(.*)$").expect("valid regex"));
+
+/// Split a command line at `;`, `&&`, `||`, and newlines outside quotes.
+/// Each segment carries the separator that preceded it.
+fn split_segments(command: &str) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    let mut sep = String::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if c == q => {
+                quote = None;
+                current.push(c);
+            }
+            (Some(_), _) => current.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.push(c);
+            }
+            (None, '\\') => {
+                current.push(c);
+                if let Some(n) = chars.next() {
+                    current.push(n);
+                }
+            }
+            (None, ';' | '\n') => {
+                out.push((std::mem::replace(&mut sep, c.to_string()), current.clone()));
+                current.clear();
+            }
+            (None, '&' | '|') if chars.peek() == Some(&c) => {
+                chars.next();
+                out.push((
+                    std::mem::replace(&mut sep, format!("{c}{c}")),
+                    current.clone(),
+                ));
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+    out.push((sep, current));
+    out
+}
+
+/// Inverse of [`split_segments`]; segments keep their own whitespace, so an
+/// unchanged command round-trips exactly.
+fn join_segments(segments: &[(String, String)]) -> String {
+    segments
+        .iter()
+        .map(|(sep, seg)| format!("{}{}", sep, seg))
+        .collect()
+}
+
+/// Quote a fish list value as one POSIX word. `PATH`-like lists are joined
+/// with ':' the way fish exports them.
+fn posix_value(name: &str, value: &str) -> String {
+    let value = value.trim();
+    let single_word = !value.contains(char::is_whitespace)
+        || (value.starts_with('"') && value.ends_with('"'))
+        || (value.starts_with('\'') && value.ends_with('\''));
+    if single_word {
+        return value.to_string();
+    }
+    let sep = if name.ends_with("PATH") { ":" } else { " " };
+    let joined: Vec<&str> = value.split_whitespace().collect();
+    format!("\"{}\"", joined.join(sep).replace('"', "\\\""))
+}
+
+/// Rewrite syntax from the user's shell that `sh` would reject or misread.
+/// Returns the adapted command and a note per rewrite, or `None` when the
+/// command needed no changes.
+pub(super) fn to_posix(command: &str, shell: &UserShell) -> Option<(String, Vec<String>)> {
+    if shell.is_posix() || cfg!(target_os = "windows") {
+        return None;
+    }
+    let mut notes = Vec::new();
+    let mut segments = split_segments(command);
+    for (sep, seg) in segments.iter_mut() {
+        let trimmed = seg.trim().to_string();
+        let lead = seg[..seg.len() - seg.trim_start().len()].to_string();
+        let mut rewritten: Option<String> = None;
+        if *shell == UserShell::Fish {
+            if let Some(c) = FISH_SET.captures(&trimmed) {
+                rewritten = Some(format!("export {}={}", &c[1], posix_value(&c[1], &c[2])));
+                notes.push(format!("`set -x {}` became `export`", &c[1]));
+            } else if let Some(c) = FISH_ERASE.captures(&trimmed) {
+                rewritten = Some(format!("unset {}", &c[1]));
+                notes.push(format!("`set -e {}` became `unset`", &c[1]));
+            } else if let Some(rest) = trimmed
+                .strip_prefix("and ")
+                .or_else(|| trimmed.strip_prefix("or "))
+            {
+                if sep == ";" {
+                    let (word, op) = if trimmed.starts_with("and ") {
+                        ("and", "&&")
+                    } else {
+                        ("or", "||")
+                    };
+                    notes.push(format!("`; {}` became `{}`", word, op));
+                    *sep = format!(" {}", op);
+                    rewritten = Some(rest.to_string());
+                }
+            }
+        } else if let Some(c) = ENV_ASSIGN.captures(&trimmed) {
+            rewritten = Some(format!("export {}={}", &c[1], c[2].trim()));
+            notes.push(format!("`$env` assignment to {} became `export`", &c[1]));
+        }
+        if let Some(new) = rewritten {
+            *seg = format!("{}{}", lead, new);
+        }
+    }
+    let mut adapted = join_segments(&segments);
+    if matches!(shell, UserShell::Nu | UserShell::PowerShell) && ENV_REF.is_match(&adapted) {
+        adapted = ENV_REF.replace_all(&adapted, "$$$1").to_string();
+        notes.push("`$env` references became `$NAME`".into());
+    }
+    if notes.is_empty() {
+        None
+    } else {
+        Some((adapted, notes))
+    }
+}
+
+/// Parse `command` with `sh -n` without running anything. Returns the
+/// parser's message when the command is not valid sh.
+pub(super) fn check_posix(command: &str) -> Option<String> {
+    if cfg!(target_os = "windows") {
+        return None;
+    }
+    let mut cmd = Command::new("sh");
+    cmd.args(["-n", "-c", command]);
+    let out = run_captured(cmd, Duration::from_secs(5)).ok()?;
+    if out.status?.success() {
+        return None;
+    }
+    let message = out.stderr.trim();
+    Some(if message.is_empty() {
+        "sh -n rejected the command".into()
+    } else {
+        message.to_string()
+    })
+}
+
+/// Rewrite `export NAME=value` statements for pasting into the user's shell.
+/// Everything else is left alone; fish and PowerShell 7 accept `&&` and `||`.
+pub(super) fn for_user_shell(command: &str, shell: &UserShell) -> String {
+    if shell.is_posix() {
+        return command.to_string();
+    }
+    let mut segments = split_segments(command);
+    for (_, seg) in segments.iter_mut() {
+        let lead = seg[..seg.len() - seg.trim_start().len()].to_string();
+        let Some(c) = POSIX_EXPORT.captures(seg.trim()) else {
+            continue;
+        };
+        let name = &c[1];
+        let value = if c[2].starts_with(['"', '\'']) {
+            c[2].to_string()
+        } else {
+            format!("\"{}\"", &c[2])
+        };
+        let line = match shell {
+            UserShell::Fish => format!("set -gx {} {}", name, value),
+            UserShell::Nu => format!("$env.{} = {}", name, value),
+            _ => format!("$env:{} = {}", name, value),
+        };
+        *seg = format!("{}{}", lead, line);
+    }
+    join_segments(&segments)
+}
@@ -0,0 +1,313 @@
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use llm::builder::ParamBuilder;
+use llm::chat::ParameterProperty;
+
+use crate::tools::dir::resolve_path;
+use crate::{say, scratch};
+
+use super::{ask_user, results, Tool};
+
+const MAX_CHANGES: usize = 200;
+
+/// Resource type fragments whose in-place updates change access or network
+/// boundaries and deserve a closer look.
+const SENSITIVE_TYPES: &[&str] = &[
+    "iam",
+    "policy",
+    "role",
+    "security_group",
+    "firewall",
+    "network_acl",
+    "kms",
+    "secret",
+    "route",
+    "dns",
+    "record",
+];
+
+/// Runs or reads a Terraform plan and summarizes its resource changes.
+pub struct TerraformPlanTool;
+
+fn terraform(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("terraform")
+        .args(args)
+        .current_dir(dir)
+        .env("TF_IN_AUTOMATION", "1")
+        .output()
+        .context("Failed to run terraform (is it installed?)")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "terraform {} failed: {}",
+            args.first().copied().unwrap_or(""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn classify(actions: &[&str]) -> &'static str {
+    match actions {
+        ["create"] => "create",
+        ["update"] => "update",
+        ["delete"] => "delete",
+        ["delete", "create"] | ["create", "delete"] => "replace",
+        ["read"] => "read",
+        _ => "no-op",
+    }
+}
+
+fn risks_for(kind: &str, resource_type: &str) -> Vec<&'static str> {
+    let mut risks = Vec::new();
+    match kind {
+        "delete" => risks.push("destroys resource"),
+        "replace" => risks.push("replaces resource (destroy and re-create)"),
+        _ => {}
+    }
+    if kind != "create" && SENSITIVE_TYPES.iter().any(|t| resource_type.contains(t)) {
+        risks.push("changes access or network controls");
+    }
+    risks
+}
+
+fn summarize(dir: &Path, plan: &Value) -> results::TerraformPlan {
+    let mut counts = BTreeMap::new();
+    for k in ["create", "update", "delete", "replace", "read", "no-op"] {
+        counts.insert(k.to_string(), 0);
+    }
+    let mut changes = Vec::new();
+    let mut risky = Vec::new();
+    let empty = Vec::new();
+    let resource_changes = plan
+        .get("resource_changes")
+        .and_then(|v| v.as_array())
+        .unwrap_or(&empty);
+    for rc in resource_changes {
+        if rc.get("mode").and_then(|v| v.as_str()) == Some("data") {
+            continue;
+        }
+        let address = rc.get("address").and_then(|v| v.as_str()).unwrap_or("?");
+        let resource_type = rc.get("type").and_then(|v| v.as_str()).unwrap_or("");
+        let actions: Vec<&str> = rc
+            .pointer("/change/actions")
+            .and_then(|v| v.as_array())
+            .map(|a| a.iter().filter_map(|x| x.as_str()).collect())
+            .unwrap_or_default();
+        let kind = classify(&actions);
+        if let Some(c) = counts.get_mut(kind) {
+            *c += 1;
+        }
+        if kind == "no-op" || kind == "read" {
+            continue;
+        }
+        let risks = risks_for(kind, resource_type);
+        if !risks.is_empty() {
+            risky.push(address.to_string());
+        }
+        if changes.len() < MAX_CHANGES {
+            changes.push(results::PlannedChange {
+                address: address.to_string(),
+                resource_type: resource_type.to_string(),
+                action: kind.to_string(),
+                risks: risks.iter().map(|r| r.to_string()).collect(),
+                replace_paths: rc
+                    .pointer("/change/replace_paths")
+                    .cloned()
+                    .unwrap_or(Value::Null),
+            });
+        }
+    }
+    results::TerraformPlan {
+        dir: dir.display().to_string(),
+        terraform_version: plan
+            .get("terraform_version")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+        summary: counts,
+        risky,
+        changes,
+        changes_truncated: resource_changes.len() > MAX_CHANGES,
+    }
+}
+
+impl Tool for TerraformPlanTool {
+    fn name(&self) -> &'static str {
+        "terraform_plan"
+    }
+    fn description(&self) -> &'static str {
+        "Run `terraform plan` (or read a saved plan file) and return a structured summary of resource creates/updates/destroys/replacements with risky changes flagged. Use this to judge whether a plan is safe to apply."
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("dir")
+                .type_of("string")
+                .description("Terraform working directory (default '.')"),
+            ParamBuilder::new("plan_file")
+                .type_of("string")
+                .description("Existing plan to analyze: a binary plan from `terraform plan -out` or its JSON from `terraform show -json`. If omitted, a new plan is created."),
+            ParamBuilder::new("args")
+                .type_of("array")
+                .items(ParameterProperty {
+                    property_type: "string".into(),
+                    description: "argument".into(),
+                    items: None,
+                    enum_list: None,
+                })
+                .description("Extra arguments for `terraform plan` (e.g. [\"-var-file=prod.tfvars\"])"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let dir_s = args.get("dir").and_then(|v| v.as_str()).unwrap_or(".");
+        let dir = resolve_path(dir_s, false)?;
+        let plan_json = if let Some(pf) = args.get("plan_file").and_then(|v| v.as_str()) {
+            let plan_path = resolve_path(pf, false)?;
+            let raw = fs::read(&plan_path)
+                .with_context(|| format!("Failed reading {}", plan_path.display()))?;
+            match serde_json::from_slice::<Value>(&raw) {
+                Ok(v) => v,
+                Err(_) => {
+                    let out =
+                        terraform(&dir, &["show", "-json", &plan_path.display().to_string()])?;
+                    serde_json::from_str(&out).context("terraform show produced invalid JSON")?
+                }
+            }
+        } else {
+            let extra: Vec<String> = args
+                .get("args")
+                .and_then(|v| v.as_array())
+                .map(|arr| {
+                    arr.iter()
+                        .filter_map(|a| a.as_str().map(|s| s.to_string()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let display = format!("terraform plan {}", extra.join(" "));
+            let choice = ask_user(&format!(
+                "Run `{}` in {}? [Y/n] ",
+                display.trim(),
+                dir.display()
+            ))?;
+            if choice == "n" {
+                say!("Command execution cancelled");
+                return results::to_value(results::NotRun::new(display.trim()));
+            }
+            let plan_out = scratch::file("plan.tfplan")?;
+            let plan_out_s = plan_out.display().to_string();
+            let mut plan_args = vec!["plan", "-input=false", "-no-color", "-lock=false"];
+            let out_flag = format!("-out={}", plan_out_s);
+            plan_args.push(&out_flag);
+            plan_args.extend(extra.iter().map(|s| s.as_str()));
+            let result = terraform(&dir, &plan_args)
+                .and_then(|_| terraform(&dir, &["show", "-json", &plan_out_s]));
+            let _ = fs::remove_file(&plan_out);
+            serde_json::from_str(&result?).context("terraform show produced invalid JSON")?
+        };
+        results::to_value(summarize(&dir, &plan_json))
+    }
+
+    fn print_result(&self, result: &Value) {
+        use nu_ansi_term::{Color as NuColor, Style};
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        let Some(summary) = result.get("summary") else {
+            println!("{}: plan not created", result_label);
+            return;
+        };
+        let n = |k: &str| summary.get(k).and_then(|v| v.as_u64()).unwrap_or(0);
+        let risky = result
+            .get("risky")
+            .and_then(|v| v.as_array())
+            .map(|a| a.len())
+            .unwrap_or(0);
+        println!(
+            "{}: {} to create, {} to update, {} to destroy, {} to replace ({} risky)",
+            result_label,
+            n("create"),
+            n("update"),
+            n("delete"),
+            n("replace"),
+            risky
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn actions_are_classified() {
+        assert_eq!(classify(&["create"]), "create");
+        assert_eq!(classify(&["update"]), "update");
+        assert_eq!(classify(&["delete"]), "delete");
+        assert_eq!(classify(&["delete", "create"]), "replace");
+        assert_eq!(classify(&["create", "delete"]), "replace");
+        assert_eq!(classify(&["read"]), "read");
+        assert_eq!(classify(&["no-op"]), "no-op");
+        assert_eq!(classify(&[]), "no-op");
+    }
+
+    fn change(address: &str, resource_type: &str, actions: &[&str]) -> Value {
+        json!({
+            "address": address,
+            "mode": "managed",
+            "type": resource_type,
+            "change": { "actions": actions },
+        })
+    }
+
+    #[test]
+    fn plans_are_summarized_with_their_risks() {
+        let plan = json!({
+            "terraform_version": "1.9.0",
+            "resource_changes": [
+                change("aws_s3_bucket.logs", "aws_s3_bucket", &["create"]),
+                change("aws_iam_role.ci", "aws_iam_role", &["update"]),
+                change("aws_instance.web", "aws_instance", &["delete", "create"]),
+                change("aws_db_instance.old", "aws_db_instance", &["delete"]),
+                change("aws_vpc.main", "aws_vpc", &["no-op"]),
+                change("aws_iam_policy.new", "aws_iam_policy", &["create"]),
+                { "address": "data.aws_ami.x", "mode": "data", "type": "aws_ami",
+                  "change": { "actions": ["read"] } },
+            ],
+        });
+        let summary = summarize(Path::new("infra"), &plan);
+        assert_eq!(summary.terraform_version.as_deref(), Some("1.9.0"));
+        let counts: Vec<_> = ["create", "update", "delete", "replace", "read", "no-op"]
+            .iter()
+            .map(|k| summary.summary[*k])
+            .collect();
+        assert_eq!(counts, [2, 1, 1, 1, 0, 1]);
+        // Creating an IAM policy is not risky; changing a role is.
+        assert_eq!(
+            summary.risky,
+            ["aws_iam_role.ci", "aws_instance.web", "aws_db_instance.old"]
+        );
+        let actions: Vec<_> = summary.changes.iter().map(|c| c.action.as_str()).collect();
+        assert_eq!(actions, ["create", "update", "replace", "delete", "create"]);
+        assert!(!summary.changes_truncated);
+        results::validate("terraform_plan", &results::to_value(summary).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn long_plans_are_truncated() {
+        let changes: Vec<_> = (0..MAX_CHANGES + 5)
+            .map(|i| {
+                change(
+                    &format!("null_resource.r{}", i),
+                    "null_resource",
+                    &["create"],
+                )
+            })
+            .collect();
+        let summary = summarize(Path::new("."), &json!({ "resource_changes": changes }));
+        assert_eq!(summary.changes.len(), MAX_CHANGES);
+        assert_eq!(summary.summary["create"], MAX_CHANGES + 5);
+        assert!(summary.changes_truncated);
+    }
+}
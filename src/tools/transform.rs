@@ -0,0 +1,233 @@
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use llm::builder::ParamBuilder;
+
+use super::results::{self, Transform};
+use super::Tool;
+
+const OPERATIONS: &[&str] = &[
+    "hash",
+    "base64_encode",
+    "base64_decode",
+    "hex_encode",
+    "hex_decode",
+    "url_encode",
+    "url_decode",
+    "jwt_decode",
+];
+
+/// Exact, local hashing and encoding so the model never has to guess at
+/// digests or decoded values.
+pub struct TransformTool;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Result<Vec<u8>> {
+    let clean: String = s
+        .trim_start_matches("0x")
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect();
+    if !clean.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Input is not valid hex"));
+    }
+    if !clean.len().is_multiple_of(2) {
+        return Err(anyhow!("Hex input has an odd number of digits"));
+    }
+    (0..clean.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&clean[i..i + 2], 16).context("Invalid hex digit"))
+        .collect()
+}
+
+fn digest(algorithm: &str, data: &[u8]) -> Result<String> {
+    let alg = match algorithm {
+        "md5" => return Ok(format!("{:x}", md5::compute(data))),
+        "sha1" => &ring::digest::SHA1_FOR_LEGACY_USE_ONLY,
+        "sha256" => &ring::digest::SHA256,
+        "sha384" => &ring::digest::SHA384,
+        "sha512" => &ring::digest::SHA512,
+        other => return Err(anyhow!("Unsupported hash algorithm: {}", other)),
+    };
+    Ok(to_hex(ring::digest::digest(alg, data).as_ref()))
+}
+
+fn decode_base64(s: &str) -> Result<Vec<u8>> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    STANDARD
+        .decode(&s)
+        .or_else(|_| URL_SAFE.decode(&s))
+        .or_else(|_| URL_SAFE_NO_PAD.decode(s.trim_end_matches('=')))
+        .context("Input is not valid base64")
+}
+
+/// Decoded bytes as text when they are UTF-8, otherwise as hex.
+fn bytes_output(bytes: Vec<u8>) -> Transform {
+    match String::from_utf8(bytes) {
+        Ok(text) => Transform {
+            binary: Some(false),
+            ..Transform::output(text)
+        },
+        Err(e) => Transform {
+            binary: Some(true),
+            encoding: Some("hex".into()),
+            ..Transform::output(to_hex(e.as_bytes()))
+        },
+    }
+}
+
+fn jwt_decode(token: &str) -> Result<Transform> {
+    let token = token.trim().trim_start_matches("Bearer ").trim();
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        return Err(anyhow!(
+            "Not a JWT: expected 3 dot-separated parts, found {}",
+            parts.len()
+        ));
+    }
+    let part = |i: usize, what: &str| -> Result<Value> {
+        let raw = URL_SAFE_NO_PAD
+            .decode(parts[i].trim_end_matches('='))
+            .with_context(|| format!("JWT {} is not valid base64url", what))?;
+        serde_json::from_slice(&raw).with_context(|| format!("JWT {} is not valid JSON", what))
+    };
+    let header = part(0, "header")?;
+    let claims = part(1, "claims")?;
+    let now = chrono::Utc::now().timestamp();
+    let mut times = BTreeMap::new();
+    for key in ["iat", "nbf", "exp"] {
+        if let Some(ts) = claims.get(key).and_then(|v| v.as_i64()) {
+            if let Some(t) = chrono::DateTime::from_timestamp(ts, 0) {
+                times.insert(key.to_string(), t.to_rfc3339());
+            }
+        }
+    }
+    let expired = claims
+        .get("exp")
+        .and_then(|v| v.as_i64())
+        .map(|exp| exp < now);
+    Ok(Transform {
+        header: Some(header),
+        claims: Some(claims),
+        times: Some(times),
+        expired,
+        signature_verified: Some(false),
+        ..Transform::default()
+    })
+}
+
+impl Tool for TransformTool {
+    fn name(&self) -> &'static str {
+        "transform"
+    }
+    fn description(&self) -> &'static str {
+        "Compute exact results locally: hash a string or file (md5, sha1, sha256, sha384, sha512), base64/hex/URL encode or decode, or decode a JWT's header and claims (signature is not verified). Always use this instead of computing digests or decodings yourself."
+    }
+    fn required_params(&self) -> &'static [&'static str] {
+        &["operation"]
+    }
+    fn params(&self) -> Vec<ParamBuilder> {
+        vec![
+            ParamBuilder::new("operation")
+                .type_of("string")
+                .description(format!("One of: {}", OPERATIONS.join(", "))),
+            ParamBuilder::new("input")
+                .type_of("string")
+                .description("Input text (or token for jwt_decode)"),
+            ParamBuilder::new("path")
+                .type_of("string")
+                .description("File to read instead of 'input' (hash and encode operations)"),
+            ParamBuilder::new("algorithm")
+                .type_of("string")
+                .description("Hash algorithm for 'hash' (default sha256)"),
+            ParamBuilder::new("url_safe")
+                .type_of("boolean")
+                .description("Use the URL-safe alphabet without padding for base64_encode"),
+        ]
+    }
+    fn execute_blocking(&self, args: Value) -> Result<Value> {
+        let op = args
+            .get("operation")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing 'operation'"))?;
+        if !OPERATIONS.contains(&op) {
+            return Err(anyhow!("Unknown operation: {}", op));
+        }
+        let path = args.get("path").and_then(|v| v.as_str());
+        let data: Vec<u8> = match (path, args.get("input").and_then(|v| v.as_str())) {
+            // Only digests/encodings are returned, and files can be anywhere the
+            // user points (e.g. a downloaded ISO), so no workspace restriction.
+            (Some(p), _) => std::fs::read(p).with_context(|| format!("Failed reading {}", p))?,
+            (None, Some(s)) => s.as_bytes().to_vec(),
+            (None, None) => return Err(anyhow!("Provide 'input' or 'path'")),
+        };
+        let text = || String::from_utf8_lossy(&data).trim().to_string();
+
+        let mut result = match op {
+            "hash" => {
+                let algorithm = args
+                    .get("algorithm")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("sha256")
+                    .to_lowercase()
+                    .replace('-', "");
+                Transform {
+                    output: Some(digest(&algorithm, &data)?),
+                    algorithm: Some(algorithm),
+                    ..Transform::default()
+                }
+            }
+            "base64_encode" => {
+                let url_safe = args
+                    .get("url_safe")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let out = if url_safe {
+                    URL_SAFE_NO_PAD.encode(&data)
+                } else {
+                    STANDARD.encode(&data)
+                };
+                Transform::output(out)
+            }
+            "base64_decode" => bytes_output(decode_base64(&text())?),
+            "hex_encode" => Transform::output(to_hex(&data)),
+            "hex_decode" => bytes_output(from_hex(&text())?),
+            "url_encode" => {
+                let raw = String::from_utf8_lossy(&data);
+                Transform::output(utf8_percent_encode(&raw, NON_ALPHANUMERIC).to_string())
+            }
+            "url_decode" => {
+                let plus_as_space = text().replace('+', " ");
+                bytes_output(percent_decode_str(&plus_as_space).collect())
+            }
+            _ => jwt_decode(&text())?,
+        };
+        result.operation = op.to_string();
+        result.input_bytes = data.len();
+        result.path = path.map(str::to_string);
+        results::to_value(result)
+    }
+
+    fn print_result(&self, result: &Value) {
+        use nu_ansi_term::{Color as NuColor, Style};
+        let result_label = Style::new().fg(NuColor::LightMagenta).paint("result");
+        if let Some(out) = result.get("output").and_then(|v| v.as_str()) {
+            let shown: String = out.chars().take(200).collect();
+            let ellipsis = if out.chars().count() > 200 { "…" } else { "" };
+            println!("{}: {}{}", result_label, shown, ellipsis);
+        } else if let Some(claims) = result.get("claims") {
+            let expired = match result.get("expired").and_then(|v| v.as_bool()) {
+                Some(true) => " (expired)",
+                _ => "",
+            };
+            println!("{}: JWT claims{} {}", result_label, expired, claims);
+        }
+    }
+}
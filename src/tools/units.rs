@@ -0,0 +1,92 @@
+use std::sync::OnceLock;
+
+/// How numbers are written where the user is: the decimal mark and the
+/// separator between groups of thousands.
+struct Locale {
+    decimal: char,
+    group: char,
+}
+
+/// Languages that write 1.234,5 and 1 234,5; the rest write 1,234.5.
+const DOT_GROUPS: &[&str] = &["de", "es", "it", "nl", "pt", "da", "tr", "id", "el", "ro"];
+const SPACE_GROUPS: &[&str] = &[
+    "fr", "ru", "pl", "sv", "fi", "cs", "sk", "nb", "nn", "no", "uk", "hu", "bg", "et", "lt", "lv",
+];
+
+/// The locale from `LC_ALL`, `LC_NUMERIC`, or `LANG`, the first one set,
+/// as the C library picks it.
+fn locale() -> &'static Locale {
+    static LOCALE: OnceLock<Locale> = OnceLock::new();
+    LOCALE.get_or_init(|| {
+        let name = ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        let language = name.split(['_', '.', '@']).next().unwrap_or_default();
+        if DOT_GROUPS.contains(&language) {
+            Locale {
+                decimal: ',',
+                group: '.',
+            }
+        } else if SPACE_GROUPS.contains(&language) {
+            Locale {
+                decimal: ',',
+                group: '\u{a0}',
+            }
+        } else {
+            Locale {
+                decimal: '.',
+                group: ',',
+            }
+        }
+    })
+}
+
+/// `value` with one decimal, in the user's notation.
+fn decimal(value: f64) -> String {
+    format!("{:.1}", value).replace('.', &locale().decimal.to_string())
+}
+
+/// A count with its thousands grouped: 12,345.
+pub(crate) fn count(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(locale().group);
+        }
+        out.push(digit);
+    }
+    out
+}
+
+/// A size in binary units: 512 B, 1.4 MiB.
+pub(super) fn size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{} {}", decimal(value), UNITS[unit])
+}
+
+/// A duration: 0.4s, 12.3s, 3m 12s, 2h 05m.
+pub(crate) fn duration(secs: f64) -> String {
+    if secs < 59.95 {
+        return format!("{}s", decimal(secs));
+    }
+    let whole = secs.round() as u64;
+    if whole < 3_600 {
+        format!("{}m {:02}s", whole / 60, whole % 60)
+    } else if whole < 86_400 {
+        format!("{}h {:02}m", whole / 3_600, whole % 3_600 / 60)
+    } else {
+        format!("{}d {:02}h", whole / 86_400, whole % 86_400 / 3_600)
+    }
+}
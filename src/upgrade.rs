@@ -0,0 +1,419 @@
+use anyhow::{bail, Context, Result};
+use nu_ansi_term::{Color as NuColor, Style};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::chat::Check;
+use crate::config::Config;
+use crate::output;
+use crate::project::{detected_diagnostics, node_package_manager, run_diagnostics};
+use crate::say;
+use crate::tools::{ask_user, auto_approve_edits, record_changes, recorded_changes, remember};
+
+/// Dependency tables of Cargo.toml, with the `cargo add` flag for each.
+const CARGO_SECTIONS: &[(&str, Option<&str>)] = &[
+    ("dependencies", None),
+    ("dev-dependencies", Some("--dev")),
+    ("build-dependencies", Some("--build")),
+];
+
+/// Dependency objects of package.json, with whether each is for development.
+const NODE_SECTIONS: &[(&str, bool)] = &[
+    ("dependencies", false),
+    ("devDependencies", true),
+    ("optionalDependencies", false),
+];
+
+const NODE_LOCK_FILES: &[&str] = &[
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "pnpm-lock.yaml",
+    "yarn.lock",
+    "bun.lock",
+    "bun.lockb",
+];
+
+enum Manifest {
+    /// The `cargo add` flag of the dependency's table.
+    Cargo(Option<&'static str>),
+    /// The package manager, and whether it is a development dependency.
+    Node(&'static str, bool),
+}
+
+/// The version requirement of `dep` in the Cargo.toml at `dir`, and its table.
+fn cargo_requirement(dir: &Path, dep: &str) -> Result<Option<(String, Option<&'static str>)>> {
+    let Ok(text) = fs::read_to_string(dir.join("Cargo.toml")) else {
+        return Ok(None);
+    };
+    let manifest: toml::Table = toml::from_str(&text).context("Failed to parse Cargo.toml")?;
+    for (section, flag) in CARGO_SECTIONS {
+        let Some(spec) = manifest
+            .get(*section)
+            .and_then(|s| s.as_table())
+            .and_then(|t| t.get(dep))
+        else {
+            continue;
+        };
+        if spec.get("workspace").and_then(|w| w.as_bool()) == Some(true) {
+            bail!(
+                "{} is inherited from [workspace.dependencies], which tai upgrade cannot change",
+                dep
+            );
+        }
+        let version = spec
+            .as_str()
+            .or_else(|| spec.get("version").and_then(|v| v.as_str()))
+            .with_context(|| format!("{} has no version requirement in Cargo.toml", dep))?;
+        return Ok(Some((version.to_string(), *flag)));
+    }
+    Ok(None)
+}
+
+/// The version requirement of `dep` in the package.json at `dir`, and
+/// whether it is a development dependency.
+fn node_requirement(dir: &Path, dep: &str) -> Result<Option<(String, bool)>> {
+    let Ok(text) = fs::read_to_string(dir.join("package.json")) else {
+        return Ok(None);
+    };
+    let manifest: serde_json::Value =
+        serde_json::from_str(&text).context("Failed to parse package.json")?;
+    Ok(NODE_SECTIONS.iter().find_map(|(section, dev)| {
+        let version = manifest.get(*section)?.get(dep)?.as_str()?;
+        Some((version.to_string(), *dev))
+    }))
+}
+
+/// The first of `names` found in `dir` or the directories above it, where
+/// workspaces keep their lock file.
+fn find_upwards(dir: &Path, names: &[&str]) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|d| names.iter().map(move |n| d.join(n)))
+        .find(|p| p.is_file())
+}
+
+/// The newest version of crate `dep` that `cargo search` knows.
+fn latest_crate(dep: &str) -> Result<String> {
+    let output = Command::new("cargo")
+        .args(["search", "--limit", "1", dep])
+        .output()
+        .context("Failed to run cargo search")?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines()
+        .next()
+        .and_then(|line| line.strip_prefix(&format!("{} = \"", dep)))
+        .and_then(|rest| rest.split('"').next())
+        .map(str::to_string)
+        .with_context(|| format!("No crate named {} found with cargo search", dep))
+}
+
+/// Added and removed lines between `old` and `new`, ignoring moves.
+fn line_changes(old: &str, new: &str) -> (usize, usize) {
+    let mut balance: HashMap<&str, isize> = HashMap::new();
+    for line in old.lines() {
+        *balance.entry(line).or_default() += 1;
+    }
+    for line in new.lines() {
+        *balance.entry(line).or_default() -= 1;
+    }
+    balance
+        .values()
+        .fold((0, 0), |(added, removed), &n| match n {
+            n if n < 0 => (added + n.unsigned_abs(), removed),
+            n => (added, removed + n.unsigned_abs()),
+        })
+}
+
+/// Put every recorded file back as it was; files that did not exist are
+/// removed.
+fn restore(changes: &[(PathBuf, Option<Vec<u8>>)]) -> Result<()> {
+    for (path, original) in changes {
+        match original {
+            Some(bytes) => fs::write(path, bytes)
+                .with_context(|| format!("Failed to restore {}", path.display()))?,
+            None => fs::remove_file(path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?,
+        }
+    }
+    Ok(())
+}
+
+/// `tai upgrade`: a dependency bumped in the manifest, with every change
+/// made afterwards recorded so it can be rolled back.
+pub struct Upgrade {
+    dep: String,
+    from: String,
+    to: String,
+    manifest: Manifest,
+}
+
+impl Upgrade {
+    /// Check that the build passes, bump `dep` to `to` (the newest version
+    /// by default), and build again. Returns the request for the model and
+    /// the check it must pass when the build broke, `None` when it did not.
+    pub fn start(
+        cfg: &Config,
+        dep: &str,
+        to: Option<&str>,
+        verify: Option<&str>,
+        attempts: u32,
+    ) -> Result<(Self, Option<(String, Check)>)> {
+        let dir = std::env::current_dir()?.canonicalize()?;
+        let (manifest, from) = if let Some((from, flag)) = cargo_requirement(&dir, dep)? {
+            (Manifest::Cargo(flag), from)
+        } else if let Some((from, dev)) = node_requirement(&dir, dep)? {
+            (Manifest::Node(node_package_manager(&dir), dev), from)
+        } else {
+            bail!(
+                "{} is not a dependency in ./Cargo.toml or ./package.json",
+                dep
+            );
+        };
+
+        let command = verify
+            .map(str::to_string)
+            .or_else(|| detected_diagnostics(cfg, &dir))
+            .context("No build check found for this project; pass --verify with one")?;
+        say!("Checking that `{}` passes before the upgrade", command);
+        if run_diagnostics(&command, &dir)?.is_some() {
+            bail!(
+                "`{}` fails before the upgrade; get the build passing first",
+                command
+            );
+        }
+
+        record_changes();
+        // Every change is reviewed, and can be rolled back, at the end.
+        auto_approve_edits();
+        let (manifest_file, lock_files): (&str, &[&str]) = match manifest {
+            Manifest::Cargo(_) => ("Cargo.toml", &["Cargo.lock"]),
+            Manifest::Node(..) => ("package.json", NODE_LOCK_FILES),
+        };
+        remember(&dir.join(manifest_file));
+        if let Some(lock) = find_upwards(&dir, lock_files) {
+            remember(&lock);
+        }
+
+        let (program, args) = match &manifest {
+            Manifest::Cargo(flag) => {
+                let version = match to {
+                    Some(v) => v.to_string(),
+                    None => latest_crate(dep)?,
+                };
+                let mut args = vec!["add".to_string(), format!("{}@{}", dep, version)];
+                args.extend(flag.map(str::to_string));
+                ("cargo", args)
+            }
+            Manifest::Node(pm, dev) => {
+                let spec = format!("{}@{}", dep, to.unwrap_or("latest"));
+                let mut args = vec![
+                    if *pm == "npm" { "install" } else { "add" }.to_string(),
+                    spec,
+                ];
+                if *dev {
+                    args.push(if *pm == "npm" { "--save-dev" } else { "-D" }.to_string());
+                }
+                (*pm, args)
+            }
+        };
+        say!("Running {} {}", program, args.join(" "));
+        let bumped = Command::new(program)
+            .args(&args)
+            .current_dir(&dir)
+            .output()
+            .with_context(|| format!("Failed to run {}", program))?;
+        if !bumped.status.success() {
+            restore(&recorded_changes())?;
+            bail!(
+                "{} {} failed:\n{}{}",
+                program,
+                args.join(" "),
+                String::from_utf8_lossy(&bumped.stdout),
+                String::from_utf8_lossy(&bumped.stderr)
+            );
+        }
+        let to = match &manifest {
+            Manifest::Cargo(_) => cargo_requirement(&dir, dep)?.map(|(v, _)| v),
+            Manifest::Node(..) => node_requirement(&dir, dep)?.map(|(v, _)| v),
+        }
+        .with_context(|| format!("{} disappeared from {}", dep, manifest_file))?;
+        if to == from {
+            restore(&recorded_changes())?;
+            bail!("{} is already at {}", dep, to);
+        }
+        say!("Bumped {} {} → {}", dep, from, to);
+        let upgrade = Self {
+            dep: dep.to_string(),
+            from,
+            to,
+            manifest,
+        };
+
+        say!("Building with `{}`", command);
+        let Some(failure) = run_diagnostics(&command, &dir)? else {
+            say!(
+                "{}",
+                NuColor::Green.paint(format!("`{}` passes; no code changes needed", command))
+            );
+            return Ok((upgrade, None));
+        };
+        say!(
+            "{}",
+            NuColor::Red.paint(format!(
+                "`{}` fails; handing the errors to the model",
+                command
+            ))
+        );
+
+        let sources = match upgrade.manifest {
+            Manifest::Cargo(_) => "its sources under ~/.cargo/registry/src",
+            Manifest::Node(..) => "its sources and type definitions under node_modules",
+        };
+        let prompt = format!(
+            r#"Upgrade {dep} from {from} to {to} in this project. {manifest_file} is already changed, and `{command}` now fails:
+
+```
+{failure}
+```
+
+Fix the code for the new version of {dep}.
+
+Rules:
+- Find out what changed in {dep} from its changelog or {sources}, using run_shell, rather than guessing at the new API.
+- Change files with patch_file or apply_patch, or write_file for new files, so the changes can be rolled back. Do not edit files with shell commands.
+- Keep {dep} at the new version.
+- Change other dependencies only if the new version requires it, and say so when you do.
+- Make the smallest changes that build; do not refactor unrelated code.
+- `{command}` is run afterwards and must pass.
+- Finish with a list of the changes, and anything the user should check by hand."#,
+            dep = upgrade.dep,
+            from = upgrade.from,
+            to = upgrade.to,
+            failure = failure.trim_end(),
+        );
+        let check = Check {
+            attempts,
+            run: Box::new(move || {
+                say!("Building with `{}`", command);
+                let failure = run_diagnostics(&command, &dir)?;
+                match &failure {
+                    None => say!(
+                        "{}",
+                        NuColor::Green.paint(format!("`{}` passes; upgrade verified", command))
+                    ),
+                    Some(_) => say!("{}", NuColor::Red.paint(format!("`{}` fails", command))),
+                }
+                Ok(failure.map(|output| {
+                    format!(
+                        "`{}` still fails:\n```\n{}\n```\nFix it.",
+                        command,
+                        output.trim_end()
+                    )
+                }))
+            }),
+        };
+        Ok((upgrade, Some((prompt, check))))
+    }
+
+    /// Summarize what changed and offer to roll it all back: by default when
+    /// `result` is a failure, on request when it succeeded. `result` is
+    /// passed through.
+    pub fn finish(&self, result: Result<()>) -> Result<()> {
+        let changes = recorded_changes();
+        let cwd = std::env::current_dir()?.canonicalize()?;
+        let files: Vec<(String, &'static str, usize, usize)> = changes
+            .iter()
+            .map(|(path, original)| {
+                let shown = path
+                    .strip_prefix(&cwd)
+                    .unwrap_or(path)
+                    .display()
+                    .to_string();
+                let new = fs::read(path).unwrap_or_default();
+                let old = original.as_deref().unwrap_or_default();
+                let (added, removed) = line_changes(
+                    &String::from_utf8_lossy(old),
+                    &String::from_utf8_lossy(&new),
+                );
+                let status = if original.is_none() {
+                    "created"
+                } else {
+                    "modified"
+                };
+                (shown, status, added, removed)
+            })
+            .collect();
+
+        if output::is_json() {
+            output::emit(
+                "upgrade",
+                json!({
+                    "dependency": self.dep,
+                    "from": self.from,
+                    "to": self.to,
+                    "passed": result.is_ok(),
+                    "files": files.iter().map(|(path, status, added, removed)| json!({
+                        "path": path,
+                        "status": status,
+                        "added": added,
+                        "removed": removed,
+                    })).collect::<Vec<_>>(),
+                }),
+            );
+            return result;
+        }
+
+        say!();
+        say!(
+            "{}",
+            Style::new().bold().paint(format!(
+                "Upgrade of {} {} → {}: {}",
+                self.dep,
+                self.from,
+                self.to,
+                if result.is_ok() {
+                    "the build passes"
+                } else {
+                    "the build does not pass"
+                }
+            ))
+        );
+        let width = files
+            .iter()
+            .map(|(p, ..)| p.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (path, status, added, removed) in &files {
+            say!(
+                "  {:width$}  {:8}  {} {}",
+                path,
+                status,
+                NuColor::Green.paint(format!("+{}", added)),
+                NuColor::Red.paint(format!("-{}", removed)),
+                width = width
+            );
+        }
+
+        let roll_back = if result.is_ok() {
+            ask_user("Keep these changes? [Y/n] ")? == "n"
+        } else {
+            // An empty answer, which is also what a closed stdin gives,
+            // leaves the tree as it was before the upgrade.
+            ask_user("Roll back all changes? [Y/n] ")? != "n"
+        };
+        if roll_back {
+            restore(&changes)?;
+            say!(
+                "Rolled back {} file{}",
+                changes.len(),
+                if changes.len() == 1 { "" } else { "s" }
+            );
+            if let Manifest::Node(pm, _) = self.manifest {
+                say!("Run `{} install` to bring node_modules back in line", pm);
+            }
+        }
+        result
+    }
+}